@@ -0,0 +1,158 @@
+use num::BigUint;
+use crate::point::Point;
+use crate::script::Script;
+use crate::script_debugger::ScriptTracer;
+use crate::signature::{Signature, SignatureChecks};
+use crate::verify_flags::VerifyFlags;
+use crate::tx::Tx;
+use crate::tx_output::TxOutput;
+
+/// Which signature hash a legacy or segwit v0 script commits to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigVersion {
+    /// legacy and P2SH scripts, the original signature hash
+    Base,
+    /// P2WPKH and P2WSH scripts, the BIP143 signature hash
+    WitnessV0,
+}
+
+/// How OP_CHECKSIG decides whether an ECDSA signature is good
+pub trait SignatureChecker {
+    /// sig ends with its hash type byte, script_code is the script the signature commits
+    /// to: the executed script from its last OP_CODESEPARATOR
+    fn check_ecdsa_signature(&self, context: &ExecutionContext, sig: &[u8], pubkey: &[u8], script_code: &Script, sig_version: SigVersion) -> bool;
+}
+
+/// Checks signatures against the signature hash of the spending transaction
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransactionSignatureChecker;
+impl SignatureChecker for TransactionSignatureChecker {
+    fn check_ecdsa_signature(&self, context: &ExecutionContext, sig: &[u8], pubkey: &[u8], script_code: &Script, sig_version: SigVersion) -> bool {
+        let Some((&hash_type, _)) = sig.split_last() else {
+            return false;
+        };
+        let z = context.sig_hash(script_code, hash_type, sig_version);
+        verify_ecdsa(sig, pubkey, &z)
+    }
+}
+
+/// Checks every signature against a known z, for scripts evaluated without the
+/// transaction they unlock, like the examples of chapter 6
+#[derive(Debug, Clone)]
+pub struct FixedHashChecker {
+    z: BigUint,
+}
+impl FixedHashChecker {
+    pub fn new(z: BigUint) -> Self {
+        FixedHashChecker { z }
+    }
+}
+impl SignatureChecker for FixedHashChecker {
+    fn check_ecdsa_signature(&self, _context: &ExecutionContext, sig: &[u8], pubkey: &[u8], _script_code: &Script, _sig_version: SigVersion) -> bool {
+        verify_ecdsa(sig, pubkey, &self.z)
+    }
+}
+
+// sig is DER followed by the hash type byte, pubkey is SEC. DERSIG rejected a non
+// strict encoding before this, without it the signatures of old blocks parse laxly.
+fn verify_ecdsa(sig: &[u8], pubkey: &[u8], z: &BigUint) -> bool {
+    let Some((_, der)) = sig.split_last() else {
+        return false;
+    };
+    match (Point::try_parse(pubkey), Signature::parse_der_lax(der)) {
+        (Ok(point), Some(signature)) => point.verify(z, &signature),
+        _ => false,
+    }
+}
+
+/// What a script needs to know about the input it unlocks: the spending transaction,
+/// the input index, the amount of the output spent, how signatures are checked and the
+/// rules the script is verified under. A taproot spend also needs every spent output.
+pub struct ExecutionContext<'a> {
+    tx: &'a Tx,
+    input_index: usize,
+    amount: u64,
+    prevouts: Option<&'a [TxOutput]>,
+    checker: &'a dyn SignatureChecker,
+    flags: VerifyFlags,
+    tracer: Option<&'a dyn ScriptTracer>,
+}
+impl<'a> ExecutionContext<'a> {
+    pub fn new(tx: &'a Tx, input_index: usize, amount: u64) -> Self {
+        ExecutionContext {
+            tx,
+            input_index,
+            amount,
+            prevouts: None,
+            checker: &TransactionSignatureChecker,
+            flags: VerifyFlags::NONE,
+            tracer: None,
+        }
+    }
+    pub fn with_checker(mut self, checker: &'a dyn SignatureChecker) -> Self {
+        self.checker = checker;
+        self
+    }
+    /// prevouts are the outputs spent by every input of tx, in input order, what BIP341
+    /// signatures commit to
+    pub fn with_prevouts(mut self, prevouts: &'a [TxOutput]) -> Self {
+        self.prevouts = Some(prevouts);
+        self
+    }
+    pub fn with_flags(mut self, flags: VerifyFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+    /// reports every instruction executed and signature hash computed to tracer
+    pub fn with_tracer(mut self, tracer: &'a dyn ScriptTracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+    pub fn tracer(&self) -> Option<&'a dyn ScriptTracer> {
+        self.tracer
+    }
+    pub fn tx(&self) -> &Tx {
+        self.tx
+    }
+    pub fn input_index(&self) -> usize {
+        self.input_index
+    }
+    pub fn amount(&self) -> u64 {
+        self.amount
+    }
+    pub fn prevouts(&self) -> Option<&'a [TxOutput]> {
+        self.prevouts
+    }
+    pub fn flags(&self) -> VerifyFlags {
+        self.flags
+    }
+    /// true when every flag of flags is set
+    pub fn has(&self, flags: VerifyFlags) -> bool {
+        self.flags.contains(flags)
+    }
+    /// the signature encoding rules of the flags
+    pub fn checks(&self) -> SignatureChecks {
+        self.flags.signature_checks()
+    }
+    /// nSequence of the input, what OP_CHECKSEQUENCEVERIFY and OP_CHECKLOCKTIMEVERIFY look at
+    pub fn sequence(&self) -> u32 {
+        self.tx.tx_ins()[self.input_index].sequence()
+    }
+    /// the signature hash for hash_type, legacy or BIP143 depending on sig_version
+    pub fn sig_hash(&self, script_code: &Script, hash_type: u8, sig_version: SigVersion) -> BigUint {
+        let z = match sig_version {
+            SigVersion::Base => self.tx.sig_hash_legacy(self.input_index, script_code, hash_type),
+            SigVersion::WitnessV0 => self.tx.sig_hash_segwit_v0(self.input_index, script_code, self.amount, hash_type),
+        };
+        if let Some(tracer) = self.tracer {
+            let bytes = z.to_bytes_be();
+            let mut hash = [0u8; 32];
+            hash[32 - bytes.len()..].copy_from_slice(&bytes);
+            tracer.sig_hash(&hash);
+        }
+        z
+    }
+    pub fn check_ecdsa_signature(&self, sig: &[u8], pubkey: &[u8], script_code: &Script, sig_version: SigVersion) -> bool {
+        self.checker.check_ecdsa_signature(self, sig, pubkey, script_code, sig_version)
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use num::BigUint;
+use crate::helpers::limbs::{add4, adc, eq4, from_be_bytes, is_zero4, mask, mul_wide, select4, sub4, to_be_bytes};
+
+/// p = 2^256 - 2^32 - 977
+const P: [u64; 4] = [0xFFFFFFFEFFFFFC2F, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF, 0xFFFFFFFFFFFFFFFF];
+/// 2^256 mod p
+const R: u64 = 0x1000003D1;
+
+/// Element of the secp256k1 base field, four 64-bit limbs, always fully reduced.
+/// All the arithmetic runs in constant time, only `pow` with a public exponent,
+/// `invert` and `sqrt` branch on the (public) exponent bits.
+#[derive(Clone, Copy)]
+pub struct Fe([u64; 4]);
+
+impl Fe {
+    pub const ZERO: Fe = Fe([0, 0, 0, 0]);
+    pub const ONE: Fe = Fe([1, 0, 0, 0]);
+
+    pub fn from_u64(v: u64) -> Self {
+        Fe([v, 0, 0, 0])
+    }
+    /// None when the value is not lower than p
+    pub fn from_bytes(bytes: &[u8; 32]) -> Option<Self> {
+        let limbs = from_be_bytes(bytes);
+        let (_, borrow) = sub4(&limbs, &P);
+        if borrow == 1 {
+            Some(Fe(limbs))
+        } else {
+            None
+        }
+    }
+    /// interprets the bytes as an integer and reduces it mod p
+    pub fn from_bytes_reduced(bytes: &[u8; 32]) -> Self {
+        Fe(reduce_once(from_be_bytes(bytes), 0))
+    }
+    pub fn to_bytes(&self) -> [u8; 32] {
+        to_be_bytes(&self.0)
+    }
+    pub fn from_biguint(n: &BigUint) -> Self {
+        let reduced = n % BigUint::from_bytes_be(&to_be_bytes(&P));
+        let mut bytes = [0u8; 32];
+        let be = reduced.to_bytes_be();
+        bytes[32 - be.len()..].copy_from_slice(&be);
+        Fe(from_be_bytes(&bytes))
+    }
+    pub fn to_biguint(&self) -> BigUint {
+        BigUint::from_bytes_be(&self.to_bytes())
+    }
+    pub fn is_zero(&self) -> bool {
+        is_zero4(&self.0) == 1
+    }
+    pub fn is_odd(&self) -> bool {
+        self.0[0] & 1 == 1
+    }
+    pub fn is_even(&self) -> bool {
+        !self.is_odd()
+    }
+    /// returns b when choice is true, a otherwise, without branching
+    pub fn conditional_select(a: &Fe, b: &Fe, choice: bool) -> Fe {
+        Fe(select4(&a.0, &b.0, choice as u64))
+    }
+    pub fn square(&self) -> Fe {
+        *self * *self
+    }
+    pub fn double(&self) -> Fe {
+        *self + *self
+    }
+    pub fn mul_u64(&self, v: u64) -> Fe {
+        *self * Fe::from_u64(v)
+    }
+    /// self^exp, exp given as big endian bytes. The exponent is assumed to be public.
+    pub fn pow(&self, exp: &[u8]) -> Fe {
+        let mut result = Fe::ONE;
+        for byte in exp {
+            for i in (0..8).rev() {
+                result = result.square();
+                if (byte >> i) & 1 == 1 {
+                    result = result * *self;
+                }
+            }
+        }
+        result
+    }
+    /// multiplicative inverse, 1/0 is defined as 0
+    pub fn invert(&self) -> Fe {
+        // Fermat: a^(p - 2)
+        let mut exp = to_be_bytes(&P);
+        exp[31] -= 2;
+        self.pow(&exp)
+    }
+    /// square root when it exists, valid because p % 4 == 3
+    pub fn sqrt(&self) -> Option<Fe> {
+        // (p + 1) / 4
+        let (p1, _) = add4(&P, &[1, 0, 0, 0]);
+        let exp = [p1[0] >> 2 | p1[1] << 62, p1[1] >> 2 | p1[2] << 62, p1[2] >> 2 | p1[3] << 62, p1[3] >> 2];
+        let root = self.pow(&to_be_bytes(&exp));
+        if root.square() == *self {
+            Some(root)
+        } else {
+            None
+        }
+    }
+}
+
+/// subtracts p when the value (with its carry bit) is not lower than p
+fn reduce_once(v: [u64; 4], carry: u64) -> [u64; 4] {
+    let (d, borrow) = sub4(&v, &P);
+    // keep the difference when there was a carry out or no borrow
+    select4(&v, &d, carry | (borrow ^ 1))
+}
+
+/// reduces a 512 bit product using 2^256 = R mod p
+fn reduce_wide(t: [u64; 8]) -> [u64; 4] {
+    // lo + hi * R, the result fits in 4 limbs plus a carry below 2^34
+    let mut r = [0u64; 4];
+    let mut carry = 0u64;
+    for i in 0..4 {
+        let v = t[i] as u128 + (t[i + 4] as u128) * (R as u128) + carry as u128;
+        r[i] = v as u64;
+        carry = (v >> 64) as u64;
+    }
+    // fold the carry again
+    let v = carry as u128 * R as u128;
+    let (r0, c) = adc(r[0], v as u64, 0);
+    let (r1, c) = adc(r[1], (v >> 64) as u64, c);
+    let (r2, c) = adc(r[2], 0, c);
+    let (r3, c) = adc(r[3], 0, c);
+    // a last carry leaves a tiny value behind, adding R cannot overflow
+    let (r0, c2) = adc(r0, R & mask(c), 0);
+    let (r1, c2) = adc(r1, 0, c2);
+    let (r2, c2) = adc(r2, 0, c2);
+    let (r3, _) = adc(r3, 0, c2);
+    reduce_once([r0, r1, r2, r3], 0)
+}
+
+impl Add for Fe {
+    type Output = Fe;
+    fn add(self, other: Fe) -> Fe {
+        let (sum, carry) = add4(&self.0, &other.0);
+        Fe(reduce_once(sum, carry))
+    }
+}
+impl Sub for Fe {
+    type Output = Fe;
+    fn sub(self, other: Fe) -> Fe {
+        let (diff, borrow) = sub4(&self.0, &other.0);
+        let p = select4(&[0; 4], &P, borrow);
+        let (r, _) = add4(&diff, &p);
+        Fe(r)
+    }
+}
+impl Neg for Fe {
+    type Output = Fe;
+    fn neg(self) -> Fe {
+        Fe::ZERO - self
+    }
+}
+impl Mul for Fe {
+    type Output = Fe;
+    fn mul(self, other: Fe) -> Fe {
+        Fe(reduce_wide(mul_wide(&self.0, &other.0)))
+    }
+}
+impl PartialEq for Fe {
+    fn eq(&self, other: &Self) -> bool {
+        eq4(&self.0, &other.0) == 1
+    }
+}
+impl Eq for Fe {}
+
+impl fmt::Debug for Fe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Fe({})", hex::encode(self.to_bytes()))
+    }
+}
+impl fmt::Display for Fe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.to_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp256k1::Secp256k1;
+    use num::Num;
+
+    fn p() -> BigUint {
+        Secp256k1::new().p
+    }
+    fn samples() -> Vec<BigUint> {
+        let p = p();
+        vec![
+            BigUint::from(0u32),
+            BigUint::from(1u32),
+            BigUint::from(977u32),
+            &p - BigUint::from(1u32),
+            &p - BigUint::from(2u32),
+            BigUint::from_str_radix("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798", 16).unwrap(),
+            BigUint::from_str_radix("483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8", 16).unwrap(),
+            BigUint::from_str_radix("fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e", 16).unwrap(),
+            BigUint::from_str_radix("00000000000000000000000000000001ffffffffffffffffffffffffffffffff", 16).unwrap(),
+        ]
+    }
+    #[test]
+    fn test_arithmetic_matches_biguint() {
+        let p = p();
+        for a in samples() {
+            for b in samples() {
+                let fa = Fe::from_biguint(&a);
+                let fb = Fe::from_biguint(&b);
+                assert_eq!((fa + fb).to_biguint(), (&a + &b) % &p);
+                assert_eq!((fa - fb).to_biguint(), (&a + &p - &b) % &p);
+                assert_eq!((fa * fb).to_biguint(), (&a * &b) % &p);
+            }
+            assert_eq!((-Fe::from_biguint(&a)).to_biguint(), (&p - &a) % &p);
+        }
+    }
+    #[test]
+    fn test_invert() {
+        for a in samples().into_iter().skip(1) {
+            let fa = Fe::from_biguint(&a);
+            assert_eq!(fa * fa.invert(), Fe::ONE);
+        }
+        assert_eq!(Fe::ZERO.invert(), Fe::ZERO);
+    }
+    #[test]
+    fn test_sqrt() {
+        for a in samples() {
+            let sq = Fe::from_biguint(&a).square();
+            let root = sq.sqrt().unwrap();
+            assert_eq!(root.square(), sq);
+        }
+        // -1 is not a square since p % 4 == 3
+        assert!((-Fe::ONE).sqrt().is_none());
+    }
+    #[test]
+    fn test_bytes() {
+        let mut bytes = [0xffu8; 32];
+        assert!(Fe::from_bytes(&bytes).is_none());
+        assert_eq!(Fe::from_bytes_reduced(&bytes).to_biguint(), BigUint::from(0x1000003d0u64));
+        bytes[0] = 0x7f;
+        let fe = Fe::from_bytes(&bytes).unwrap();
+        assert_eq!(fe.to_bytes(), bytes);
+    }
+}
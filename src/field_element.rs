@@ -36,6 +36,10 @@ impl FieldElement {
         self.num.clone()
     }
 
+    pub fn prime(&self) -> BigUint {
+        self.prime.clone()
+    }
+
     /// Valid for secp256k1 becouse p % 4 = 3
     pub fn sqrt(&self) -> Self {
         let s256 = Secp256k1::new();
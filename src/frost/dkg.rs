@@ -0,0 +1,211 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use rand::RngCore;
+use crate::frost::keys::{check_parameters, commit_polynomial, evaluate_commitment, evaluate_polynomial,
+    key_package, public_key_package, random_polynomial, Identifier, KeyPackage, PublicKeyPackage, SecretShare};
+use crate::helpers::tagged_hash::tagged_hash;
+use crate::point::Point;
+use crate::scalar::Scalar;
+
+// Pedersen DKG with proofs of knowledge (FROST paper, KeyGen). Every participant
+// deals a random polynomial: round 1 broadcasts the commitment and a Schnorr proof
+// for its constant term, round 2 sends f_i(j) privately to every other participant j.
+
+/// kept by the participant between part1 and part2
+pub struct Round1SecretPackage {
+    identifier: Identifier,
+    coefficients: Vec<Scalar>,
+    commitment: Vec<Point>,
+    max_signers: u16,
+}
+
+/// broadcast to every other participant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Round1Package {
+    commitment: Vec<Point>,
+    proof_r: Point,
+    proof_mu: Scalar,
+}
+
+/// kept by the participant between part2 and part3
+pub struct Round2SecretPackage {
+    identifier: Identifier,
+    commitment: Vec<Point>,
+    own_share: Scalar,
+    max_signers: u16,
+}
+
+impl Round1Package {
+    pub fn commitment(&self) -> &[Point] {
+        &self.commitment
+    }
+}
+
+fn proof_challenge(identifier: Identifier, constant: &Point, r: &Point) -> Scalar {
+    let mut data = identifier.to_be_bytes().to_vec();
+    data.extend(constant.sec(true));
+    data.extend(r.sec(true));
+    Scalar::from_bytes_reduced(&tagged_hash("FROST/dkg", &data))
+}
+
+pub fn part1<R: RngCore>(
+    identifier: Identifier,
+    threshold: u16,
+    max_signers: u16,
+    rng: &mut R,
+) -> Result<(Round1SecretPackage, Round1Package), Error> {
+    check_parameters(threshold, max_signers)?;
+    if identifier == 0 || identifier > max_signers {
+        return Err(Error::new(ErrorKind::InvalidInput, "identifier must be between 1 and max_signers"));
+    }
+    let coefficients = random_polynomial(Scalar::random(rng), threshold, rng);
+    let commitment = commit_polynomial(&coefficients);
+
+    // proof of knowledge of a_0, stops rogue key attacks
+    let k = Scalar::random(rng);
+    let proof_r = Point::mul_generator(&k);
+    let c = proof_challenge(identifier, &commitment[0], &proof_r);
+    let proof_mu = k + coefficients[0] * c;
+
+    let package = Round1Package { commitment: commitment.clone(), proof_r, proof_mu };
+    let secret = Round1SecretPackage { identifier, coefficients, commitment, max_signers };
+    Ok((secret, package))
+}
+
+/// checks the round 1 packages of the others and returns the share for each of them
+pub fn part2(
+    secret: Round1SecretPackage,
+    round1: &BTreeMap<Identifier, Round1Package>,
+) -> Result<(Round2SecretPackage, BTreeMap<Identifier, SecretShare>), Error> {
+    let threshold = secret.coefficients.len();
+    if round1.len() != secret.max_signers as usize - 1 || round1.contains_key(&secret.identifier) {
+        return Err(Error::new(ErrorKind::InvalidInput, "expected one round 1 package from every other participant"));
+    }
+    for (id, package) in round1 {
+        if package.commitment.len() != threshold {
+            return Err(Error::new(ErrorKind::InvalidData, format!("participant {} sent a commitment of the wrong size", id)));
+        }
+        let c = proof_challenge(*id, &package.commitment[0], &package.proof_r);
+        let expected = package.proof_r.clone() + package.commitment[0].mul_vartime(&c);
+        if Point::mul_generator(&package.proof_mu) != expected {
+            return Err(Error::new(ErrorKind::InvalidData, format!("participant {} sent an invalid proof of knowledge", id)));
+        }
+    }
+    let shares = round1
+        .keys()
+        .map(|id| (*id, SecretShare::new(*id, evaluate_polynomial(&secret.coefficients, *id), &secret.commitment)))
+        .collect();
+    let own_share = evaluate_polynomial(&secret.coefficients, secret.identifier);
+    let round2 = Round2SecretPackage {
+        identifier: secret.identifier,
+        commitment: secret.commitment.clone(),
+        own_share,
+        max_signers: secret.max_signers,
+    };
+    Ok((round2, shares))
+}
+
+/// checks the received shares against the round 1 commitments and derives the keys
+pub fn part3(
+    secret: &Round2SecretPackage,
+    round1: &BTreeMap<Identifier, Round1Package>,
+    round2: &BTreeMap<Identifier, SecretShare>,
+) -> Result<(KeyPackage, PublicKeyPackage), Error> {
+    if round2.len() != round1.len() || round1.keys().any(|id| !round2.contains_key(id)) {
+        return Err(Error::new(ErrorKind::InvalidInput, "expected one share from every other participant"));
+    }
+    let mut share = secret.own_share;
+    for (id, received) in round2 {
+        // verify against the broadcast commitment, not the one attached to the share
+        let commitment = &round1[id].commitment;
+        if received.identifier() != secret.identifier
+            || Point::mul_generator(&received.value()) != evaluate_commitment(commitment, secret.identifier)
+        {
+            return Err(Error::new(ErrorKind::InvalidData, format!("participant {} sent an invalid share", id)));
+        }
+        share = share + received.value();
+    }
+    let mut commitments = vec![secret.commitment.clone()];
+    commitments.extend(round1.values().map(|p| p.commitment.clone()));
+    let threshold = secret.commitment.len() as u16;
+    let public = public_key_package(&commitments, threshold, secret.max_signers);
+    Ok((key_package(secret.identifier, share, &public), public))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frost::keys::lagrange_coefficient;
+    use crate::frost::signing;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn run_dkg(threshold: u16, max_signers: u16, seed: u64) -> (Vec<KeyPackage>, PublicKeyPackage) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut secrets1 = BTreeMap::new();
+        let mut packages1 = BTreeMap::new();
+        for id in 1..=max_signers {
+            let (secret, package) = part1(id, threshold, max_signers, &mut rng).unwrap();
+            secrets1.insert(id, secret);
+            packages1.insert(id, package);
+        }
+        let others = |me: Identifier| -> BTreeMap<Identifier, Round1Package> {
+            packages1.iter().filter(|(id, _)| **id != me).map(|(id, p)| (*id, p.clone())).collect()
+        };
+        let mut secrets2 = BTreeMap::new();
+        let mut sent: BTreeMap<Identifier, BTreeMap<Identifier, SecretShare>> = BTreeMap::new();
+        for (id, secret) in secrets1 {
+            let (secret2, shares) = part2(secret, &others(id)).unwrap();
+            secrets2.insert(id, secret2);
+            for (to, share) in shares {
+                sent.entry(to).or_default().insert(id, share);
+            }
+        }
+        let mut key_packages = Vec::new();
+        let mut public = None;
+        for (id, secret2) in &secrets2 {
+            let (key, pubkey) = part3(secret2, &others(*id), &sent[id]).unwrap();
+            if let Some(p) = &public {
+                assert_eq!(p, &pubkey);
+            }
+            public = Some(pubkey);
+            key_packages.push(key);
+        }
+        (key_packages, public.unwrap())
+    }
+    #[test]
+    fn test_dkg() {
+        let (packages, public) = run_dkg(2, 3, 7);
+        let signers = [1u16, 3];
+        let mut sum = Scalar::ZERO;
+        for id in signers {
+            let package = &packages[id as usize - 1];
+            assert_eq!(Point::mul_generator(&package.signing_share()), package.verifying_share());
+            sum = sum + lagrange_coefficient(id, &signers) * package.signing_share();
+        }
+        assert_eq!(Point::mul_generator(&sum), public.group_public());
+    }
+    #[test]
+    fn test_dkg_sign() {
+        let (packages, public) = run_dkg(2, 3, 9);
+        let mut rng = StdRng::seed_from_u64(10);
+        let (n1, c1) = signing::commit(&packages[0], &mut rng);
+        let (n3, c3) = signing::commit(&packages[2], &mut rng);
+        let package = signing::SigningPackage::new(BTreeMap::from([(1, c1), (3, c3)]), b"dkg");
+        let shares = BTreeMap::from([
+            (1, signing::sign(&package, n1, &packages[0]).unwrap()),
+            (3, signing::sign(&package, n3, &packages[2]).unwrap()),
+        ]);
+        let sig = signing::aggregate(&package, &shares, &public).unwrap();
+        assert!(public.x_only_public_key().verify_schnorr(b"dkg", &sig));
+    }
+    #[test]
+    fn test_dkg_rejects_bad_proof() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let (secret, _) = part1(1, 2, 2, &mut rng).unwrap();
+        let (_, mut package) = part1(2, 2, 2, &mut rng).unwrap();
+        package.proof_mu = package.proof_mu + Scalar::ONE;
+        let round1 = BTreeMap::from([(2, package)]);
+        assert!(part2(secret, &round1).is_err());
+    }
+}
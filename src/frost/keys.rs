@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use rand::RngCore;
+use crate::point::Point;
+use crate::private_key::PrivateKey;
+use crate::scalar::Scalar;
+use crate::x_only_public_key::XOnlyPublicKey;
+
+/// participant index, the x coordinate of its share, never 0
+pub type Identifier = u16;
+
+/// f(identifier) for a polynomial of degree threshold - 1, with the commitment to
+/// the coefficients so the receiver can check it (Feldman VSS)
+#[derive(Debug, Clone)]
+pub struct SecretShare {
+    identifier: Identifier,
+    value: Scalar,
+    commitment: Vec<Point>,
+}
+
+/// what a participant needs to sign
+#[derive(Debug, Clone)]
+pub struct KeyPackage {
+    identifier: Identifier,
+    signing_share: Scalar,
+    verifying_share: Point,
+    group_public: Point,
+    threshold: u16,
+}
+
+/// public data to verify signature shares and the final signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKeyPackage {
+    verifying_shares: BTreeMap<Identifier, Point>,
+    group_public: Point,
+    threshold: u16,
+    // the shares were negated to give the group key an even y
+    negated: bool,
+}
+
+impl SecretShare {
+    pub(crate) fn new(identifier: Identifier, value: Scalar, commitment: &[Point]) -> Self {
+        SecretShare { identifier, value, commitment: commitment.to_vec() }
+    }
+    pub fn identifier(&self) -> Identifier {
+        self.identifier
+    }
+    pub(crate) fn value(&self) -> Scalar {
+        self.value
+    }
+    /// f(i)*G must match the committed polynomial evaluated at i
+    pub fn verify(&self) -> bool {
+        Point::mul_generator(&self.value) == evaluate_commitment(&self.commitment, self.identifier)
+    }
+}
+
+impl KeyPackage {
+    pub fn identifier(&self) -> Identifier {
+        self.identifier
+    }
+    pub(crate) fn signing_share(&self) -> Scalar {
+        self.signing_share
+    }
+    pub fn verifying_share(&self) -> Point {
+        self.verifying_share.clone()
+    }
+    pub fn group_public(&self) -> Point {
+        self.group_public.clone()
+    }
+    pub fn threshold(&self) -> u16 {
+        self.threshold
+    }
+}
+
+impl PublicKeyPackage {
+    pub fn verifying_share(&self, identifier: Identifier) -> Option<&Point> {
+        self.verifying_shares.get(&identifier)
+    }
+    pub fn group_public(&self) -> Point {
+        self.group_public.clone()
+    }
+    pub fn x_only_public_key(&self) -> XOnlyPublicKey {
+        XOnlyPublicKey::from_point(&self.group_public).0
+    }
+    pub fn threshold(&self) -> u16 {
+        self.threshold
+    }
+}
+
+/// Splits a secret (a random one when None) in max_signers shares, any threshold of them can sign
+pub fn trusted_dealer_keygen<R: RngCore>(
+    secret: Option<&PrivateKey>,
+    threshold: u16,
+    max_signers: u16,
+    rng: &mut R,
+) -> Result<(Vec<KeyPackage>, PublicKeyPackage), Error> {
+    check_parameters(threshold, max_signers)?;
+    let secret = match secret {
+        Some(key) => key.scalar(),
+        None => Scalar::random(rng),
+    };
+    let coefficients = random_polynomial(secret, threshold, rng);
+    let commitment = commit_polynomial(&coefficients);
+    let shares: Vec<SecretShare> = (1..=max_signers)
+        .map(|i| SecretShare::new(i, evaluate_polynomial(&coefficients, i), &commitment))
+        .collect();
+    let public = public_key_package(&[commitment], threshold, max_signers);
+    let packages = shares.iter().map(|share| key_package(share.identifier, share.value, &public)).collect();
+    Ok((packages, public))
+}
+
+pub(crate) fn check_parameters(threshold: u16, max_signers: u16) -> Result<(), Error> {
+    if threshold < 1 || threshold > max_signers {
+        return Err(Error::new(ErrorKind::InvalidInput, "threshold must be between 1 and max_signers"));
+    }
+    Ok(())
+}
+
+/// a0 = secret, the other threshold - 1 coefficients random
+pub(crate) fn random_polynomial<R: RngCore>(secret: Scalar, threshold: u16, rng: &mut R) -> Vec<Scalar> {
+    let mut coefficients = vec![secret];
+    for _ in 1..threshold {
+        coefficients.push(Scalar::random(rng));
+    }
+    coefficients
+}
+
+pub(crate) fn commit_polynomial(coefficients: &[Scalar]) -> Vec<Point> {
+    coefficients.iter().map(Point::mul_generator).collect()
+}
+
+pub(crate) fn evaluate_polynomial(coefficients: &[Scalar], identifier: Identifier) -> Scalar {
+    let x = Scalar::from_u64(identifier as u64);
+    // Horner
+    coefficients.iter().rev().fold(Scalar::ZERO, |acc, c| acc * x + *c)
+}
+
+/// sum of C_j * i^j, the public image of f(i)
+pub(crate) fn evaluate_commitment(commitment: &[Point], identifier: Identifier) -> Point {
+    let x = Scalar::from_u64(identifier as u64);
+    commitment.iter().rev().fold(Point::infinity(), |acc, c| acc.mul_vartime(&x) + c.clone())
+}
+
+/// Builds the public package from the commitments of every dealer (one for a trusted
+/// dealer, every participant in a DKG). A group key with odd y is negated, together
+/// with every share, so the group key is a valid BIP340 key as is.
+pub(crate) fn public_key_package(commitments: &[Vec<Point>], threshold: u16, max_signers: u16) -> PublicKeyPackage {
+    let mut group_commitment = vec![Point::infinity(); threshold as usize];
+    for commitment in commitments {
+        for (sum, c) in group_commitment.iter_mut().zip(commitment) {
+            *sum = sum.clone() + c.clone();
+        }
+    }
+    let negate = group_has_odd_y(&group_commitment[0]);
+    let verifying_shares = (1..=max_signers)
+        .map(|i| {
+            let share = evaluate_commitment(&group_commitment, i);
+            (i, if negate { -share } else { share })
+        })
+        .collect();
+    let group_public = if negate { -group_commitment[0].clone() } else { group_commitment[0].clone() };
+    PublicKeyPackage { verifying_shares, group_public, threshold, negated: negate }
+}
+
+fn group_has_odd_y(point: &Point) -> bool {
+    point.affine().map(|(_, y)| y.is_odd()).unwrap_or(false)
+}
+
+/// the participant's package, its share negated when the group key had to be negated
+pub(crate) fn key_package(identifier: Identifier, share: Scalar, public: &PublicKeyPackage) -> KeyPackage {
+    let verifying_share = public.verifying_shares[&identifier].clone();
+    let signing_share = Scalar::conditional_select(&share, &-share, public.negated);
+    KeyPackage {
+        identifier,
+        signing_share,
+        verifying_share,
+        group_public: public.group_public.clone(),
+        threshold: public.threshold,
+    }
+}
+
+/// Lagrange coefficient at 0 of identifier within the signer set
+pub(crate) fn lagrange_coefficient(identifier: Identifier, signers: &[Identifier]) -> Scalar {
+    let xi = Scalar::from_u64(identifier as u64);
+    let mut num = Scalar::ONE;
+    let mut den = Scalar::ONE;
+    for j in signers.iter().filter(|j| **j != identifier) {
+        let xj = Scalar::from_u64(*j as u64);
+        num = num * xj;
+        den = den * (xj - xi);
+    }
+    num * den.invert()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigUint;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_dealer_shares_interpolate() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let secret = PrivateKey::new(&BigUint::from(123456u32));
+        let (packages, public) = trusted_dealer_keygen(Some(&secret), 3, 5, &mut rng).unwrap();
+        assert_eq!(public.x_only_public_key(), secret.x_only_public_key());
+        assert!(!group_has_odd_y(&public.group_public()));
+        for signers in [[1u16, 2, 3], [2, 4, 5], [1, 3, 5]] {
+            let mut sum = Scalar::ZERO;
+            for id in signers {
+                let package = &packages[id as usize - 1];
+                assert_eq!(Point::mul_generator(&package.signing_share()), package.verifying_share());
+                sum = sum + lagrange_coefficient(id, &signers) * package.signing_share();
+            }
+            assert_eq!(Point::mul_generator(&sum), public.group_public());
+        }
+    }
+    #[test]
+    fn test_secret_share_verify() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let coefficients = random_polynomial(Scalar::from_u64(5), 2, &mut rng);
+        let commitment = commit_polynomial(&coefficients);
+        let share = SecretShare::new(3, evaluate_polynomial(&coefficients, 3), &commitment);
+        assert!(share.verify());
+        let bad = SecretShare::new(3, share.value() + Scalar::ONE, &commitment);
+        assert!(!bad.verify());
+    }
+    #[test]
+    fn test_invalid_parameters() {
+        let mut rng = StdRng::seed_from_u64(3);
+        assert!(trusted_dealer_keygen(None, 0, 3, &mut rng).is_err());
+        assert!(trusted_dealer_keygen(None, 4, 3, &mut rng).is_err());
+    }
+}
@@ -0,0 +1,9 @@
+// FROST threshold Schnorr signatures producing BIP340 signatures. Keys come either
+// from a trusted dealer or from a distributed key generation, any `threshold` of
+// the `max_signers` participants can then sign for the group key.
+pub mod keys;
+pub mod dkg;
+pub mod signing;
+
+pub use keys::{trusted_dealer_keygen, Identifier, KeyPackage, PublicKeyPackage, SecretShare};
+pub use signing::{aggregate, commit, sign, verify_share, SignatureShare, SigningCommitments, SigningNonces, SigningPackage};
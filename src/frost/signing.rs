@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use crate::frost::keys::{lagrange_coefficient, Identifier, KeyPackage, PublicKeyPackage};
+use crate::helpers::tagged_hash::tagged_hash;
+use crate::point::Point;
+use crate::scalar::Scalar;
+use crate::schnorr_signature::{challenge, SchnorrSignature};
+
+/// hiding and binding nonces (d, e) of one signing round, consumed by `sign`
+pub struct SigningNonces {
+    hiding: Scalar,
+    binding: Scalar,
+    commitments: SigningCommitments,
+}
+
+/// D = d*G, E = e*G, sent to the coordinator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningCommitments {
+    hiding: Point,
+    binding: Point,
+}
+
+/// the message and the commitments of the participating signers
+#[derive(Debug, Clone)]
+pub struct SigningPackage {
+    commitments: BTreeMap<Identifier, SigningCommitments>,
+    message: Vec<u8>,
+}
+
+/// z_i of one signer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureShare(Scalar);
+
+impl Drop for SigningNonces {
+    fn drop(&mut self) {
+        self.hiding = Scalar::ZERO;
+        self.binding = Scalar::ZERO;
+    }
+}
+
+impl SigningCommitments {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.hiding.sec(true);
+        result.extend(self.binding.sec(true));
+        result
+    }
+}
+
+impl SigningPackage {
+    pub fn new(commitments: BTreeMap<Identifier, SigningCommitments>, message: &[u8]) -> Self {
+        SigningPackage { commitments, message: message.to_vec() }
+    }
+    fn signers(&self) -> Vec<Identifier> {
+        self.commitments.keys().copied().collect()
+    }
+    /// rho_i for every signer, binds each nonce to the message and the whole signer set
+    fn binding_factors(&self, group_public: &Point) -> BTreeMap<Identifier, Scalar> {
+        let mut encoded = Vec::new();
+        for (id, c) in &self.commitments {
+            encoded.extend(id.to_be_bytes());
+            encoded.extend(c.serialize());
+        }
+        let mut prefix = group_public.sec(true);
+        prefix.extend(Sha256::digest(&self.message));
+        prefix.extend(Sha256::digest(&encoded));
+        self.commitments
+            .keys()
+            .map(|id| {
+                let mut data = prefix.clone();
+                data.extend(id.to_be_bytes());
+                (*id, Scalar::from_bytes_reduced(&tagged_hash("FROST/rho", &data)))
+            })
+            .collect()
+    }
+    /// R = sum(D_i + rho_i*E_i), the challenge and whether the nonces must be negated
+    /// because R has an odd y
+    fn group_commitment(&self, group_public: &Point) -> Result<(BTreeMap<Identifier, Scalar>, Point, Scalar, bool), Error> {
+        let rhos = self.binding_factors(group_public);
+        let mut r = Point::infinity();
+        for (id, c) in &self.commitments {
+            r = r + c.hiding.clone() + c.binding.mul_vartime(&rhos[id]);
+        }
+        let Some((rx, ry)) = r.affine() else {
+            return Err(Error::new(ErrorKind::InvalidData, "group commitment is infinite"));
+        };
+        let (qx, _) = group_public.affine().unwrap();
+        let c = challenge(&rx.to_bytes(), &qx.to_bytes(), &self.message);
+        Ok((rhos, r, c, ry.is_odd()))
+    }
+}
+
+impl SignatureShare {
+    pub fn parse(bytes: &[u8; 32]) -> Result<Self, Error> {
+        Scalar::from_bytes(bytes)
+            .map(SignatureShare)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "signature share is not lower than n"))
+    }
+    pub fn serialize(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+}
+
+/// round 1: fresh nonces, mixed with the signing share so a weak rng alone does not leak them
+pub fn commit<R: RngCore>(key: &KeyPackage, rng: &mut R) -> (SigningNonces, SigningCommitments) {
+    let mut nonce = || {
+        let mut data = [0u8; 32].to_vec();
+        rng.fill_bytes(&mut data);
+        data.extend(key.signing_share().to_bytes());
+        Scalar::from_bytes_reduced(&tagged_hash("FROST/nonce", &data))
+    };
+    let hiding = nonce();
+    let binding = nonce();
+    let commitments = SigningCommitments {
+        hiding: Point::mul_generator(&hiding),
+        binding: Point::mul_generator(&binding),
+    };
+    (SigningNonces { hiding, binding, commitments: commitments.clone() }, commitments)
+}
+
+/// round 2: the signature share of one signer
+pub fn sign(package: &SigningPackage, nonces: SigningNonces, key: &KeyPackage) -> Result<SignatureShare, Error> {
+    if package.commitments.len() < key.threshold() as usize {
+        return Err(Error::new(ErrorKind::InvalidInput, "not enough signers"));
+    }
+    match package.commitments.get(&key.identifier()) {
+        Some(c) if *c == nonces.commitments => {}
+        _ => return Err(Error::new(ErrorKind::InvalidInput, "signing package does not hold this signer's commitments")),
+    }
+    let (rhos, _, c, negate) = package.group_commitment(&key.group_public())?;
+    let lambda = lagrange_coefficient(key.identifier(), &package.signers());
+    let k = nonces.hiding + nonces.binding * rhos[&key.identifier()];
+    let k = Scalar::conditional_select(&k, &-k, negate);
+    Ok(SignatureShare(k + lambda * key.signing_share() * c))
+}
+
+/// z_i*G == (D_i + rho_i*E_i) + c*lambda_i*Y_i, with the commitment negated for an odd R
+pub fn verify_share(package: &SigningPackage, identifier: Identifier, share: &SignatureShare, public: &PublicKeyPackage) -> bool {
+    let (Some(commitments), Some(verifying_share)) = (package.commitments.get(&identifier), public.verifying_share(identifier)) else {
+        return false;
+    };
+    let Ok((rhos, _, c, negate)) = package.group_commitment(&public.group_public()) else {
+        return false;
+    };
+    let r_i = commitments.hiding.clone() + commitments.binding.mul_vartime(&rhos[&identifier]);
+    let r_i = if negate { -r_i } else { r_i };
+    let lambda = lagrange_coefficient(identifier, &package.signers());
+    Point::mul_generator(&share.0) == r_i + verifying_share.mul_vartime(&(c * lambda))
+}
+
+/// sums the shares into a BIP340 signature for the group key, naming any bad share
+pub fn aggregate(
+    package: &SigningPackage,
+    shares: &BTreeMap<Identifier, SignatureShare>,
+    public: &PublicKeyPackage,
+) -> Result<SchnorrSignature, Error> {
+    if shares.len() != package.commitments.len() || shares.keys().any(|id| !package.commitments.contains_key(id)) {
+        return Err(Error::new(ErrorKind::InvalidInput, "expected one share from every signer"));
+    }
+    let (_, r, _, _) = package.group_commitment(&public.group_public())?;
+    let mut z = Scalar::ZERO;
+    for share in shares.values() {
+        z = z + share.0;
+    }
+    let (rx, _) = r.affine().unwrap();
+    let signature = SchnorrSignature::new(&rx.to_bytes(), &z.to_bytes());
+    if !public.x_only_public_key().verify_schnorr(&package.message, &signature) {
+        for (id, share) in shares {
+            if !verify_share(package, *id, share, public) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("invalid signature share from {}", id)));
+            }
+        }
+        return Err(Error::new(ErrorKind::InvalidData, "invalid signature"));
+    }
+    Ok(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frost::keys::trusted_dealer_keygen;
+    use crate::private_key::PrivateKey;
+    use num::BigUint;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn sign_with(packages: &[KeyPackage], public: &PublicKeyPackage, signers: &[Identifier], msg: &[u8], rng: &mut StdRng) -> Result<SchnorrSignature, Error> {
+        let mut nonces = BTreeMap::new();
+        let mut commitments = BTreeMap::new();
+        for id in signers {
+            let (n, c) = commit(&packages[*id as usize - 1], rng);
+            nonces.insert(*id, n);
+            commitments.insert(*id, c);
+        }
+        let package = SigningPackage::new(commitments, msg);
+        let mut shares = BTreeMap::new();
+        for (id, n) in nonces {
+            let share = sign(&package, n, &packages[id as usize - 1])?;
+            assert!(verify_share(&package, id, &share, public));
+            shares.insert(id, share);
+        }
+        aggregate(&package, &shares, public)
+    }
+    #[test]
+    fn test_sign_with_dealer() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let (packages, public) = trusted_dealer_keygen(None, 3, 5, &mut rng).unwrap();
+        for signers in [vec![1u16, 2, 3], vec![2, 4, 5], vec![1, 2, 3, 4, 5]] {
+            let sig = sign_with(&packages, &public, &signers, b"frost", &mut rng).unwrap();
+            assert!(public.x_only_public_key().verify_schnorr(b"frost", &sig));
+            assert!(public.group_public().verify_schnorr(b"frost", &sig));
+        }
+        assert!(sign_with(&packages, &public, &[1, 2], b"frost", &mut rng).is_err());
+    }
+    #[test]
+    fn test_sign_with_odd_key() {
+        // whatever the parity of the secret's key, the group signs for its x-only key
+        let mut rng = StdRng::seed_from_u64(12);
+        for secret in 1..5u32 {
+            let key = PrivateKey::new(&BigUint::from(secret));
+            let (packages, public) = trusted_dealer_keygen(Some(&key), 2, 3, &mut rng).unwrap();
+            let sig = sign_with(&packages, &public, &[1, 3], b"odd", &mut rng).unwrap();
+            assert!(key.x_only_public_key().verify_schnorr(b"odd", &sig));
+        }
+    }
+    #[test]
+    fn test_bad_share_is_reported() {
+        let mut rng = StdRng::seed_from_u64(13);
+        let (packages, public) = trusted_dealer_keygen(None, 2, 2, &mut rng).unwrap();
+        let (n1, c1) = commit(&packages[0], &mut rng);
+        let (n2, c2) = commit(&packages[1], &mut rng);
+        let package = SigningPackage::new(BTreeMap::from([(1, c1), (2, c2)]), b"m");
+        let s1 = sign(&package, n1, &packages[0]).unwrap();
+        let s2 = sign(&package, n2, &packages[1]).unwrap();
+        let bad = SignatureShare(s2.0 + Scalar::ONE);
+        assert!(!verify_share(&package, 2, &bad, &public));
+        let err = aggregate(&package, &BTreeMap::from([(1, s1), (2, bad)]), &public).unwrap_err();
+        assert!(err.to_string().contains("from 2"));
+    }
+}
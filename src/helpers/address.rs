@@ -1,4 +1,7 @@
-use crate::helpers::base58::base58_encode_checksum;
+use std::io::{Error, ErrorKind};
+use crate::helpers::base58::{base58_encode_checksum, decode_base58_checksum};
+use crate::helpers::bech32::bech32_segwit_decode;
+use crate::script::Script;
 
 pub fn h160_to_p2pkh_address(h160: Vec<u8>, testnet: bool ) -> Vec<u8> {
 
@@ -24,9 +27,26 @@ pub fn h160_to_p2sh_address(h160: Vec<u8>, testnet: bool ) -> Vec<u8> {
     result.extend_from_slice(&h160);
     base58_encode_checksum(result)
 }
+/// ScriptPubKey paid by a base58 (P2PKH, P2SH) or bech32 segwit address, mainnet or testnet
+pub fn address_to_script_pubkey(address: &str) -> Result<Script, Error> {
+    if let Ok((version, program)) = bech32_segwit_decode(address) {
+        let version_op = if version == 0 { 0x00 } else { 0x50 + version };
+        return Ok(Script::new(vec![vec![version_op], program]));
+    }
+    let decoded = decode_base58_checksum(address.as_bytes())?;
+    if decoded.len() != 21 {
+        return Err(Error::new(ErrorKind::InvalidData, "invalid address length"));
+    }
+    let h160 = decoded[1..].to_vec();
+    match decoded[0] {
+        0x00 | 0x6f => Ok(Script::p2pkh_script(h160)),
+        0x05 | 0xc4 => Ok(Script::new(vec![vec![0xa9], h160, vec![0x87]])),
+        _ => Err(Error::new(ErrorKind::InvalidData, "unknown address version")),
+    }
+}
 #[cfg(test)]
 mod tests {
-    use crate::helpers::address::{h160_to_p2pkh_address, h160_to_p2sh_address};
+    use crate::helpers::address::{address_to_script_pubkey, h160_to_p2pkh_address, h160_to_p2sh_address};
 
     #[test]
     fn test_p2pkh_address() {
@@ -46,4 +66,20 @@ mod tests {
         let want = "2N3u1R6uwQfuobCqbCgBkpsgBxvr1tZpe7B".as_bytes().to_vec();
         assert_eq!(h160_to_p2sh_address(h160, true), want);
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_address_to_script_pubkey() {
+        let h160 = hex::decode("74d691da1574e6b3c192ecfb52cc8984ee7b6c56").unwrap();
+        let script = address_to_script_pubkey("1BenRpVUFK65JFWcQSuHnJKzc4M8ZP8Eqa").unwrap();
+        assert!(script.is_p2pkh_script_pubkey());
+        assert_eq!(script.cmds[2], h160);
+        let script = address_to_script_pubkey("2N3u1R6uwQfuobCqbCgBkpsgBxvr1tZpe7B").unwrap();
+        assert!(script.is_p2sh_script_pubkey());
+        assert_eq!(script.cmds[1], h160);
+        let script = address_to_script_pubkey("bc1qvrtwrt8qqt7catejz59l2ly963xpdct2j370g3").unwrap();
+        assert!(script.is_p2wpkh_script_pubkey());
+        let script = address_to_script_pubkey("bc1pwyzhgwy30q2juhau2f2c4qscasddle5ymw9m7scq5kc62t8kyzkqyz059k").unwrap();
+        assert_eq!(script.cmds[0], vec![0x51]);
+        assert_eq!(script.cmds[1].len(), 32);
+        assert!(address_to_script_pubkey("not an address").is_err());
+    }
+}
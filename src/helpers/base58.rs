@@ -1,6 +1,7 @@
 
 use num::{BigUint, ToPrimitive};
 use num::traits::Euclid;
+use std::io::{Error, ErrorKind};
 use crate::helpers::hash256::hash256;
 
 static BASE58_ALPHABET : &'static [u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
@@ -61,6 +62,29 @@ pub fn decode_base58(val: Vec<u8>) -> Vec<u8> {
     let result = combined[1..combined.len() - 4].to_vec();
     result
 }
+/// Checked decode that keeps the version byte and leading zeros, errors instead of panicking
+pub fn decode_base58_checksum(val: &[u8]) -> Result<Vec<u8>, Error> {
+    let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+    let mut num = BigUint::from(0u32);
+    for c in val {
+        let index = BASE58_ALPHABET.iter().position(|r| r == c).ok_or_else(|| invalid("invalid base58 character"))?;
+        num *= BigUint::from(58u8);
+        num += BigUint::from(index);
+    }
+    let leading_zeros = val.iter().take_while(|&&c| c == BASE58_ALPHABET[0]).count();
+    let mut combined = vec![0u8; leading_zeros];
+    if num > BigUint::from(0u32) {
+        combined.extend(num.to_bytes_be());
+    }
+    if combined.len() < 5 {
+        return Err(invalid("base58 payload too short"));
+    }
+    let (rest, checksum) = combined.split_at(combined.len() - 4);
+    if hash256(rest)[..4] != *checksum {
+        return Err(invalid("base58 checksum mismatch"));
+    }
+    Ok(rest.to_vec())
+}
 #[cfg(test)]
 mod tests {
     use std::io::Write;
@@ -115,4 +139,13 @@ mod tests {
         let got = base58_encode_checksum(buffer);
         assert_eq!(addr, got);
     }
-}
\ No newline at end of file
+    #[test]
+    fn decode_58_checksum() {
+        let decoded = decode_base58_checksum(b"1BenRpVUFK65JFWcQSuHnJKzc4M8ZP8Eqa").unwrap();
+        assert_eq!(hex::encode(decoded), "0074d691da1574e6b3c192ecfb52cc8984ee7b6c56");
+        let decoded = decode_base58_checksum(b"mrAjisaT4LXL5MzE81sfcDYKU3wqWSvf9q").unwrap();
+        assert_eq!(hex::encode(decoded), "6f74d691da1574e6b3c192ecfb52cc8984ee7b6c56");
+        assert!(decode_base58_checksum(b"1BenRpVUFK65JFWcQSuHnJKzc4M8ZP8Eqb").is_err());
+        assert!(decode_base58_checksum(b"1BenRpVUFK65JFWcQSuHnJKzc4M8ZP8Eq0").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use bech32::{hrp, segwit, Hrp, Bech32m};
 use num::BigUint;
+use std::io::{Error, ErrorKind};
 
 pub enum SegwitVersion {
     version_0,
@@ -12,6 +13,12 @@ pub fn bech32_segwit_encode(hash: Vec<u8>, segwit_version: SegwitVersion) -> Str
     };
     segwit::encode(hrp::BC, version, &hash).expect("valid witness version and program")
 }
+/// Decodes a segwit address of any network into (witness version, witness program)
+pub fn bech32_segwit_decode(address: &str) -> Result<(u8, Vec<u8>), Error> {
+    let (_hrp, version, program) = segwit::decode(address)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    Ok((version.to_u8(), program))
+}
 #[cfg(test)]
 mod tests {
     const DATA: [u8; 20] = [0xab; 20]; // Arbitrary data to be encoded.
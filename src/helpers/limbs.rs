@@ -0,0 +1,161 @@
+// Little-endian 64-bit limb arithmetic shared by the fixed-width field and scalar types.
+// Every loop runs a fixed number of times and no branch depends on limb values, so the
+// callers can build constant-time operations on top of these.
+
+#[inline(always)]
+pub(crate) fn adc(a: u64, b: u64, carry: u64) -> (u64, u64) {
+    let t = a as u128 + b as u128 + carry as u128;
+    (t as u64, (t >> 64) as u64)
+}
+
+#[inline(always)]
+pub(crate) fn sbb(a: u64, b: u64, borrow: u64) -> (u64, u64) {
+    let t = (a as u128).wrapping_sub(b as u128 + borrow as u128);
+    (t as u64, (t >> 127) as u64)
+}
+
+/// a + b, returns the sum and the carry out (0 or 1)
+pub(crate) fn add4(a: &[u64; 4], b: &[u64; 4]) -> ([u64; 4], u64) {
+    let mut r = [0u64; 4];
+    let mut carry = 0;
+    for i in 0..4 {
+        let (v, c) = adc(a[i], b[i], carry);
+        r[i] = v;
+        carry = c;
+    }
+    (r, carry)
+}
+
+/// a - b, returns the difference and the borrow out (0 or 1)
+pub(crate) fn sub4(a: &[u64; 4], b: &[u64; 4]) -> ([u64; 4], u64) {
+    let mut r = [0u64; 4];
+    let mut borrow = 0;
+    for i in 0..4 {
+        let (v, b) = sbb(a[i], b[i], borrow);
+        r[i] = v;
+        borrow = b;
+    }
+    (r, borrow)
+}
+
+/// out += a * b, carries propagate up to the end of out
+pub(crate) fn mul_acc(a: &[u64], b: &[u64], out: &mut [u64]) {
+    for i in 0..a.len() {
+        let mut carry = 0u64;
+        for j in 0..b.len() {
+            let t = out[i + j] as u128 + (a[i] as u128) * (b[j] as u128) + carry as u128;
+            out[i + j] = t as u64;
+            carry = (t >> 64) as u64;
+        }
+        for limb in out.iter_mut().skip(i + b.len()) {
+            let (v, c) = adc(*limb, carry, 0);
+            *limb = v;
+            carry = c;
+        }
+    }
+}
+
+/// 256 x 256 -> 512 bit product
+pub(crate) fn mul_wide(a: &[u64; 4], b: &[u64; 4]) -> [u64; 8] {
+    let mut out = [0u64; 8];
+    mul_acc(a, b, &mut out);
+    out
+}
+
+/// all ones when choice is 1, zero when choice is 0
+#[inline(always)]
+pub(crate) fn mask(choice: u64) -> u64 {
+    0u64.wrapping_sub(choice & 1)
+}
+
+/// returns b when choice is 1, a when choice is 0
+pub(crate) fn select4(a: &[u64; 4], b: &[u64; 4], choice: u64) -> [u64; 4] {
+    let m = mask(choice);
+    let mut r = [0u64; 4];
+    for i in 0..4 {
+        r[i] = a[i] ^ ((a[i] ^ b[i]) & m);
+    }
+    r
+}
+
+/// 1 if a == b, 0 otherwise
+pub(crate) fn eq4(a: &[u64; 4], b: &[u64; 4]) -> u64 {
+    let mut acc = 0u64;
+    for i in 0..4 {
+        acc |= a[i] ^ b[i];
+    }
+    is_zero_u64(acc)
+}
+
+/// 1 if every limb is zero, 0 otherwise
+pub(crate) fn is_zero4(a: &[u64; 4]) -> u64 {
+    is_zero_u64(a[0] | a[1] | a[2] | a[3])
+}
+
+#[inline(always)]
+fn is_zero_u64(v: u64) -> u64 {
+    // (v | -v) has the top bit set for every non zero v
+    1 ^ ((v | v.wrapping_neg()) >> 63)
+}
+
+pub(crate) fn from_be_bytes(bytes: &[u8; 32]) -> [u64; 4] {
+    let mut r = [0u64; 4];
+    for i in 0..4 {
+        let mut limb = [0u8; 8];
+        limb.copy_from_slice(&bytes[24 - i * 8..32 - i * 8]);
+        r[i] = u64::from_be_bytes(limb);
+    }
+    r
+}
+
+pub(crate) fn to_be_bytes(limbs: &[u64; 4]) -> [u8; 32] {
+    let mut r = [0u8; 32];
+    for i in 0..4 {
+        r[24 - i * 8..32 - i * 8].copy_from_slice(&limbs[i].to_be_bytes());
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_sub() {
+        let a = [u64::MAX, u64::MAX, u64::MAX, u64::MAX];
+        let one = [1, 0, 0, 0];
+        let (sum, carry) = add4(&a, &one);
+        assert_eq!(sum, [0, 0, 0, 0]);
+        assert_eq!(carry, 1);
+        let (diff, borrow) = sub4(&sum, &one);
+        assert_eq!(diff, a);
+        assert_eq!(borrow, 1);
+    }
+    #[test]
+    fn test_mul_wide() {
+        let a = [u64::MAX, u64::MAX, u64::MAX, u64::MAX];
+        // (2^256 - 1)^2 = 2^512 - 2^257 + 1
+        let p = mul_wide(&a, &a);
+        assert_eq!(p, [1, 0, 0, 0, u64::MAX - 1, u64::MAX, u64::MAX, u64::MAX]);
+    }
+    #[test]
+    fn test_bytes() {
+        let bytes = hex::decode("0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20").unwrap();
+        let mut b = [0u8; 32];
+        b.copy_from_slice(&bytes);
+        let limbs = from_be_bytes(&b);
+        assert_eq!(limbs[0], 0x191a1b1c1d1e1f20);
+        assert_eq!(to_be_bytes(&limbs), b);
+    }
+    #[test]
+    fn test_select_eq() {
+        let a = [1, 2, 3, 4];
+        let b = [5, 6, 7, 8];
+        assert_eq!(select4(&a, &b, 0), a);
+        assert_eq!(select4(&a, &b, 1), b);
+        assert_eq!(eq4(&a, &a), 1);
+        assert_eq!(eq4(&a, &b), 0);
+        assert_eq!(is_zero4(&[0, 0, 0, 0]), 1);
+        assert_eq!(is_zero4(&[0, 0, 1, 0]), 0);
+    }
+}
@@ -1,11 +1,21 @@
 use num::integer::div_floor;
 use crate::helpers::hash256::hash256;
+use crate::helpers::tagged_hash::tagged_hash;
 pub fn merkle_parent(hash1: Vec<u8>, hash2: Vec<u8>) -> Vec<u8> {
     let mut sum: Vec<u8> = vec![];
     sum.extend(hash1);
     sum.extend(hash2);
     hash256(&sum).to_vec()
 }
+// taproot script tree parent: the two children are sorted, so a merkle path needs no
+// left/right flags
+pub fn tap_branch_hash(hash1: &[u8; 32], hash2: &[u8; 32]) -> [u8; 32] {
+    let (first, second) = if hash1 <= hash2 { (hash1, hash2) } else { (hash2, hash1) };
+    let mut sum: Vec<u8> = vec![];
+    sum.extend(first);
+    sum.extend(second);
+    tagged_hash("TapBranch", &sum)
+}
 pub fn merkle_parent_level(hashes: &mut Vec<Vec<u8>>) -> Vec<Vec<u8>> {
     let mut parent_level: Vec<Vec<u8>> = vec![];
     if hashes.len() == 1 {
@@ -62,6 +72,15 @@ mod tests {
         assert_eq!(merkle_parent(tx_hash0, tx_hash1), want);
     }
     #[test]
+    fn test_tap_branch_hash() {
+        let a = [1u8; 32];
+        let b = [2u8; 32];
+        let mut sum = a.to_vec();
+        sum.extend(b);
+        assert_eq!(tap_branch_hash(&a, &b), tagged_hash("TapBranch", &sum));
+        assert_eq!(tap_branch_hash(&b, &a), tap_branch_hash(&a, &b));
+    }
+    #[test]
     fn test_merkle_parent_level() {
         let hex_hashes = [
             "c117ea8ec828342f4dfb0ad6bd140e03a50720ece40169ee38bdc15d9eb64cf5",
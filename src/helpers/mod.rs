@@ -8,4 +8,6 @@ pub mod sig_hash;
 pub mod address;
 pub mod block_bits;
 pub mod merkle_hash;
-mod bech32;
+pub mod tagged_hash;
+pub(crate) mod bech32;
+pub(crate) mod limbs;
@@ -1,11 +1,12 @@
 use std::collections::HashMap;
-use num::BigUint;
 use sha2::{Digest, Sha256};
 use sha1::Sha1;
 use ripemd::{Ripemd160};
-use crate::point::Point;
 // use crate::helpers::hex;
-use crate::signature::Signature;
+use crate::execution_context::{ExecutionContext, SigVersion};
+use crate::script::Script;
+use crate::script_error::ScriptError;
+use crate::verify_flags::VerifyFlags;
 
 pub fn is_op(cmd: &Vec<u8>) -> bool {
     if cmd.len() == 1 {
@@ -22,7 +23,8 @@ pub fn op_code_names() -> HashMap<u8, &'static str> {
     op_code_names.insert(OP_PUSHDATA1, "OP_PUSHDATA1");
     op_code_names.insert(OP_PUSHDATA2, "OP_PUSHDATA2");
     op_code_names.insert(OP_PUSHDATA4, "OP_PUSHDATA4");
-    op_code_names.insert(OP_1NEGATE, "OP_NEGATE");
+    op_code_names.insert(OP_1NEGATE, "OP_1NEGATE");
+    op_code_names.insert(OP_RESERVED, "OP_RESERVED");
     op_code_names.insert(OP_1, "OP_1");
     op_code_names.insert(OP_2, "OP_2");
     op_code_names.insert(OP_3, "OP_3");
@@ -40,8 +42,11 @@ pub fn op_code_names() -> HashMap<u8, &'static str> {
     op_code_names.insert(OP_15, "OP_15");
     op_code_names.insert(OP_16, "OP_16");
     op_code_names.insert(OP_NOP, "OP_NOP");
+    op_code_names.insert(OP_VER, "OP_VER");
     op_code_names.insert(OP_IF, "OP_IF");
     op_code_names.insert(OP_NOTIF, "OP_NOTIF");
+    op_code_names.insert(OP_VERIF, "OP_VERIF");
+    op_code_names.insert(OP_VERNOTIF, "OP_VERNOTIF");
     op_code_names.insert(OP_ELSE, "OP_ELSE");
     op_code_names.insert(OP_ENDIF, "OP_ENDIF");
     op_code_names.insert(OP_VERIFY, "OP_VERIFY");
@@ -65,11 +70,23 @@ pub fn op_code_names() -> HashMap<u8, &'static str> {
     op_code_names.insert(OP_ROT, "OP_ROT");
     op_code_names.insert(OP_SWAP, "OP_SWAP");
     op_code_names.insert(OP_TUCK, "OP_TUCK");
+    op_code_names.insert(OP_CAT, "OP_CAT");
+    op_code_names.insert(OP_SUBSTR, "OP_SUBSTR");
+    op_code_names.insert(OP_LEFT, "OP_LEFT");
+    op_code_names.insert(OP_RIGHT, "OP_RIGHT");
     op_code_names.insert(OP_SIZE, "OP_SIZE");
+    op_code_names.insert(OP_INVERT, "OP_INVERT");
+    op_code_names.insert(OP_AND, "OP_AND");
+    op_code_names.insert(OP_OR, "OP_OR");
+    op_code_names.insert(OP_XOR, "OP_XOR");
     op_code_names.insert(OP_EQUAL, "OP_EQUAL");
     op_code_names.insert(OP_EQUALVERIFY, "OP_EQUALVERIFY");
+    op_code_names.insert(OP_RESERVED1, "OP_RESERVED1");
+    op_code_names.insert(OP_RESERVED2, "OP_RESERVED2");
     op_code_names.insert(OP_1ADD, "OP_1ADD");
     op_code_names.insert(OP_1SUB, "OP_1SUB");
+    op_code_names.insert(OP_2MUL, "OP_2MUL");
+    op_code_names.insert(OP_2DIV, "OP_2DIV");
     op_code_names.insert(OP_NEGATE, "OP_NEGATE");
     op_code_names.insert(OP_ABS, "OP_ABS");
     op_code_names.insert(OP_NOT, "OP_NOT");
@@ -77,6 +94,10 @@ pub fn op_code_names() -> HashMap<u8, &'static str> {
     op_code_names.insert(OP_ADD, "OP_ADD");
     op_code_names.insert(OP_SUB, "OP_SUB");
     op_code_names.insert(OP_MUL, "OP_MUL");
+    op_code_names.insert(OP_DIV, "OP_DIV");
+    op_code_names.insert(OP_MOD, "OP_MOD");
+    op_code_names.insert(OP_LSHIFT, "OP_LSHIFT");
+    op_code_names.insert(OP_RSHIFT, "OP_RSHIFT");
     op_code_names.insert(OP_BOOLAND, "OP_BOOLAND");
     op_code_names.insert(OP_BOOLOR, "OP_BOOLOR");
     op_code_names.insert(OP_NUMEQUAL, "OP_NUMEQUAL");
@@ -84,6 +105,7 @@ pub fn op_code_names() -> HashMap<u8, &'static str> {
     op_code_names.insert(OP_NUMNOTEQUAL, "OP_NUMNOTEQUAL");
     op_code_names.insert(OP_LESSTHAN, "OP_LESSTHAN");
     op_code_names.insert(OP_GREATERTHAN, "OP_GREATERTHAN");
+    op_code_names.insert(OP_LESSTHANOREQUAL, "OP_LESSTHANOREQUAL");
     op_code_names.insert(OP_GREATERTHANOREQUAL, "OP_GREATERTHANOREQUAL");
     op_code_names.insert(OP_MIN, "OP_MIN");
     op_code_names.insert(OP_MAX, "OP_MAX");
@@ -97,7 +119,18 @@ pub fn op_code_names() -> HashMap<u8, &'static str> {
     op_code_names.insert(OP_CHECKSIG, "OP_CHECKSIG");
     op_code_names.insert(OP_CHECKSIGVERIFY, "OP_CHECKSIGVERIFY");
     op_code_names.insert(OP_CHECKMULTISIG, "OP_CHECKMULTISIG");
+    op_code_names.insert(OP_CHECKMULTISIGVERIFY, "OP_CHECKMULTISIGVERIFY");
+    op_code_names.insert(OP_NOOP1, "OP_NOP1");
+    op_code_names.insert(OP_CHECKLOCKTIMEVERIFY, "OP_CHECKLOCKTIMEVERIFY");
     op_code_names.insert(OP_CHECKSEQUENCEVERIFY, "OP_CHECKSEQUENCEVERIFY");
+    op_code_names.insert(OP_NOP4, "OP_NOP4");
+    op_code_names.insert(OP_NOP5, "OP_NOP5");
+    op_code_names.insert(OP_NOP6, "OP_NOP6");
+    op_code_names.insert(OP_NOP7, "OP_NOP7");
+    op_code_names.insert(OP_NOP8, "OP_NOP8");
+    op_code_names.insert(OP_NOP9, "OP_NOP9");
+    op_code_names.insert(OP_NOP10, "OP_NOP10");
+    op_code_names.insert(OP_CHECKSIGADD, "OP_CHECKSIGADD");
 
     op_code_names
 }
@@ -106,6 +139,7 @@ pub const OP_PUSHDATA1: u8 = 76;
 pub const OP_PUSHDATA2: u8 = 77;
 pub const OP_PUSHDATA4: u8 = 78;
 pub const OP_1NEGATE: u8 = 79;
+pub const OP_RESERVED: u8 = 80;
 pub const OP_1: u8 = 81;
 pub const OP_2: u8 = 82;
 pub const OP_3: u8 = 83;
@@ -123,8 +157,11 @@ pub const OP_14: u8 = 94;
 pub const OP_15: u8 = 95;
 pub const OP_16: u8 = 96;
 pub const OP_NOP: u8 = 97;
+pub const OP_VER: u8 = 98;
 pub const OP_IF: u8 = 99;
 pub const OP_NOTIF: u8 = 100;
+pub const OP_VERIF: u8 = 101;
+pub const OP_VERNOTIF: u8 = 102;
 pub const OP_ELSE: u8 = 103;
 pub const OP_ENDIF: u8 = 104;
 pub const OP_VERIFY: u8 = 105;
@@ -148,11 +185,23 @@ pub const OP_ROLL: u8 = 122;
 pub const OP_ROT: u8 = 123;
 pub const OP_SWAP: u8 = 124;
 pub const OP_TUCK: u8 = 125;
+pub const OP_CAT: u8 = 126;
+pub const OP_SUBSTR: u8 = 127;
+pub const OP_LEFT: u8 = 128;
+pub const OP_RIGHT: u8 = 129;
 pub const OP_SIZE: u8 = 130;
+pub const OP_INVERT: u8 = 131;
+pub const OP_AND: u8 = 132;
+pub const OP_OR: u8 = 133;
+pub const OP_XOR: u8 = 134;
 pub const OP_EQUAL: u8 = 135;
 pub const OP_EQUALVERIFY: u8 = 136;
+pub const OP_RESERVED1: u8 = 137;
+pub const OP_RESERVED2: u8 = 138;
 pub const OP_1ADD: u8 = 139;
 pub const OP_1SUB: u8 = 140;
+pub const OP_2MUL: u8 = 141;
+pub const OP_2DIV: u8 = 142;
 pub const OP_NEGATE: u8 = 143;
 pub const OP_ABS: u8 = 144;
 pub const OP_NOT: u8 = 145;
@@ -160,6 +209,10 @@ pub const OP_0NOTEQUAL: u8 = 146;
 pub const OP_ADD: u8 = 147;
 pub const OP_SUB: u8 = 148;
 pub const OP_MUL: u8 = 149;
+pub const OP_DIV: u8 = 150;
+pub const OP_MOD: u8 = 151;
+pub const OP_LSHIFT: u8 = 152;
+pub const OP_RSHIFT: u8 = 153;
 pub const OP_BOOLAND: u8 = 154;
 pub const OP_BOOLOR: u8 = 155;
 pub const OP_NUMEQUAL: u8 = 156;
@@ -185,12 +238,54 @@ pub const OP_CHECKMULTISIGVERIFY: u8 = 175;
 pub const OP_NOOP1: u8 = 176;
 pub const OP_CHECKLOCKTIMEVERIFY: u8 = 177;
 pub const OP_CHECKSEQUENCEVERIFY: u8 = 178;
+pub const OP_NOP4: u8 = 179;
+pub const OP_NOP5: u8 = 180;
+pub const OP_NOP6: u8 = 181;
+pub const OP_NOP7: u8 = 182;
+pub const OP_NOP8: u8 = 183;
+pub const OP_NOP9: u8 = 184;
+pub const OP_NOP10: u8 = 185;
+pub const OP_CHECKSIGADD: u8 = 186;
+
+// opcodes disabled in 2010, a script containing one fails even where it does not run
+pub fn is_disabled(op_code: u8) -> bool {
+    matches!(
+        op_code,
+        OP_CAT | OP_SUBSTR | OP_LEFT | OP_RIGHT | OP_INVERT | OP_AND | OP_OR | OP_XOR
+            | OP_2MUL | OP_2DIV | OP_MUL | OP_DIV | OP_MOD | OP_LSHIFT | OP_RSHIFT
+    )
+}
+// BIP342 OP_SUCCESSx: in tapscript any of these makes the script succeed
+pub fn is_op_success(op_code: u8) -> bool {
+    matches!(op_code, 80 | 98 | 126..=129 | 131..=134 | 137..=138 | 141..=142 | 149..=153 | 187..=254)
+}
+/// true when element is a number in its shortest encoding: no extra 0x00 or 0x80 byte
+/// in front, which is all a sign byte can be, unless the next byte needs it
+pub fn is_minimal_num(element: &[u8]) -> bool {
+    match element {
+        [] => true,
+        [.., last] if last & 0x7f != 0 => true,
+        [_] => false,
+        [.., before, _] => before & 0x80 != 0,
+    }
+}
+// any non zero byte is true, except for a last byte of 0x80 (negative zero)
+pub fn cast_to_bool(element: &[u8]) -> bool {
+    match element.split_last() {
+        Some((last, rest)) => rest.iter().any(|b| *b != 0) || (*last != 0 && *last != 0x80),
+        None => false,
+    }
+}
 
 pub fn encode_num(num: i32) -> Vec<u8> {
+    encode_num_i64(num as i64)
+}
+// encode_num for results that may not fit 4 bytes, like the sum of OP_CHECKSIGADD
+pub fn encode_num_i64(num: i64) -> Vec<u8> {
     if num == 0 {
         return vec![];
     }
-    let abs_num = num.abs();
+    let abs_num = num.unsigned_abs();
     let negative = num < 0;
     let mut result = Vec::new();
     let mut remaining = abs_num;
@@ -211,20 +306,36 @@ pub fn encode_num(num: i32) -> Vec<u8> {
     result
 }
 
+// script numbers are limited to 4 bytes as operands, but results may overflow that
+fn pop_num(stack: &mut Vec<Vec<u8>>) -> Result<i64, ScriptError> {
+    let num = peek_num(stack, 4)?;
+    stack.pop();
+    Ok(num)
+}
+fn peek_num(stack: &[Vec<u8>], max_len: usize) -> Result<i64, ScriptError> {
+    let element = stack.last().ok_or(ScriptError::StackUnderflow)?;
+    if element.len() > max_len {
+        return Err(ScriptError::InvalidNumber);
+    }
+    Ok(decode_num_i64(element))
+}
 pub fn decode_num(element: &[u8]) -> i32 {
+    decode_num_i64(element) as i32
+}
+pub fn decode_num_i64(element: &[u8]) -> i64 {
     if element.is_empty() {
         return 0;
     }
     let big_endian = element.iter().rev().cloned().collect::<Vec<_>>();
     let negative = big_endian[0] & 0x80 != 0;
     let mut result = if negative {
-        (big_endian[0] & 0x7f) as i32
+        (big_endian[0] & 0x7f) as i64
     } else {
-        big_endian[0] as i32
+        big_endian[0] as i64
     };
     for &c in &big_endian[1..] {
         result <<= 8;
-        result += c as i32;
+        result += c as i64;
     }
     if negative {
         -result
@@ -233,241 +344,172 @@ pub fn decode_num(element: &[u8]) -> i32 {
     }
 }
 
-pub fn op_0(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_0(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(0));
-    true
+    Ok(())
 }
-pub fn op_1negate(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_1negate(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(-1));
-    true
+    Ok(())
 }
-pub fn op_1(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_1(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(1));
-    true
+    Ok(())
 }
-pub fn op_2(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_2(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(2));
-    true
+    Ok(())
 }
-pub fn op_3(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_3(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(3));
-    true
+    Ok(())
 }
-pub fn op_4(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_4(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(4));
-    true
+    Ok(())
 }
-pub fn op_5(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_5(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(5));
-    true
+    Ok(())
 }
-pub fn op_6(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_6(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(6));
-    true
+    Ok(())
 }
-pub fn op_7(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_7(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(7));
-    true
+    Ok(())
 }
-pub fn op_8(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_8(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(8));
-    true
+    Ok(())
 }
-pub fn op_9(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_9(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(9));
-    true
+    Ok(())
 }
-pub fn op_10(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_10(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(10));
-    true
+    Ok(())
 }
-pub fn op_11(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_11(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(11));
-    true
+    Ok(())
 }
-pub fn op_12(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_12(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(12));
-    true
+    Ok(())
 }
-pub fn op_13(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_13(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(13));
-    true
+    Ok(())
 }
-pub fn op_14(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_14(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(14));
-    true
+    Ok(())
 }
-pub fn op_15(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_15(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(15));
-    true
+    Ok(())
 }
-pub fn op_16(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_16(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     stack.push(encode_num(16));
-    true
+    Ok(())
 }
-pub fn op_nop(_stack: &mut Vec<Vec<u8>>) -> bool {
-    true
+pub fn op_nop(_stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    Ok(())
 }
-pub fn op_if(stack: &mut Vec<Vec<u8>>, items: &mut Vec<u8>) -> bool {
-    if stack.len() < 1 {
-        return false;
+// the condition stack holds one entry per open OP_IF; ops only run while every entry is true.
+// in an unexecuted branch the condition is not popped, only recorded as false
+pub fn op_if(stack: &mut Vec<Vec<u8>>, exec_stack: &mut Vec<bool>) -> Result<(), ScriptError> {
+    if exec_stack.contains(&false) {
+        exec_stack.push(false);
+        return Ok(());
     }
-
-    let mut true_items = Vec::new();
-    let mut false_items = Vec::new();
-    let mut current_array = &mut true_items;
-    let mut found = false;
-    let mut num_endifs_needed = 1;
-
-    while !items.is_empty() {
-        let item = items.remove(0);
-        match item {
-            99 | 100 => {
-                num_endifs_needed += 1;
-                current_array.push(item);
-            }
-            103 if num_endifs_needed == 1 => {
-                current_array = &mut false_items;
-            }
-            104 => {
-                if num_endifs_needed == 1 {
-                    found = true;
-                    break;
-                } else {
-                    num_endifs_needed -= 1;
-                    current_array.push(item);
-                }
-            }
-            _ => {
-                current_array.push(item);
-            }
-        }
-    }
-
-    if !found {
-        return false;
+    if stack.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
     }
-
     let element = stack.pop().unwrap();
-    if decode_num(&element) == 0 {
-        items.splice(0..0, false_items.into_iter());
-    } else {
-        items.splice(0..0, true_items.into_iter());
-    }
-
-    true
+    exec_stack.push(cast_to_bool(&element));
+    Ok(())
 }
-pub fn op_notif(stack: &mut Vec<Vec<u8>>, items: &mut Vec<u8>) -> bool {
-    if stack.len() < 1 {
-        return false;
-    }
-
-    let mut true_items = Vec::new();
-    let mut false_items = Vec::new();
-    let mut current_array = &mut true_items;
-    let mut found = false;
-    let mut num_endifs_needed = 1;
-
-    while !items.is_empty() {
-        let item = items.remove(0);
-        match item {
-            99 | 100 => {
-                num_endifs_needed += 1;
-                current_array.push(item);
-            }
-            103 if num_endifs_needed == 1 => {
-                current_array = &mut false_items;
-            }
-            104 => {
-                if num_endifs_needed == 1 {
-                    found = true;
-                    break;
-                } else {
-                    num_endifs_needed -= 1;
-                    current_array.push(item);
-                }
-            }
-            _ => {
-                current_array.push(item);
-            }
-        }
+pub fn op_notif(stack: &mut Vec<Vec<u8>>, exec_stack: &mut Vec<bool>) -> Result<(), ScriptError> {
+    if exec_stack.contains(&false) {
+        exec_stack.push(false);
+        return Ok(());
     }
-
-    if !found {
-        return false;
+    if stack.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
     }
-
     let element = stack.pop().unwrap();
-    if decode_num(&element) == 0 {
-        items.splice(0..0, true_items.into_iter());
-    } else {
-        items.splice(0..0, false_items.into_iter());
+    exec_stack.push(!cast_to_bool(&element));
+    Ok(())
+}
+pub fn op_else(exec_stack: &mut [bool]) -> Result<(), ScriptError> {
+    match exec_stack.last_mut() {
+        Some(last) => {
+            *last = !*last;
+            Ok(())
+        }
+        None => Err(ScriptError::UnbalancedConditional),
     }
-
-    true
 }
-pub fn op_else(stack: &mut Vec<Vec<u8>>, items: &mut Vec<u8>) -> bool {
-    println!("{}", stack.len());
-    println!("{:?}", items);
-    panic!("op_else not implemented")
-}
-pub fn op_endif(stack: &mut Vec<Vec<u8>>, items: &mut Vec<u8>) -> bool {
-    println!("{}", stack.len());
-    println!("{:?}", items);
-    panic!("op_endif not implemented")
+pub fn op_endif(exec_stack: &mut Vec<bool>) -> Result<(), ScriptError> {
+    match exec_stack.pop() {
+        Some(_) => Ok(()),
+        None => Err(ScriptError::UnbalancedConditional),
+    }
 }
-pub fn op_verify(stack: &mut Vec<Vec<u8>>) -> bool {
-    if stack.len() < 1 {
-        return false;
+pub fn op_verify(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    if stack.is_empty() {
+        return Err(ScriptError::StackUnderflow);
     }
-
     let element = stack.pop().unwrap();
-    if decode_num(&element) == 0 {
-        return false;
+    if !cast_to_bool(&element) {
+        return Err(ScriptError::Verify);
     }
-
-    true
+    Ok(())
 }
-pub fn op_return(_stack: &mut Vec<Vec<u8>>) -> bool {
-    return false;
+pub fn op_return(_stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    Err(ScriptError::OpReturn)
 }
-pub fn op_toaltstack(stack: &mut Vec<Vec<u8>>, altstack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_toaltstack(stack: &mut Vec<Vec<u8>>, altstack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item = stack.pop().unwrap();
     altstack.push(item);
-    true
+    Ok(())
 }
-pub fn op_fromaltstack(stack: &mut Vec<Vec<u8>>, altstack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_fromaltstack(stack: &mut Vec<Vec<u8>>, altstack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if altstack.is_empty() {
-        return false;
+        return Err(ScriptError::AltStackUnderflow);
     }
     let item = altstack.pop().unwrap();
     stack.push(item);
-    true
+    Ok(())
 }
-pub fn op_2drop(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_2drop(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     stack.pop();
     stack.pop();
-    true
+    Ok(())
 }
-pub fn op_2dup(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_2dup(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item1 = stack[stack.len() - 2].clone();
     let item2 = stack[stack.len() - 1].clone();
     stack.push(item1);
     stack.push(item2);
-    true
+    Ok(())
 }
-pub fn op_3dup(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_3dup(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 3 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item1 = stack[stack.len() - 3].clone();
     let item2 = stack[stack.len() - 2].clone();
@@ -475,21 +517,21 @@ pub fn op_3dup(stack: &mut Vec<Vec<u8>>) -> bool {
     stack.push(item1);
     stack.push(item2);
     stack.push(item3);
-    true
+    Ok(())
 }
-pub fn op_2over(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_2over(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 4 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item1 = stack[stack.len() - 4].clone();
     let item2 = stack[stack.len() - 3].clone();
     stack.push(item1);
     stack.push(item2);
-    true
+    Ok(())
 }
-pub fn op_2rot(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_2rot(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 6 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item1 = stack.pop().unwrap();
     let item2 = stack.pop().unwrap();
@@ -497,103 +539,103 @@ pub fn op_2rot(stack: &mut Vec<Vec<u8>>) -> bool {
     let item4 = stack.pop().unwrap();
     let item5 = stack.pop().unwrap();
     let item6 = stack.pop().unwrap();
-    stack.push(item3);
     stack.push(item4);
-    stack.push(item1);
+    stack.push(item3);
     stack.push(item2);
-    stack.push(item5);
+    stack.push(item1);
     stack.push(item6);
-    true
+    stack.push(item5);
+    Ok(())
 }
-pub fn op_2swap(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_2swap(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 4 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item1 = stack.pop().unwrap();
     let item2 = stack.pop().unwrap();
     let item3 = stack.pop().unwrap();
     let item4 = stack.pop().unwrap();
-    stack.push(item3);
-    stack.push(item4);
-    stack.push(item1);
     stack.push(item2);
-    true
+    stack.push(item1);
+    stack.push(item4);
+    stack.push(item3);
+    Ok(())
 }
-pub fn op_ifdup(stack: &mut Vec<Vec<u8>>) -> bool {
-    if stack.len() < 1 {
-        return false;
+pub fn op_ifdup(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    if stack.is_empty() {
+        return Err(ScriptError::StackUnderflow);
     }
     let item = stack[stack.len() - 1].clone();
-    if decode_num(&item) != 0 {
+    if cast_to_bool(&item) {
         stack.push(item);
     }
-    true
+    Ok(())
 }
-pub fn op_depth(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_depth(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     let depth = stack.len() as i32;
     stack.push(encode_num(depth));
-    true
+    Ok(())
 }
-pub fn op_drop(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_drop(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     stack.pop();
-    true
+    Ok(())
 }
-pub fn op_dup(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_dup(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        println!("op_dup called with empty stack");
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item = stack[stack.len() - 1].clone();
     stack.push(item);
-    true
+    Ok(())
 }
-pub fn op_nip(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_nip(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     stack.remove(stack.len() - 2);
-    true
+    Ok(())
 }
-pub fn op_over(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_over(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item = stack[stack.len() - 2].clone();
     stack.push(item);
-    true
+    Ok(())
 }
-pub fn op_pick(stack: &mut Vec<Vec<u8>>) -> bool {
-    if stack.len() < 1 {
-        return false;
+pub fn op_pick(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    if stack.is_empty() {
+        return Err(ScriptError::StackUnderflow);
     }
-    let item = stack.pop().unwrap();
-    let n = decode_num(&item) as usize;
-    if stack.len() < n {
-        return false;
+    // n counts from the element below n itself, so 0 PICK is OP_DUP
+    let n = pop_num(stack)?;
+    if n < 0 || n >= stack.len() as i64 {
+        return Err(ScriptError::StackUnderflow);
     }
-    let item = stack[stack.len() - n].clone();
+    let item = stack[stack.len() - 1 - n as usize].clone();
     stack.push(item);
-    true
+    Ok(())
 }
-pub fn op_roll(stack: &mut Vec<Vec<u8>>) -> bool {
-    if stack.len() < 1 {
-        return false;
+pub fn op_roll(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    if stack.is_empty() {
+        return Err(ScriptError::StackUnderflow);
     }
-    let item = stack.pop().unwrap();
-    let n = decode_num(&item) as usize;
-    if stack.len() < n {
-        return false;
+    // moves the element n below n itself to the top, so 0 ROLL does nothing and 1 ROLL
+    // is OP_SWAP
+    let n = pop_num(stack)?;
+    if n < 0 || n >= stack.len() as i64 {
+        return Err(ScriptError::StackUnderflow);
     }
-    let item = stack.remove(stack.len() - n);
+    let item = stack.remove(stack.len() - 1 - n as usize);
     stack.push(item);
-    true
+    Ok(())
 }
-pub fn op_rot(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_rot(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 3 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item1 = stack.pop().unwrap();
     let item2 = stack.pop().unwrap();
@@ -601,329 +643,277 @@ pub fn op_rot(stack: &mut Vec<Vec<u8>>) -> bool {
     stack.push(item2);
     stack.push(item1);
     stack.push(item3);
-    true
+    Ok(())
 }
-pub fn op_swap(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_swap(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item1 = stack.pop().unwrap();
     let item2 = stack.pop().unwrap();
     stack.push(item1);
     stack.push(item2);
-    true
+    Ok(())
 }
-pub fn op_tuck(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_tuck(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item1 = stack.pop().unwrap();
     let item2 = stack.pop().unwrap();
     stack.push(item1.clone());
     stack.push(item2);
     stack.push(item1);
-    true
+    Ok(())
 }
-pub fn op_size(stack: &mut Vec<Vec<u8>>) -> bool {
-    if stack.is_empty() {
-        return false;
-    }
-    let item = stack.pop().unwrap();
+pub fn op_size(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    // the element stays on the stack under its size
+    let Some(item) = stack.last() else {
+        return Err(ScriptError::StackUnderflow);
+    };
     let size = item.len() as i32;
     stack.push(encode_num(size));
-    true
+    Ok(())
 }
-pub fn op_equal(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_equal(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item1 = stack.pop().unwrap();
     let item2 = stack.pop().unwrap();
     let result = if item1 == item2 { 1 } else { 0 };
     stack.push(encode_num(result));
-    true
+    Ok(())
 }
-pub fn op_equalverify(stack: &mut Vec<Vec<u8>>) -> bool {
-    if !op_equal(stack) {
-        return false;
-    }
-    if stack.is_empty() {
-        return false;
-    }
+pub fn op_equalverify(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    op_equal(stack)?;
     let item = stack.pop().unwrap();
-    if decode_num(&item) == 0 {
-        return false;
+    if !cast_to_bool(&item) {
+        return Err(ScriptError::EqualVerify);
     }
-    true
+    Ok(())
 }
-pub fn op_1add(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_1add(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item = stack.pop().unwrap();
-    let num = decode_num(&item);
-    stack.push(encode_num(num + 1));
-    true
+    let num = pop_num(stack)?;
+    stack.push(encode_num_i64(num + 1));
+    Ok(())
 }
-pub fn op_1sub(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_1sub(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item = stack.pop().unwrap();
-    let num = decode_num(&item);
-    stack.push(encode_num(num - 1));
-    true
+    let num = pop_num(stack)?;
+    stack.push(encode_num_i64(num - 1));
+    Ok(())
 }
-pub fn op_negate(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_negate(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item = stack.pop().unwrap();
-    let num = decode_num(&item);
-    stack.push(encode_num(-num));
-    true
+    let num = pop_num(stack)?;
+    stack.push(encode_num_i64(-num));
+    Ok(())
 }
-pub fn op_abs(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_abs(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item = stack.pop().unwrap();
-    let num = decode_num(&item);
-    stack.push(encode_num(num.abs()));
-    true
+    let num = pop_num(stack)?;
+    stack.push(encode_num_i64(num.abs()));
+    Ok(())
 }
-pub fn op_not(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_not(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item = stack.pop().unwrap();
-    let num = decode_num(&item);
+    let num = pop_num(stack)?;
     let result = if num == 0 { 1 } else { 0 };
-    stack.push(encode_num(result));
-    true
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_0notequal(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_0notequal(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item = stack.pop().unwrap();
-    let num = decode_num(&item);
+    let num = pop_num(stack)?;
     let result = if num == 0 { 0 } else { 1 };
-    stack.push(encode_num(result));
-    true
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_add(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_add(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
-    stack.push(encode_num(num1 + num2));
-    true
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
+    stack.push(encode_num_i64(num1 + num2));
+    Ok(())
 }
-pub fn op_sub(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_sub(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
-    stack.push(encode_num(num1 - num2));
-    true
-}
-pub fn op_mul(stack: &mut Vec<Vec<u8>>) -> bool {
-    if stack.len() < 2 {
-        return false;
-    }
-    let item1 = decode_num(stack.pop().unwrap().as_slice());
-    let item2 = decode_num(stack.pop().unwrap().as_slice());
-    stack.push(encode_num(item1 * item2));
-    true
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
+    stack.push(encode_num_i64(num2 - num1));
+    Ok(())
 }
-pub fn op_booland(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_booland(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
     let result = if num1 != 0 && num2 != 0 { 1 } else { 0 };
-    stack.push(encode_num(result));
-    true
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_boolor(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_boolor(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
     let result = if num1 != 0 || num2 != 0 { 1 } else { 0 };
-    stack.push(encode_num(result));
-    true
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_numequal(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_numequal(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
     let result = if num1 == num2 { 1 } else { 0 };
-    stack.push(encode_num(result));
-    true
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_numequalverify(stack: &mut Vec<Vec<u8>>) -> bool {
-    if !op_numequal(stack) {
-        return false;
-    }
-    if stack.is_empty() {
-        return false;
-    }
+pub fn op_numequalverify(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    op_numequal(stack)?;
     let item = stack.pop().unwrap();
-    if decode_num(&item) == 0 {
-        return false;
+    if !cast_to_bool(&item) {
+        return Err(ScriptError::NumEqualVerify);
     }
-    true
+    Ok(())
 }
-pub fn op_numnotequal(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_numnotequal(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
     let result = if num1 != num2 { 1 } else { 0 };
-    stack.push(encode_num(result));
-    true
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_lessthan(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_lessthan(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
-    let result = if num1 < num2 { 1 } else { 0 };
-    stack.push(encode_num(result));
-    true
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
+    let result = if num2 < num1 { 1 } else { 0 };
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_greaterthan(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_greaterthan(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
-    let result = if num1 > num2 { 1 } else { 0 };
-    stack.push(encode_num(result));
-    true
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
+    let result = if num2 > num1 { 1 } else { 0 };
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_lessthanorequal(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_lessthanorequal(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
-    let result = if num1 <= num2 { 1 } else { 0 };
-    stack.push(encode_num(result));
-    true
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
+    let result = if num2 <= num1 { 1 } else { 0 };
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_greaterthanorequal(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_greaterthanorequal(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
-    let result = if num1 >= num2 { 1 } else { 0 };
-    stack.push(encode_num(result));
-    true
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
+    let result = if num2 >= num1 { 1 } else { 0 };
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_min(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_min(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
     let result = if num1 < num2 { num1 } else { num2 };
-    stack.push(encode_num(result));
-    true
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_max(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_max(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
     let result = if num1 > num2 { num1 } else { num2 };
-    stack.push(encode_num(result));
-    true
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_within(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_within(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.len() < 3 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let item1 = stack.pop().unwrap();
-    let item2 = stack.pop().unwrap();
-    let item3 = stack.pop().unwrap();
-    let num1 = decode_num(&item1);
-    let num2 = decode_num(&item2);
-    let num3 = decode_num(&item3);
-    let result = if num2 <= num1 && num1 < num3 { 1 } else { 0 };
-    stack.push(encode_num(result));
-    true
+    let num1 = pop_num(stack)?;
+    let num2 = pop_num(stack)?;
+    let num3 = pop_num(stack)?;
+    let result = if num2 <= num3 && num3 < num1 { 1 } else { 0 };
+    stack.push(encode_num_i64(result));
+    Ok(())
 }
-pub fn op_ripemd160(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_ripemd160(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let item = stack.pop().unwrap();
     let hash = Ripemd160::digest(&item);
     stack.push(hash.to_vec());
-    true
+    Ok(())
 }
-pub fn op_sha1(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_sha1(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let element = stack.pop().unwrap();
     let mut hasher = Sha1::new();
     hasher.update(&element);
     let result = hasher.finalize();
     stack.push(result.to_vec());
-    true
+    Ok(())
 }
-pub fn op_sha256(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_sha256(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let element = stack.pop().unwrap();
     let mut hasher = Sha256::new();
     hasher.update(&element);
     let result = hasher.finalize();
     stack.push(result.to_vec());
-    true
+    Ok(())
 }
-pub fn op_hash160(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_hash160(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let element = stack.pop().unwrap();
     let mut hasher = Sha256::new();
@@ -933,11 +923,11 @@ pub fn op_hash160(stack: &mut Vec<Vec<u8>>) -> bool {
     hasher.update(&result);
     let result = hasher.finalize();
     stack.push(result.to_vec());
-    true
+    Ok(())
 }
-pub fn op_hash256(stack: &mut Vec<Vec<u8>>) -> bool {
+pub fn op_hash256(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
     if stack.is_empty() {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     let element = stack.pop().unwrap();
     let mut hasher = Sha256::new();
@@ -947,149 +937,218 @@ pub fn op_hash256(stack: &mut Vec<Vec<u8>>) -> bool {
     hasher.update(&result);
     let result = hasher.finalize();
     stack.push(result.to_vec());
-    true
+    Ok(())
 }
-pub fn op_codeseparator(stack: &mut Vec<Vec<u8>>) -> bool {
-    println!("{}", stack.len());
-    panic!("op_codeseparator not implemented")
+// the position of the last OP_CODESEPARATOR is tracked by the interpreter, the stack is untouched
+pub fn op_codeseparator(_stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    Ok(())
 }
-pub fn op_checksig(stack: &mut Vec<Vec<u8>>, z: &BigUint) -> bool {
+pub fn op_checksig(stack: &mut Vec<Vec<u8>>, context: &ExecutionContext, script_code: &Script, sig_version: SigVersion) -> Result<(), ScriptError> {
     if stack.len() < 2 {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
     // the top element of the stack is the SEC pubkey
     // the top element is the last added
     // sec is the last added, pop takes the last!
     let sec = stack.pop().unwrap();
     // the next element of the stack is the DER signature
-    let mut der = stack.pop().unwrap();
-
-    // take off the last byte of the signature as that's the hash_type
-    der.pop();
-
-    let point1 = Point::parse(&sec);
-    let point2 = Signature::parse(&der);
+    let sig = stack.pop().unwrap();
 
-    if point1.verify(z, &point2.unwrap()) == true {
-        stack.push(encode_num(1));
-    } else {
-        stack.push(encode_num(0));
+    // a badly encoded signature or key fails the script when the policy asks for it
+    context.checks().check_signature(&sig)?;
+    context.checks().check_pubkey(&sec)?;
+    check_witness_pubkey_type(&sec, context, sig_version)?;
+    let script_code = match sig_version {
+        SigVersion::Base => remove_signature(script_code, &sig, context)?,
+        SigVersion::WitnessV0 => script_code.clone(),
+    };
+    // the last byte of the signature is the hash_type, it picks the sighash
+    let verified = !sig.is_empty() && context.check_ecdsa_signature(&sig, &sec, &script_code, sig_version);
+    if !verified && !sig.is_empty() && context.has(VerifyFlags::NULLFAIL) {
+        return Err(ScriptError::NullFail);
     }
-    true
+    stack.push(encode_num(verified as i32));
+    Ok(())
 }
-pub fn op_checksigverify(stack: &mut Vec<Vec<u8>>, z: &BigUint) -> bool {
-    op_checksig(stack, z) && op_verify(stack)
+pub fn op_checksigverify(stack: &mut Vec<Vec<u8>>, context: &ExecutionContext, script_code: &Script, sig_version: SigVersion) -> Result<(), ScriptError> {
+    op_checksig(stack, context, script_code, sig_version)?;
+    op_verify(stack).map_err(|_| ScriptError::CheckSigVerify)
 }
-pub fn op_checkmultisig(stack: &mut Vec<Vec<u8>>, z: &BigUint) -> bool {
-    if stack.len() < 1 {
-        return false;
+pub fn op_checkmultisig(stack: &mut Vec<Vec<u8>>, context: &ExecutionContext, script_code: &Script, sig_version: SigVersion) -> Result<(), ScriptError> {
+    if stack.is_empty() {
+        return Err(ScriptError::StackUnderflow);
     }
     // m of n : m signatures oof n pub keys
-    let n = decode_num(&stack.pop().unwrap()) as usize;
+    let n = pop_count(stack, context)?;
+    if !(0..=20).contains(&n) {
+        return Err(ScriptError::PubKeyCount);
+    }
+    let n = n as usize;
     if stack.len() < (n + 1) {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
+    // keys and signatures are popped last pushed first, the order they are matched in
     let mut sec_pubkeys: Vec<Vec<u8>> = vec![];
     for _ in 0..n {
         sec_pubkeys.push(stack.pop().unwrap())
     }
 
-    let m = decode_num(&stack.pop().unwrap()) as usize;
+    let m = pop_count(stack, context)?;
+    if m < 0 || m > n as i64 {
+        return Err(ScriptError::SigCount);
+    }
+    let m = m as usize;
     if stack.len() < (m + 1) {
-        return false;
+        return Err(ScriptError::StackUnderflow);
     }
-    let mut der_signatures: Vec<Vec<u8>> = vec![];
+    let mut signatures: Vec<Vec<u8>> = vec![];
     for _ in 0..m {
-        let mut der_signature = stack.pop().unwrap();
-        der_signature.pop(); // der signature is assumed to be signed with SIGHASH_ALL
-        der_signatures.push(der_signature);
+        signatures.push(stack.pop().unwrap());
     }
 
-    // OP_CHECKMULTISIG bug
-    stack.pop();
-
-    let mut points: Vec<Point> = vec![];
-    let mut sigs: Vec<Signature> = vec![];
+    // OP_CHECKMULTISIG bug: one more element than needed is popped
+    let dummy = stack.pop().unwrap();
 
-    for sec_pubkey in sec_pubkeys {
-        points.push(Point::parse(sec_pubkey.as_slice()));
-    }
-    for der in der_signatures {
-        sigs.push(Signature::parse(&der).unwrap());
+    // no legacy signature commits to any of the signatures
+    let mut script_code = script_code.clone();
+    if sig_version == SigVersion::Base {
+        for signature in &signatures {
+            script_code = remove_signature(&script_code, signature, context)?;
+        }
     }
 
-    for sig in sigs {
-        // if we have no more points, signatures are no good
-        if points.len() == 0 {
-            return false;
+    // each signature has to match a key after the key the previous one matched,
+    // only the signatures and keys that get compared have their encoding checked
+    let mut success = true;
+    let (mut isig, mut ikey) = (0, 0);
+    while success && isig < signatures.len() {
+        let signature = &signatures[isig];
+        let sec_pubkey = &sec_pubkeys[ikey];
+        context.checks().check_signature(signature)?;
+        context.checks().check_pubkey(sec_pubkey)?;
+        check_witness_pubkey_type(sec_pubkey, context, sig_version)?;
+        if !signature.is_empty() && context.check_ecdsa_signature(signature, sec_pubkey, &script_code, sig_version) {
+            isig += 1;
         }
-        while points.len() > 0 {
-            let point = points.remove(0);
-            if point.verify(z, &sig) {
-                break
-            } else { println!("not verfied"); }
+        ikey += 1;
+        // more signatures left than keys
+        if signatures.len() - isig > sec_pubkeys.len() - ikey {
+            success = false;
         }
     }
-    stack.push(encode_num(1));
-    true
+    if !success && context.has(VerifyFlags::NULLFAIL) && signatures.iter().any(|sig| !sig.is_empty()) {
+        return Err(ScriptError::NullFail);
+    }
+    if !dummy.is_empty() && context.has(VerifyFlags::NULLDUMMY) {
+        return Err(ScriptError::SigNullDummy);
+    }
+    stack.push(encode_num(success as i32));
+    Ok(())
 }
-pub fn op_checkmultisigverify(stack: &mut Vec<Vec<u8>>, z: &BigUint) -> bool {
-    op_checkmultisig(stack, z) && op_verify(stack)
+// the key and signature counts of OP_CHECKMULTISIG, minimally encoded under MINIMALDATA
+fn pop_count(stack: &mut Vec<Vec<u8>>, context: &ExecutionContext) -> Result<i64, ScriptError> {
+    if context.has(VerifyFlags::MINIMALDATA) && !stack.last().is_some_and(|count| is_minimal_num(count)) {
+        return Err(ScriptError::InvalidNumber);
+    }
+    pop_num(stack)
 }
-pub fn op_noop1(_stack: &mut Vec<Vec<u8>>) -> bool {
-    panic!("op_noop1 not implemented")
+// witness v0 scripts only take compressed keys under WITNESS_PUBKEYTYPE
+fn check_witness_pubkey_type(sec: &[u8], context: &ExecutionContext, sig_version: SigVersion) -> Result<(), ScriptError> {
+    let compressed = sec.len() == 33 && (sec[0] == 0x02 || sec[0] == 0x03);
+    if sig_version == SigVersion::WitnessV0 && context.has(VerifyFlags::WITNESS_PUBKEYTYPE) && !compressed {
+        return Err(ScriptError::WitnessPubKeyType);
+    }
+    Ok(())
 }
-pub fn op_checklocktimeverify(stack: &mut Vec<Vec<u8>>, locktime: u32, sequence: u32) -> bool {
-    if sequence == 0xffffffff {
-        return false;
+// FindAndDelete of a legacy script code, an error when it finds the signature under CONST_SCRIPTCODE
+fn remove_signature(script_code: &Script, sig: &[u8], context: &ExecutionContext) -> Result<Script, ScriptError> {
+    let removed = script_code.find_and_delete(sig);
+    if removed.instructions().len() != script_code.instructions().len() && context.has(VerifyFlags::CONST_SCRIPTCODE) {
+        return Err(ScriptError::SigFindAndDelete);
     }
-    if stack.len() < 1 {
-        return false;
+    Ok(removed)
+}
+pub fn op_checkmultisigverify(stack: &mut Vec<Vec<u8>>, context: &ExecutionContext, script_code: &Script, sig_version: SigVersion) -> Result<(), ScriptError> {
+    op_checkmultisig(stack, context, script_code, sig_version)?;
+    op_verify(stack).map_err(|_| ScriptError::CheckMultisigVerify)
+}
+pub fn op_noop1(_stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    Ok(())
+}
+const LOCKTIME_THRESHOLD: i64 = 500_000_000;
+pub fn op_checklocktimeverify(stack: &[Vec<u8>], locktime: u32, sequence: u32) -> Result<(), ScriptError> {
+    if stack.is_empty() {
+        return Err(ScriptError::StackUnderflow);
     }
-    let element = decode_num(stack.last().unwrap());
+    // a locktime may use 5 bytes so that it can reach past 2^31
+    let element = peek_num(stack, 5)?;
     if element < 0 {
-        return false;
+        return Err(ScriptError::NegativeLocktime);
     }
-    if element < 500_000_000 && locktime > 500_000_000 {
-        return false;
+    // block heights and timestamps can not be compared
+    if (element < LOCKTIME_THRESHOLD) != ((locktime as i64) < LOCKTIME_THRESHOLD) {
+        return Err(ScriptError::UnsatisfiedLocktime);
     }
-    if locktime < element as u32 {
-        return false;
+    if element > locktime as i64 {
+        return Err(ScriptError::UnsatisfiedLocktime);
     }
-    true
-}
-pub fn op_checksequenceverify(stack: &mut Vec<Vec<u8>>, version: u32, sequence: u32) -> bool {
-    if sequence & (1 << 31) == (1 << 31) {
-        return false;
+    // a final input disables the transaction locktime
+    if sequence == 0xffffffff {
+        return Err(ScriptError::UnsatisfiedLocktime);
     }
-    if stack.len() < 1 {
-        return false;
+    Ok(())
+}
+pub fn op_checksequenceverify(stack: &[Vec<u8>], version: u32, sequence: u32) -> Result<(), ScriptError> {
+    if stack.is_empty() {
+        return Err(ScriptError::StackUnderflow);
     }
-    let element = decode_num(stack.last().unwrap());
+    let element = peek_num(stack, 5)?;
     if element < 0 {
-        return false;
-    }
-    if element as u32 & (1 << 31) == (1 << 31) {
-        if version < 2 {
-            return false;
-        } else if sequence & (1 << 31) == (1 << 31) {
-            return false;
-        } else if element as u32 & (1 << 22) != sequence & (1 << 22) {
-            return false;
-        } else if element as u32 & 0xffff > sequence & 0xffff {
-            return false;
-        }
+        return Err(ScriptError::NegativeLocktime);
+    }
+    // with the disable flag set the opcode behaves as a NOP
+    if element & (1 << 31) != 0 {
+        return Ok(());
     }
-    true
+    if version < 2 || sequence & (1 << 31) != 0 {
+        return Err(ScriptError::UnsatisfiedLocktime);
+    }
+    // only the type flag and the 16 bit value take part in the comparison
+    let mask = (1 << 22) | 0xffff;
+    let element = element as u32 & mask;
+    let sequence = sequence & mask;
+    if (element & (1 << 22)) != (sequence & (1 << 22)) || element > sequence {
+        return Err(ScriptError::UnsatisfiedLocktime);
+    }
+    Ok(())
 }
 #[cfg(test)]
 mod tests {
     use super::*;
-    use num::Num;
+    use num::{BigUint, Num};
+    use crate::execution_context::FixedHashChecker;
+    use crate::signature::{Signature, SignatureError};
+    use crate::tx::Tx;
+
+    type SigOp = fn(&mut Vec<Vec<u8>>, &ExecutionContext, &Script, SigVersion) -> Result<(), ScriptError>;
+
+    // the book checks signatures against a known z, there is no transaction
+    fn run_sig_op(op: SigOp, stack: &mut Vec<Vec<u8>>, z: &BigUint, flags: VerifyFlags, sig_version: SigVersion) -> Result<(), ScriptError> {
+        let tx = Tx::new(1, vec![], vec![], 0, false, false);
+        let checker = FixedHashChecker::new(z.clone());
+        let context = ExecutionContext::new(&tx, 0, 0).with_checker(&checker).with_flags(flags);
+        op(stack, &context, &Script::new(vec![]), sig_version)
+    }
+    fn checksig(stack: &mut Vec<Vec<u8>>, z: &BigUint, flags: VerifyFlags) -> Result<(), ScriptError> {
+        run_sig_op(op_checksig, stack, z, flags, SigVersion::Base)
+    }
+    fn checkmultisig(stack: &mut Vec<Vec<u8>>, z: &BigUint, flags: VerifyFlags) -> Result<(), ScriptError> {
+        run_sig_op(op_checkmultisig, stack, z, flags, SigVersion::Base)
+    }
     #[test]
     fn test_op_hash160() {
         let mut stack: Vec<Vec<u8>> = vec![b"hello world".to_vec()];
-        assert_eq!(op_hash160(&mut stack), true);
+        assert!(op_hash160(&mut stack).is_ok());
         assert_eq!(hex::encode(stack[0].clone()), "d7d5ee7824ff93f94c3055af9382c86c68b5ca92");
     }
     #[test]
@@ -1099,10 +1158,63 @@ mod tests {
         let sec = hex::decode("04887387e452b8eacc4acfde10d9aaf7f6d9a0f975aabb10d006e4da568744d06c61de6d95231cd89026e286df3b6ae4a894a3378e393e93a0f45b666329a0ae34").unwrap();
         let sig = hex::decode("3045022000eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c022100c7207fee197d27c618aea621406f6bf5ef6fca38681d82b2f06fddbdce6feab601").unwrap();
         let mut stack: Vec<Vec<u8>> = vec![sig, sec];
-        assert_eq!(op_checksig(&mut stack, &z), true);
+        assert!(checksig(&mut stack, &z, VerifyFlags::NONE).is_ok());
         assert_eq!(decode_num(stack[0].as_slice()), 1);
     }
     #[test]
+    fn test_op_checksig_policy() {
+        let z = BigUint::from_str_radix("7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d", 16).unwrap();
+        let sec = hex::decode("04887387e452b8eacc4acfde10d9aaf7f6d9a0f975aabb10d006e4da568744d06c61de6d95231cd89026e286df3b6ae4a894a3378e393e93a0f45b666329a0ae34").unwrap();
+        let sig = hex::decode("3045022000eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c022100c7207fee197d27c618aea621406f6bf5ef6fca38681d82b2f06fddbdce6feab601").unwrap();
+        // valid DER but a high s
+        let mut stack: Vec<Vec<u8>> = vec![sig.clone(), sec.clone()];
+        assert!(checksig(&mut stack, &z, VerifyFlags::DERSIG).is_ok());
+        assert_eq!(decode_num(stack[0].as_slice()), 1);
+        let mut stack: Vec<Vec<u8>> = vec![sig.clone(), sec.clone()];
+        assert_eq!(checksig(&mut stack, &z, VerifyFlags::STANDARD), Err(ScriptError::Signature(SignatureError::HighS)));
+
+        // the low s twin passes the relay policy
+        let der = sig[..sig.len() - 1].to_vec();
+        let mut low = Signature::parse(&der).unwrap().normalize_s().der();
+        low.push(0x01);
+        let mut stack: Vec<Vec<u8>> = vec![low.clone(), sec.clone()];
+        assert!(checksig(&mut stack, &z, VerifyFlags::STANDARD).is_ok());
+        assert_eq!(decode_num(stack[0].as_slice()), 1);
+
+        // undefined sighash byte
+        let mut bad_type = low.clone();
+        *bad_type.last_mut().unwrap() = 0x04;
+        let mut stack: Vec<Vec<u8>> = vec![bad_type, sec.clone()];
+        assert_eq!(checksig(&mut stack, &z, VerifyFlags::STANDARD), Err(ScriptError::Signature(SignatureError::HashType)));
+
+        // an empty signature is just false
+        let mut stack: Vec<Vec<u8>> = vec![vec![], sec.clone()];
+        assert!(checksig(&mut stack, &z, VerifyFlags::STANDARD).is_ok());
+        assert_eq!(decode_num(stack[0].as_slice()), 0);
+
+        // a signature for another z is false, or an error when failures need an empty signature
+        let other = BigUint::from(1u8);
+        let mut stack: Vec<Vec<u8>> = vec![low.clone(), sec.clone()];
+        assert!(checksig(&mut stack, &other, VerifyFlags::NONE).is_ok());
+        assert_eq!(decode_num(stack[0].as_slice()), 0);
+        let mut stack: Vec<Vec<u8>> = vec![low, sec.clone()];
+        assert_eq!(checksig(&mut stack, &other, VerifyFlags::NULLFAIL), Err(ScriptError::NullFail));
+
+        // before BIP66 a signature only had to parse laxly, here the sequence length is
+        // wrong and r has an extra zero
+        let lax = hex::decode("3000022100\
+            00eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c022100c7207fee197d27c618aea621406f6bf5ef6fca38681d82b2f06fddbdce6feab601").unwrap();
+        let mut stack: Vec<Vec<u8>> = vec![lax.clone(), sec.clone()];
+        assert!(checksig(&mut stack, &z, VerifyFlags::NONE).is_ok());
+        assert_eq!(decode_num(stack[0].as_slice()), 1);
+        let mut stack: Vec<Vec<u8>> = vec![lax, sec.clone()];
+        assert_eq!(checksig(&mut stack, &z, VerifyFlags::DERSIG), Err(ScriptError::Signature(SignatureError::Length)));
+
+        // witness v0 scripts only take compressed keys
+        let mut stack: Vec<Vec<u8>> = vec![sig, sec];
+        assert_eq!(run_sig_op(op_checksig, &mut stack, &z, VerifyFlags::WITNESS_PUBKEYTYPE, SigVersion::WitnessV0), Err(ScriptError::WitnessPubKeyType));
+    }
+    #[test]
     fn test_op_checkmultisig() {
         let z = BigUint::from_str_radix("e71bfa115715d6fd33796948126f40a8cdd39f187e4afb03896795189fe1423c", 16).unwrap();
         let sig1 = hex::decode("3045022100dc92655fe37036f47756db8102e0d7d5e28b3beb83a8fef4f5dc0559bddfb94e02205a36d4e4e6c7fcd16658c50783e00c341609977aed3ad00937bf4ee942a8993701").unwrap();
@@ -1111,13 +1223,134 @@ mod tests {
         let sec2 = hex::decode("03b287eaf122eea69030a0e9feed096bed8045c8b98bec453e1ffac7fbdbd4bb71").unwrap();
         let mut stack: Vec<Vec<u8>> = vec![];
         stack.push(b"".to_vec());
-        stack.push(sig1);
-        stack.push(sig2);
+        stack.push(sig1.clone());
+        stack.push(sig2.clone());
         stack.push(b"\x02".to_vec());
-        stack.push(sec1);
-        stack.push(sec2);
+        stack.push(sec1.clone());
+        stack.push(sec2.clone());
         stack.push(b"\x02".to_vec());
-        assert_eq!(op_checkmultisig(&mut stack, &z), true);
+        let mut dummy_stack = stack.clone();
+        assert!(checkmultisig(&mut stack, &z, VerifyFlags::NONE).is_ok());
         assert_eq!(decode_num(stack[0].as_slice()), 1);
+
+        // BIP147 wants the element popped by the off by one bug empty
+        dummy_stack[0] = vec![0x01];
+        let mut stack = dummy_stack.clone();
+        assert!(checkmultisig(&mut stack, &z, VerifyFlags::NONE).is_ok());
+        assert_eq!(decode_num(stack[0].as_slice()), 1);
+        let mut stack = dummy_stack;
+        assert_eq!(checkmultisig(&mut stack, &z, VerifyFlags::NULLDUMMY), Err(ScriptError::SigNullDummy));
+
+        // the last signature matching no key left fails, even after the first matched
+        let mut stack: Vec<Vec<u8>> = vec![vec![], sig2.clone(), sig2.clone(), vec![2], sec1.clone(), sec2.clone(), vec![2]];
+        assert!(checkmultisig(&mut stack, &z, VerifyFlags::NONE).is_ok());
+        assert_eq!(stack, vec![encode_num(0)]);
+        // signatures in a different order than their keys
+        let mut stack: Vec<Vec<u8>> = vec![vec![], sig2.clone(), sig1, vec![2], sec1.clone(), sec2.clone(), vec![2]];
+        assert!(checkmultisig(&mut stack, &z, VerifyFlags::NONE).is_ok());
+        assert_eq!(stack, vec![encode_num(0)]);
+
+        // at most 20 keys, and no more signatures than keys
+        let mut stack: Vec<Vec<u8>> = vec![vec![], vec![], vec![21]];
+        assert_eq!(checkmultisig(&mut stack, &z, VerifyFlags::NONE), Err(ScriptError::PubKeyCount));
+        let mut stack: Vec<Vec<u8>> = vec![vec![], sig2.clone(), sig2, vec![2], sec1, vec![1]];
+        assert_eq!(checkmultisig(&mut stack, &z, VerifyFlags::NONE), Err(ScriptError::SigCount));
+    }
+    #[test]
+    fn test_operand_order() {
+        // the top of the stack is the second operand, <5> <3> OP_SUB is 2
+        let mut stack: Vec<Vec<u8>> = vec![encode_num(5), encode_num(3)];
+        assert!(op_sub(&mut stack).is_ok());
+        assert_eq!(stack, vec![encode_num(2)]);
+        for (op, want) in [(op_lessthan as fn(&mut _) -> _, 0), (op_greaterthan, 1), (op_lessthanorequal, 0), (op_greaterthanorequal, 1)] {
+            let mut stack: Vec<Vec<u8>> = vec![encode_num(5), encode_num(3)];
+            assert!(op(&mut stack).is_ok());
+            assert_eq!(stack, vec![encode_num(want)]);
+        }
+        // <x> <min> <max> OP_WITHIN is min <= x < max
+        let mut stack: Vec<Vec<u8>> = vec![encode_num(2), encode_num(2), encode_num(5)];
+        assert!(op_within(&mut stack).is_ok());
+        assert_eq!(stack, vec![encode_num(1)]);
+        let mut stack: Vec<Vec<u8>> = vec![encode_num(5), encode_num(2), encode_num(5)];
+        assert!(op_within(&mut stack).is_ok());
+        assert_eq!(stack, vec![encode_num(0)]);
+
+        // x1 x2 x3 x4 OP_2SWAP is x3 x4 x1 x2
+        let mut stack: Vec<Vec<u8>> = (1..=4).map(|i| vec![i]).collect();
+        assert!(op_2swap(&mut stack).is_ok());
+        assert_eq!(stack, vec![vec![3], vec![4], vec![1], vec![2]]);
+        // x1 x2 x3 x4 x5 x6 OP_2ROT is x3 x4 x5 x6 x1 x2
+        let mut stack: Vec<Vec<u8>> = (1..=6).map(|i| vec![i]).collect();
+        assert!(op_2rot(&mut stack).is_ok());
+        assert_eq!(stack, vec![vec![3], vec![4], vec![5], vec![6], vec![1], vec![2]]);
+    }
+    #[test]
+    fn test_stack_errors() {
+        let mut stack: Vec<Vec<u8>> = vec![];
+        assert_eq!(op_dup(&mut stack), Err(ScriptError::StackUnderflow));
+        assert_eq!(op_add(&mut stack), Err(ScriptError::StackUnderflow));
+        let mut altstack: Vec<Vec<u8>> = vec![];
+        assert_eq!(op_fromaltstack(&mut stack, &mut altstack), Err(ScriptError::AltStackUnderflow));
+        assert_eq!(op_return(&mut stack), Err(ScriptError::OpReturn));
+
+        let mut stack: Vec<Vec<u8>> = vec![vec![1], vec![2]];
+        assert_eq!(op_equalverify(&mut stack), Err(ScriptError::EqualVerify));
+        // negative zero is false
+        let mut stack: Vec<Vec<u8>> = vec![vec![0x80]];
+        assert_eq!(op_verify(&mut stack), Err(ScriptError::Verify));
+        // numeric operands are at most 4 bytes
+        let mut stack: Vec<Vec<u8>> = vec![vec![1; 5], vec![1]];
+        assert_eq!(op_add(&mut stack), Err(ScriptError::InvalidNumber));
+        let mut stack: Vec<Vec<u8>> = vec![vec![0xff, 0xff, 0xff, 0x7f], vec![1]];
+        assert!(op_add(&mut stack).is_ok());
+        assert_eq!(stack, vec![vec![0, 0, 0, 0x80, 0]]);
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_op_size() {
+        let mut stack: Vec<Vec<u8>> = vec![b"abc".to_vec()];
+        assert!(op_size(&mut stack).is_ok());
+        assert_eq!(stack, vec![b"abc".to_vec(), encode_num(3)]);
+        let mut stack: Vec<Vec<u8>> = vec![vec![]];
+        assert!(op_size(&mut stack).is_ok());
+        assert_eq!(stack, vec![vec![], encode_num(0)]);
+        assert_eq!(op_size(&mut vec![]), Err(ScriptError::StackUnderflow));
+    }
+    #[test]
+    fn test_op_pick_roll() {
+        let mut stack: Vec<Vec<u8>> = vec![vec![1], vec![2], vec![3], vec![]];
+        assert!(op_pick(&mut stack).is_ok());
+        assert_eq!(stack, vec![vec![1], vec![2], vec![3], vec![3]]);
+        let mut stack: Vec<Vec<u8>> = vec![vec![1], vec![2], vec![3], vec![2]];
+        assert!(op_roll(&mut stack).is_ok());
+        assert_eq!(stack, vec![vec![2], vec![3], vec![1]]);
+        let mut stack: Vec<Vec<u8>> = vec![vec![1], vec![1]];
+        assert_eq!(op_pick(&mut stack), Err(ScriptError::StackUnderflow));
+    }
+    #[test]
+    fn test_is_minimal_num() {
+        for n in [0, 1, -1, 127, 128, -128, 255, 256, 0x7fffffff] {
+            assert!(is_minimal_num(&encode_num_i64(n)), "{}", n);
+        }
+        assert!(!is_minimal_num(&[0x00]));
+        assert!(!is_minimal_num(&[0x80]));
+        assert!(!is_minimal_num(&[0x01, 0x00]));
+        assert!(!is_minimal_num(&[0x01, 0x80]));
+    }
+    #[test]
+    fn test_op_if() {
+        let mut exec_stack: Vec<bool> = vec![];
+        let mut stack: Vec<Vec<u8>> = vec![vec![1]];
+        assert!(op_if(&mut stack, &mut exec_stack).is_ok());
+        assert_eq!(exec_stack, vec![true]);
+        assert!(op_else(&mut exec_stack).is_ok());
+        assert_eq!(exec_stack, vec![false]);
+        // nested in an unexecuted branch nothing is popped
+        let mut stack: Vec<Vec<u8>> = vec![];
+        assert!(op_notif(&mut stack, &mut exec_stack).is_ok());
+        assert_eq!(exec_stack, vec![false, false]);
+        assert!(op_endif(&mut exec_stack).is_ok());
+        assert!(op_endif(&mut exec_stack).is_ok());
+        assert_eq!(op_endif(&mut exec_stack), Err(ScriptError::UnbalancedConditional));
+        assert_eq!(op_else(&mut exec_stack), Err(ScriptError::UnbalancedConditional));
+    }
+}
@@ -1,3 +1,6 @@
 pub const SIGHASH_ALL: u8 = 1;
 pub const SIGHASH_NONE: u8 = 2;
 pub const SIGHASH_SINGLE: u8 = 3;
+pub const SIGHASH_ANYONECANPAY: u8 = 0x80;
+// taproot only: signs like SIGHASH_ALL, but a 64 byte signature carries no hash type byte
+pub const SIGHASH_DEFAULT: u8 = 0;
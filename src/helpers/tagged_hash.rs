@@ -0,0 +1,45 @@
+use sha2::{Digest, Sha256};
+
+// BIP340 tagged hash: sha256(sha256(tag) || sha256(tag) || data)
+pub fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let mut hasher = tagged_hasher(tag);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+// sha256 that has already absorbed the 64 byte tag prefix. The prefix is exactly one
+// block, so cloning the returned hasher reuses the midstate for data hashed in parts
+// or under the same tag many times.
+pub fn tagged_hasher(tag: &str) -> Sha256 {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    hasher
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tagged_hash() {
+        let tag_hash = Sha256::digest(b"TapLeaf");
+        let mut preimage = tag_hash.to_vec();
+        preimage.extend(tag_hash);
+        preimage.extend(b"data");
+        assert_eq!(tagged_hash("TapLeaf", b"data").to_vec(), Sha256::digest(&preimage).to_vec());
+    }
+    #[test]
+    fn test_tagged_hasher() {
+        let engine = tagged_hasher("BIP0340/challenge");
+        let mut parts = engine.clone();
+        parts.update(b"da");
+        parts.update(b"ta");
+        let parts: [u8; 32] = parts.finalize().into();
+        assert_eq!(parts, tagged_hash("BIP0340/challenge", b"data"));
+        let mut again = engine.clone();
+        again.update(b"other");
+        assert_eq!(<[u8; 32]>::from(again.finalize()), tagged_hash("BIP0340/challenge", b"other"));
+    }
+}
@@ -0,0 +1,397 @@
+use std::sync::OnceLock;
+use crate::fe::Fe;
+use crate::projective_point::ProjectivePoint;
+use crate::scalar::Scalar;
+
+/// window used for the wNAF of arbitrary points
+const WNAF_WINDOW: usize = 5;
+/// window used for the generator in the dual multiplication
+const GEN_WNAF_WINDOW: usize = 8;
+/// the generator table splits the scalar in 64 nibbles
+const GEN_WINDOWS: usize = 64;
+const GEN_ENTRIES: usize = 16;
+
+/// secp256k1 point in Jacobian coordinates (X:Y:Z), x = X/Z^2, y = Y/Z^3, Z = 0 is infinity.
+/// The formulas branch on the inputs, use them only with public data (verification);
+/// operations on secrets go through `ProjectivePoint` or the generator table.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct JacobianPoint {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+}
+
+impl JacobianPoint {
+    pub(crate) const INFINITY: JacobianPoint = JacobianPoint { x: Fe::ONE, y: Fe::ONE, z: Fe::ZERO };
+
+    pub(crate) fn from_affine(x: &Fe, y: &Fe) -> Self {
+        JacobianPoint { x: *x, y: *y, z: Fe::ONE }
+    }
+    pub(crate) fn is_infinity(&self) -> bool {
+        self.z.is_zero()
+    }
+    /// None for the point at infinity
+    pub(crate) fn to_affine(self) -> Option<(Fe, Fe)> {
+        if self.is_infinity() {
+            return None;
+        }
+        let z_inv = self.z.invert();
+        let z_inv2 = z_inv.square();
+        Some((self.x * z_inv2, self.y * z_inv2 * z_inv))
+    }
+    /// converts many points with a single field inversion (Montgomery's trick)
+    pub(crate) fn batch_to_affine(points: &[JacobianPoint]) -> Vec<Option<(Fe, Fe)>> {
+        // prefix products of the non zero z
+        let mut prefix = Vec::with_capacity(points.len());
+        let mut acc = Fe::ONE;
+        for p in points {
+            prefix.push(acc);
+            if !p.is_infinity() {
+                acc = acc * p.z;
+            }
+        }
+        let mut inv = acc.invert();
+        let mut result = vec![None; points.len()];
+        for i in (0..points.len()).rev() {
+            let p = &points[i];
+            if p.is_infinity() {
+                continue;
+            }
+            let z_inv = inv * prefix[i];
+            inv = inv * p.z;
+            let z_inv2 = z_inv.square();
+            result[i] = Some((p.x * z_inv2, p.y * z_inv2 * z_inv));
+        }
+        result
+    }
+    pub(crate) fn neg(&self) -> JacobianPoint {
+        JacobianPoint { x: self.x, y: -self.y, z: self.z }
+    }
+    pub(crate) fn double(&self) -> JacobianPoint {
+        // dbl-2009-l, secp256k1 has no point with y = 0
+        if self.is_infinity() {
+            return *self;
+        }
+        let a = self.x.square();
+        let b = self.y.square();
+        let c = b.square();
+        let d = ((self.x + b).square() - a - c).double();
+        let e = a.double() + a;
+        let f = e.square();
+        let x3 = f - d.double();
+        let y3 = e * (d - x3) - c.mul_u64(8);
+        let z3 = (self.y * self.z).double();
+        JacobianPoint { x: x3, y: y3, z: z3 }
+    }
+    pub(crate) fn add(&self, other: &JacobianPoint) -> JacobianPoint {
+        // add-1998-cmo-2
+        if self.is_infinity() {
+            return *other;
+        }
+        if other.is_infinity() {
+            return *self;
+        }
+        let z1z1 = self.z.square();
+        let z2z2 = other.z.square();
+        let u1 = self.x * z2z2;
+        let u2 = other.x * z1z1;
+        let s1 = self.y * other.z * z2z2;
+        let s2 = other.y * self.z * z1z1;
+        self.add_inner(&u1, &u2, &s1, &s2, self.z * other.z)
+    }
+    /// self + (x, y), cheaper than `add` because the second point has z = 1
+    pub(crate) fn add_affine(&self, x: &Fe, y: &Fe) -> JacobianPoint {
+        if self.is_infinity() {
+            return JacobianPoint::from_affine(x, y);
+        }
+        let z1z1 = self.z.square();
+        let u2 = *x * z1z1;
+        let s2 = *y * self.z * z1z1;
+        self.add_inner(&self.x, &u2, &self.y, &s2, self.z)
+    }
+    fn add_inner(&self, u1: &Fe, u2: &Fe, s1: &Fe, s2: &Fe, z1z2: Fe) -> JacobianPoint {
+        let h = *u2 - *u1;
+        let r = *s2 - *s1;
+        if h.is_zero() {
+            if r.is_zero() {
+                return self.double();
+            }
+            return JacobianPoint::INFINITY;
+        }
+        let hh = h.square();
+        let hhh = h * hh;
+        let v = *u1 * hh;
+        let x3 = r.square() - hhh - v.double();
+        let y3 = r * (v - x3) - *s1 * hhh;
+        let z3 = z1z2 * h;
+        JacobianPoint { x: x3, y: y3, z: z3 }
+    }
+    /// true when the affine x coordinate, taken mod n, equals r. Works on the Jacobian
+    /// coordinates (x = X/Z^2) so no inversion is needed.
+    pub(crate) fn x_equals_mod_n(&self, r: &Scalar) -> bool {
+        // p - n, x mod n == r also holds for x = r + n when r + n < p
+        const P_MINUS_N: [u8; 32] = [
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+            0x45, 0x51, 0x23, 0x19, 0x50, 0xb7, 0x5f, 0xc4, 0x40, 0x2d, 0xa1, 0x72, 0x2f, 0xc9, 0xba, 0xee,
+        ];
+        const N: [u8; 32] = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+            0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+        ];
+        if self.is_infinity() {
+            return false;
+        }
+        let r_bytes = r.to_bytes();
+        // r < n < p
+        let r_fe = Fe::from_bytes(&r_bytes).unwrap();
+        let zz = self.z.square();
+        if r_fe * zz == self.x {
+            return true;
+        }
+        if r_bytes < P_MINUS_N {
+            let r_plus_n = r_fe + Fe::from_bytes(&N).unwrap();
+            return r_plus_n * zz == self.x;
+        }
+        false
+    }
+    /// odd multiples P, 3P, 5P, ... used by the wNAF loops
+    pub(crate) fn odd_multiples(&self, count: usize) -> Vec<JacobianPoint> {
+        let double = self.double();
+        let mut table = Vec::with_capacity(count);
+        table.push(*self);
+        for i in 1..count {
+            table.push(table[i - 1].add(&double));
+        }
+        table
+    }
+    /// k * self with a width 5 wNAF, variable time
+    pub(crate) fn mul_wnaf(&self, k: &Scalar) -> JacobianPoint {
+        let table = self.odd_multiples(1 << (WNAF_WINDOW - 2));
+        let naf = wnaf(k, WNAF_WINDOW);
+        let mut result = JacobianPoint::INFINITY;
+        for digit in naf.iter().rev() {
+            result = result.double();
+            result = add_digit(&result, *digit, &table);
+        }
+        result
+    }
+}
+
+/// u * G + v * p with Strauss' method: a single doubling chain shared by both wNAF
+/// expansions, G using a wider precomputed window. Variable time.
+pub(crate) fn mul_double_vartime(u: &Scalar, p: &JacobianPoint, v: &Scalar) -> JacobianPoint {
+    let g_table = generator_odd_multiples();
+    let p_table = p.odd_multiples(1 << (WNAF_WINDOW - 2));
+    let u_naf = wnaf(u, GEN_WNAF_WINDOW);
+    let v_naf = wnaf(v, WNAF_WINDOW);
+    let mut result = JacobianPoint::INFINITY;
+    for i in (0..u_naf.len()).rev() {
+        result = result.double();
+        let digit = u_naf[i];
+        if digit > 0 {
+            let (x, y) = g_table[(digit as usize - 1) / 2];
+            result = result.add_affine(&x, &y);
+        } else if digit < 0 {
+            let (x, y) = g_table[((-digit) as usize - 1) / 2];
+            result = result.add_affine(&x, &-y);
+        }
+        result = add_digit(&result, v_naf[i], &p_table);
+    }
+    result
+}
+
+/// adds digit * P given the odd multiples of P, digit is odd or zero
+pub(crate) fn add_digit(acc: &JacobianPoint, digit: i32, table: &[JacobianPoint]) -> JacobianPoint {
+    if digit > 0 {
+        acc.add(&table[(digit as usize - 1) / 2])
+    } else if digit < 0 {
+        acc.add(&table[((-digit) as usize - 1) / 2].neg())
+    } else {
+        *acc
+    }
+}
+
+/// width w non adjacent form of k, little endian digits: every non zero digit is odd,
+/// lower than 2^(w-1) in absolute value and followed by at least w - 1 zeros
+pub(crate) fn wnaf(k: &Scalar, w: usize) -> Vec<i32> {
+    let mut naf = vec![0i32; 257];
+    let mut carry = 0u32;
+    let mut bit = 0;
+    while bit < 256 {
+        if k.bits(bit, 1) == carry {
+            bit += 1;
+            continue;
+        }
+        let now = w.min(256 - bit);
+        let mut word = (k.bits(bit, now) + carry) as i32;
+        carry = ((word >> (w - 1)) & 1) as u32;
+        word -= (carry << w) as i32;
+        naf[bit] = word;
+        bit += now;
+    }
+    naf[256] = carry as i32;
+    naf
+}
+
+/// table[i][j] = j * 16^i * G, the point at infinity included so lookups are uniform
+fn generator_table() -> &'static Vec<[ProjectivePoint; GEN_ENTRIES]> {
+    static TABLE: OnceLock<Vec<[ProjectivePoint; GEN_ENTRIES]>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let (gx, gy) = generator_affine();
+        let mut points = Vec::with_capacity(GEN_WINDOWS * GEN_ENTRIES);
+        let mut base = JacobianPoint::from_affine(&gx, &gy);
+        for _ in 0..GEN_WINDOWS {
+            let mut current = JacobianPoint::INFINITY;
+            for _ in 0..GEN_ENTRIES {
+                points.push(current);
+                current = current.add(&base);
+            }
+            // current is now 16 * base
+            base = current;
+        }
+        let affine = JacobianPoint::batch_to_affine(&points);
+        affine
+            .chunks(GEN_ENTRIES)
+            .map(|window| {
+                let mut row = [ProjectivePoint::IDENTITY; GEN_ENTRIES];
+                for (entry, point) in row.iter_mut().zip(window) {
+                    if let Some((x, y)) = point {
+                        *entry = ProjectivePoint::from_affine(x, y);
+                    }
+                }
+                row
+            })
+            .collect()
+    })
+}
+
+/// G, 3G, 5G, ... in affine coordinates for the generator side of `mul_double_vartime`
+fn generator_odd_multiples() -> &'static Vec<(Fe, Fe)> {
+    static TABLE: OnceLock<Vec<(Fe, Fe)>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let (gx, gy) = generator_affine();
+        let points = JacobianPoint::from_affine(&gx, &gy).odd_multiples(1 << (GEN_WNAF_WINDOW - 2));
+        JacobianPoint::batch_to_affine(&points).into_iter().map(|p| p.unwrap()).collect()
+    })
+}
+
+pub(crate) fn generator_affine() -> (Fe, Fe) {
+    let gx = [
+        0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce, 0x87, 0x0b, 0x07,
+        0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2, 0x81, 0x5b, 0x16, 0xf8, 0x17, 0x98,
+    ];
+    let gy = [
+        0x48, 0x3a, 0xda, 0x77, 0x26, 0xa3, 0xc4, 0x65, 0x5d, 0xa4, 0xfb, 0xfc, 0x0e, 0x11, 0x08, 0xa8,
+        0xfd, 0x17, 0xb4, 0x48, 0xa6, 0x85, 0x54, 0x19, 0x9c, 0x47, 0xd0, 0x8f, 0xfb, 0x10, 0xd4, 0xb8,
+    ];
+    (Fe::from_bytes(&gx).unwrap(), Fe::from_bytes(&gy).unwrap())
+}
+
+/// k * G from the precomputed table. Every window reads all 16 entries and uses the
+/// complete projective addition, so the time does not depend on k.
+pub(crate) fn mul_generator(k: &Scalar) -> ProjectivePoint {
+    let table = generator_table();
+    let mut result = ProjectivePoint::IDENTITY;
+    for (i, row) in table.iter().enumerate() {
+        let nibble = k.bits(4 * i, 4);
+        let mut entry = ProjectivePoint::IDENTITY;
+        for (j, candidate) in row.iter().enumerate() {
+            entry = ProjectivePoint::conditional_select(&entry, candidate, ct_eq_u32(j as u32, nibble));
+        }
+        result = result.add(&entry);
+    }
+    result
+}
+
+fn ct_eq_u32(a: u32, b: u32) -> bool {
+    let v = a ^ b;
+    ((v | v.wrapping_neg()) >> 31) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigUint;
+    use num::Num;
+
+    fn scalars() -> Vec<Scalar> {
+        vec![
+            Scalar::ONE,
+            Scalar::from_u64(2),
+            Scalar::from_u64(0xdeadbeef),
+            -Scalar::ONE,
+            Scalar::from_biguint(&BigUint::from_str_radix("ec208baa0fc1c19f708a9ca96fdeff3ac3f230bb4a7ba4aede4942ad003c0f60", 16).unwrap()),
+        ]
+    }
+    #[test]
+    fn test_wnaf_reconstructs_scalar() {
+        for k in scalars() {
+            let naf = wnaf(&k, WNAF_WINDOW);
+            let mut acc = Scalar::ZERO;
+            for digit in naf.iter().rev() {
+                acc = acc + acc;
+                if *digit > 0 {
+                    acc = acc + Scalar::from_u64(*digit as u64);
+                } else if *digit < 0 {
+                    acc = acc - Scalar::from_u64((-*digit) as u64);
+                }
+            }
+            assert_eq!(acc, k);
+        }
+    }
+    #[test]
+    fn test_generator_table_matches_ladder() {
+        let (gx, gy) = generator_affine();
+        let g = ProjectivePoint::from_affine(&gx, &gy);
+        for k in scalars() {
+            assert_eq!(mul_generator(&k).to_affine(), g.mul_ct(&k).to_affine());
+        }
+        assert_eq!(mul_generator(&Scalar::ZERO).to_affine(), None);
+    }
+    #[test]
+    fn test_wnaf_matches_ladder() {
+        let (gx, gy) = generator_affine();
+        let p = ProjectivePoint::from_affine(&gx, &gy).mul_ct(&Scalar::from_u64(12345)).to_affine().unwrap();
+        let jp = JacobianPoint::from_affine(&p.0, &p.1);
+        let pp = ProjectivePoint::from_affine(&p.0, &p.1);
+        for k in scalars() {
+            assert_eq!(jp.mul_wnaf(&k).to_affine(), pp.mul_ct(&k).to_affine());
+        }
+    }
+    #[test]
+    fn test_mul_double_matches_separate() {
+        let (gx, gy) = generator_affine();
+        let p = ProjectivePoint::from_affine(&gx, &gy).mul_ct(&Scalar::from_u64(777)).to_affine().unwrap();
+        let jp = JacobianPoint::from_affine(&p.0, &p.1);
+        for u in scalars() {
+            for v in scalars() {
+                let separate = mul_generator(&u).to_affine().map(|(x, y)| jp.mul_wnaf(&v).add_affine(&x, &y));
+                let combined = mul_double_vartime(&u, &jp, &v);
+                assert_eq!(separate.unwrap().to_affine(), combined.to_affine());
+            }
+        }
+        // u * G + (-u) * G is the point at infinity
+        let g = JacobianPoint::from_affine(&gx, &gy);
+        assert!(mul_double_vartime(&Scalar::ONE, &g, &-Scalar::ONE).is_infinity());
+    }
+    #[test]
+    fn test_x_equals_mod_n() {
+        let (gx, gy) = generator_affine();
+        let p = JacobianPoint::from_affine(&gx, &gy).double().add(&JacobianPoint::from_affine(&gx, &gy));
+        let (x, _) = p.to_affine().unwrap();
+        let r = Scalar::from_bytes_reduced(&x.to_bytes());
+        assert!(p.x_equals_mod_n(&r));
+        assert!(!p.x_equals_mod_n(&(r + Scalar::ONE)));
+        assert!(!JacobianPoint::INFINITY.x_equals_mod_n(&r));
+    }
+    #[test]
+    fn test_batch_to_affine() {
+        let (gx, gy) = generator_affine();
+        let g = JacobianPoint::from_affine(&gx, &gy);
+        let points = vec![g.double(), JacobianPoint::INFINITY, g.double().add(&g), g.neg()];
+        let affine = JacobianPoint::batch_to_affine(&points);
+        for (p, a) in points.iter().zip(affine) {
+            assert_eq!(p.to_affine(), a);
+        }
+    }
+}
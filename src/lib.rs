@@ -2,15 +2,32 @@ extern crate core;
 
 pub mod point_scalar;
 pub mod field_element;
+pub mod fe;
+pub mod scalar;
+mod projective_point;
+mod jacobian_point;
 pub mod point;
 pub mod secp256k1;
 pub mod signature;
+pub mod recoverable_signature;
+pub mod schnorr_signature;
+pub mod x_only_public_key;
+pub mod musig2;
+pub mod frost;
 pub mod private_key;
 pub mod helpers;
 pub mod tx;
 pub mod tx_input;
 pub mod tx_output;
 pub mod script;
+pub mod script_error;
+pub mod script_debugger;
+pub mod script_type;
+pub mod policy;
+pub mod execution_context;
+pub mod verify_flags;
+pub mod taproot;
+pub mod message;
 pub mod tx_fetcher;
 pub mod block;
 pub mod network;
@@ -0,0 +1,205 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use num::BigUint;
+use crate::helpers::address::address_to_script_pubkey;
+use crate::helpers::hash160::hash160;
+use crate::helpers::hash256::hash256;
+use crate::helpers::varint::encode_varint;
+use crate::point::Point;
+use crate::private_key::PrivateKey;
+use crate::script::Script;
+use crate::signature::Signature;
+
+const MESSAGE_MAGIC: &[u8] = b"Bitcoin Signed Message:\n";
+
+/// Address type a BIP137 signature claims through its header byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressKind {
+    P2pkhUncompressed,
+    P2pkhCompressed,
+    P2shP2wpkh,
+    P2wpkh,
+}
+
+impl AddressKind {
+    const ALL: [AddressKind; 4] = [
+        AddressKind::P2pkhUncompressed,
+        AddressKind::P2pkhCompressed,
+        AddressKind::P2shP2wpkh,
+        AddressKind::P2wpkh,
+    ];
+
+    /// first header byte of the kind, the recovery id is added to it
+    fn header_base(&self) -> u8 {
+        match self {
+            AddressKind::P2pkhUncompressed => 27,
+            AddressKind::P2pkhCompressed => 31,
+            AddressKind::P2shP2wpkh => 35,
+            AddressKind::P2wpkh => 39,
+        }
+    }
+    fn from_header(header: u8) -> Option<(Self, u8)> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| (kind.header_base()..kind.header_base() + 4).contains(&header))
+            .map(|kind| (kind, header - kind.header_base()))
+    }
+    pub fn compressed(&self) -> bool {
+        *self != AddressKind::P2pkhUncompressed
+    }
+    /// P2WPKH program (0 <h160>) that P2SH-P2WPKH wraps as its redeem script
+    pub fn redeem_script(public_key: &Point) -> Script {
+        Script::new(vec![vec![0x00], hash160(&public_key.sec(true)).to_vec()])
+    }
+    /// scriptPubKey of the address this kind derives from public_key
+    pub fn script_pubkey(&self, public_key: &Point) -> Script {
+        let h160 = hash160(&public_key.sec(self.compressed())).to_vec();
+        match self {
+            AddressKind::P2pkhUncompressed | AddressKind::P2pkhCompressed => Script::p2pkh_script(h160),
+            AddressKind::P2shP2wpkh => {
+                let redeem = Self::redeem_script(public_key).serialize();
+                // serialize() carries the length prefix, the hash is over the raw script
+                let script_h160 = hash160(&redeem[1..]).to_vec();
+                Script::new(vec![vec![0xa9], script_h160, vec![0x87]])
+            }
+            AddressKind::P2wpkh => Script::new(vec![vec![0x00], h160]),
+        }
+    }
+    /// the kind whose address of public_key pays script_pubkey
+    pub fn for_script_pubkey(public_key: &Point, script_pubkey: &Script) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.script_pubkey(public_key) == *script_pubkey)
+    }
+}
+
+/// hash256 of the varint prefixed magic and message, as signed by `signmessage`
+pub fn message_hash(message: &[u8]) -> [u8; 32] {
+    let mut data = encode_varint(MESSAGE_MAGIC.len() as u64).unwrap();
+    data.extend(MESSAGE_MAGIC);
+    data.extend(encode_varint(message.len() as u64).unwrap());
+    data.extend(message);
+    hash256(&data)
+}
+
+/// base64 of the 65 byte compact signature whose header commits to kind
+pub fn sign_message(private_key: &PrivateKey, message: &[u8], kind: AddressKind) -> String {
+    let z = BigUint::from_bytes_be(&message_hash(message));
+    let signature = private_key.sign_recoverable(&z);
+    let mut compact = signature.serialize_compact(false).expect("r and s of a signature made here are below n");
+    compact[0] = kind.header_base() + signature.recid();
+    STANDARD.encode(compact)
+}
+
+/// Recovers the key from the signature and checks it derives address. Headers 31-34 are
+/// also accepted for segwit addresses, as several wallets sign those with them.
+pub fn verify_message(address: &str, signature: &str, message: &[u8]) -> bool {
+    let Ok(bytes) = STANDARD.decode(signature) else {
+        return false;
+    };
+    if bytes.len() != 65 {
+        return false;
+    }
+    let Some((kind, recid)) = AddressKind::from_header(bytes[0]) else {
+        return false;
+    };
+    let Ok(script_pubkey) = address_to_script_pubkey(address) else {
+        return false;
+    };
+    let r = BigUint::from_bytes_be(&bytes[1..33]);
+    let s = BigUint::from_bytes_be(&bytes[33..65]);
+    let z = BigUint::from_bytes_be(&message_hash(message));
+    let Ok(public_key) = Point::recover(&z, &Signature::new(&r, &s), recid) else {
+        return false;
+    };
+    match (kind, AddressKind::for_script_pubkey(&public_key, &script_pubkey)) {
+        (_, None) => false,
+        (AddressKind::P2pkhCompressed, Some(found)) => found.compressed(),
+        (kind, Some(found)) => kind == found,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::address::h160_to_p2pkh_address;
+
+    fn key() -> PrivateKey {
+        PrivateKey::new(&BigUint::from_bytes_be(&hash256(b"bip137 test key")))
+    }
+    fn address(kind: AddressKind, testnet: bool) -> String {
+        let point = key().point();
+        match kind {
+            AddressKind::P2pkhUncompressed | AddressKind::P2pkhCompressed => {
+                let h160 = hash160(&point.sec(kind.compressed())).to_vec();
+                String::from_utf8(h160_to_p2pkh_address(h160, testnet)).unwrap()
+            }
+            _ => panic!("no encoder for {:?} here", kind),
+        }
+    }
+
+    #[test]
+    fn test_message_hash() {
+        // signmessage digest of the empty message
+        assert_eq!(
+            hex::encode(message_hash(b"")),
+            "80e795d4a4caadd7047af389d9f7f220562feb6196032e2131e10563352c4bcc"
+        );
+    }
+    #[test]
+    fn test_sign_verify_p2pkh() {
+        for kind in [AddressKind::P2pkhUncompressed, AddressKind::P2pkhCompressed] {
+            for testnet in [false, true] {
+                let address = address(kind, testnet);
+                let signature = sign_message(&key(), b"Hello World", kind);
+                assert!(verify_message(&address, &signature, b"Hello World"));
+                assert!(!verify_message(&address, &signature, b"Hello World!"));
+            }
+        }
+        // the uncompressed and compressed addresses are different
+        let signature = sign_message(&key(), b"Hello World", AddressKind::P2pkhUncompressed);
+        assert!(!verify_message(&address(AddressKind::P2pkhCompressed, false), &signature, b"Hello World"));
+    }
+    #[test]
+    fn test_sign_verify_segwit() {
+        let point = key().point();
+        let p2wpkh = crate::helpers::bech32::bech32_segwit_encode(
+            hash160(&point.sec(true)).to_vec(),
+            crate::helpers::bech32::SegwitVersion::version_0,
+        );
+        let signature = sign_message(&key(), b"segwit", AddressKind::P2wpkh);
+        assert!(verify_message(&p2wpkh, &signature, b"segwit"));
+        // Electrum style: compressed P2PKH header for a segwit address
+        let signature = sign_message(&key(), b"segwit", AddressKind::P2pkhCompressed);
+        assert!(verify_message(&p2wpkh, &signature, b"segwit"));
+        // a P2SH-P2WPKH header does not match a native segwit address
+        let signature = sign_message(&key(), b"segwit", AddressKind::P2shP2wpkh);
+        assert!(!verify_message(&p2wpkh, &signature, b"segwit"));
+
+        let redeem = AddressKind::redeem_script(&point).serialize();
+        let p2sh = String::from_utf8(crate::helpers::address::h160_to_p2sh_address(hash160(&redeem[1..]).to_vec(), false)).unwrap();
+        assert!(verify_message(&p2sh, &signature, b"segwit"));
+        assert_eq!(AddressKind::for_script_pubkey(&point, &address_to_script_pubkey(&p2sh).unwrap()), Some(AddressKind::P2shP2wpkh));
+    }
+    #[test]
+    fn test_verify_bitcoinjs_vector() {
+        // the example in the bitcoinjs-message README, signed with the key sha256(""),
+        // WIF L4rK1yDtCWekvXuE6oXD9jCYfFNV2cWRpVuPLBcCU2z8TrisoyY1
+        let address = "1F3sAm6ZtwLAUnj7d38pGFxtP3RVEvtsbV";
+        let signature = "H9L5yLFjti0QTHhPyFrZCT1V/MMnBtXKmoiKDZ78NDBjERki6ZTQZdSMCtkgoNmp17By9ItJr8o7ChX0XxY91nk=";
+        assert!(verify_message(address, signature, b"This is an example of a signed message."));
+        assert!(!verify_message(address, signature, b"This is an example of a signed message!"));
+        let secret = hex::decode("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855").unwrap();
+        let key = PrivateKey::new(&BigUint::from_bytes_be(&secret));
+        let h160 = hash160(&key.point().sec(true)).to_vec();
+        assert_eq!(String::from_utf8(h160_to_p2pkh_address(h160, false)).unwrap(), address);
+    }
+    #[test]
+    fn test_verify_malformed() {
+        let address = address(AddressKind::P2pkhCompressed, false);
+        assert!(!verify_message(&address, "not base64!", b"Hello World"));
+        assert!(!verify_message(&address, &STANDARD.encode([31u8; 64]), b"Hello World"));
+        let mut compact = [0u8; 65];
+        compact[0] = 43;
+        assert!(!verify_message(&address, &STANDARD.encode(compact), b"Hello World"));
+        let signature = sign_message(&key(), b"Hello World", AddressKind::P2pkhCompressed);
+        assert!(!verify_message("1BadAddress", &signature, b"Hello World"));
+    }
+}
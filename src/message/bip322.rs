@@ -0,0 +1,302 @@
+use std::io::{Cursor, Error, ErrorKind, Read};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use rand::RngCore;
+use crate::helpers::address::address_to_script_pubkey;
+use crate::helpers::sig_hash::{SIGHASH_ALL, SIGHASH_DEFAULT};
+use crate::helpers::tagged_hash::tagged_hash;
+use crate::helpers::varint::{encode_varint, read_varint};
+use crate::message::bip137::AddressKind;
+use crate::private_key::PrivateKey;
+use crate::script::Script;
+use crate::verify_flags::VerifyFlags;
+use crate::tx::Tx;
+use crate::tx_input::TxInput;
+use crate::tx_output::TxOutput;
+
+/// tagged hash committed to by the to_spend scriptSig
+pub fn message_hash(message: &[u8]) -> [u8; 32] {
+    tagged_hash("BIP0322-signed-message", message)
+}
+
+/// Virtual transaction paying 0 to script_pubkey, its only input commits to the message
+pub fn to_spend(script_pubkey: &Script, message: &[u8]) -> Tx {
+    let script_sig = Script::new(vec![vec![0x00], message_hash(message).to_vec()]);
+    let tx_in = TxInput::new(vec![0u8; 32], 0xffffffff, script_sig, 0);
+    let tx_out = TxOutput::new(0, script_pubkey.clone());
+    Tx::new(0, vec![tx_in], vec![tx_out], 0, false, false)
+}
+
+/// Virtual transaction spending to_spend:0 into a single OP_RETURN output
+pub fn to_sign(to_spend: &Tx, script_sig: Script, witness: Option<Vec<Vec<u8>>>) -> Tx {
+    let mut tx_in = TxInput::new(hex::decode(to_spend.tx_id()).unwrap(), 0, script_sig, 0);
+    tx_in.set_prev_output(to_spend.tx_outs()[0].clone());
+    let segwit = witness.is_some();
+    tx_in.witness = witness;
+    let tx_out = TxOutput::new(0, Script::new(vec![vec![0x6a]]));
+    Tx::new(0, vec![tx_in], vec![tx_out], 0, false, segwit)
+}
+
+/// Signed to_sign transaction for a P2PKH, P2SH-P2WPKH, P2WPKH or key path P2TR address
+/// of private_key
+pub fn sign(private_key: &PrivateKey, address: &str, message: &[u8]) -> Result<Tx, Error> {
+    let script_pubkey = address_to_script_pubkey(address)?;
+    if script_pubkey.is_p2tr_script_pubkey() {
+        return sign_taproot(private_key, &script_pubkey, message);
+    }
+    let public_key = private_key.point();
+    let kind = AddressKind::for_script_pubkey(&public_key, &script_pubkey)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "address is not a single key address of this key"))?;
+    let to_spend = to_spend(&script_pubkey, message);
+    let mut unsigned = to_sign(&to_spend, Script::new(vec![]), None);
+    let redeem_script = AddressKind::redeem_script(&public_key);
+    let z = match kind {
+        AddressKind::P2wpkh => unsigned.sig_hash_bip143(0, None, None),
+        AddressKind::P2shP2wpkh => unsigned.sig_hash_bip143(0, Some(redeem_script.clone()), None),
+        _ => unsigned.sig_hash(0, None),
+    };
+    let mut sig = private_key.sign(&z).der();
+    sig.push(SIGHASH_ALL);
+    let sec = public_key.sec(kind.compressed());
+    Ok(match kind {
+        AddressKind::P2wpkh => to_sign(&to_spend, Script::new(vec![]), Some(vec![sig, sec])),
+        AddressKind::P2shP2wpkh => {
+            let redeem = redeem_script.serialize()[1..].to_vec();
+            to_sign(&to_spend, Script::new(vec![redeem]), Some(vec![sig, sec]))
+        }
+        _ => to_sign(&to_spend, Script::new(vec![sig, sec]), None),
+    })
+}
+
+// key path spend of a P2TR address whose output key commits to no script tree
+fn sign_taproot(private_key: &PrivateKey, script_pubkey: &Script, message: &[u8]) -> Result<Tx, Error> {
+    let (output_key, _) = private_key.point().taproot_tweak(None);
+    if Script::p2tr_script(&output_key) != *script_pubkey {
+        return Err(Error::new(ErrorKind::InvalidInput, "address is not the key path taproot address of this key"));
+    }
+    let to_spend = to_spend(script_pubkey, message);
+    let unsigned = to_sign(&to_spend, Script::new(vec![]), None);
+    let msg = unsigned.sig_hash_taproot(0, &to_spend.tx_outs(), SIGHASH_DEFAULT, None, None)?;
+    let mut aux_rand = [0u8; 32];
+    rand::rng().fill_bytes(&mut aux_rand);
+    let sig = private_key.tap_tweak(None).sign_schnorr(&msg, &aux_rand);
+    Ok(to_sign(&to_spend, Script::new(vec![]), Some(vec![sig.serialize().to_vec()])))
+}
+
+/// "simple" proof: base64 of the to_sign witness stack, only for witness-only addresses
+pub fn sign_simple(private_key: &PrivateKey, address: &str, message: &[u8]) -> Result<String, Error> {
+    let tx = sign(private_key, address, message)?;
+    let tx_in = &tx.tx_ins()[0];
+    match &tx_in.witness {
+        Some(witness) if tx_in.script_sig.cmds.is_empty() => Ok(STANDARD.encode(serialize_witness(witness))),
+        _ => Err(Error::new(ErrorKind::InvalidInput, "simple proofs need a witness-only address")),
+    }
+}
+
+/// "full" proof: base64 of the whole to_sign transaction
+pub fn sign_full(private_key: &PrivateKey, address: &str, message: &[u8]) -> Result<String, Error> {
+    let tx = sign(private_key, address, message)?;
+    Ok(STANDARD.encode(tx.serialize(false)))
+}
+
+pub fn verify_simple(address: &str, message: &[u8], signature: &str) -> bool {
+    let Ok(bytes) = STANDARD.decode(signature) else {
+        return false;
+    };
+    let Ok(witness) = parse_witness(bytes) else {
+        return false;
+    };
+    let Ok(script_pubkey) = address_to_script_pubkey(address) else {
+        return false;
+    };
+    let to_spend = to_spend(&script_pubkey, message);
+    verify_to_sign(to_sign(&to_spend, Script::new(vec![]), Some(witness)))
+}
+
+/// Checks a full proof is a to_sign of this message and address, version, locktime and
+/// sequence are the signer's choice
+pub fn verify_full(address: &str, message: &[u8], signature: &str) -> bool {
+    let Ok(bytes) = STANDARD.decode(signature) else {
+        return false;
+    };
+    let len = bytes.len() as u64;
+    let mut stream = Cursor::new(bytes);
+    let Ok(tx) = Tx::parse(&mut stream, false) else {
+        return false;
+    };
+    if stream.position() != len {
+        return false;
+    }
+    let Ok(script_pubkey) = address_to_script_pubkey(address) else {
+        return false;
+    };
+    let to_spend = to_spend(&script_pubkey, message);
+    let (inputs, outputs) = (tx.tx_ins(), tx.tx_outs());
+    if inputs.len() != 1 || outputs.len() != 1 {
+        return false;
+    }
+    let mut tx_in = inputs[0].clone();
+    if tx_in.prev_tx() != hex::decode(to_spend.tx_id()).unwrap() || tx_in.prev_index() != 0 {
+        return false;
+    }
+    if outputs[0].amount() != 0 || outputs[0].script_pubkey() != Script::new(vec![vec![0x6a]]) {
+        return false;
+    }
+    tx_in.set_prev_output(to_spend.tx_outs()[0].clone());
+    verify_to_sign(Tx::new(tx.version(), vec![tx_in], outputs, tx.locktime(), false, tx.segwit()))
+}
+
+/// Runs the interpreter on the only input of to_sign, which must carry its prevout
+fn verify_to_sign(mut to_sign: Tx) -> bool {
+    let tx_in = to_sign.tx_ins()[0].clone();
+    let script_pubkey = tx_in.prev_output().unwrap().script_pubkey();
+    let has_witness = tx_in.witness.as_ref().is_some_and(|w| !w.is_empty());
+    if script_pubkey.is_p2wpkh_script_pubkey() || script_pubkey.is_p2wsh_script_pubkey() || script_pubkey.is_p2tr_script_pubkey() {
+        if !has_witness || !tx_in.script_sig.cmds.is_empty() {
+            return false;
+        }
+    } else if script_pubkey.is_p2sh_script_pubkey() {
+        let Some(redeem) = tx_in.script_sig.cmds.last() else {
+            return false;
+        };
+        let mut raw = encode_varint(redeem.len() as u64).unwrap();
+        raw.extend(redeem);
+        let Ok(redeem_script) = Script::parse(&mut Cursor::new(raw)) else {
+            return false;
+        };
+        let nested = redeem_script.is_p2wpkh_script_pubkey() || redeem_script.is_p2wsh_script_pubkey();
+        if nested != has_witness {
+            return false;
+        }
+    } else if !script_pubkey.is_p2pkh_script_pubkey() || has_witness {
+        // other witness versions are still anyone can spend for the interpreter
+        return false;
+    }
+    to_sign.verify_input_with_flags(0, VerifyFlags::STANDARD)
+}
+
+fn serialize_witness(witness: &[Vec<u8>]) -> Vec<u8> {
+    let mut result = encode_varint(witness.len() as u64).unwrap();
+    for item in witness {
+        result.extend(encode_varint(item.len() as u64).unwrap());
+        result.extend(item);
+    }
+    result
+}
+
+fn parse_witness(bytes: Vec<u8>) -> Result<Vec<Vec<u8>>, Error> {
+    let len = bytes.len() as u64;
+    let mut stream = Cursor::new(bytes);
+    let num_items = read_varint(&mut stream)?;
+    let mut witness = vec![];
+    for _ in 0..num_items {
+        let item_len = read_varint(&mut stream)?;
+        if item_len > len {
+            return Err(Error::new(ErrorKind::InvalidData, "witness item too long"));
+        }
+        let mut item = vec![0u8; item_len as usize];
+        stream.read_exact(&mut item)?;
+        witness.push(item);
+    }
+    if stream.position() != len {
+        return Err(Error::new(ErrorKind::InvalidData, "trailing bytes after witness"));
+    }
+    Ok(witness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigUint;
+    use crate::helpers::base58::decode_base58_checksum;
+    use crate::helpers::hash256::hash256;
+
+    // BIP322 test vectors
+    const ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const WIF: &str = "L3VFeEujGtevx9w18HD1fhRbCH67Az2dpCymeRE1SoPK6XQtaN2k";
+
+    fn wif_key() -> PrivateKey {
+        let decoded = decode_base58_checksum(WIF.as_bytes()).unwrap();
+        PrivateKey::new(&BigUint::from_bytes_be(&decoded[1..33]))
+    }
+
+    #[test]
+    fn test_message_hash() {
+        assert_eq!(hex::encode(message_hash(b"")), "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1");
+        assert_eq!(hex::encode(message_hash(b"Hello World")), "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a");
+    }
+    #[test]
+    fn test_virtual_transactions() {
+        let script_pubkey = address_to_script_pubkey(ADDRESS).unwrap();
+        let spend = to_spend(&script_pubkey, b"");
+        assert_eq!(spend.tx_id(), "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7");
+        assert_eq!(to_sign(&spend, Script::new(vec![]), None).tx_id(), "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6");
+        let spend = to_spend(&script_pubkey, b"Hello World");
+        assert_eq!(spend.tx_id(), "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b");
+        assert_eq!(to_sign(&spend, Script::new(vec![]), None).tx_id(), "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf");
+    }
+    #[test]
+    fn test_verify_simple_vectors() {
+        let empty = "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        let hello = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        assert!(verify_simple(ADDRESS, b"", empty));
+        assert!(verify_simple(ADDRESS, b"Hello World", hello));
+        assert!(!verify_simple(ADDRESS, b"Hello World", empty));
+        assert!(!verify_simple(ADDRESS, b"", hello));
+    }
+    #[test]
+    fn test_sign_verify_simple() {
+        let key = wif_key();
+        let proof = sign_simple(&key, ADDRESS, b"Hello World").unwrap();
+        assert!(verify_simple(ADDRESS, b"Hello World", &proof));
+        assert!(!verify_simple(ADDRESS, b"Hello World!", &proof));
+        let other = PrivateKey::new(&BigUint::from_bytes_be(&hash256(b"other")));
+        assert!(sign_simple(&other, ADDRESS, b"Hello World").is_err());
+    }
+    #[test]
+    fn test_sign_verify_full() {
+        let key = PrivateKey::new(&BigUint::from_bytes_be(&hash256(b"bip322 full")));
+        let point = key.point();
+        let h160 = crate::helpers::hash160::hash160(&point.sec(true)).to_vec();
+        let p2pkh = String::from_utf8(crate::helpers::address::h160_to_p2pkh_address(h160.clone(), false)).unwrap();
+        let redeem = AddressKind::redeem_script(&point).serialize();
+        let p2sh_h160 = crate::helpers::hash160::hash160(&redeem[1..]).to_vec();
+        let p2sh = String::from_utf8(crate::helpers::address::h160_to_p2sh_address(p2sh_h160, true)).unwrap();
+        let p2wpkh = crate::helpers::bech32::bech32_segwit_encode(h160, crate::helpers::bech32::SegwitVersion::version_0);
+
+        for address in [&p2pkh, &p2sh, &p2wpkh] {
+            let proof = sign_full(&key, address, b"proof of control").unwrap();
+            assert!(verify_full(address, b"proof of control", &proof));
+            assert!(!verify_full(address, b"proof of something else", &proof));
+        }
+        assert!(sign_simple(&key, &p2pkh, b"legacy").is_err());
+        assert!(sign_simple(&key, &p2sh, b"nested").is_err());
+        let proof = sign_full(&key, &p2wpkh, b"m").unwrap();
+        assert!(!verify_full(&p2pkh, b"m", &proof));
+    }
+    #[test]
+    fn test_verify_rejects() {
+        // a taproot key path signature that does not verify
+        let p2tr = "bc1pwyzhgwy30q2juhau2f2c4qscasddle5ymw9m7scq5kc62t8kyzkqyz059k";
+        assert!(!verify_simple(p2tr, b"", &STANDARD.encode(serialize_witness(&[vec![1u8; 64]]))));
+        assert!(!verify_simple(ADDRESS, b"", "AQ=="));
+        assert!(!verify_simple(ADDRESS, b"", "not base64"));
+        assert!(!verify_full(ADDRESS, b"", "AAAA"));
+        assert!(parse_witness(vec![0x01, 0x05, 0x00]).is_err());
+        assert_eq!(parse_witness(vec![0x02, 0x00, 0x01, 0xab]).unwrap(), vec![vec![], vec![0xab]]);
+    }
+    #[test]
+    fn test_taproot() {
+        // BIP322 test vector of the P2TR address of the test key
+        let p2tr = "bc1ppv609nr0vr25u07u95waq5lucwfm6tde4nydujnu8npg4q75mr5sxq8lt3";
+        let hello = "AUHd69PrJQEv+oKTfZ8l+WROBHuy9HKrbFCJu7U1iK2iiEy1vMU5EfMtjc+VSHM7aU0SDbak5IUZRVno2P5mjSafAQ==";
+        assert!(verify_simple(p2tr, b"Hello World", hello));
+        assert!(!verify_simple(p2tr, b"Hello World!", hello));
+
+        let proof = sign_simple(&wif_key(), p2tr, b"taproot").unwrap();
+        assert!(verify_simple(p2tr, b"taproot", &proof));
+        assert!(!verify_simple(p2tr, b"Hello World", &proof));
+        // a script path witness that is not a valid control block
+        let witness = vec![vec![0x51], vec![0xc0; 34]];
+        assert!(!verify_simple(p2tr, b"", &STANDARD.encode(serialize_witness(&witness))));
+    }
+}
@@ -0,0 +1,6 @@
+// Signed messages proving control of an address: the legacy BIP137 compact signatures
+// and BIP322 proofs, which are run through the script interpreter as virtual transactions.
+pub mod bip137;
+pub mod bip322;
+
+pub use bip137::{sign_message, verify_message, AddressKind};
@@ -0,0 +1,163 @@
+use std::io::{Error, ErrorKind};
+use crate::helpers::tagged_hash::tagged_hash;
+use crate::point::Point;
+use crate::scalar::Scalar;
+use crate::x_only_public_key::XOnlyPublicKey;
+
+/// KeyAgg output plus the tweaks applied so far (BIP327 KeyGen Context). It also caches
+/// the hash of the key list and the second distinct key, so the coefficient of every
+/// signer is a single tagged hash.
+#[derive(Debug, Clone)]
+pub struct KeyAggContext {
+    pubkeys: Vec<[u8; 33]>,
+    q: Point,
+    gacc: Scalar,
+    tacc: Scalar,
+    list_hash: [u8; 32],
+    second_key: [u8; 33],
+}
+
+impl KeyAggContext {
+    /// aggregates the keys in the given order, use `key_sort` first for an order independent key
+    pub fn new(pubkeys: &[Point]) -> Result<Self, Error> {
+        if pubkeys.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "no public keys to aggregate"));
+        }
+        let pubkeys: Vec<[u8; 33]> = pubkeys.iter().map(sec33).collect();
+        let list_hash = tagged_hash("KeyAgg list", &pubkeys.concat());
+        let second_key = pubkeys.iter().find(|pk| **pk != pubkeys[0]).copied().unwrap_or([0u8; 33]);
+        let mut ctx = KeyAggContext {
+            pubkeys: pubkeys.clone(),
+            q: Point::infinity(),
+            gacc: Scalar::ONE,
+            tacc: Scalar::ZERO,
+            list_hash,
+            second_key,
+        };
+        let mut q = Point::infinity();
+        for pk in &pubkeys {
+            let a = ctx.coefficient(pk);
+            q = q + Point::parse(pk).mul_vartime(&a);
+        }
+        if q == Point::infinity() {
+            return Err(Error::new(ErrorKind::InvalidInput, "aggregate key is infinite"));
+        }
+        ctx.q = q;
+        Ok(ctx)
+    }
+    /// sorts the keys by their compressed SEC encoding
+    pub fn key_sort(pubkeys: &[Point]) -> Vec<Point> {
+        let mut sorted = pubkeys.to_vec();
+        sorted.sort_by_key(sec33);
+        sorted
+    }
+    /// KeyAggCoeff: 1 for the second distinct key, a hash of the key list and the key otherwise
+    pub fn coefficient(&self, pk: &[u8; 33]) -> Scalar {
+        if *pk == self.second_key {
+            return Scalar::ONE;
+        }
+        let mut data = self.list_hash.to_vec();
+        data.extend(pk);
+        Scalar::from_bytes_reduced(&tagged_hash("KeyAgg coefficient", &data))
+    }
+    /// plain (tweaked) aggregate key Q
+    pub fn aggregated_point(&self) -> Point {
+        self.q.clone()
+    }
+    pub fn x_only_public_key(&self) -> XOnlyPublicKey {
+        XOnlyPublicKey::from_point(&self.q).0
+    }
+    pub fn pubkeys(&self) -> &[[u8; 33]] {
+        &self.pubkeys
+    }
+    pub fn contains(&self, pk: &[u8; 33]) -> bool {
+        self.pubkeys.contains(pk)
+    }
+    pub(crate) fn gacc(&self) -> Scalar {
+        self.gacc
+    }
+    pub(crate) fn tacc(&self) -> Scalar {
+        self.tacc
+    }
+    /// ApplyTweak: plain tweaks (BIP32) add t*G to Q, x-only tweaks (taproot) first
+    /// negate Q when its y is odd
+    pub fn apply_tweak(&mut self, tweak: &[u8; 32], is_xonly: bool) -> Result<(), Error> {
+        let (_, y) = self.q.affine().unwrap();
+        let g = if is_xonly && y.is_odd() { -Scalar::ONE } else { Scalar::ONE };
+        let t = Scalar::from_bytes(tweak)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "tweak is not lower than n"))?;
+        let q = self.q.mul_vartime(&g) + Point::mul_generator(&t);
+        if q == Point::infinity() {
+            return Err(Error::new(ErrorKind::InvalidInput, "tweaked key is infinite"));
+        }
+        self.q = q;
+        self.gacc = g * self.gacc;
+        self.tacc = t + g * self.tacc;
+        Ok(())
+    }
+}
+
+pub(crate) fn sec33(point: &Point) -> [u8; 33] {
+    point.sec(true).try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn key(s: &str) -> Point {
+        Point::parse(&hex::decode(s).unwrap())
+    }
+    fn hex_list(value: &Value) -> Vec<Vec<u8>> {
+        value.as_array().unwrap().iter().map(|v| hex::decode(v.as_str().unwrap()).unwrap()).collect()
+    }
+    fn indices(value: &Value) -> Vec<usize> {
+        value.as_array().unwrap().iter().map(|v| v.as_u64().unwrap() as usize).collect()
+    }
+    #[test]
+    fn test_key_agg_vectors() {
+        let vectors: Value = serde_json::from_str(include_str!("../../test_vectors/bip327/key_agg_vectors.json")).unwrap();
+        let pubkeys = hex_list(&vectors["pubkeys"]);
+        let tweaks = hex_list(&vectors["tweaks"]);
+        for case in vectors["valid_test_cases"].as_array().unwrap() {
+            let keys: Vec<Point> = indices(&case["key_indices"]).iter().map(|i| Point::parse(&pubkeys[*i])).collect();
+            let ctx = KeyAggContext::new(&keys).unwrap();
+            assert_eq!(hex::encode_upper(ctx.x_only_public_key().serialize()), case["expected"].as_str().unwrap());
+        }
+        for case in vectors["error_test_cases"].as_array().unwrap() {
+            let comment = case["comment"].as_str().unwrap();
+            let parsed: Vec<Result<Point, Error>> =
+                indices(&case["key_indices"]).iter().map(|i| Point::try_parse(&pubkeys[*i])).collect();
+            if case["error"]["type"] == "invalid_contribution" {
+                let signer = parsed.iter().position(|p| p.is_err());
+                assert_eq!(signer, Some(case["error"]["signer"].as_u64().unwrap() as usize), "{}", comment);
+                continue;
+            }
+            let keys: Vec<Point> = parsed.into_iter().map(|p| p.unwrap()).collect();
+            let mut ctx = KeyAggContext::new(&keys).unwrap();
+            let is_xonly = case["is_xonly"].as_array().unwrap();
+            let result = indices(&case["tweak_indices"]).iter().zip(is_xonly).try_for_each(|(i, xonly)| {
+                ctx.apply_tweak(tweaks[*i].as_slice().try_into().unwrap(), xonly.as_bool().unwrap())
+            });
+            assert!(result.is_err(), "{}", comment);
+        }
+    }
+    #[test]
+    fn test_key_sort() {
+        let a = key("02DD308AFEC5777E13121FA72B9CC1B7CC0139715309B086C960E18FD969774EB8");
+        let b = key("03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659");
+        let c = key("023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66");
+        assert_eq!(KeyAggContext::key_sort(&[a.clone(), b.clone(), c.clone()]), vec![c, a, b]);
+    }
+    #[test]
+    fn test_apply_tweak() {
+        let keys = [Point::generator(), Point::generator() + Point::generator()];
+        let mut ctx = KeyAggContext::new(&keys).unwrap();
+        let q = ctx.aggregated_point();
+        let tweak = [7u8; 32];
+        ctx.apply_tweak(&tweak, false).unwrap();
+        assert_eq!(ctx.aggregated_point(), q + Point::mul_generator(&Scalar::from_bytes(&tweak).unwrap()));
+        assert!(ctx.apply_tweak(&[0xff; 32], true).is_err());
+    }
+}
@@ -0,0 +1,9 @@
+// MuSig2 n-of-n Schnorr multi-signatures (BIP327). The aggregate signature is a
+// plain BIP340 signature for the aggregate x-only key.
+pub mod key_agg;
+pub mod nonce;
+pub mod session;
+
+pub use key_agg::KeyAggContext;
+pub use nonce::{nonce_agg, nonce_gen, AggNonce, PubNonce, SecNonce};
+pub use session::{PartialSignature, Session};
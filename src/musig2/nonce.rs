@@ -0,0 +1,220 @@
+use std::io::{Error, ErrorKind};
+use crate::helpers::tagged_hash::tagged_hash;
+use crate::musig2::key_agg::sec33;
+use crate::point::Point;
+use crate::private_key::PrivateKey;
+use crate::scalar::Scalar;
+use crate::x_only_public_key::XOnlyPublicKey;
+
+/// Secret nonce (k1, k2) bound to the signer's public key. It is not Clone and
+/// `Session::partial_sign` consumes it, so a nonce cannot be used twice.
+pub struct SecNonce {
+    k1: Scalar,
+    k2: Scalar,
+    pk: [u8; 33],
+}
+
+/// Public nonce R1 = k1*G, R2 = k2*G, sent to the other signers
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PubNonce {
+    r1: Point,
+    r2: Point,
+}
+
+/// Sum of all the public nonces, either point may be infinite
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AggNonce {
+    r1: Point,
+    r2: Point,
+}
+
+impl SecNonce {
+    /// the 97 byte encoding k1 || k2 || pk
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.k1.to_bytes().to_vec();
+        result.extend(self.k2.to_bytes());
+        result.extend(self.pk);
+        result
+    }
+    pub(crate) fn scalars(&self) -> (Scalar, Scalar) {
+        (self.k1, self.k2)
+    }
+    pub(crate) fn pk(&self) -> &[u8; 33] {
+        &self.pk
+    }
+}
+
+impl Drop for SecNonce {
+    fn drop(&mut self) {
+        self.k1 = Scalar::ZERO;
+        self.k2 = Scalar::ZERO;
+    }
+}
+
+impl PubNonce {
+    pub(crate) fn points(&self) -> (Point, Point) {
+        (self.r1.clone(), self.r2.clone())
+    }
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != 66 {
+            return Err(Error::new(ErrorKind::InvalidData, "public nonce must be 66 bytes"));
+        }
+        Ok(PubNonce {
+            r1: Point::try_parse(&bytes[..33])?,
+            r2: Point::try_parse(&bytes[33..])?,
+        })
+    }
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.r1.sec(true);
+        result.extend(self.r2.sec(true));
+        result
+    }
+}
+
+impl AggNonce {
+    pub(crate) fn points(&self) -> (Point, Point) {
+        (self.r1.clone(), self.r2.clone())
+    }
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != 66 {
+            return Err(Error::new(ErrorKind::InvalidData, "aggregate nonce must be 66 bytes"));
+        }
+        Ok(AggNonce {
+            r1: parse_ext(&bytes[..33])?,
+            r2: parse_ext(&bytes[33..])?,
+        })
+    }
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = serialize_ext(&self.r1).to_vec();
+        result.extend(serialize_ext(&self.r2));
+        result
+    }
+}
+
+// cbytes_ext: the point at infinity is 33 zero bytes
+fn serialize_ext(point: &Point) -> [u8; 33] {
+    if *point == Point::infinity() {
+        [0u8; 33]
+    } else {
+        sec33(point)
+    }
+}
+
+fn parse_ext(bytes: &[u8]) -> Result<Point, Error> {
+    if bytes.iter().all(|b| *b == 0) {
+        Ok(Point::infinity())
+    } else {
+        Point::try_parse(bytes)
+    }
+}
+
+/// NonceGen. rand must be fresh randomness for every call; the optional secret key,
+/// aggregate key, message and extra input only add defence in depth.
+pub fn nonce_gen(
+    rand: &[u8; 32],
+    secret: Option<&PrivateKey>,
+    pk: &Point,
+    aggpk: Option<&XOnlyPublicKey>,
+    msg: Option<&[u8]>,
+    extra_in: Option<&[u8]>,
+) -> Result<(SecNonce, PubNonce), Error> {
+    let mut rand = *rand;
+    if let Some(secret) = secret {
+        let aux = tagged_hash("MuSig/aux", &rand);
+        for (r, (s, a)) in rand.iter_mut().zip(secret.scalar().to_bytes().iter().zip(aux)) {
+            *r = s ^ a;
+        }
+    }
+    let pk = sec33(pk);
+    let aggpk = aggpk.map(|k| k.serialize().to_vec()).unwrap_or_default();
+    let mut data = rand.to_vec();
+    data.push(pk.len() as u8);
+    data.extend(pk);
+    data.push(aggpk.len() as u8);
+    data.extend(&aggpk);
+    match msg {
+        Some(msg) => {
+            data.push(1);
+            data.extend((msg.len() as u64).to_be_bytes());
+            data.extend(msg);
+        }
+        None => data.push(0),
+    }
+    let extra_in = extra_in.unwrap_or_default();
+    data.extend((extra_in.len() as u32).to_be_bytes());
+    data.extend(extra_in);
+
+    let mut k = [Scalar::ZERO; 2];
+    for (i, k) in k.iter_mut().enumerate() {
+        let mut preimage = data.clone();
+        preimage.push(i as u8);
+        *k = Scalar::from_bytes_reduced(&tagged_hash("MuSig/nonce", &preimage));
+        if k.is_zero() {
+            return Err(Error::other("nonce is zero"));
+        }
+    }
+    let pubnonce = PubNonce {
+        r1: Point::mul_generator(&k[0]),
+        r2: Point::mul_generator(&k[1]),
+    };
+    Ok((SecNonce { k1: k[0], k2: k[1], pk }, pubnonce))
+}
+
+/// NonceAgg: sums the public nonces of all the signers
+pub fn nonce_agg(pubnonces: &[PubNonce]) -> AggNonce {
+    let mut r1 = Point::infinity();
+    let mut r2 = Point::infinity();
+    for nonce in pubnonces {
+        r1 = r1 + nonce.r1.clone();
+        r2 = r2 + nonce.r2.clone();
+    }
+    AggNonce { r1, r2 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigUint;
+    use serde_json::Value;
+
+    #[test]
+    fn test_nonce_gen_binds_inputs() {
+        let key = PrivateKey::new(&BigUint::from(42u32));
+        let rand = [3u8; 32];
+        let (sec1, pub1) = nonce_gen(&rand, Some(&key), &key.point(), None, Some(b"msg"), None).unwrap();
+        let (_, pub2) = nonce_gen(&rand, Some(&key), &key.point(), None, Some(b"msg"), None).unwrap();
+        let (_, pub3) = nonce_gen(&rand, Some(&key), &key.point(), None, None, None).unwrap();
+        assert_eq!(pub1, pub2);
+        assert_ne!(pub1, pub3);
+        assert_eq!(sec1.serialize().len(), 97);
+        assert_eq!(PubNonce::parse(&pub1.serialize()).unwrap(), pub1);
+    }
+    #[test]
+    fn test_nonce_agg_vectors() {
+        let vectors: Value = serde_json::from_str(include_str!("../../test_vectors/bip327/nonce_agg_vectors.json")).unwrap();
+        let pnonces: Vec<Vec<u8>> = vectors["pnonces"].as_array().unwrap().iter()
+            .map(|v| hex::decode(v.as_str().unwrap()).unwrap()).collect();
+        let parse = |case: &Value| -> Vec<Result<PubNonce, Error>> {
+            case["pnonce_indices"].as_array().unwrap().iter()
+                .map(|i| PubNonce::parse(&pnonces[i.as_u64().unwrap() as usize])).collect()
+        };
+        for case in vectors["valid_test_cases"].as_array().unwrap() {
+            let nonces: Vec<PubNonce> = parse(case).into_iter().map(|n| n.unwrap()).collect();
+            assert_eq!(hex::encode_upper(nonce_agg(&nonces).serialize()), case["expected"].as_str().unwrap());
+        }
+        for case in vectors["error_test_cases"].as_array().unwrap() {
+            let signer = parse(case).iter().position(|n| n.is_err());
+            assert_eq!(signer, Some(case["error"]["signer"].as_u64().unwrap() as usize), "{}", case["comment"]);
+        }
+    }
+    #[test]
+    fn test_nonce_agg_infinity() {
+        let key = PrivateKey::new(&BigUint::from(42u32));
+        let (_, nonce) = nonce_gen(&[1u8; 32], None, &key.point(), None, None, None).unwrap();
+        let (r1, r2) = nonce.points();
+        let negated = PubNonce { r1: -r1, r2: -r2 };
+        let agg = nonce_agg(&[nonce, negated]);
+        assert_eq!(agg.serialize(), vec![0u8; 66]);
+        assert_eq!(AggNonce::parse(&agg.serialize()).unwrap(), agg);
+    }
+}
@@ -0,0 +1,175 @@
+use std::io::{Error, ErrorKind};
+use crate::helpers::tagged_hash::tagged_hash;
+use crate::musig2::key_agg::{sec33, KeyAggContext};
+use crate::musig2::nonce::{AggNonce, PubNonce, SecNonce};
+use crate::point::Point;
+use crate::private_key::PrivateKey;
+use crate::scalar::Scalar;
+use crate::schnorr_signature::{challenge, SchnorrSignature};
+
+/// s_i of one signer
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PartialSignature(Scalar);
+
+impl PartialSignature {
+    pub fn parse(bytes: &[u8; 32]) -> Result<Self, Error> {
+        Scalar::from_bytes(bytes)
+            .map(PartialSignature)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "partial signature is not lower than n"))
+    }
+    pub fn serialize(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+}
+
+/// Signing session for one message: the values shared by every signer
+/// (GetSessionValues) are computed once here.
+#[derive(Debug, Clone)]
+pub struct Session {
+    key_agg: KeyAggContext,
+    msg: Vec<u8>,
+    b: Scalar,
+    r: Point,
+    e: Scalar,
+}
+
+impl Session {
+    pub fn new(key_agg: &KeyAggContext, aggnonce: &AggNonce, msg: &[u8]) -> Self {
+        let q_bytes = key_agg.x_only_public_key().serialize();
+        let mut data = aggnonce.serialize();
+        data.extend(q_bytes);
+        data.extend(msg);
+        let b = Scalar::from_bytes_reduced(&tagged_hash("MuSig/noncecoef", &data));
+        let (r1, r2) = aggnonce.points();
+        let r = r1 + r2.mul_vartime(&b);
+        // an infinite R can only come from a malicious aggregator, fall back to G
+        let r = if r == Point::infinity() { Point::generator() } else { r };
+        let (rx, _) = r.affine().unwrap();
+        let e = challenge(&rx.to_bytes(), &q_bytes, msg);
+        Session {
+            key_agg: key_agg.clone(),
+            msg: msg.to_vec(),
+            b,
+            r,
+            e,
+        }
+    }
+    pub fn msg(&self) -> &[u8] {
+        &self.msg
+    }
+    fn r_has_even_y(&self) -> bool {
+        self.r.affine().unwrap().1.is_even()
+    }
+    // g * gacc, with g = -1 when Q has an odd y
+    fn key_parity_factor(&self) -> Scalar {
+        let (_, qy) = self.key_agg.aggregated_point().affine().unwrap();
+        let g = if qy.is_odd() { -Scalar::ONE } else { Scalar::ONE };
+        g * self.key_agg.gacc()
+    }
+    /// Sign: consumes the secret nonce so it can never be reused
+    pub fn partial_sign(&self, secnonce: SecNonce, secret: &PrivateKey) -> Result<PartialSignature, Error> {
+        let (k1, k2) = secnonce.scalars();
+        if k1.is_zero() || k2.is_zero() {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid secret nonce"));
+        }
+        let even = self.r_has_even_y();
+        let k1 = Scalar::conditional_select(&-k1, &k1, even);
+        let k2 = Scalar::conditional_select(&-k2, &k2, even);
+        let d_prime = secret.scalar();
+        if d_prime.is_zero() {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid secret key"));
+        }
+        let pk = sec33(&secret.point());
+        if pk != *secnonce.pk() {
+            return Err(Error::new(ErrorKind::InvalidInput, "secret nonce was generated for another key"));
+        }
+        if !self.key_agg.contains(&pk) {
+            return Err(Error::new(ErrorKind::InvalidInput, "signer is not part of the aggregate key"));
+        }
+        let a = self.key_agg.coefficient(&pk);
+        let d = self.key_parity_factor() * d_prime;
+        let s = k1 + self.b * k2 + self.e * a * d;
+        Ok(PartialSignature(s))
+    }
+    /// PartialSigVerify for the signer with the given public nonce and key
+    pub fn partial_sig_verify(&self, psig: &PartialSignature, pubnonce: &PubNonce, pk: &Point) -> bool {
+        let pk_bytes = sec33(pk);
+        if !self.key_agg.contains(&pk_bytes) {
+            return false;
+        }
+        let (r1, r2) = pubnonce.points();
+        let re = r1 + r2.mul_vartime(&self.b);
+        let re = if self.r_has_even_y() { re } else { -re };
+        let a = self.key_agg.coefficient(&pk_bytes);
+        let expected = re + pk.mul_vartime(&(self.e * a * self.key_parity_factor()));
+        Point::mul_generator(&psig.0) == expected
+    }
+    /// PartialSigAgg: the final BIP340 signature for the aggregate x-only key
+    pub fn partial_sig_agg(&self, psigs: &[PartialSignature]) -> SchnorrSignature {
+        let mut s = Scalar::ZERO;
+        for psig in psigs {
+            s = s + psig.0;
+        }
+        let (_, qy) = self.key_agg.aggregated_point().affine().unwrap();
+        let g = if qy.is_odd() { -Scalar::ONE } else { Scalar::ONE };
+        s = s + self.e * g * self.key_agg.tacc();
+        let (rx, _) = self.r.affine().unwrap();
+        SchnorrSignature::new(&rx.to_bytes(), &s.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigUint;
+    use crate::musig2::nonce::{nonce_agg, nonce_gen};
+
+    fn run(secrets: &[u32], tweaks: &[([u8; 32], bool)], msg: &[u8]) {
+        let keys: Vec<PrivateKey> = secrets.iter().map(|s| PrivateKey::new(&BigUint::from(*s))).collect();
+        let points: Vec<Point> = keys.iter().map(|k| k.point()).collect();
+        let mut ctx = KeyAggContext::new(&KeyAggContext::key_sort(&points)).unwrap();
+        for (tweak, is_xonly) in tweaks {
+            ctx.apply_tweak(tweak, *is_xonly).unwrap();
+        }
+        let mut secnonces = Vec::new();
+        let mut pubnonces = Vec::new();
+        for (i, key) in keys.iter().enumerate() {
+            let (sec, public) = nonce_gen(&[i as u8; 32], Some(key), &key.point(), None, Some(msg), None).unwrap();
+            secnonces.push(sec);
+            pubnonces.push(public);
+        }
+        let session = Session::new(&ctx, &nonce_agg(&pubnonces), msg);
+        let mut psigs = Vec::new();
+        for (i, (key, sec)) in keys.iter().zip(secnonces).enumerate() {
+            let psig = session.partial_sign(sec, key).unwrap();
+            assert!(session.partial_sig_verify(&psig, &pubnonces[i], &key.point()));
+            // the share does not verify for someone else
+            assert!(!session.partial_sig_verify(&psig, &pubnonces[(i + 1) % keys.len()], &keys[(i + 1) % keys.len()].point()));
+            psigs.push(psig);
+        }
+        let sig = session.partial_sig_agg(&psigs);
+        assert!(ctx.x_only_public_key().verify_schnorr(msg, &sig));
+        assert!(!ctx.x_only_public_key().verify_schnorr(b"other", &sig));
+    }
+    #[test]
+    fn test_sign_and_aggregate() {
+        run(&[11, 22, 33], &[], b"message");
+        run(&[5, 6], &[], &[]);
+    }
+    #[test]
+    fn test_sign_with_tweaks() {
+        run(&[11, 22, 33], &[([1u8; 32], true)], b"taproot");
+        run(&[7, 8, 9, 10], &[([2u8; 32], false), ([3u8; 32], true), ([4u8; 32], false)], b"tweaked");
+    }
+    #[test]
+    fn test_wrong_signer() {
+        let keys: Vec<PrivateKey> = [1u32, 2].iter().map(|s| PrivateKey::new(&BigUint::from(*s))).collect();
+        let ctx = KeyAggContext::new(&[keys[0].point(), keys[1].point()]).unwrap();
+        let outsider = PrivateKey::new(&BigUint::from(3u32));
+        let (sec, public) = nonce_gen(&[9u8; 32], None, &outsider.point(), None, None, None).unwrap();
+        let session = Session::new(&ctx, &nonce_agg(&[public]), b"m");
+        assert!(session.partial_sign(sec, &outsider).is_err());
+        let (sec, _) = nonce_gen(&[9u8; 32], None, &keys[0].point(), None, None, None).unwrap();
+        assert!(session.partial_sign(sec, &keys[1]).is_err());
+    }
+}
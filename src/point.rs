@@ -1,22 +1,43 @@
-use std::ops::{Add, Mul};
-use num::{BigInt, BigUint, Integer};
+use std::io::{Error, ErrorKind};
+use std::ops::{Add, Mul, Neg};
+use num::{BigInt, BigUint};
 use std::{fmt};
+use crate::fe::Fe;
 use crate::field_element::FieldElement;
+use crate::jacobian_point::{self, JacobianPoint};
+use crate::projective_point::ProjectivePoint;
+use crate::scalar::Scalar;
 use crate::secp256k1;
 use crate::signature::Signature;
+use crate::schnorr_signature::SchnorrSignature;
+use crate::x_only_public_key::XOnlyPublicKey;
 use crate::helpers::hash160::hash160;
 use crate::helpers::base58::base58_encode_checksum;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Point {
-    x: Option<FieldElement>,
-    y: Option<FieldElement>,
-    a: FieldElement,
-    b: FieldElement,
+    coords: Coords,
+}
+
+/// Points on secp256k1 use the fixed-width `Fe` arithmetic, any other curve
+/// (the small teaching curves) goes through the generic `FieldElement`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Coords {
+    Generic {
+        x: Option<FieldElement>,
+        y: Option<FieldElement>,
+        a: FieldElement,
+        b: FieldElement,
+    },
+    Secp256k1(Option<(Fe, Fe)>),
 }
 
 impl Point {
     pub fn new(x: &Option<FieldElement>, y: &Option<FieldElement>, a: &FieldElement, b: &FieldElement) -> Self {
+        let s = secp256k1::Secp256k1::new();
+        if a.num_value() == s.a && b.num_value() == s.b && a.prime() == s.p {
+            return Self::new_secp256k1(x, y);
+        }
         if !x.is_none() && !y.is_none() {
             let x = x.clone().unwrap();
             let y = y.clone().unwrap();
@@ -27,136 +48,268 @@ impl Point {
             }
         }
         Point {
-            x: x.clone(),
-            y: y.clone(),
-            a: a.clone(),
-            b: b.clone()
+            coords: Coords::Generic {
+                x: x.clone(),
+                y: y.clone(),
+                a: a.clone(),
+                b: b.clone(),
+            }
         }
     }
     pub fn new_secp256k1(x: &Option<FieldElement>, y: &Option<FieldElement>) -> Self {
-        let s = secp256k1::Secp256k1::new();
-        let a = FieldElement::new(&s.a, &s.p);
-        let b = FieldElement::new(&s.b, &s.p);
-        if !x.is_none() && !y.is_none() {
-            let x = x.clone().unwrap();
-            let y = y.clone().unwrap();
-            if y.pow(BigInt::from(2u32)) != x.pow(BigInt::from(3u32)) + a.clone() * x + b.clone() {
-                panic!("Point is not on the curve");
+        match (x, y) {
+            (Some(x), Some(y)) => {
+                let x = Fe::from_biguint(&x.num_value());
+                let y = Fe::from_biguint(&y.num_value());
+                Self::from_affine(&x, &y).expect("Point is not on the curve")
             }
+            _ => Self::infinity(),
         }
-        Point {
-            x: x.clone(),
-            y: y.clone(),
-            a: a,
-            b: b
+    }
+    /// secp256k1 point from affine coordinates, None when it is not on the curve
+    pub fn from_affine(x: &Fe, y: &Fe) -> Option<Self> {
+        if y.square() != x.square() * *x + Fe::from_u64(7) {
+            return None;
         }
+        Some(Point { coords: Coords::Secp256k1(Some((*x, *y))) })
+    }
+    /// the point with the given x and an even y, None when x is not on the curve
+    pub fn lift_x(x: &Fe) -> Option<Self> {
+        let y = (x.square() * *x + Fe::from_u64(7)).sqrt()?;
+        let y = Fe::conditional_select(&y, &-y, y.is_odd());
+        Some(Point { coords: Coords::Secp256k1(Some((*x, y))) })
+    }
+    /// the secp256k1 point at infinity
+    pub fn infinity() -> Self {
+        Point { coords: Coords::Secp256k1(None) }
+    }
+    /// the secp256k1 generator G
+    pub fn generator() -> Self {
+        Point { coords: Coords::Secp256k1(Some(jacobian_point::generator_affine())) }
     }
     pub fn verify(&self, z: &BigUint, signature: &Signature) -> bool {
-        let s256 = secp256k1::Secp256k1::new();
-
-        let n = &s256.n;
-        let s = signature.s();
-        let two = &BigUint::from(2u8);
-        let s_inv = s.modpow(&(n - two), &n);
-
+        let Some((r, s)) = signature_scalars(signature) else { return false };
+        self.verify_prepared(z, &r, &s.invert())
+    }
+    /// Verifies every (public key, z, signature) and returns true only if all of them
+    /// are valid. Each signature is still checked on its own, ECDSA has no batch
+    /// verification since a signature only carries the x coordinate of R; the one
+    /// saving over verify in a loop is a single inversion shared by all the s values.
+    pub fn verify_all(items: &[(Point, BigUint, Signature)]) -> bool {
+        let mut rs = Vec::with_capacity(items.len());
+        let mut ss = Vec::with_capacity(items.len());
+        for (_, _, signature) in items {
+            let Some((r, s)) = signature_scalars(signature) else { return false };
+            rs.push(r);
+            ss.push(s);
+        }
+        let s_invs = Scalar::batch_invert(&ss);
+        items.iter().zip(rs.iter().zip(s_invs.iter())).all(|((point, z, _), (r, s_inv))| {
+            point.verify_prepared(z, r, s_inv)
+        })
+    }
+    fn verify_prepared(&self, z: &BigUint, r: &Scalar, s_inv: &Scalar) -> bool {
         // u = z / s
-        let u = z * &s_inv % n;
+        let u = Scalar::from_biguint(z) * *s_inv;
 
         // v = r / s
-        let v = signature.r() * &s_inv % n;
+        let v = *r * *s_inv;
 
         // u*G + v*P should have as the x coordinate, r
-        let s = secp256k1::Secp256k1::new();
-        let g = Point::new_secp256k1(
-            &Some(FieldElement::new(&s.gx, &s.p)),
-            &Some(FieldElement::new(&s.gy, &s.p))
-        );
-        let p = self.clone();
-        let total = g * u + p * v;
-
-        total.x.unwrap().num_value() == signature.r().clone()
+        let Some((px, py)) = self.affine() else { return false };
+        let total = jacobian_point::mul_double_vartime(&u, &JacobianPoint::from_affine(&px, &py), &v);
+        total.x_equals_mod_n(r)
+    }
+    /// Recovers the public key that produced signature on z: R is rebuilt from r and the
+    /// recovery id (x = r + n when bit 1 is set, the parity of y from bit 0) and the
+    /// key is r^-1 * (s*R - z*G).
+    pub fn recover(z: &BigUint, signature: &Signature, recid: u8) -> Result<Self, Error> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+        if recid > 3 {
+            return Err(invalid("recovery id must be between 0 and 3"));
+        }
+        let (r, s) = signature_scalars(signature).ok_or_else(|| invalid("r or s out of range"))?;
+        let mut x = Fe::from_bytes(&r.to_bytes()).unwrap();
+        if recid & 2 != 0 {
+            // r + n must still be a field element
+            let s256 = secp256k1::Secp256k1::new();
+            if signature.r() + &s256.n >= s256.p {
+                return Err(invalid("r + n is not a field element"));
+            }
+            x = x + Fe::from_biguint(&s256.n);
+        }
+        let big_r = Self::lift_x(&x).ok_or_else(|| invalid("R is not on the curve"))?;
+        let big_r = if recid & 1 == 1 { -big_r } else { big_r };
+
+        let r_inv = r.invert();
+        let u1 = -(Scalar::from_biguint(z) * r_inv);
+        let u2 = s * r_inv;
+        let q = Self::mul_double(&u1, &big_r, &u2);
+        if q == Self::infinity() {
+            return Err(invalid("recovered key is infinite"));
+        }
+        Ok(q)
+    }
+    /// BIP340 verification against the x-only key of this point
+    pub fn verify_schnorr(&self, msg: &[u8], signature: &SchnorrSignature) -> bool {
+        let (key, _) = XOnlyPublicKey::from_point(self);
+        key.verify_schnorr(msg, signature)
+    }
+    /// BIP341 output key of this internal key: Q = P + t*G where P is the even y lift of
+    /// this point and t its TapTweak hash. Also returns whether Q has an odd y, which
+    /// goes in the control block of script path spends.
+    pub fn taproot_tweak(&self, merkle_root: Option<[u8; 32]>) -> (XOnlyPublicKey, bool) {
+        let (internal, _) = XOnlyPublicKey::from_point(self);
+        let t = Scalar::from_bytes(&internal.tap_tweak_hash(merkle_root))
+            .expect("taproot tweak is not lower than n");
+        let q = Self::mul_double(&t, &internal.point(), &Scalar::ONE);
+        XOnlyPublicKey::from_point(&q)
+    }
+    /// u * G + v * p computed in a single pass (Strauss), variable time
+    pub fn mul_double(u: &Scalar, p: &Point, v: &Scalar) -> Self {
+        match p.affine() {
+            Some((x, y)) => {
+                let total = jacobian_point::mul_double_vartime(u, &JacobianPoint::from_affine(&x, &y), v);
+                Point { coords: Coords::Secp256k1(total.to_affine()) }
+            }
+            None => Self::mul_generator(u),
+        }
+    }
+    /// k * G from the precomputed generator table, constant time in k
+    pub fn mul_generator(k: &Scalar) -> Self {
+        Self::from_projective(&jacobian_point::mul_generator(k))
+    }
+    /// k * self for a secp256k1 point with wNAF, faster than `mul_scalar` but the
+    /// time depends on k, so use it only when k is public
+    pub fn mul_vartime(&self, k: &Scalar) -> Self {
+        match &self.coords {
+            Coords::Secp256k1(Some((x, y))) => {
+                let p = JacobianPoint::from_affine(x, y).mul_wnaf(k);
+                Point { coords: Coords::Secp256k1(p.to_affine()) }
+            }
+            Coords::Secp256k1(None) => Self::infinity(),
+            Coords::Generic { .. } => panic!("mul_vartime is only defined on secp256k1"),
+        }
+    }
+    /// k * self for a secp256k1 point, constant time in k
+    pub fn mul_scalar(&self, k: &Scalar) -> Self {
+        match &self.coords {
+            Coords::Secp256k1(Some((x, y))) => {
+                let p = ProjectivePoint::from_affine(x, y).mul_ct(k);
+                Self::from_projective(&p)
+            }
+            Coords::Secp256k1(None) => Self::infinity(),
+            Coords::Generic { .. } => panic!("mul_scalar is only defined on secp256k1"),
+        }
+    }
+    fn from_projective(p: &ProjectivePoint) -> Self {
+        Point { coords: Coords::Secp256k1(p.to_affine()) }
     }
     fn is_inf(&self) -> bool {
-        self.x.is_none() && self.y.is_none()
+        match &self.coords {
+            Coords::Generic { x, y, .. } => x.is_none() && y.is_none(),
+            Coords::Secp256k1(xy) => xy.is_none(),
+        }
+    }
+    /// affine secp256k1 coordinates, None for the point at infinity
+    pub fn affine(&self) -> Option<(Fe, Fe)> {
+        match &self.coords {
+            Coords::Secp256k1(xy) => *xy,
+            Coords::Generic { .. } => panic!("affine is only defined on secp256k1"),
+        }
     }
     pub fn x(&self) -> Option<FieldElement> {
-        self.x.clone()
+        match &self.coords {
+            Coords::Generic { x, .. } => x.clone(),
+            Coords::Secp256k1(xy) => {
+                let p = secp256k1::Secp256k1::new().p;
+                xy.map(|(x, _)| FieldElement::new(&x.to_biguint(), &p))
+            }
+        }
+    }
+    pub fn y(&self) -> Option<FieldElement> {
+        match &self.coords {
+            Coords::Generic { y, .. } => y.clone(),
+            Coords::Secp256k1(xy) => {
+                let p = secp256k1::Secp256k1::new().p;
+                xy.map(|(_, y)| FieldElement::new(&y.to_biguint(), &p))
+            }
+        }
     }
     pub fn sec(&self, compressed: bool) -> Vec<u8> {
+        let (x, y) = self.affine().expect("point at infinity has no SEC encoding");
         let mut sec : Vec<u8> = Vec::new();
         if compressed {
-            if self.y.clone().unwrap().num_value() % BigUint::from(2u32) == BigUint::from(0u32) {
+            if y.is_even() {
                 sec.push(0x02);
-                sec.extend(self.x.clone().unwrap().num_value().to_bytes_be());
-                sec
             } else {
                 sec.push(0x03);
-                sec.extend(self.x.clone().unwrap().num_value().to_bytes_be());
-                sec
             }
+            sec.extend(x.to_bytes());
+            sec
         } else {
             sec.push(0x04);
-            sec.extend(self.x.clone().unwrap().num_value().to_bytes_be());
-            sec.extend(self.y.clone().unwrap().num_value().to_bytes_be());
+            sec.extend(x.to_bytes());
+            sec.extend(y.to_bytes());
             sec
         }
     }
     pub fn address(&self, compressed: bool, testnet: bool) -> Vec<u8> {
         let sec = self.sec(compressed);
 
-        let h160 = hash160(&sec.as_slice());
+        let h160 = hash160(sec.as_slice());
         let prefix = if testnet { b"\x6f" } else { b"\x00" };
 
         let mut address = prefix.to_vec();
         address.extend(h160);
-        let to_retrun = base58_encode_checksum(address);
-        to_retrun
+        base58_encode_checksum(address)
     }
     pub fn parse(data: &[u8]) -> Self {
-        let s256 = secp256k1::Secp256k1::new();
-        if data[0] == 0x04 { // uncompressed
-            let x = BigUint::from_bytes_be(&data[1..33]);
-            let y = BigUint::from_bytes_be(&data[33..65]);
-
-            return Self::new_secp256k1(
-                &Some(FieldElement::new(&x, &s256.p)),
-                &Some(FieldElement::new(&y, &s256.p)),
-            );
+        Self::try_parse(data).expect("Invalid SEC public key")
+    }
+    /// SEC parsing that reports malformed keys instead of panicking
+    pub fn try_parse(data: &[u8]) -> Result<Self, Error> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+        match (data.first(), data.len()) {
+            (Some(0x04), 65) => { // uncompressed
+                let x = Fe::from_bytes(data[1..33].try_into().unwrap()).ok_or_else(|| invalid("x not in field"))?;
+                let y = Fe::from_bytes(data[33..65].try_into().unwrap()).ok_or_else(|| invalid("y not in field"))?;
+                Self::from_affine(&x, &y).ok_or_else(|| invalid("Point is not on the curve"))
+            }
+            (Some(prefix @ (0x02 | 0x03)), 33) => {
+                let is_even = *prefix == 0x02;
+                let x = Fe::from_bytes(data[1..33].try_into().unwrap()).ok_or_else(|| invalid("x not in field"))?;
+
+                // even y solving y^2 = x^3 + 7
+                let even = Self::lift_x(&x).ok_or_else(|| invalid("Point is not on the curve"))?;
+                if is_even {
+                    Ok(even)
+                } else {
+                    Ok(-even)
+                }
+            }
+            _ => Err(invalid("Invalid SEC encoding")),
         }
+    }
+}
 
-        let is_even = data[0] == 0x02;
-        let x = BigUint::from_bytes_be(&data[1..]);
-        let x = FieldElement::new(&x, &s256.p);
-
-        // right side of the equation y^2 = x^3 + 7
-        let alpha = x.pow(BigInt::from(3u32)) + FieldElement::new(&s256.b, &s256.p);
-
-        // solve for left side
-        let beta = alpha.sqrt();
-
-        let even_beta = if beta.num_value().is_even() {
-            beta.clone()
-        } else {
-            FieldElement::new(&(&s256.p - beta.clone().num_value()), &s256.p)
-        };
-
-        let odd_beta = if beta.num_value().is_even() {
-            FieldElement::new(&(&s256.p - beta.clone().num_value()), &s256.p)
-        } else {
-            beta.clone()
-        };
-
-        if is_even {
-            Self::new_secp256k1(&Some(x), &Some(even_beta))
-        } else {
-            Self::new_secp256k1(&Some(x), &Some(odd_beta))
+/// r and s as scalars, None unless both are in [1, n - 1]
+fn signature_scalars(signature: &Signature) -> Option<(Scalar, Scalar)> {
+    let to_scalar = |v: &BigUint| {
+        let be = v.to_bytes_be();
+        if be.len() > 32 {
+            return None;
         }
-    }
+        let mut bytes = [0u8; 32];
+        bytes[32 - be.len()..].copy_from_slice(&be);
+        Scalar::from_bytes(&bytes).filter(|s| !s.is_zero())
+    };
+    Some((to_scalar(signature.r())?, to_scalar(signature.s())?))
 }
 
 impl fmt::Display for Point {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match (self.x.as_ref(), self.y.as_ref()) {
+        match (self.x(), self.y()) {
             (Some(x), Some(y)) => write!(f, "({:x}, {:x})", x.num_value(), y.num_value()),
             (Some(x), None) => write!(f, "({:x}, ∞)", x.num_value()),
             (None, Some(y)) => write!(f, "(∞, {:x})", y.num_value()),
@@ -165,10 +318,42 @@ impl fmt::Display for Point {
     }
 }
 
+impl Neg for Point {
+    type Output = Self;
+    fn neg(self) -> Self {
+        match self.coords {
+            Coords::Secp256k1(xy) => Point { coords: Coords::Secp256k1(xy.map(|(x, y)| (x, -y))) },
+            Coords::Generic { .. } => panic!("neg is only defined on secp256k1"),
+        }
+    }
+}
+
 impl Add for Point {
     type Output = Self;
     fn add(self, other: Self) -> Self {
-        if self.a != other.a || self.b != other.b {
+        match (&self.coords, &other.coords) {
+            (Coords::Secp256k1(p1), Coords::Secp256k1(p2)) => {
+                match (p1, p2) {
+                    (None, _) => other.clone(),
+                    (_, None) => self.clone(),
+                    (Some((x1, y1)), Some((x2, y2))) => {
+                        let sum = ProjectivePoint::from_affine(x1, y1).add(&ProjectivePoint::from_affine(x2, y2));
+                        Self::from_projective(&sum)
+                    }
+                }
+            }
+            (Coords::Generic { .. }, Coords::Generic { .. }) => self.add_generic(other),
+            _ => panic!("Points are not on the same curve"),
+        }
+    }
+}
+
+impl Point {
+    fn add_generic(self, other: Self) -> Self {
+        let (Coords::Generic { x: x1, y: y1, a, b }, Coords::Generic { x: x2, y: y2, a: a2, b: b2 }) = (&self.coords, &other.coords) else {
+            panic!("Points are not on the same curve");
+        };
+        if a != a2 || b != b2 {
             panic!("Points are not on the same curve");
         }
         if self.is_inf() {
@@ -177,40 +362,40 @@ impl Add for Point {
         if other.is_inf() {
             return self.clone();
         }
-        if self.x == other.x && self.y != other.y {
+        if x1 == x2 && y1 != y2 {
             Point::new(
                 &None,
                 &None,
-                &self.a.clone(),
-                &self.b.clone(),
+                &a.clone(),
+                &b.clone(),
             )
-        } else if self.x != other.x {
-            let s = (other.y.clone().unwrap() - self.y.clone().unwrap())/(other.x.clone().unwrap() - self.x.clone().unwrap());
-            let x = s.pow(BigInt::from(2u32)).clone() - self.x.clone().unwrap() - other.x.clone().unwrap();
-            let y = s * (self.x.clone().unwrap() - x.clone()) - self.y.clone().unwrap();
+        } else if x1 != x2 {
+            let s = (y2.clone().unwrap() - y1.clone().unwrap())/(x2.clone().unwrap() - x1.clone().unwrap());
+            let x = s.pow(BigInt::from(2u32)).clone() - x1.clone().unwrap() - x2.clone().unwrap();
+            let y = s * (x1.clone().unwrap() - x.clone()) - y1.clone().unwrap();
             Point::new(
                 &Some(x.clone()),
                 &Some(y.clone()),
-                &self.a.clone(),
-                &self.b.clone(),
+                &a.clone(),
+                &b.clone(),
             )
         } else if self == other {
-            if (self.y.clone().unwrap()).num_value() == BigUint::from(0u32) {
+            if (y1.clone().unwrap()).num_value() == BigUint::from(0u32) {
                 Point::new(
                     &None,
                     &None,
-                    &self.a.clone(),
-                    &self.b.clone(),
+                    &a.clone(),
+                    &b.clone(),
                 )
             } else {
-                let s = ((self.x.clone().unwrap().pow(BigInt::from(2u32))*BigUint::from(3u32)) + self.a.clone())/ (self.y.clone().unwrap()*BigUint::from(2u32));
-                let x = (s.clone() * s.clone()) - self.x.clone().unwrap() * BigUint::from(2u32);
-                let y = s * (self.x.clone().unwrap() - x.clone()) - self.y.clone().unwrap();
+                let s = ((x1.clone().unwrap().pow(BigInt::from(2u32))*BigUint::from(3u32)) + a.clone())/ (y1.clone().unwrap()*BigUint::from(2u32));
+                let x = (s.clone() * s.clone()) - x1.clone().unwrap() * BigUint::from(2u32);
+                let y = s * (x1.clone().unwrap() - x.clone()) - y1.clone().unwrap();
                 Point::new(
                     &Some(x),
                     &Some(y),
-                    &self.a.clone(),
-                    &self.b.clone(),
+                    &a.clone(),
+                    &b.clone(),
                 )
             }
         } else {
@@ -223,14 +408,24 @@ impl Mul<BigUint> for Point {
     type Output = Self;
 
     fn mul(self, coefficient: BigUint) -> Self {
+        if let Coords::Secp256k1(_) = self.coords {
+            // every point on secp256k1 has order n. The coefficient may be secret, so
+            // this stays constant time; callers with a public k use mul_vartime
+            let k = Scalar::from_biguint(&coefficient);
+            if self == Self::generator() {
+                return Self::mul_generator(&k);
+            }
+            return self.mul_scalar(&k);
+        }
+        let Coords::Generic { a, b, .. } = &self.coords else { unreachable!() };
         let mut coef = coefficient;
         let mut current = self.clone();
         // We start the result at 0, or the point at infinity.
         let mut result = Point::new(
             &None,
             &None,
-            &self.a.clone(),
-            &self.b.clone(),
+            &a.clone(),
+            &b.clone(),
         );
 
         while coef > BigUint::from(0u32) {
@@ -248,6 +443,8 @@ impl Mul<BigUint> for Point {
 mod tests {
     use num::BigUint;
     use num::Num;
+    use crate::helpers::hash256::hash256;
+    use crate::private_key::PrivateKey;
     use super::*;
     #[test]
     fn test_point1_on() {
@@ -610,4 +807,100 @@ mod tests {
             assert_eq!(point.address(compressed, testnet), address.as_bytes().to_vec());
         }
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_secp256k1_mul() {
+        let s256 = secp256k1::Secp256k1::new();
+        let generator = Point::generator();
+        let two_g = generator.clone() * BigUint::from(2u32);
+        assert_eq!(two_g, generator.clone() + generator.clone());
+        assert_eq!(
+            two_g.x().unwrap().num_value(),
+            BigUint::from_str_radix("c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5", 16).unwrap()
+        );
+        assert_eq!(generator.clone() * s256.n.clone(), Point::infinity());
+        let minus_g = generator.clone() * (&s256.n - BigUint::from(1u32));
+        assert_eq!(minus_g.x(), generator.x());
+        assert_eq!(minus_g + generator, Point::infinity());
+    }
+    #[test]
+    fn test_mul_double() {
+        let p = Point::generator() * BigUint::from(999u32);
+        let u = Scalar::from_u64(12);
+        let v = Scalar::from_u64(34);
+        assert_eq!(Point::mul_double(&u, &p, &v), Point::mul_generator(&u) + p.mul_scalar(&v));
+        assert_eq!(Point::mul_double(&u, &Point::infinity(), &v), Point::mul_generator(&u));
+    }
+    #[test]
+    fn test_verify_all() {
+        let mut items = Vec::new();
+        for i in 1..5u32 {
+            let key = PrivateKey::new(&BigUint::from(1000u32 + i));
+            let z = BigUint::from_bytes_be(&hash256(&i.to_be_bytes()));
+            let sig = key.sign(&z);
+            items.push((key.point(), z, sig));
+        }
+        assert!(Point::verify_all(&items));
+        assert!(Point::verify_all(&[]));
+        items[2].1 += BigUint::from(1u32);
+        assert!(!Point::verify_all(&items));
+    }
+    #[test]
+    fn test_recover_x_above_n() {
+        // R with n <= x < p, only reachable with recid 2 and 3
+        let n = secp256k1::Secp256k1::new().n;
+        let big_r = (1u32..)
+            .map(|i| &n + BigUint::from(i))
+            .find_map(|x| Point::lift_x(&Fe::from_biguint(&x)))
+            .unwrap();
+        let (x, _) = big_r.affine().unwrap();
+        let r = x.to_biguint() - &n;
+        let z = BigUint::from_bytes_be(&hash256(b"overflow"));
+        let sig = Signature::new(&r, &BigUint::from(12345u32));
+        for recid in [2u8, 3] {
+            let key = Point::recover(&z, &sig, recid).unwrap();
+            assert!(key.verify(&z, &sig));
+        }
+        // recid 0 and 1 rebuild a different R from the same r
+        for recid in [0u8, 1] {
+            if let Ok(key) = Point::recover(&z, &sig, recid) {
+                assert!(key.verify(&z, &sig));
+                assert_ne!(Some(key.clone()), Point::recover(&z, &sig, recid + 2).ok());
+            }
+        }
+        // r + n above p cannot be an x coordinate
+        let sig = Signature::new(&(&n - BigUint::from(1u32)), &BigUint::from(1u32));
+        assert!(Point::recover(&z, &sig, 2).is_err());
+    }
+    #[test]
+    fn test_verify_out_of_range() {
+        let s256 = secp256k1::Secp256k1::new();
+        let point = Point::generator();
+        let z = BigUint::from(1u32);
+        assert!(!point.verify(&z, &Signature::new(&BigUint::from(0u32), &BigUint::from(1u32))));
+        assert!(!point.verify(&z, &Signature::new(&BigUint::from(1u32), &s256.n)));
+    }
+    fn hex_32(s: &str) -> [u8; 32] {
+        hex::decode(s).unwrap().try_into().unwrap()
+    }
+    #[test]
+    fn test_taproot_tweak() {
+        // BIP341 wallet test vectors, scriptPubKey section
+        let internal = Point::lift_x(&Fe::from_bytes(&hex_32("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d")).unwrap()).unwrap();
+        let (output, _) = internal.taproot_tweak(None);
+        assert_eq!(output.to_string(), "53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343");
+        let address = crate::helpers::bech32::bech32_segwit_encode(output.serialize().to_vec(), crate::helpers::bech32::SegwitVersion::version_1);
+        assert_eq!(address, "bc1p2wsldez5mud2yam29q22wgfh9439spgduvct83k3pm50fcxa5dps59h4z5");
+
+        let internal = Point::lift_x(&Fe::from_bytes(&hex_32("187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27")).unwrap()).unwrap();
+        let script = hex::decode("20d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8ac").unwrap();
+        let mut leaf = vec![0xc0, script.len() as u8];
+        leaf.extend(script);
+        let merkle_root = crate::helpers::tagged_hash::tagged_hash("TapLeaf", &leaf);
+        assert_eq!(hex::encode(merkle_root), "5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21");
+        let (output, _) = internal.taproot_tweak(Some(merkle_root));
+        assert_eq!(output.to_string(), "147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3");
+
+        // the odd y lift of the internal key gives the same output
+        assert_eq!((-internal.clone()).taproot_tweak(Some(merkle_root)), internal.taproot_tweak(Some(merkle_root)));
+    }
+}
@@ -0,0 +1,412 @@
+use std::fmt;
+use crate::execution_context::{ExecutionContext, SigVersion};
+use crate::helpers::op_codes::OP_RETURN;
+use crate::script::{Script, MAX_SCRIPT_SIZE};
+use crate::script_type::ScriptType;
+use crate::taproot::{TAPROOT_ANNEX_TAG, TAPROOT_LEAF_TAPSCRIPT};
+use crate::tx::Tx;
+use crate::tx_output::TxOutput;
+
+/// Bitcoin Core's default relay policy, the limits a transaction has to stay within for
+/// nodes to relay it. None of them are consensus rules.
+pub const TX_MAX_STANDARD_VERSION: u32 = 3;
+pub const MAX_STANDARD_TX_WEIGHT: usize = 400_000;
+/// enough for a P2SH 15 of 15 multisig with compressed keys
+pub const MAX_STANDARD_SCRIPTSIG_SIZE: usize = 1650;
+/// the whole OP_RETURN scriptPubKey, 80 bytes of data and the opcodes pushing it
+pub const MAX_OP_RETURN_RELAY: usize = 83;
+/// at most 3 keys in a bare multisig output
+pub const MAX_STANDARD_MULTISIG_KEYS: usize = 3;
+pub const MAX_P2SH_SIGOPS: usize = 15;
+pub const MAX_STANDARD_P2WSH_SCRIPT_SIZE: usize = 3600;
+pub const MAX_STANDARD_P2WSH_STACK_ITEMS: usize = 100;
+pub const MAX_STANDARD_P2WSH_STACK_ITEM_SIZE: usize = 80;
+pub const MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE: usize = 80;
+/// fee rate in satoshis per 1000 virtual bytes below which spending an output costs
+/// more than it is worth
+pub const DUST_RELAY_TX_FEE: u64 = 3000;
+
+/// Why a transaction is not standard. reason() is the reject reason Bitcoin Core gives,
+/// the input or output index tells which part of the transaction broke the rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyError {
+    /// version 0, or above TX_MAX_STANDARD_VERSION
+    Version(u32),
+    /// weight above MAX_STANDARD_TX_WEIGHT
+    TxSize(usize),
+    ScriptSigSize { input: usize },
+    ScriptSigNotPushOnly { input: usize },
+    /// an output matching no standard template
+    ScriptPubKey { output: usize },
+    /// a bare multisig output with more than MAX_STANDARD_MULTISIG_KEYS keys
+    MultisigKeys { output: usize },
+    /// an OP_RETURN output longer than MAX_OP_RETURN_RELAY
+    OpReturnSize { output: usize },
+    /// more than one OP_RETURN output
+    MultiOpReturn,
+    /// an output worth less than spending it costs at DUST_RELAY_TX_FEE
+    Dust { output: usize },
+    /// an input spending a non standard output, or an output of an unknown witness version
+    NonStandardInput { input: usize },
+    /// an input whose P2SH redeem script has more than MAX_P2SH_SIGOPS signature operations
+    P2shSigOps { input: usize },
+    /// a witness on an input spending an output that is not a witness program, or a P2A
+    UnexpectedWitness { input: usize },
+    WitnessScriptSize { input: usize },
+    WitnessStackItems { input: usize },
+    WitnessItemSize { input: usize },
+    TaprootAnnex { input: usize },
+    /// a taproot script path spend with an empty control block
+    TaprootControlBlock { input: usize },
+}
+impl PolicyError {
+    /// Bitcoin Core's reject reason
+    pub fn reason(&self) -> &'static str {
+        match self {
+            PolicyError::Version(_) => "version",
+            PolicyError::TxSize(_) => "tx-size",
+            PolicyError::ScriptSigSize { .. } => "scriptsig-size",
+            PolicyError::ScriptSigNotPushOnly { .. } => "scriptsig-not-pushonly",
+            PolicyError::ScriptPubKey { .. } | PolicyError::MultisigKeys { .. } | PolicyError::OpReturnSize { .. } => "scriptpubkey",
+            PolicyError::MultiOpReturn => "multi-op-return",
+            PolicyError::Dust { .. } => "dust",
+            PolicyError::NonStandardInput { .. } | PolicyError::P2shSigOps { .. } => "bad-txns-nonstandard-inputs",
+            PolicyError::UnexpectedWitness { .. }
+            | PolicyError::WitnessScriptSize { .. }
+            | PolicyError::WitnessStackItems { .. }
+            | PolicyError::WitnessItemSize { .. }
+            | PolicyError::TaprootAnnex { .. }
+            | PolicyError::TaprootControlBlock { .. } => "bad-witness-nonstandard",
+        }
+    }
+}
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = self.reason();
+        match self {
+            PolicyError::Version(version) => write!(f, "{}: version {} is not 1 to {}", reason, version, TX_MAX_STANDARD_VERSION),
+            PolicyError::TxSize(weight) => write!(f, "{}: weight {} above {}", reason, weight, MAX_STANDARD_TX_WEIGHT),
+            PolicyError::ScriptSigSize { input } => write!(f, "{}: input {} scriptSig longer than {} bytes", reason, input, MAX_STANDARD_SCRIPTSIG_SIZE),
+            PolicyError::ScriptSigNotPushOnly { input } => write!(f, "{}: input {} scriptSig runs opcodes other than pushes", reason, input),
+            PolicyError::ScriptPubKey { output } => write!(f, "{}: output {} matches no standard template", reason, output),
+            PolicyError::MultisigKeys { output } => write!(f, "{}: output {} is a bare multisig of more than {} keys", reason, output, MAX_STANDARD_MULTISIG_KEYS),
+            PolicyError::OpReturnSize { output } => write!(f, "{}: output {} OP_RETURN longer than {} bytes", reason, output, MAX_OP_RETURN_RELAY),
+            PolicyError::MultiOpReturn => write!(f, "{}: more than one OP_RETURN output", reason),
+            PolicyError::Dust { output } => write!(f, "{}: output {} is dust", reason, output),
+            PolicyError::NonStandardInput { input } => write!(f, "{}: input {} spends a non standard output", reason, input),
+            PolicyError::P2shSigOps { input } => write!(f, "{}: input {} redeem script has more than {} sigops", reason, input, MAX_P2SH_SIGOPS),
+            PolicyError::UnexpectedWitness { input } => write!(f, "{}: input {} has a witness it does not need", reason, input),
+            PolicyError::WitnessScriptSize { input } => write!(f, "{}: input {} witness script longer than {} bytes", reason, input, MAX_STANDARD_P2WSH_SCRIPT_SIZE),
+            PolicyError::WitnessStackItems { input } => write!(f, "{}: input {} witness has more than {} items", reason, input, MAX_STANDARD_P2WSH_STACK_ITEMS),
+            PolicyError::WitnessItemSize { input } => write!(f, "{}: input {} witness item longer than 80 bytes", reason, input),
+            PolicyError::TaprootAnnex { input } => write!(f, "{}: input {} witness has an annex", reason, input),
+            PolicyError::TaprootControlBlock { input } => write!(f, "{}: input {} witness has an empty control block", reason, input),
+        }
+    }
+}
+impl std::error::Error for PolicyError {}
+
+/// Every relay policy check: is_standard_tx, then are_inputs_standard and
+/// is_witness_standard, which need the spent outputs
+pub fn check_standard(tx: &Tx) -> Result<(), PolicyError> {
+    is_standard_tx(tx)?;
+    are_inputs_standard(tx)?;
+    is_witness_standard(tx)
+}
+
+/// Bitcoin Core's IsStandardTx: the version, the weight, the scriptSigs and the outputs
+pub fn is_standard_tx(tx: &Tx) -> Result<(), PolicyError> {
+    if tx.version() < 1 || tx.version() > TX_MAX_STANDARD_VERSION {
+        return Err(PolicyError::Version(tx.version()));
+    }
+    let weight = tx.weight();
+    if weight > MAX_STANDARD_TX_WEIGHT {
+        return Err(PolicyError::TxSize(weight));
+    }
+    for (input, tx_in) in tx.tx_ins().iter().enumerate() {
+        if tx_in.script_sig.serialize_raw().len() > MAX_STANDARD_SCRIPTSIG_SIZE {
+            return Err(PolicyError::ScriptSigSize { input });
+        }
+        if !tx_in.script_sig.is_push_only() {
+            return Err(PolicyError::ScriptSigNotPushOnly { input });
+        }
+    }
+    let mut op_returns = 0;
+    for (output, tx_out) in tx.tx_outs().iter().enumerate() {
+        let script_pubkey = tx_out.script_pubkey();
+        match script_pubkey.script_type() {
+            ScriptType::NonStandard => return Err(PolicyError::ScriptPubKey { output }),
+            ScriptType::Multisig { pubkeys, .. } if pubkeys.len() > MAX_STANDARD_MULTISIG_KEYS => {
+                return Err(PolicyError::MultisigKeys { output });
+            }
+            ScriptType::NullData { .. } => {
+                if script_pubkey.serialize_raw().len() > MAX_OP_RETURN_RELAY {
+                    return Err(PolicyError::OpReturnSize { output });
+                }
+                op_returns += 1;
+            }
+            _ => {}
+        }
+        if is_dust(tx_out, DUST_RELAY_TX_FEE) {
+            return Err(PolicyError::Dust { output });
+        }
+    }
+    if op_returns > 1 {
+        return Err(PolicyError::MultiOpReturn);
+    }
+    Ok(())
+}
+
+/// Bitcoin Core's AreInputsStandard: every input spends a standard output of a known
+/// type, and P2SH redeem scripts stay within MAX_P2SH_SIGOPS
+pub fn are_inputs_standard(tx: &Tx) -> Result<(), PolicyError> {
+    if tx.is_coinbase() {
+        return Ok(());
+    }
+    for (input, tx_in) in tx.tx_ins().iter().enumerate() {
+        match tx_in.script_pubkey(tx.testnet()).script_type() {
+            ScriptType::NonStandard | ScriptType::WitnessUnknown { .. } => return Err(PolicyError::NonStandardInput { input }),
+            ScriptType::P2sh { .. } => {
+                let Some(redeem_script) = redeem_script(tx, input) else {
+                    return Err(PolicyError::NonStandardInput { input });
+                };
+                if redeem_script.sig_op_count(true) > MAX_P2SH_SIGOPS {
+                    return Err(PolicyError::P2shSigOps { input });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Bitcoin Core's IsWitnessStandard: witnesses only where a witness program is spent, P2WSH
+/// scripts and stacks within their limits, no annex and short tapscript stack items
+pub fn is_witness_standard(tx: &Tx) -> Result<(), PolicyError> {
+    if tx.is_coinbase() {
+        return Ok(());
+    }
+    for (input, tx_in) in tx.tx_ins().iter().enumerate() {
+        let witness = match &tx_in.witness {
+            Some(witness) if !witness.is_empty() => witness,
+            _ => continue,
+        };
+        let mut prev_script = tx_in.script_pubkey(tx.testnet());
+        if prev_script.script_type() == ScriptType::P2a {
+            return Err(PolicyError::UnexpectedWitness { input });
+        }
+        let is_p2sh = prev_script.is_p2sh_script_pubkey();
+        if is_p2sh {
+            prev_script = redeem_script(tx, input).ok_or(PolicyError::UnexpectedWitness { input })?;
+        }
+        let Some((version, program)) = prev_script.witness_program() else {
+            return Err(PolicyError::UnexpectedWitness { input });
+        };
+        let (last, items) = witness.split_last().unwrap();
+        match (version, program.len()) {
+            (0, 32) => {
+                if last.len() > MAX_STANDARD_P2WSH_SCRIPT_SIZE {
+                    return Err(PolicyError::WitnessScriptSize { input });
+                }
+                if items.len() > MAX_STANDARD_P2WSH_STACK_ITEMS {
+                    return Err(PolicyError::WitnessStackItems { input });
+                }
+                if items.iter().any(|item| item.len() > MAX_STANDARD_P2WSH_STACK_ITEM_SIZE) {
+                    return Err(PolicyError::WitnessItemSize { input });
+                }
+            }
+            (1, 32) if !is_p2sh => {
+                if witness.len() >= 2 && last.first() == Some(&TAPROOT_ANNEX_TAG) {
+                    return Err(PolicyError::TaprootAnnex { input });
+                }
+                // a script path spend: the inputs, the script and the control block
+                if witness.len() >= 2 && last.is_empty() {
+                    return Err(PolicyError::TaprootControlBlock { input });
+                }
+                let is_tapscript = witness.len() >= 2 && last.first().map(|b| b & 0xfe) == Some(TAPROOT_LEAF_TAPSCRIPT);
+                if is_tapscript && witness[..witness.len() - 2].iter().any(|item| item.len() > MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE) {
+                    return Err(PolicyError::WitnessItemSize { input });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Bitcoin Core's GetDustThreshold: the fee at dust_relay_fee sat/kvB to create the output
+/// and later spend it with a typical input, 0 for an output nothing can spend
+pub fn dust_threshold(output: &TxOutput, dust_relay_fee: u64) -> u64 {
+    let script_pubkey = output.script_pubkey();
+    let raw = script_pubkey.serialize_raw();
+    if raw.len() > MAX_SCRIPT_SIZE || raw.first() == Some(&OP_RETURN) {
+        return 0;
+    }
+    // outpoint, scriptSig length, sequence and the scriptSig or witness of a typical spend:
+    // 107 bytes, discounted to 26 virtual bytes in a witness
+    let spend_size = match script_pubkey.witness_program() {
+        Some(_) => 32 + 4 + 1 + 107 / 4 + 4,
+        None => 32 + 4 + 1 + 107 + 4,
+    };
+    let size = output.serialize().len() + spend_size;
+    dust_relay_fee * size as u64 / 1000
+}
+
+/// true when output is worth less than dust_threshold
+pub fn is_dust(output: &TxOutput, dust_relay_fee: u64) -> bool {
+    output.amount() < dust_threshold(output, dust_relay_fee)
+}
+
+// the script the last scriptSig push of input holds, what a P2SH output runs
+fn redeem_script(tx: &Tx, input: usize) -> Option<Script> {
+    let context = ExecutionContext::new(tx, input, 0);
+    let mut stack = vec![];
+    tx.tx_ins()[input].script_sig.execute(&mut stack, &context, SigVersion::Base).ok()?;
+    stack.pop().map(Script::from_raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+    use crate::helpers::hash160::hash160;
+    use crate::helpers::op_codes::{OP_1, OP_CHECKSIG, OP_NOP};
+    use crate::tx_input::TxInput;
+
+    fn p2pkh() -> Script {
+        Script::p2pkh_script(vec![0x11; 20])
+    }
+    fn tx_with(version: u32, tx_in: TxInput, outputs: Vec<TxOutput>) -> Tx {
+        let segwit = tx_in.witness.is_some();
+        Tx::new(version, vec![tx_in], outputs, 0, false, segwit)
+    }
+    // spends an output of prev_script with script_sig and witness, paying 10,000 to a P2PKH
+    fn spend(prev_script: Script, script_sig: Script, witness: Option<Vec<Vec<u8>>>) -> Tx {
+        let mut tx_in = TxInput::new(vec![0x22; 32], 0, script_sig, 0xffffffff);
+        tx_in.set_prev_output(TxOutput::new(50_000, prev_script));
+        tx_in.witness = witness;
+        tx_with(2, tx_in, vec![TxOutput::new(10_000, p2pkh())])
+    }
+    fn paying(outputs: Vec<TxOutput>) -> Tx {
+        let mut tx_in = TxInput::new(vec![0x22; 32], 0, Script::new(vec![]), 0xffffffff);
+        tx_in.set_prev_output(TxOutput::new(50_000, p2pkh()));
+        tx_with(2, tx_in, outputs)
+    }
+
+    #[test]
+    fn test_dust_threshold() {
+        let output = |script_pubkey: &str| TxOutput::new(0, Script::from_raw(hex::decode(script_pubkey).unwrap()));
+        let p2wpkh = format!("0014{}", "11".repeat(20));
+        let p2tr = format!("5120{}", "11".repeat(32));
+        let p2sh = format!("a914{}87", "11".repeat(20));
+        for (script_pubkey, want) in [(hex::encode(p2pkh().serialize_raw()), 546), (p2sh, 540), (p2wpkh, 294), (p2tr, 330), ("51024e73".to_string(), 240), ("6a".to_string(), 0)] {
+            assert_eq!(dust_threshold(&output(&script_pubkey), DUST_RELAY_TX_FEE), want, "{}", script_pubkey);
+        }
+        assert!(is_dust(&TxOutput::new(545, p2pkh()), DUST_RELAY_TX_FEE));
+        assert!(!is_dust(&TxOutput::new(546, p2pkh()), DUST_RELAY_TX_FEE));
+    }
+
+    #[test]
+    fn test_is_standard_tx() {
+        let tx = spend(p2pkh(), Script::new(vec![vec![0x30; 72], vec![0x02; 33]]), None);
+        assert_eq!(check_standard(&tx), Ok(()));
+        for version in [0, 4] {
+            let mut tx_in = TxInput::new(vec![0x22; 32], 0, Script::new(vec![]), 0xffffffff);
+            tx_in.set_prev_output(TxOutput::new(50_000, p2pkh()));
+            let tx = tx_with(version, tx_in, vec![TxOutput::new(10_000, p2pkh())]);
+            assert_eq!(is_standard_tx(&tx), Err(PolicyError::Version(version)));
+        }
+        // witness bytes count once
+        let tx = spend(Script::new(vec![vec![OP_1], vec![0x11; 32]]), Script::new(vec![]), Some(vec![vec![0; 399_000]]));
+        assert!(is_standard_tx(&tx).is_ok());
+        let tx = spend(Script::new(vec![vec![OP_1], vec![0x11; 32]]), Script::new(vec![]), Some(vec![vec![0; 400_000]]));
+        assert!(matches!(is_standard_tx(&tx), Err(PolicyError::TxSize(_))));
+
+        let push = [vec![0x4d, 0x08, 0x02], vec![1; 520]].concat();
+        let tx = spend(p2pkh(), Script::from_raw(push.repeat(3)), None);
+        assert!(is_standard_tx(&tx).is_ok());
+        let tx = spend(p2pkh(), Script::from_raw(push.repeat(4)), None);
+        assert_eq!(is_standard_tx(&tx), Err(PolicyError::ScriptSigSize { input: 0 }));
+        let tx = spend(p2pkh(), Script::new(vec![vec![1; 20], vec![OP_NOP]]), None);
+        assert_eq!(is_standard_tx(&tx), Err(PolicyError::ScriptSigNotPushOnly { input: 0 }));
+        assert_eq!(is_standard_tx(&tx).unwrap_err().reason(), "scriptsig-not-pushonly");
+    }
+
+    #[test]
+    fn test_standard_outputs() {
+        let keys: Vec<Vec<u8>> = (0..4u8).map(|i| [vec![0x02], vec![i; 32]].concat()).collect();
+        let op_return = |len: usize| Script::new(vec![vec![OP_RETURN], vec![0xaa; len]]);
+        let tx = paying(vec![TxOutput::new(1_000, Script::multisig_script(1, &keys[..3]).unwrap()), TxOutput::new(0, op_return(80))]);
+        assert_eq!(is_standard_tx(&tx), Ok(()));
+        let tx = paying(vec![TxOutput::new(1_000, Script::multisig_script(1, &keys).unwrap())]);
+        assert_eq!(is_standard_tx(&tx), Err(PolicyError::MultisigKeys { output: 0 }));
+        let tx = paying(vec![TxOutput::new(1_000, p2pkh()), TxOutput::new(0, op_return(81))]);
+        assert_eq!(is_standard_tx(&tx), Err(PolicyError::OpReturnSize { output: 1 }));
+        let tx = paying(vec![TxOutput::new(0, op_return(2)), TxOutput::new(0, op_return(2))]);
+        assert_eq!(is_standard_tx(&tx), Err(PolicyError::MultiOpReturn));
+        let tx = paying(vec![TxOutput::new(1_000, Script::from_raw(vec![OP_NOP]))]);
+        assert_eq!(is_standard_tx(&tx), Err(PolicyError::ScriptPubKey { output: 0 }));
+        // unknown witness versions are standard to pay to
+        let tx = paying(vec![TxOutput::new(1_000, Script::from_raw(hex::decode("52020000").unwrap())), TxOutput::new(1_000, p2pkh())]);
+        assert_eq!(is_standard_tx(&tx), Ok(()));
+        let tx = paying(vec![TxOutput::new(1_000, p2pkh()), TxOutput::new(545, p2pkh())]);
+        let error = is_standard_tx(&tx).unwrap_err();
+        assert_eq!(error, PolicyError::Dust { output: 1 });
+        assert_eq!(error.to_string(), "dust: output 1 is dust");
+    }
+
+    #[test]
+    fn test_are_inputs_standard() {
+        let tx = spend(Script::from_raw(hex::decode("52020000").unwrap()), Script::new(vec![]), Some(vec![vec![1]]));
+        assert_eq!(are_inputs_standard(&tx), Err(PolicyError::NonStandardInput { input: 0 }));
+        let tx = spend(Script::from_raw(vec![OP_NOP]), Script::new(vec![]), None);
+        assert_eq!(are_inputs_standard(&tx), Err(PolicyError::NonStandardInput { input: 0 }));
+        for (sig_ops, want) in [(15, Ok(())), (16, Err(PolicyError::P2shSigOps { input: 0 }))] {
+            let redeem_script = Script::from_raw(vec![OP_CHECKSIG; sig_ops]);
+            let p2sh = Script::p2sh_script(hash160(&redeem_script.serialize_raw()).to_vec());
+            let tx = spend(p2sh, Script::new(vec![redeem_script.serialize_raw()]), None);
+            assert_eq!(are_inputs_standard(&tx), want);
+        }
+    }
+
+    #[test]
+    fn test_is_witness_standard() {
+        let p2wsh = |witness_script: &[u8]| Script::new(vec![vec![0x00], Sha256::digest(witness_script).to_vec()]);
+        let script = vec![OP_1];
+        let p2wsh_spend = |items: Vec<Vec<u8>>, script: &[u8]| {
+            let mut witness = items;
+            witness.push(script.to_vec());
+            spend(p2wsh(script), Script::new(vec![]), Some(witness))
+        };
+        assert_eq!(is_witness_standard(&p2wsh_spend(vec![vec![1; 80]; 100], &script)), Ok(()));
+        assert_eq!(is_witness_standard(&p2wsh_spend(vec![vec![1]; 101], &script)), Err(PolicyError::WitnessStackItems { input: 0 }));
+        assert_eq!(is_witness_standard(&p2wsh_spend(vec![vec![1; 81]], &script)), Err(PolicyError::WitnessItemSize { input: 0 }));
+        assert_eq!(is_witness_standard(&p2wsh_spend(vec![], &[OP_NOP; 3601])), Err(PolicyError::WitnessScriptSize { input: 0 }));
+        // the same limits nested in P2SH
+        let nested = p2wsh(&script);
+        let p2sh = Script::p2sh_script(hash160(&nested.serialize_raw()).to_vec());
+        let tx = spend(p2sh, Script::new(vec![nested.serialize_raw()]), Some(vec![vec![1; 81], script.clone()]));
+        assert_eq!(is_witness_standard(&tx), Err(PolicyError::WitnessItemSize { input: 0 }));
+
+        let tx = spend(p2pkh(), Script::new(vec![]), Some(vec![vec![1]]));
+        assert_eq!(is_witness_standard(&tx), Err(PolicyError::UnexpectedWitness { input: 0 }));
+        let tx = spend(Script::from_raw(hex::decode("51024e73").unwrap()), Script::new(vec![]), Some(vec![vec![1]]));
+        assert_eq!(is_witness_standard(&tx), Err(PolicyError::UnexpectedWitness { input: 0 }));
+        let tx = spend(p2pkh(), Script::new(vec![]), Some(vec![]));
+        assert_eq!(is_witness_standard(&tx), Ok(()));
+
+        let p2tr = Script::new(vec![vec![OP_1], vec![0x11; 32]]);
+        let tx = spend(p2tr.clone(), Script::new(vec![]), Some(vec![vec![1; 64]]));
+        assert_eq!(is_witness_standard(&tx), Ok(()));
+        let tx = spend(p2tr.clone(), Script::new(vec![]), Some(vec![vec![1; 64], vec![TAPROOT_ANNEX_TAG]]));
+        assert_eq!(is_witness_standard(&tx), Err(PolicyError::TaprootAnnex { input: 0 }));
+        let control = [vec![TAPROOT_LEAF_TAPSCRIPT], vec![0x11; 32]].concat();
+        let tx = spend(p2tr.clone(), Script::new(vec![]), Some(vec![vec![1; 80], script.clone(), control.clone()]));
+        assert_eq!(is_witness_standard(&tx), Ok(()));
+        let tx = spend(p2tr.clone(), Script::new(vec![]), Some(vec![vec![1; 81], script.clone(), control]));
+        assert_eq!(is_witness_standard(&tx), Err(PolicyError::WitnessItemSize { input: 0 }));
+        let tx = spend(p2tr, Script::new(vec![]), Some(vec![vec![1; 81], script, vec![]]));
+        assert_eq!(is_witness_standard(&tx), Err(PolicyError::TaprootControlBlock { input: 0 }));
+        assert_eq!(is_witness_standard(&tx).unwrap_err().reason(), "bad-witness-nonstandard");
+    }
+}
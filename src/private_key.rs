@@ -1,49 +1,130 @@
 use num::BigUint;
 use sha2::{Digest, Sha256};
 use crate::signature::Signature;
-use crate::field_element::FieldElement;
+use crate::recoverable_signature::RecoverableSignature;
+use crate::schnorr_signature::{challenge, SchnorrSignature};
+use crate::x_only_public_key::XOnlyPublicKey;
+use crate::helpers::tagged_hash::tagged_hash;
 use crate::point::Point;
+use crate::scalar::Scalar;
 use crate::secp256k1::Secp256k1;
 use rfc6979::consts::U32;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct PrivateKey {
     secret: BigUint,
+    scalar: Scalar,
     public_key: Point,
 }
 
 impl PrivateKey {
     pub fn new(secret: &BigUint) -> Self {
-        let s256 = Secp256k1::new();
-        let generator = Point::new_secp256k1(&Some(FieldElement::new(&s256.gx, &s256.p)), &Some(FieldElement::new(&s256.gy, &s256.p)));
-        let public_key = generator * secret.clone();
+        let scalar = Scalar::from_biguint(secret);
+        let public_key = Point::mul_generator(&scalar);
         PrivateKey {
             secret: secret.clone(),
-            public_key: public_key.clone(),
+            scalar,
+            public_key,
         }
     }
     pub fn sign(&self, z: &BigUint) -> Signature {
+        self.sign_recoverable(z).signature().clone()
+    }
+    /// ECDSA signature plus the recovery id that lets `Point::recover` find the key
+    pub fn sign_recoverable(&self, z: &BigUint) -> RecoverableSignature {
 
-        let k = self.deterministic_k(&z);
+        let k = Scalar::from_biguint(&self.deterministic_k(z));
 
-        let s256 = Secp256k1::new();
-        let n = &s256.n;
+        let (x, y) = Point::mul_generator(&k).affine().unwrap();
+        let x_bytes = x.to_bytes();
+        let r = Scalar::from_bytes_reduced(&x_bytes);
+        let k_inv = k.invert();
 
-        let generator = Point::new_secp256k1(&Some(FieldElement::new(&s256.gx, &s256.p)), &Some(FieldElement::new(&s256.gy, &s256.p)));
-        let r = (generator * k.clone()).x().unwrap().num_value();
-        let k_inv = k.modpow(&(n - &BigUint::from(2u8)), &n);
+        // s = (z + r*e) / k
+        let s = (Scalar::from_biguint(z) + r * self.scalar) * k_inv;
 
-        //let sig = (z + r * &self.secret) * k_inv % n;
-        let mut s = (z + &r * &self.secret) * k_inv % n;
+        // a high s is negated, which is the signature of -R: flip the parity
+        let overflow = Scalar::from_bytes(&x_bytes).is_none();
+        let recid = (y.is_odd() ^ s.is_high()) as u8 | (overflow as u8) << 1;
+        let s = s.normalize_high();
 
-        if s > n / BigUint::from(2u8) {
-            s = n - s;
+        RecoverableSignature::new(&Signature::new(&r.to_biguint(), &s.to_biguint()), recid)
+    }
+    /// BIP340 signature of msg, aux_rand is fresh randomness (zeros are allowed but weaker)
+    pub fn sign_schnorr(&self, msg: &[u8], aux_rand: &[u8; 32]) -> SchnorrSignature {
+        if self.scalar.is_zero() {
+            panic!("Invalid private key for Schnorr signing");
+        }
+        let (public_key, odd) = XOnlyPublicKey::from_point(&self.public_key);
+        let p = public_key.serialize();
+        let d = Scalar::conditional_select(&self.scalar, &-self.scalar, odd);
+
+        // t = bytes(d) xor hash_BIP0340/aux(a)
+        let aux = tagged_hash("BIP0340/aux", aux_rand);
+        let mut data = d.to_bytes().to_vec();
+        for (t, a) in data.iter_mut().zip(aux) {
+            *t ^= a;
         }
-        Signature::new(&r, &s)
+        data.extend(p);
+        data.extend(msg);
+        let k = Scalar::from_bytes_reduced(&tagged_hash("BIP0340/nonce", &data));
+        if k.is_zero() {
+            panic!("Schnorr nonce is zero");
+        }
+        let (rx, ry) = Point::mul_generator(&k).affine().unwrap();
+        let k = Scalar::conditional_select(&k, &-k, ry.is_odd());
+        let r = rx.to_bytes();
+
+        let e = challenge(&r, &p, msg);
+        let sig = SchnorrSignature::new(&r, &(k + e * d).to_bytes());
+        if !public_key.verify_schnorr(msg, &sig) {
+            panic!("Schnorr signature does not verify");
+        }
+        sig
+    }
+    /// BIP341 tweaked key that signs for `Point::taproot_tweak` of this key's public
+    /// key: the secret is negated first when the internal key has an odd y
+    pub fn tap_tweak(&self, merkle_root: Option<[u8; 32]>) -> PrivateKey {
+        let (internal, odd) = XOnlyPublicKey::from_point(&self.public_key);
+        let d = Scalar::conditional_select(&self.scalar, &-self.scalar, odd);
+        let t = Scalar::from_bytes(&internal.tap_tweak_hash(merkle_root))
+            .expect("taproot tweak is not lower than n");
+        let tweaked = d + t;
+        if tweaked.is_zero() {
+            panic!("tweaked private key is zero");
+        }
+        PrivateKey::new(&tweaked.to_biguint())
+    }
+    /// ECDH shared secret as libsecp256k1 computes it: sha256 of the compressed
+    /// SEC encoding of secret * point
+    pub fn ecdh(&self, point: &Point) -> [u8; 32] {
+        self.ecdh_with(point, |x, y| {
+            let mut hasher = Sha256::new();
+            hasher.update([0x02 | (y[31] & 1)]);
+            hasher.update(x);
+            hasher.finalize().into()
+        })
+    }
+    /// ECDH shared secret with a custom hash of the shared point's affine x and y
+    pub fn ecdh_with<F>(&self, point: &Point, hash_fn: F) -> [u8; 32]
+    where
+        F: FnOnce(&[u8; 32], &[u8; 32]) -> [u8; 32],
+    {
+        let shared = point.mul_scalar(&self.scalar);
+        let Some((x, y)) = shared.affine() else {
+            panic!("ECDH with the point at infinity");
+        };
+        hash_fn(&x.to_bytes(), &y.to_bytes())
+    }
+    pub(crate) fn scalar(&self) -> Scalar {
+        self.scalar
     }
     pub fn point(&self) -> Point {
         self.public_key.clone()
     }
+    pub fn x_only_public_key(&self) -> XOnlyPublicKey {
+        XOnlyPublicKey::from_point(&self.public_key).0
+    }
     pub fn deterministic_k(&self, z: &BigUint) -> BigUint {
         let s256 = Secp256k1::new();
         let n_bytes = s256.n.to_bytes_be();
@@ -51,15 +132,13 @@ impl PrivateKey {
         //let mut p = GenericArray::<u8, 32>::default();
         n.copy_from_slice(&n_bytes);
 
-        let k_bytes = self.secret.to_bytes_be();
-        let mut k = [0; 32];
-        k.copy_from_slice(&k_bytes);
+        let k = self.scalar.to_bytes();
 
         let z_bytes = z.to_bytes_be();
         let mut z = [0; 32];
-        z.copy_from_slice(&z_bytes);
+        z[32 - z_bytes.len()..].copy_from_slice(&z_bytes);
 
-        let h = Sha256::digest(&z);
+        let h = Sha256::digest(z);
 
         // secret, field modulus, hash/digest (modulus reduced), additional data
         let k = rfc6979::generate_k::<Sha256, U32>(&k.into(), &n.into(), &h, b"");
@@ -73,6 +152,7 @@ impl PrivateKey {
 mod tests {
     use num::BigUint;
     use crate::helpers::hash256::hash256;
+    use crate::field_element::FieldElement;
     use super::*;
 
     #[test]
@@ -95,4 +175,69 @@ mod tests {
 
         assert!(point.verify(&z, &sig));
     }
+    #[test]
+    fn test_sign_recoverable() {
+        for secret in [1u32, 2, 3, 1000, 123456789] {
+            let key = PrivateKey::new(&BigUint::from(secret));
+            for msg in [&b"a"[..], b"b", b"c", b"d"] {
+                let z = BigUint::from_bytes_be(&hash256(msg));
+                let rsig = key.sign_recoverable(&z);
+                assert_eq!(rsig.signature(), &key.sign(&z));
+                assert_eq!(Point::recover(&z, rsig.signature(), rsig.recid()).unwrap(), key.point());
+                let wrong = rsig.recid() ^ 1;
+                assert_ne!(Point::recover(&z, rsig.signature(), wrong).ok(), Some(key.point()));
+            }
+        }
+    }
+    #[test]
+    fn test_ecdh() {
+        let alice = PrivateKey::new(&BigUint::from_bytes_be(&hash256(b"alice")));
+        let bob = PrivateKey::new(&BigUint::from_bytes_be(&hash256(b"bob")));
+        let secret = alice.ecdh(&bob.point());
+        assert_eq!(secret, bob.ecdh(&alice.point()));
+
+        let shared = bob.point().mul_scalar(&alice.scalar());
+        assert_eq!(secret.to_vec(), Sha256::digest(shared.sec(true)).to_vec());
+
+        // raw x coordinate and a tagged hash of the uncompressed point
+        let x = alice.ecdh_with(&bob.point(), |x, _| *x);
+        assert_eq!(x.to_vec(), shared.sec(true)[1..].to_vec());
+        let tagged = alice.ecdh_with(&bob.point(), |x, y| {
+            tagged_hash("test/ecdh", &[x.as_slice(), y.as_slice()].concat())
+        });
+        assert_eq!(tagged, tagged_hash("test/ecdh", &shared.sec(false)[1..]));
+        assert_ne!(secret, alice.ecdh(&alice.point()));
+    }
+    #[test]
+    fn test_ecdh_known_answer() {
+        // 3G, whose x coordinate is the BIP340 vector 0 public key, has an even y, so
+        // libsecp256k1's default hash is sha256(02 || x)
+        let three = PrivateKey::new(&BigUint::from(3u8));
+        let one = PrivateKey::new(&BigUint::from(1u8));
+        let want = "eae10cdd2f289bdad44615809cb422d2fabe9622ed706ad5d9d3ffd2cdd1c001";
+        assert_eq!(hex::encode(three.ecdh(&Point::generator())), want);
+        assert_eq!(hex::encode(one.ecdh(&three.point())), want);
+        let x = three.ecdh_with(&Point::generator(), |x, _| *x);
+        assert_eq!(hex::encode(x), "f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9");
+    }
+    #[test]
+    fn test_tap_tweak() {
+        // BIP341 wallet test vectors, keyPathSpending input 0
+        let key = PrivateKey::new(&BigUint::parse_bytes(b"6b973d88838f27366ed61c9ad6367663045cb456e28335c109e30717ae0c6baa", 16).unwrap());
+        assert_eq!(key.x_only_public_key().to_string(), "d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+        let tweaked = key.tap_tweak(None);
+        assert_eq!(hex::encode(tweaked.scalar().to_bytes()), "2405b971772ad26915c8dcdf10f238753a9b837e5f8e6a86fd7c0cce5b7296d9");
+
+        // signatures of the tweaked key verify against the output key, for both parities
+        let merkle_root = [7u8; 32];
+        for secret in 1u32..5 {
+            let key = PrivateKey::new(&BigUint::from(secret));
+            let (output, odd) = key.point().taproot_tweak(Some(merkle_root));
+            let tweaked = key.tap_tweak(Some(merkle_root));
+            assert_eq!(tweaked.x_only_public_key(), output);
+            assert_eq!(XOnlyPublicKey::from_point(&tweaked.point()).1, odd);
+            let sig = tweaked.sign_schnorr(&[secret as u8; 32], &[0u8; 32]);
+            assert!(output.verify_schnorr(&[secret as u8; 32], &sig));
+        }
+    }
 }
@@ -0,0 +1,111 @@
+use crate::fe::Fe;
+use crate::scalar::Scalar;
+
+/// 3 * b for secp256k1 (b = 7)
+const B3: u64 = 21;
+
+/// secp256k1 point in homogeneous projective coordinates (X:Y:Z), x = X/Z, y = Y/Z.
+/// Addition and doubling use the complete formulas for a = 0 curves from
+/// Renes, Costello, Batina "Complete addition formulas for prime order elliptic curves"
+/// (algorithms 7 and 9): there are no special cases for the identity or for P == Q,
+/// so combined with conditional selects they give constant-time scalar multiplication.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ProjectivePoint {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+}
+
+impl ProjectivePoint {
+    pub(crate) const IDENTITY: ProjectivePoint = ProjectivePoint { x: Fe::ZERO, y: Fe::ONE, z: Fe::ZERO };
+
+    pub(crate) fn from_affine(x: &Fe, y: &Fe) -> Self {
+        ProjectivePoint { x: *x, y: *y, z: Fe::ONE }
+    }
+    /// None for the point at infinity
+    pub(crate) fn to_affine(self) -> Option<(Fe, Fe)> {
+        if self.z.is_zero() {
+            return None;
+        }
+        let z_inv = self.z.invert();
+        Some((self.x * z_inv, self.y * z_inv))
+    }
+    pub(crate) fn add(&self, other: &ProjectivePoint) -> ProjectivePoint {
+        let (x1, y1, z1) = (self.x, self.y, self.z);
+        let (x2, y2, z2) = (other.x, other.y, other.z);
+        let mut t0 = x1 * x2;
+        let mut t1 = y1 * y2;
+        let mut t2 = z1 * z2;
+        let mut t3 = x1 + y1;
+        let mut t4 = x2 + y2;
+        t3 = t3 * t4;
+        t4 = t0 + t1;
+        t3 = t3 - t4;
+        t4 = y1 + z1;
+        let mut x3 = y2 + z2;
+        t4 = t4 * x3;
+        x3 = t1 + t2;
+        t4 = t4 - x3;
+        x3 = x1 + z1;
+        let mut y3 = x2 + z2;
+        x3 = x3 * y3;
+        y3 = t0 + t2;
+        y3 = x3 - y3;
+        x3 = t0 + t0;
+        t0 = x3 + t0;
+        t2 = t2.mul_u64(B3);
+        let mut z3 = t1 + t2;
+        t1 = t1 - t2;
+        y3 = y3.mul_u64(B3);
+        x3 = t4 * y3;
+        t2 = t3 * t1;
+        x3 = t2 - x3;
+        y3 = y3 * t0;
+        t1 = t1 * z3;
+        y3 = t1 + y3;
+        t0 = t0 * t3;
+        z3 = z3 * t4;
+        z3 = z3 + t0;
+        ProjectivePoint { x: x3, y: y3, z: z3 }
+    }
+    pub(crate) fn double(&self) -> ProjectivePoint {
+        let (x, y, z) = (self.x, self.y, self.z);
+        let mut t0 = y * y;
+        let mut z3 = t0 + t0;
+        z3 = z3 + z3;
+        z3 = z3 + z3;
+        let mut t1 = y * z;
+        let mut t2 = z * z;
+        t2 = t2.mul_u64(B3);
+        let mut x3 = t2 * z3;
+        let mut y3 = t0 + t2;
+        z3 = t1 * z3;
+        t1 = t2 + t2;
+        t2 = t1 + t2;
+        t0 = t0 - t2;
+        y3 = t0 * y3;
+        y3 = x3 + y3;
+        t1 = x * y;
+        x3 = t0 * t1;
+        x3 = x3 + x3;
+        ProjectivePoint { x: x3, y: y3, z: z3 }
+    }
+    pub(crate) fn conditional_select(a: &ProjectivePoint, b: &ProjectivePoint, choice: bool) -> ProjectivePoint {
+        ProjectivePoint {
+            x: Fe::conditional_select(&a.x, &b.x, choice),
+            y: Fe::conditional_select(&a.y, &b.y, choice),
+            z: Fe::conditional_select(&a.z, &b.z, choice),
+        }
+    }
+    /// k * self with a double-and-add-always ladder, the sequence of field
+    /// operations does not depend on k
+    pub(crate) fn mul_ct(&self, k: &Scalar) -> ProjectivePoint {
+        let mut result = ProjectivePoint::IDENTITY;
+        for i in (0..256).rev() {
+            result = result.double();
+            let sum = result.add(self);
+            result = ProjectivePoint::conditional_select(&result, &sum, k.bit(i));
+        }
+        result
+    }
+}
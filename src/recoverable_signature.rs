@@ -0,0 +1,110 @@
+use core::fmt;
+use std::io::{Error, ErrorKind};
+use num::BigUint;
+use crate::scalar::Scalar;
+use crate::signature::Signature;
+
+/// ECDSA signature with the recovery id needed to get the public key back from it:
+/// bit 0 is the parity of R.y, bit 1 is set when R.x was not lower than n.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RecoverableSignature {
+    signature: Signature,
+    recid: u8,
+}
+
+impl RecoverableSignature {
+    pub fn new(signature: &Signature, recid: u8) -> Self {
+        if recid > 3 {
+            panic!("recovery id must be between 0 and 3");
+        }
+        RecoverableSignature {
+            signature: signature.clone(),
+            recid,
+        }
+    }
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+    pub fn recid(&self) -> u8 {
+        self.recid
+    }
+    /// 65 byte compact form used by Bitcoin Core: a header byte 27 + recid (+ 4 when the
+    /// key is compressed), then r and s as 32 bytes big endian. Errors when r or s is
+    /// not between 1 and n - 1, as no valid signature has such a value.
+    pub fn serialize_compact(&self, compressed: bool) -> Result<[u8; 65], Error> {
+        let mut result = [0u8; 65];
+        result[0] = 27 + self.recid + if compressed { 4 } else { 0 };
+        result[1..33].copy_from_slice(&scalar_bytes(self.signature.r())?);
+        result[33..].copy_from_slice(&scalar_bytes(self.signature.s())?);
+        Ok(result)
+    }
+    /// parses the compact form, also returns whether the header marks a compressed key
+    pub fn parse_compact(bytes: &[u8]) -> Result<(Self, bool), Error> {
+        if bytes.len() != 65 {
+            return Err(Error::new(ErrorKind::InvalidData, "compact signature must be 65 bytes"));
+        }
+        let header = bytes[0];
+        if !(27..=34).contains(&header) {
+            return Err(Error::new(ErrorKind::InvalidData, "invalid compact signature header"));
+        }
+        let compressed = header >= 31;
+        let recid = (header - 27) & 3;
+        let r = BigUint::from_bytes_be(&bytes[1..33]);
+        let s = BigUint::from_bytes_be(&bytes[33..65]);
+        Ok((Self::new(&Signature::new(&r, &s), recid), compressed))
+    }
+}
+
+// the 32 byte big endian form of a value in [1, n - 1]
+fn scalar_bytes(value: &BigUint) -> Result<[u8; 32], Error> {
+    let out_of_range = || Error::new(ErrorKind::InvalidInput, "r and s must be between 1 and n - 1");
+    let be = value.to_bytes_be();
+    if be.len() > 32 {
+        return Err(out_of_range());
+    }
+    let mut bytes = [0u8; 32];
+    bytes[32 - be.len()..].copy_from_slice(&be);
+    match Scalar::from_bytes(&bytes) {
+        Some(scalar) if scalar != Scalar::ZERO => Ok(bytes),
+        _ => Err(out_of_range()),
+    }
+}
+
+impl fmt::Display for RecoverableSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} recid {}", self.signature, self.recid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_round_trip() {
+        let sig = Signature::new(&BigUint::from(1234u32), &BigUint::from(5678u32));
+        for recid in 0..4 {
+            for compressed in [false, true] {
+                let rsig = RecoverableSignature::new(&sig, recid);
+                let bytes = rsig.serialize_compact(compressed).unwrap();
+                assert_eq!(bytes[0], 27 + recid + if compressed { 4 } else { 0 });
+                assert_eq!(bytes[31..33], 1234u16.to_be_bytes());
+                assert_eq!(bytes[63..], 5678u16.to_be_bytes());
+                assert_eq!(RecoverableSignature::parse_compact(&bytes).unwrap(), (rsig, compressed));
+            }
+        }
+        assert!(RecoverableSignature::parse_compact(&[0u8; 65]).is_err());
+        assert!(RecoverableSignature::parse_compact(&[27u8; 64]).is_err());
+    }
+    #[test]
+    fn test_compact_out_of_range() {
+        let n = crate::secp256k1::Secp256k1::new().n;
+        let one = BigUint::from(1u8);
+        let n_minus_1 = &n - &one;
+        assert!(RecoverableSignature::new(&Signature::new(&n_minus_1, &one), 0).serialize_compact(true).is_ok());
+        for (r, s) in [(n.clone(), one.clone()), (one.clone(), &n + &one), (BigUint::from(0u8), one.clone()), (one.clone(), BigUint::from(1u8) << 256)] {
+            let rsig = RecoverableSignature::new(&Signature::new(&r, &s), 0);
+            assert_eq!(rsig.serialize_compact(true).unwrap_err().kind(), ErrorKind::InvalidInput);
+        }
+    }
+}
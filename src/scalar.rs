@@ -0,0 +1,279 @@
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+use num::BigUint;
+use rand::RngCore;
+use crate::helpers::limbs::{add4, eq4, from_be_bytes, is_zero4, mul_acc, mul_wide, select4, sub4, to_be_bytes};
+
+/// n, the order of the secp256k1 group
+const N: [u64; 4] = [0xBFD25E8CD0364141, 0xBAAEDCE6AF48A03B, 0xFFFFFFFFFFFFFFFE, 0xFFFFFFFFFFFFFFFF];
+/// 2^256 - n
+const NC: [u64; 3] = [0x402DA1732FC9BEBF, 0x4551231950B75FC4, 0x1];
+/// n / 2
+const N_HALF: [u64; 4] = [0xDFE92F46681B20A0, 0x5D576E7357A4501D, 0xFFFFFFFFFFFFFFFF, 0x7FFFFFFFFFFFFFFF];
+
+/// Integer modulo the secp256k1 group order, four 64-bit limbs, always fully reduced.
+/// Arithmetic runs in constant time, `invert` only branches on the public exponent n - 2.
+#[derive(Clone, Copy)]
+pub struct Scalar([u64; 4]);
+
+impl Scalar {
+    pub const ZERO: Scalar = Scalar([0, 0, 0, 0]);
+    pub const ONE: Scalar = Scalar([1, 0, 0, 0]);
+
+    pub fn from_u64(v: u64) -> Self {
+        Scalar([v, 0, 0, 0])
+    }
+    /// None when the value is not lower than n
+    pub fn from_bytes(bytes: &[u8; 32]) -> Option<Self> {
+        let limbs = from_be_bytes(bytes);
+        let (_, borrow) = sub4(&limbs, &N);
+        if borrow == 1 {
+            Some(Scalar(limbs))
+        } else {
+            None
+        }
+    }
+    /// interprets the bytes as an integer and reduces it mod n
+    pub fn from_bytes_reduced(bytes: &[u8; 32]) -> Self {
+        Scalar(reduce_once(from_be_bytes(bytes), 0))
+    }
+    pub fn to_bytes(&self) -> [u8; 32] {
+        to_be_bytes(&self.0)
+    }
+    /// reduces any integer mod n
+    pub fn from_biguint(v: &BigUint) -> Self {
+        let reduced = v % BigUint::from_bytes_be(&to_be_bytes(&N));
+        let mut bytes = [0u8; 32];
+        let be = reduced.to_bytes_be();
+        bytes[32 - be.len()..].copy_from_slice(&be);
+        Scalar(from_be_bytes(&bytes))
+    }
+    pub fn to_biguint(&self) -> BigUint {
+        BigUint::from_bytes_be(&self.to_bytes())
+    }
+    pub fn is_zero(&self) -> bool {
+        is_zero4(&self.0) == 1
+    }
+    /// true when the value is greater than n / 2
+    pub fn is_high(&self) -> bool {
+        let (_, borrow) = sub4(&N_HALF, &self.0);
+        borrow == 1
+    }
+    pub fn is_even(&self) -> bool {
+        self.0[0] & 1 == 0
+    }
+    /// bit i of the value, bit 0 is the least significant
+    pub fn bit(&self, i: usize) -> bool {
+        (self.0[i / 64] >> (i % 64)) & 1 == 1
+    }
+    /// uniformly random non zero scalar
+    pub fn random<R: RngCore>(rng: &mut R) -> Scalar {
+        loop {
+            let mut bytes = [0u8; 32];
+            rng.fill_bytes(&mut bytes);
+            if let Some(s) = Scalar::from_bytes(&bytes) {
+                if !s.is_zero() {
+                    return s;
+                }
+            }
+        }
+    }
+    /// count bits (at most 32) starting at bit offset, bits past 255 read as zero
+    pub fn bits(&self, offset: usize, count: usize) -> u32 {
+        let mut v = 0u32;
+        for i in (0..count).rev() {
+            let bit = offset + i;
+            v <<= 1;
+            if bit < 256 {
+                v |= self.bit(bit) as u32;
+            }
+        }
+        v
+    }
+    /// returns b when choice is true, a otherwise, without branching
+    pub fn conditional_select(a: &Scalar, b: &Scalar, choice: bool) -> Scalar {
+        Scalar(select4(&a.0, &b.0, choice as u64))
+    }
+    /// the value when it is not high, its negation otherwise
+    pub fn normalize_high(&self) -> Scalar {
+        Scalar::conditional_select(self, &-*self, self.is_high())
+    }
+    pub fn square(&self) -> Scalar {
+        *self * *self
+    }
+    /// multiplicative inverse, 1/0 is defined as 0
+    pub fn invert(&self) -> Scalar {
+        // Fermat: a^(n - 2)
+        let mut exp = to_be_bytes(&N);
+        exp[31] -= 2;
+        let mut result = Scalar::ONE;
+        for byte in exp {
+            for i in (0..8).rev() {
+                result = result.square();
+                if (byte >> i) & 1 == 1 {
+                    result = result * *self;
+                }
+            }
+        }
+        result
+    }
+    /// inverts every scalar with a single inversion (Montgomery's trick), zeros stay zero
+    pub fn batch_invert(values: &[Scalar]) -> Vec<Scalar> {
+        let mut prefix = Vec::with_capacity(values.len());
+        let mut acc = Scalar::ONE;
+        for v in values {
+            prefix.push(acc);
+            acc = Scalar::conditional_select(&(acc * *v), &acc, v.is_zero());
+        }
+        let mut inv = acc.invert();
+        let mut result = vec![Scalar::ZERO; values.len()];
+        for i in (0..values.len()).rev() {
+            let v = values[i];
+            result[i] = Scalar::conditional_select(&(inv * prefix[i]), &Scalar::ZERO, v.is_zero());
+            inv = Scalar::conditional_select(&(inv * v), &inv, v.is_zero());
+        }
+        result
+    }
+}
+
+/// subtracts n when the value (with its carry bit) is not lower than n
+fn reduce_once(v: [u64; 4], carry: u64) -> [u64; 4] {
+    let (d, borrow) = sub4(&v, &N);
+    select4(&v, &d, carry | (borrow ^ 1))
+}
+
+/// reduces a 512 bit product folding the high half with 2^256 = NC mod n
+fn reduce_wide(t: [u64; 8]) -> [u64; 4] {
+    // 512 -> 386 bits
+    let mut m = [0u64; 8];
+    m[..4].copy_from_slice(&t[..4]);
+    mul_acc(&t[4..], &NC, &mut m);
+    // 386 -> 259 bits
+    let mut p = [0u64; 8];
+    p[..4].copy_from_slice(&m[..4]);
+    mul_acc(&m[4..7], &NC, &mut p);
+    // 259 -> 256 bits plus a carry
+    let mut r = [0u64; 8];
+    r[..4].copy_from_slice(&p[..4]);
+    mul_acc(&p[4..5], &NC, &mut r);
+    // a carry left means the low part is tiny, folding it once more cannot overflow
+    let c = r[4];
+    let nc = select4(&[0; 4], &[NC[0], NC[1], NC[2], 0], c);
+    let (v, _) = add4(&[r[0], r[1], r[2], r[3]], &nc);
+    reduce_once(v, 0)
+}
+
+impl Add for Scalar {
+    type Output = Scalar;
+    fn add(self, other: Scalar) -> Scalar {
+        let (sum, carry) = add4(&self.0, &other.0);
+        Scalar(reduce_once(sum, carry))
+    }
+}
+impl Sub for Scalar {
+    type Output = Scalar;
+    fn sub(self, other: Scalar) -> Scalar {
+        let (diff, borrow) = sub4(&self.0, &other.0);
+        let n = select4(&[0; 4], &N, borrow);
+        let (r, _) = add4(&diff, &n);
+        Scalar(r)
+    }
+}
+impl Neg for Scalar {
+    type Output = Scalar;
+    fn neg(self) -> Scalar {
+        Scalar::ZERO - self
+    }
+}
+impl Mul for Scalar {
+    type Output = Scalar;
+    fn mul(self, other: Scalar) -> Scalar {
+        Scalar(reduce_wide(mul_wide(&self.0, &other.0)))
+    }
+}
+impl PartialEq for Scalar {
+    fn eq(&self, other: &Self) -> bool {
+        eq4(&self.0, &other.0) == 1
+    }
+}
+impl Eq for Scalar {}
+
+impl fmt::Debug for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Scalar({})", hex::encode(self.to_bytes()))
+    }
+}
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.to_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secp256k1::Secp256k1;
+    use num::Num;
+
+    fn n() -> BigUint {
+        Secp256k1::new().n
+    }
+    fn samples() -> Vec<BigUint> {
+        let n = n();
+        vec![
+            BigUint::from(0u32),
+            BigUint::from(1u32),
+            BigUint::from(2u32),
+            &n - BigUint::from(1u32),
+            &n / BigUint::from(2u32),
+            &n / BigUint::from(2u32) + BigUint::from(1u32),
+            BigUint::from_str_radix("ec208baa0fc1c19f708a9ca96fdeff3ac3f230bb4a7ba4aede4942ad003c0f60", 16).unwrap(),
+            BigUint::from_str_radix("ac8d1c87e51d0d441be8b3dd5b05c8795b48875dffe00b7ffcfac23010d3a395", 16).unwrap(),
+            BigUint::from_str_radix("0000000000000000000000000000000100000000000000000000000000000000", 16).unwrap(),
+        ]
+    }
+    #[test]
+    fn test_arithmetic_matches_biguint() {
+        let n = n();
+        for a in samples() {
+            for b in samples() {
+                let sa = Scalar::from_biguint(&a);
+                let sb = Scalar::from_biguint(&b);
+                assert_eq!((sa + sb).to_biguint(), (&a + &b) % &n);
+                assert_eq!((sa - sb).to_biguint(), (&a + &n - &b) % &n);
+                assert_eq!((sa * sb).to_biguint(), (&a * &b) % &n);
+            }
+        }
+    }
+    #[test]
+    fn test_invert() {
+        for a in samples().into_iter().skip(1) {
+            let sa = Scalar::from_biguint(&a);
+            assert_eq!(sa * sa.invert(), Scalar::ONE);
+        }
+    }
+    #[test]
+    fn test_batch_invert() {
+        let values: Vec<Scalar> = samples().iter().map(Scalar::from_biguint).collect();
+        let inverted = Scalar::batch_invert(&values);
+        for (v, inv) in values.iter().zip(inverted) {
+            assert_eq!(v.invert(), inv);
+        }
+    }
+    #[test]
+    fn test_is_high() {
+        let n = n();
+        let half = Scalar::from_biguint(&(&n / BigUint::from(2u32)));
+        assert!(!half.is_high());
+        assert!((half + Scalar::ONE).is_high());
+        assert!(!(half + Scalar::ONE).normalize_high().is_high());
+    }
+    #[test]
+    fn test_bytes() {
+        let bytes = [0xffu8; 32];
+        assert!(Scalar::from_bytes(&bytes).is_none());
+        let reduced = Scalar::from_bytes_reduced(&bytes);
+        let want = (BigUint::from_bytes_be(&bytes)) % n();
+        assert_eq!(reduced.to_biguint(), want);
+    }
+}
@@ -0,0 +1,98 @@
+use core::fmt;
+use std::io::{Error, ErrorKind};
+use crate::helpers::tagged_hash::tagged_hash;
+use crate::scalar::Scalar;
+
+/// BIP340 signature: the x coordinate of R followed by s, 32 bytes each
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SchnorrSignature {
+    r: [u8; 32],
+    s: [u8; 32],
+}
+
+impl SchnorrSignature {
+    pub fn new(r: &[u8; 32], s: &[u8; 32]) -> Self {
+        SchnorrSignature {
+            r: *r,
+            s: *s,
+        }
+    }
+    pub fn r(&self) -> &[u8; 32] {
+        &self.r
+    }
+    pub fn s(&self) -> &[u8; 32] {
+        &self.s
+    }
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != 64 {
+            return Err(Error::new(ErrorKind::InvalidData, "Schnorr signature must be 64 bytes"));
+        }
+        let mut r = [0u8; 32];
+        let mut s = [0u8; 32];
+        r.copy_from_slice(&bytes[..32]);
+        s.copy_from_slice(&bytes[32..]);
+        Ok(Self::new(&r, &s))
+    }
+    pub fn serialize(&self) -> [u8; 64] {
+        let mut result = [0u8; 64];
+        result[..32].copy_from_slice(&self.r);
+        result[32..].copy_from_slice(&self.s);
+        result
+    }
+}
+
+impl fmt::Display for SchnorrSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SchnorrSignature({})", hex::encode(self.serialize()))
+    }
+}
+
+// e = int(hash_BIP0340/challenge(bytes(R) || bytes(P) || m)) mod n
+pub(crate) fn challenge(r: &[u8; 32], p: &[u8; 32], msg: &[u8]) -> Scalar {
+    let mut data = Vec::with_capacity(64 + msg.len());
+    data.extend(r);
+    data.extend(p);
+    data.extend(msg);
+    Scalar::from_bytes_reduced(&tagged_hash("BIP0340/challenge", &data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigUint;
+    use crate::private_key::PrivateKey;
+    use crate::x_only_public_key::XOnlyPublicKey;
+
+    #[test]
+    fn test_parse_serialize() {
+        let bytes: Vec<u8> = (0..64).collect();
+        let sig = SchnorrSignature::parse(&bytes).unwrap();
+        assert_eq!(sig.r()[0], 0);
+        assert_eq!(sig.s()[0], 32);
+        assert_eq!(sig.serialize().to_vec(), bytes);
+        assert!(SchnorrSignature::parse(&bytes[..63]).is_err());
+    }
+    #[test]
+    fn test_bip340_vectors() {
+        let csv = include_str!("../test_vectors/bip340_test_vectors.csv");
+        assert_eq!(csv.lines().skip(1).count(), 19);
+        for line in csv.lines().skip(1) {
+            let fields: Vec<&str> = line.split(',').collect();
+            let (index, secret, public_key, aux_rand, msg, sig, result) =
+                (fields[0], fields[1], fields[2], fields[3], fields[4], fields[5], fields[6]);
+            let msg = hex::decode(msg).unwrap();
+            let sig = SchnorrSignature::parse(&hex::decode(sig).unwrap()).unwrap();
+            if !secret.is_empty() {
+                let key = PrivateKey::new(&BigUint::from_bytes_be(&hex::decode(secret).unwrap()));
+                assert_eq!(hex::encode_upper(key.x_only_public_key().serialize()), public_key, "vector {}", index);
+                let aux: [u8; 32] = hex::decode(aux_rand).unwrap().try_into().unwrap();
+                assert_eq!(key.sign_schnorr(&msg, &aux), sig, "vector {}", index);
+            }
+            let valid = match XOnlyPublicKey::parse(&hex::decode(public_key).unwrap()) {
+                Ok(key) => key.verify_schnorr(&msg, &sig),
+                Err(_) => false,
+            };
+            assert_eq!(valid, result == "TRUE", "vector {}", index);
+        }
+    }
+}
@@ -1,99 +1,259 @@
 use std::ops::{Add};
-use std::{io::{Cursor, Read, Error}};
+use std::{io::{Cursor, Read, Error, ErrorKind}};
 use crate::helpers::varint::{encode_varint, read_varint};
 use core::fmt;
 use num::{BigUint, ToPrimitive};
 use sha2::{Digest, Sha256};
 use crate::helpers::endianness::{int_to_little_endian, little_endian_to_int};
 use crate::helpers::op_codes::*;
+use crate::execution_context::{ExecutionContext, SigVersion};
+use crate::helpers::sig_hash::SIGHASH_DEFAULT;
+use crate::script_debugger::{ScriptPhase, TraceState};
+use crate::script_error::ScriptError;
+use crate::script_type::ScriptType;
+use crate::verify_flags::VerifyFlags;
+use crate::taproot::{tap_leaf_hash, ControlBlock, TaprootChecker, TAPROOT_ANNEX_TAG, TAPROOT_LEAF_TAPSCRIPT, VALIDATION_WEIGHT_OFFSET, VALIDATION_WEIGHT_PER_SIGOP_PASSED};
+use crate::x_only_public_key::XOnlyPublicKey;
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+/// consensus limits of legacy and witness v0 scripts; tapscript only keeps the element
+/// size and stack size limits
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+pub const MAX_STACK_SIZE: usize = 1000;
+
+#[derive(Debug, Clone)]
 pub struct Script {
     pub cmds: Vec<Vec<u8>>,
+    // bytes the script was parsed from, serialized as is while cmds still match them
+    raw: Option<Vec<u8>>,
 }
 impl Script {
     pub fn new(cmds: Vec<Vec<u8>>) -> Self {
-        Self { cmds: cmds }
+        Self { cmds, raw: None }
+    }
+}
+// scripts compare by their commands, however they were encoded
+impl PartialEq for Script {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmds == other.cmds
     }
 }
+impl Eq for Script {}
+/// A script element as the interpreter sees it: an opcode, or data with the opcode
+/// that pushed it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    Op(u8),
+    Push { op_code: u8, data: Vec<u8> },
+}
+// split raw script bytes into instructions, false when the last push runs past the end
+fn parse_instructions(raw: &[u8]) -> (Vec<Instruction>, bool) {
+    let mut instructions = vec![];
+    let mut i = 0;
+    while i < raw.len() {
+        let op_code = raw[i];
+        i += 1;
+        let n = match op_code {
+            1..=75 => op_code as usize,
+            OP_PUSHDATA1 | OP_PUSHDATA2 | OP_PUSHDATA4 => {
+                let size_len = match op_code {
+                    OP_PUSHDATA1 => 1,
+                    OP_PUSHDATA2 => 2,
+                    _ => 4,
+                };
+                if i + size_len > raw.len() {
+                    return (instructions, false);
+                }
+                let n = little_endian_to_int(&raw[i..i + size_len]).to_usize().unwrap();
+                i += size_len;
+                n
+            }
+            _ => {
+                instructions.push(Instruction::Op(op_code));
+                continue;
+            }
+        };
+        if i + n > raw.len() {
+            return (instructions, false);
+        }
+        instructions.push(Instruction::Push { op_code, data: raw[i..i + n].to_vec() });
+        i += n;
+    }
+    (instructions, true)
+}
+// append the shortest push of data: OP_0, OP_1NEGATE and OP_1..OP_16 for the data
+// they push, then a length byte and PUSHDATA1/2/4
+fn push_minimal(raw: &mut Vec<u8>, data: &[u8]) {
+    match data {
+        [] => return raw.push(OP_0),
+        [n @ 1..=16] => return raw.push(OP_1 - 1 + n),
+        [0x81] => return raw.push(OP_1NEGATE),
+        _ => {}
+    }
+    let len = data.len();
+    if len < OP_PUSHDATA1 as usize {
+        raw.push(len as u8);
+    } else if len <= 0xff {
+        raw.extend([OP_PUSHDATA1, len as u8]);
+    } else if len <= 0xffff {
+        raw.push(OP_PUSHDATA2);
+        raw.extend((len as u16).to_le_bytes());
+    } else {
+        raw.push(OP_PUSHDATA4);
+        raw.extend((len as u32).to_le_bytes());
+    }
+    raw.extend(data);
+}
+fn instructions_to_cmds(instructions: &[Instruction]) -> Vec<Vec<u8>> {
+    instructions
+        .iter()
+        .map(|instruction| match instruction {
+            Instruction::Op(op_code) => vec![*op_code],
+            Instruction::Push { data, .. } => data.clone(),
+        })
+        .collect()
+}
+// split raw script bytes into commands, false when the last push runs past the end
+fn parse_cmds(raw: &[u8]) -> (Vec<Vec<u8>>, bool) {
+    let (instructions, complete) = parse_instructions(raw);
+    (instructions_to_cmds(&instructions), complete)
+}
 impl Script {
     pub fn parse(stream: &mut Cursor<Vec<u8>>) -> Result<Script, Error> {
-        let mut cmds = vec![];
-        let mut count = 0;
         let length = read_varint(stream)?; // length of entire script
-        while count < length {
-            let mut current = [0u8; 1];
-            stream.read(&mut current)?;
-            count += 1;
-            let current_byte = current[0];
-
-            match current_byte {
-                _len @ 1..=75 => {
-                    let n = current_byte;
-                    let mut cmd = vec![0u8; n as usize];
-                    stream.read(&mut cmd)?;
-                    cmds.push(cmd);
-                    count += n as u64;
-                }
-                OP_PUSHDATA1 => {
-                    let mut buffer = [0; 1];
-                    stream.read(&mut buffer)?;
-                    let ln = little_endian_to_int(buffer.as_slice()).to_u16().unwrap();
-                    let mut cmd = vec![0; ln.to_usize().unwrap()];
-                    stream.read(&mut cmd)?;
-                    cmds.push(cmd);
-                    count += ln as u64 + 1;
-                }
-                OP_PUSHDATA2 => {
-                    let mut buffer = [0; 2];
-                    stream.read(&mut buffer)?;
-                    let ln = little_endian_to_int(buffer.as_slice()).to_u16().unwrap();
-                    let mut cmd = vec![0; ln.to_usize().unwrap()];
-                    stream.read(&mut cmd)?;
-                    cmds.push(cmd);
-                    count += ln as u64 + 2;
-                }
-                _ => {
-                    let op_code = current_byte;
-                    cmds.push(vec![op_code]);
+        // the length is untrusted, so nothing is allocated for it up front
+        let mut raw = vec![];
+        stream.take(length).read_to_end(&mut raw)?;
+        if (raw.len() as u64) < length {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "script shorter than its length prefix"));
+        }
+        // a push past the end is kept: the bytes still hash and serialize, but the
+        // script can not be executed
+        let (cmds, _) = parse_cmds(&raw);
+        Ok(Script { cmds, raw: Some(raw) })
+    }
+    /// script from its bytes without the length prefix, as witness scripts are pushed
+    pub fn from_raw(raw: Vec<u8>) -> Script {
+        let (cmds, _) = parse_cmds(&raw);
+        Script { cmds, raw: Some(raw) }
+    }
+    /// Script from assembly text, the reverse of Display: opcode names with or without
+    /// OP_, decimal numbers, hex data either bare or as <hex>, 'text', and Bitcoin Core's
+    /// 0x notation for raw bytes inserted as they are. Numbers and data get their
+    /// minimal push. Up to ten digits read as a number, such hex is written as <hex>.
+    pub fn from_asm(asm: &str) -> Result<Script, Error> {
+        let invalid = |token: &str| Error::new(ErrorKind::InvalidInput, format!("invalid asm token {}", token));
+        let op_code_names = op_code_names();
+        let op_code = |token: &str| -> Option<u8> {
+            let name = if token.starts_with("OP_") { token.to_string() } else { format!("OP_{}", token) };
+            match name.as_str() {
+                "OP_FALSE" => Some(OP_0),
+                "OP_TRUE" => Some(OP_1),
+                "OP_NOP2" => Some(OP_CHECKLOCKTIMEVERIFY),
+                "OP_NOP3" => Some(OP_CHECKSEQUENCEVERIFY),
+                _ => op_code_names.iter().find(|(_, known)| **known == name).map(|(op_code, _)| *op_code),
+            }
+        };
+        let mut raw = vec![];
+        for token in asm.split_whitespace() {
+            let digits = token.strip_prefix('-').unwrap_or(token);
+            if let Some(hex) = token.strip_prefix("0x") {
+                raw.extend(hex::decode(hex).map_err(|_| invalid(token))?);
+            } else if (1..=10).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit()) {
+                // the range Core's ParseScript takes
+                let n: i64 = token.parse().map_err(|_| invalid(token))?;
+                if n.unsigned_abs() > 0xffff_ffff {
+                    return Err(invalid(token));
                 }
+                push_minimal(&mut raw, &encode_num_i64(n));
+            } else if token.len() >= 2 && token.starts_with('\'') && token.ends_with('\'') {
+                push_minimal(&mut raw, &token.as_bytes()[1..token.len() - 1]);
+            } else if let Some(op_code) = op_code(token) {
+                raw.push(op_code);
+            } else {
+                let hex = token.strip_prefix('<').and_then(|hex| hex.strip_suffix('>')).unwrap_or(token);
+                push_minimal(&mut raw, &hex::decode(hex).map_err(|_| invalid(token))?);
             }
         }
-        if count != length {
-            return Err(Error::new(
-                std::io::ErrorKind::InvalidData,
-                "parsing script failed",
-            ));
+        Ok(Script::from_raw(raw))
+    }
+    /// true when the script was parsed from bytes ending in a truncated push
+    pub fn is_malformed(&self) -> bool {
+        match &self.raw {
+            Some(raw) => !parse_cmds(raw).1,
+            None => false,
         }
-        Ok(Script { cmds })
     }
+    // size of the serialized script without its length prefix
+    fn serialized_len(&self) -> usize {
+        if let Some(raw) = &self.raw {
+            let (instructions, _) = parse_instructions(raw);
+            if instructions_to_cmds(&instructions) == self.cmds {
+                return raw.len();
+            }
+        }
+        self.instructions()
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Op(_) => 1,
+                Instruction::Push { op_code: OP_PUSHDATA1, data } => 2 + data.len(),
+                Instruction::Push { op_code: OP_PUSHDATA2, data } => 3 + data.len(),
+                Instruction::Push { op_code: OP_PUSHDATA4, data } => 5 + data.len(),
+                Instruction::Push { data, .. } => 1 + data.len(),
+            })
+            .sum()
+    }
+    /// The instructions of the script. Parsed bytes tell a 1 byte push from an opcode,
+    /// for built scripts a 1 byte command is an opcode and data gets the shortest push.
+    pub fn instructions(&self) -> Vec<Instruction> {
+        if let Some(raw) = &self.raw {
+            let (instructions, _) = parse_instructions(raw);
+            if instructions_to_cmds(&instructions) == self.cmds {
+                return instructions;
+            }
+        }
+        self.cmds
+            .iter()
+            .map(|cmd| match cmd.len() {
+                1 => Instruction::Op(cmd[0]),
+                0..=75 => Instruction::Push { op_code: cmd.len() as u8, data: cmd.clone() },
+                76..=255 => Instruction::Push { op_code: OP_PUSHDATA1, data: cmd.clone() },
+                256..=65535 => Instruction::Push { op_code: OP_PUSHDATA2, data: cmd.clone() },
+                _ => Instruction::Push { op_code: OP_PUSHDATA4, data: cmd.clone() },
+            })
+            .collect()
+    }
+    // every instruction written as it is: a 1 byte command is its byte, whether or not
+    // it names an opcode, and data of any size gets the push its instruction carries
     fn raw_serialize(&self) -> Vec<u8> {
+        if let Some(raw) = &self.raw {
+            if parse_cmds(raw).0 == self.cmds {
+                return raw.clone();
+            }
+        }
         let mut result = vec![];
-        for cmd in &self.cmds {
-            if cmd.len() == 1 {
-                if !is_op(&cmd) {
-                    panic!("OP no handled: {:?}", cmd);
-                }
-                let op_code = cmd[0];
-                result.extend(int_to_little_endian(BigUint::from(op_code), 1));
-            } else {
-                let length = cmd.len();
-                if length < 75 {
-                    result.extend(int_to_little_endian(BigUint::from(length.to_u64().unwrap()), 1));
-                } else if length < 256 {
-                    result.extend(int_to_little_endian(BigUint::from(76u64), 1));
-                    result.extend(int_to_little_endian(BigUint::from(length), 1));
-                } else if length < 520 {
-                    result.extend(int_to_little_endian(BigUint::from(77u64), 1));
-                    result.extend(int_to_little_endian(BigUint::from(length), 2));
-                } else {
-                    panic!("too long a cmd");
+        for instruction in self.instructions() {
+            match instruction {
+                Instruction::Op(op_code) => result.push(op_code),
+                Instruction::Push { op_code, data } => {
+                    result.push(op_code);
+                    match op_code {
+                        OP_PUSHDATA1 => result.extend(int_to_little_endian(BigUint::from(data.len()), 1)),
+                        OP_PUSHDATA2 => result.extend(int_to_little_endian(BigUint::from(data.len()), 2)),
+                        OP_PUSHDATA4 => result.extend(int_to_little_endian(BigUint::from(data.len()), 4)),
+                        _ => {}
+                    }
+                    result.extend(data);
                 }
-                result.extend(cmd)
             }
         }
         result
     }
+    /// the script bytes without the length prefix, as pushed in a witness
+    pub fn serialize_raw(&self) -> Vec<u8> {
+        self.raw_serialize()
+    }
     pub fn serialize(&self) -> Vec<u8> {
         let raw_result = self.raw_serialize();
         let len = raw_result.len();
@@ -103,171 +263,369 @@ impl Script {
         result.extend(raw_result);
         result
     }
-    pub fn evaluate(&self, z: &BigUint, witness: &Option<Vec<Vec<u8>>>) -> bool {
-        let mut cmds = self.cmds.clone();
+    /// Runs the script on its own and requires a true element on top at the end
+    pub fn evaluate(&self, context: &ExecutionContext) -> Result<(), ScriptError> {
         let mut stack: Vec<Vec<u8>> = vec![];
-        let mut altstack: Vec<Vec<u8>> = vec![];
-        while cmds.len() > 0 {
+        self.execute(&mut stack, context, SigVersion::Base)?;
+        match stack.last() {
+            Some(top) if cast_to_bool(top) => Ok(()),
+            _ => Err(ScriptError::EvalFalse),
+        }
+    }
+    /// Whether script_sig and witness unlock an output locked by script_pubkey under the
+    /// flags of context. The scriptSig leaves its stack to the scriptPubKey, then a P2SH
+    /// redeem script or a version 0 witness program runs on what the scriptSig pushed.
+    /// A taproot spend under TAPROOT needs the spent outputs of the context.
+    pub fn verify_script(script_sig: &Script, script_pubkey: &Script, witness: &Option<Vec<Vec<u8>>>, context: &ExecutionContext) -> Result<(), ScriptError> {
+        if context.has(VerifyFlags::SIGPUSHONLY) && !script_sig.is_push_only() {
+            return Err(ScriptError::SigPushOnly);
+        }
+        let mut stack: Vec<Vec<u8>> = vec![];
+        if let Some(tracer) = context.tracer() {
+            tracer.script(ScriptPhase::ScriptSig, script_sig);
+        }
+        script_sig.execute(&mut stack, context, SigVersion::Base)?;
+        let script_sig_stack = stack.clone();
+        if let Some(tracer) = context.tracer() {
+            tracer.script(ScriptPhase::ScriptPubKey, script_pubkey);
+        }
+        script_pubkey.execute(&mut stack, context, SigVersion::Base)?;
+        match stack.last() {
+            Some(top) if cast_to_bool(top) => {}
+            _ => return Err(ScriptError::EvalFalse),
+        }
 
-            let cmd = cmds.remove(0);
-            if cmd.len() == 1 {
-                if !is_op(&cmd) {
-                    panic!("OP not handled {:?}", cmd);
+        let mut had_witness = false;
+        if context.has(VerifyFlags::WITNESS) {
+            if let Some((version, program)) = script_pubkey.witness_program() {
+                had_witness = true;
+                if !script_sig.cmds.is_empty() {
+                    return Err(ScriptError::WitnessMalleated);
                 }
-                let op_code = cmd[0];
-                match op_code {
-                    OP_0 => { if !op_0(&mut stack) { return false; } }
-                    OP_1NEGATE => { if !op_1negate(&mut stack) { return false; } }
-
-                    OP_1 => { if !op_1(&mut stack) { return false; } }
-                    OP_2 => { if !op_2(&mut stack) { return false; } }
-                    OP_3 => { if !op_3(&mut stack) { return false; } }
-                    OP_4 => { if !op_4(&mut stack) { return false; } }
-                    OP_5 => { if !op_5(&mut stack) { return false; } }
-                    OP_6 => { if !op_6(&mut stack) { return false; } }
-                    OP_7 => { if !op_7(&mut stack) { return false; } }
-                    OP_8 => { if !op_8(&mut stack) { return false; } }
-                    OP_9 => { if !op_9(&mut stack) { return false; } }
-                    OP_10 => { if !op_10(&mut stack) { return false; } }
-                    OP_11 => { if !op_11(&mut stack) { return false; } }
-                    OP_12 => { if !op_12(&mut stack) { return false; } }
-                    OP_13 => { if !op_13(&mut stack) { return false; } }
-                    OP_14 => { if !op_14(&mut stack) { return false; } }
-                    OP_15 => { if !op_15(&mut stack) { return false; } }
-                    OP_16 => { if !op_16(&mut stack) { return false; } }
-                    OP_NOP => { if !op_nop(&mut stack) { return false; } }
-                    // OP_IF => {}
-                    // OP_NOTIF => {}
-                    // OP_ELSE => {}
-                    // OP_ENDIF => {}
-                    OP_VERIFY => { if !op_verify(&mut stack) { return false; } }
-                    OP_RETURN => { if !op_return(&mut stack) { return false; } }
-                    OP_TOALTSTACK => { if !op_toaltstack(&mut stack, &mut altstack) { return false; } }
-                    OP_FROMALTSTACK => { if !op_fromaltstack(&mut stack, &mut altstack) { return false; } }
-                    OP_2DROP => { if !op_2drop(&mut stack) { return false; } }
-                    OP_2DUP => { if !op_2dup(&mut stack) { return false; } }
-                    OP_3DUP => { if !op_3dup(&mut stack) { return false; } }
-                    OP_2OVER => { if !op_2over(&mut stack) { return false; } }
-                    OP_2ROT => { if !op_2rot(&mut stack) { return false; } }
-                    OP_2SWAP => { if !op_2swap(&mut stack) { return false; } }
-                    OP_IFDUP => { if !op_ifdup(&mut stack) { return false; } }
-                    OP_DEPTH => { if !op_depth(&mut stack) { return false; } }
-                    OP_DROP => { if !op_drop(&mut stack) { return false; } }
-                    OP_DUP => { if !op_dup(&mut stack) { return false; } }
-                    OP_NIP => { if !op_nip(&mut stack) { return false; } }
-                    OP_OVER => { if !op_over(&mut stack) { return false; } }
-                    OP_PICK => { if !op_pick(&mut stack) { return false; } }
-                    OP_ROLL => { if !op_roll(&mut stack) { return false; } }
-                    OP_ROT => { if !op_rot(&mut stack) { return false; } }
-                    OP_SWAP => { if !op_swap(&mut stack) { return false; } }
-                    OP_TUCK => { if !op_tuck(&mut stack) { return false; } }
-                    OP_SIZE => { if !op_size(&mut stack) { return false; } }
-                    OP_EQUAL => { if !op_equal(&mut stack) { return false; } }
-                    OP_EQUALVERIFY => { if !op_equalverify(&mut stack) { return false; } }
-                    OP_1ADD => { if !op_1add(&mut stack) { return false; } }
-                    OP_1SUB => { if !op_1sub(&mut stack) { return false; } }
-                    OP_NEGATE => { if !op_negate(&mut stack) { return false; } }
-                    OP_ABS => { if !op_abs(&mut stack) { return false; } }
-                    OP_NOT => { if !op_not(&mut stack) { return false; } }
-                    OP_0NOTEQUAL => { if !op_0notequal(&mut stack) { return false; } }
-                    OP_ADD => { if !op_add(&mut stack) { return false; } }
-                    OP_SUB => { if !op_sub(&mut stack) { return false; } }
-                    OP_MUL => { if !op_dup(&mut stack) { return false; } }
-                    OP_BOOLAND => { if !op_booland(&mut stack) { return false; } }
-                    OP_BOOLOR => { if !op_boolor(&mut stack) { return false; } }
-                    OP_NUMEQUAL => { if !op_numequal(&mut stack) { return false; } }
-                    OP_NUMEQUALVERIFY => { if !op_numequalverify(&mut stack) { return false; } }
-                    OP_NUMNOTEQUAL => { if !op_numnotequal(&mut stack) { return false; } }
-                    OP_LESSTHAN => { if !op_lessthan(&mut stack) { return false; } }
-                    OP_GREATERTHAN => { if !op_greaterthan(&mut stack) { return false; } }
-                    OP_LESSTHANOREQUAL => { if !op_lessthanorequal(&mut stack) { return false; } }
-                    OP_GREATERTHANOREQUAL => { if !op_greaterthanorequal(&mut stack) { return false; } }
-                    OP_MIN => { if !op_min(&mut stack) { return false; } }
-                    OP_MAX => { if !op_max(&mut stack) { return false; } }
-                    OP_WITHIN => { if !op_within(&mut stack) { return false; } }
-                    OP_RIPEMD160 => { if !op_ripemd160(&mut stack) { return false; } }
-                    OP_SHA1 => { if !op_sha1(&mut stack) { return false; } }
-                    OP_SHA256 => { if !op_sha256(&mut stack) { return false; } }
-                    OP_HASH160 => { if !op_hash160(&mut stack) { return false; } }
-                    OP_HASH256 => { if !op_hash256(&mut stack) { return false; } }
-                    OP_CODESEPARATOR => { if !op_codeseparator(&mut stack) { return false; } }
-                    OP_CHECKSIG => { if !op_checksig(&mut stack, z) { return false; } }
-                    OP_CHECKSIGVERIFY => { if !op_checksigverify(&mut stack, z) { return false; } }
-                    OP_CHECKMULTISIG => { if !op_checkmultisig(&mut stack, z) { return false; } }
-                    OP_CHECKMULTISIGVERIFY => { if !op_checkmultisigverify(&mut stack, z) { return false; } }
-
-                    _ => {
-                        panic!("UNKNOWN OP CODE {}", op_code);
+                verify_witness_program(version, &program, witness, context, false)?;
+                // the witness program checked its own stack, this one only has to pass CLEANSTACK
+                stack.truncate(1);
+            }
+        }
+        if context.has(VerifyFlags::P2SH) && script_pubkey.is_p2sh_script_pubkey() {
+            if !script_sig.is_push_only() {
+                return Err(ScriptError::SigPushOnly);
+            }
+            // the scriptPubKey checked the hash of the last push, it is the redeem script
+            stack = script_sig_stack;
+            let redeem_script = Script::from_raw(stack.pop().ok_or(ScriptError::EvalFalse)?);
+            if let Some(tracer) = context.tracer() {
+                tracer.script(ScriptPhase::RedeemScript, &redeem_script);
+            }
+            redeem_script.execute(&mut stack, context, SigVersion::Base)?;
+            match stack.last() {
+                Some(top) if cast_to_bool(top) => {}
+                _ => return Err(ScriptError::EvalFalse),
+            }
+            if context.has(VerifyFlags::WITNESS) {
+                if let Some((version, program)) = redeem_script.witness_program() {
+                    had_witness = true;
+                    // P2SH wrapped segwit: the scriptSig is exactly the canonical push of the
+                    // redeem script, a program is never short enough for an OP_n push
+                    let mut canonical = vec![];
+                    push_minimal(&mut canonical, &redeem_script.serialize_raw());
+                    if script_sig.serialize_raw() != canonical {
+                        return Err(ScriptError::WitnessMalleatedP2SH);
                     }
+                    verify_witness_program(version, &program, witness, context, true)?;
+                    stack.truncate(1);
                 }
-            } else {
-                stack.push(cmd.clone());
-                // p2sh form here. Previous row in p2sh is the push of the RedeemScript
-                if cmds.len() == 3 && self.is_p2sh(&cmds) {
-                    cmds.pop();
-                    let h160 = cmds.pop();
-                    cmds.pop();
-                    if !op_hash160(&mut stack) {
-                        return false
+            }
+        }
+        if context.has(VerifyFlags::CLEANSTACK) && stack.len() != 1 {
+            return Err(ScriptError::CleanStack);
+        }
+        if context.has(VerifyFlags::WITNESS) && !had_witness && witness.as_ref().is_some_and(|items| !items.is_empty()) {
+            return Err(ScriptError::WitnessUnexpected);
+        }
+        Ok(())
+    }
+    /// Runs the script on stack, like Bitcoin Core's EvalScript: no check of the result,
+    /// signatures commit to the script from its last OP_CODESEPARATOR
+    pub fn execute(&self, stack: &mut Vec<Vec<u8>>, context: &ExecutionContext, sig_version: SigVersion) -> Result<(), ScriptError> {
+        if self.serialized_len() > MAX_SCRIPT_SIZE {
+            return Err(ScriptError::ScriptSize);
+        }
+        if self.is_malformed() {
+            return Err(ScriptError::BadOpcode);
+        }
+        let instructions = self.instructions();
+        let mut altstack: Vec<Vec<u8>> = vec![];
+        // one entry per open OP_IF, see op_if
+        let mut exec_stack: Vec<bool> = vec![];
+        // the first instruction of the script code
+        let mut code_start = 0;
+        let mut op_count = 0;
+        for (pos, instruction) in instructions.iter().enumerate() {
+            if let Some(tracer) = context.tracer() {
+                tracer.instruction(pos, instruction, TraceState { stack, altstack: &altstack, exec_stack: &exec_stack });
+            }
+            let executing = !exec_stack.contains(&false);
+            let op_code = match instruction {
+                Instruction::Push { op_code, data } => {
+                    if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                        return Err(ScriptError::PushSize);
                     }
-                    stack.push(h160.unwrap());
-                    if !op_equal(&mut stack) {
-                        return false
+                    if executing {
+                        if context.has(VerifyFlags::MINIMALDATA) && !is_minimal_push(*op_code, data) {
+                            return Err(ScriptError::MinimalData);
+                        }
+                        stack.push(data.clone());
                     }
-                    if !op_verify(&mut stack) {
-                        println!("bad p2sh h160");
-                        return false
+                    if stack.len() + altstack.len() > MAX_STACK_SIZE {
+                        return Err(ScriptError::StackSize);
                     }
-                    let mut redeem_script: Vec<u8> = vec![];
-                    redeem_script.extend(encode_varint(cmd.len() as u64).unwrap());
-                    redeem_script.extend(cmd);
-                    let mut cursor = Cursor::new(redeem_script);
-                    cmds.extend(Script::parse(&mut cursor).unwrap().cmds);
+                    continue;
                 }
-                // witness program version 0 rule. if stack cmds are:
-                // 0 <20 byte hash> this is p2wpkh
-                if stack.len() == 2 && stack[0] == b"" && stack[1].len() == 20 {
-                    // is b"" correct ?
-                    let h160 = stack.pop();
-                    stack.pop();
-                    cmds.extend(witness.clone().unwrap());
-                    cmds.extend(Script::p2pkh_script(h160.unwrap()).cmds);
-
+                Instruction::Op(op_code) => *op_code,
+            };
+            // the limits and the opcodes that fail the script even where it does not run
+            if op_code > OP_16 {
+                op_count += 1;
+                if op_count > MAX_OPS_PER_SCRIPT {
+                    return Err(ScriptError::OpCount);
                 }
-                // witness program version 0 rule. if stack cmds are:
-                // 0 <32 byte hash> this is p2wsh
-                if stack.len() == 2 && stack[0] == b"" && stack[1].len() == 32 {
-
-                    let s256 = stack.pop();
-                    stack.pop();
-                    let mut w = witness.clone().unwrap();
-                    w.pop();
-                    cmds.extend(w);
-                    let witness_script = witness.clone().unwrap().pop().unwrap();
-                    let digest = Sha256::digest(witness_script.clone()).to_vec();
-                    if s256.clone().unwrap() != digest {
-                        println!("bad sha256 script digest: {}, s256: {}", hex::encode(digest), hex::encode(s256.clone().unwrap()));
-                        return false
+            }
+            if is_disabled(op_code) {
+                return Err(ScriptError::DisabledOpcode);
+            }
+            if op_code == OP_VERIF || op_code == OP_VERNOTIF {
+                return Err(ScriptError::BadOpcode);
+            }
+            if op_code == OP_CODESEPARATOR && sig_version == SigVersion::Base && context.has(VerifyFlags::CONST_SCRIPTCODE) {
+                return Err(ScriptError::OpCodeSeparator);
+            }
+            if executing && context.has(VerifyFlags::MINIMALDATA) {
+                let operands = numeric_operands(op_code, context.flags());
+                if stack.len() >= operands && !stack[stack.len() - operands..].iter().all(|e| is_minimal_num(e)) {
+                    return Err(ScriptError::InvalidNumber);
+                }
+            }
+            // witness v0 OP_IF takes an empty or 0x01 argument under MINIMALIF
+            if executing && (op_code == OP_IF || op_code == OP_NOTIF) && sig_version == SigVersion::WitnessV0 && context.has(VerifyFlags::MINIMALIF) {
+                if let Some(condition) = stack.last() {
+                    if condition.len() > 1 || (condition.len() == 1 && condition[0] != 1) {
+                        return Err(ScriptError::MinimalIf);
                     }
-                    let mut w_script: Vec<u8> = vec![];
-                    w_script.extend(encode_varint(witness_script.clone().len() as u64).unwrap());
-                    w_script.extend(witness_script);
-                    let mut stream = Cursor::new(w_script);
-                    let witness_script_cmds = Script::parse(stream.by_ref()).unwrap();
-                    cmds.extend(witness_script_cmds.cmds)
                 }
             }
+            match op_code {
+                OP_IF => op_if(stack, &mut exec_stack)?,
+                OP_NOTIF => op_notif(stack, &mut exec_stack)?,
+                OP_ELSE => op_else(&mut exec_stack)?,
+                OP_ENDIF => op_endif(&mut exec_stack)?,
+                _ if !executing => {}
+                OP_CODESEPARATOR => {
+                    op_codeseparator(stack)?;
+                    code_start = pos + 1;
+                }
+                OP_CHECKSIG | OP_CHECKSIGVERIFY | OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                    // every key of a multisig counts against the opcode limit
+                    if op_code == OP_CHECKMULTISIG || op_code == OP_CHECKMULTISIGVERIFY {
+                        if let Some(n) = stack.last().filter(|n| n.len() <= 4).map(|n| decode_num_i64(n)) {
+                            if (0..=20).contains(&n) {
+                                op_count += n as usize;
+                                if op_count > MAX_OPS_PER_SCRIPT {
+                                    return Err(ScriptError::OpCount);
+                                }
+                            }
+                        }
+                    }
+                    let script_code = Script::from_instructions(&instructions[code_start..]);
+                    match op_code {
+                        OP_CHECKSIG => op_checksig(stack, context, &script_code, sig_version)?,
+                        OP_CHECKSIGVERIFY => op_checksigverify(stack, context, &script_code, sig_version)?,
+                        OP_CHECKMULTISIG => op_checkmultisig(stack, context, &script_code, sig_version)?,
+                        _ => op_checkmultisigverify(stack, context, &script_code, sig_version)?,
+                    }
+                }
+                OP_CHECKLOCKTIMEVERIFY if context.has(VerifyFlags::CHECKLOCKTIMEVERIFY) => {
+                    op_checklocktimeverify(stack, context.tx().locktime(), context.sequence())?
+                }
+                OP_CHECKSEQUENCEVERIFY if context.has(VerifyFlags::CHECKSEQUENCEVERIFY) => {
+                    op_checksequenceverify(stack, context.tx().version(), context.sequence())?
+                }
+                // OP_NOP2 and OP_NOP3 before their soft forks, which are not discouraged
+                OP_CHECKLOCKTIMEVERIFY | OP_CHECKSEQUENCEVERIFY => {}
+                OP_NOOP1 | OP_NOP4..=OP_NOP10 => {
+                    if context.has(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+                        return Err(ScriptError::DiscourageUpgradableNops);
+                    }
+                }
+                _ => match execute_stack_op(op_code, stack, &mut altstack) {
+                    Some(result) => result?,
+                    None => return Err(ScriptError::BadOpcode),
+                },
+            }
+            if stack.len() + altstack.len() > MAX_STACK_SIZE {
+                return Err(ScriptError::StackSize);
+            }
         }
-        if stack.len() == 0 {
-            return false;
+        if let Some(tracer) = context.tracer() {
+            tracer.script_end(TraceState { stack, altstack: &altstack, exec_stack: &exec_stack });
         }
-        if stack.remove(0) == b"" {
-            return false;
+        if !exec_stack.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
         }
-        true
+        Ok(())
     }
-    fn is_p2sh(&self, cmds: &Vec<Vec<u8>>) -> bool {
-        cmds[0] == [0xa9] && cmds[1].len() == 20 && cmds[2] == [0x87]
+    /// BIP341 spend of the taproot output whose scriptPubKey is self: a key path
+    /// signature, or a script, its inputs and the control block proving the tree commits
+    /// to it. An unknown leaf version spends without executing the script. Only the
+    /// DISCOURAGE_* and MINIMALDATA flags matter here, the taproot rules always apply.
+    pub fn verify_taproot(&self, witness: &[Vec<u8>], checker: &TaprootChecker, flags: VerifyFlags) -> Result<(), ScriptError> {
+        if !self.is_p2tr_script_pubkey() {
+            return Err(ScriptError::WitnessProgramMismatch);
+        }
+        verify_taproot_program(&self.cmds[1], witness, checker, flags)
+    }
+    // BIP342 rules: OP_SUCCESSx, schnorr signatures checked against the validation weight
+    // budget, OP_CHECKSIGADD instead of OP_CHECKMULTISIG, minimal OP_IF arguments and a
+    // clean stack holding a single true element at the end
+    fn execute_tapscript(&self, mut stack: Vec<Vec<u8>>, checker: &TaprootChecker, mut spend: TapscriptSpend) -> Result<(), ScriptError> {
+        let instructions = self.instructions();
+        // OP_SUCCESSx anywhere in the script decides before anything runs, unless the
+        // script can not even be decoded up to it
+        if instructions.iter().any(|i| matches!(i, Instruction::Op(op_code) if is_op_success(*op_code))) {
+            if spend.flags.contains(VerifyFlags::DISCOURAGE_OP_SUCCESS) {
+                return Err(ScriptError::DiscourageOpSuccess);
+            }
+            return Ok(());
+        }
+        if self.is_malformed() {
+            return Err(ScriptError::BadOpcode);
+        }
+        if stack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
+        if stack.iter().any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+            return Err(ScriptError::PushSize);
+        }
+        let mut altstack: Vec<Vec<u8>> = vec![];
+        let mut exec_stack: Vec<bool> = vec![];
+        let mut codesep_pos: u32 = 0xffffffff;
+        for (pos, instruction) in instructions.into_iter().enumerate() {
+            if let Some(tracer) = checker.tracer() {
+                tracer.instruction(pos, &instruction, TraceState { stack: &stack, altstack: &altstack, exec_stack: &exec_stack });
+            }
+            let executing = exec_stack.iter().all(|b| *b);
+            let op_code = match instruction {
+                Instruction::Push { op_code, data } => {
+                    if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                        return Err(ScriptError::PushSize);
+                    }
+                    if executing {
+                        if spend.flags.contains(VerifyFlags::MINIMALDATA) && !is_minimal_push(op_code, &data) {
+                            return Err(ScriptError::MinimalData);
+                        }
+                        stack.push(data);
+                    }
+                    if stack.len() + altstack.len() > MAX_STACK_SIZE {
+                        return Err(ScriptError::StackSize);
+                    }
+                    continue;
+                }
+                Instruction::Op(op_code) => op_code,
+            };
+            if op_code == OP_VERIF || op_code == OP_VERNOTIF {
+                return Err(ScriptError::BadOpcode);
+            }
+            if !executing && !(OP_IF..=OP_ENDIF).contains(&op_code) {
+                continue;
+            }
+            if executing && spend.flags.contains(VerifyFlags::MINIMALDATA) {
+                let operands = numeric_operands(op_code, spend.flags);
+                if stack.len() >= operands && !stack[stack.len() - operands..].iter().all(|e| is_minimal_num(e)) {
+                    return Err(ScriptError::InvalidNumber);
+                }
+            }
+            match op_code {
+                OP_IF | OP_NOTIF => {
+                    let mut value = false;
+                    if executing {
+                        let Some(condition) = stack.pop() else {
+                            return Err(ScriptError::UnbalancedConditional);
+                        };
+                        // MINIMALIF: the argument is empty or exactly 0x01
+                        if condition.len() > 1 || (condition.len() == 1 && condition[0] != 1) {
+                            return Err(ScriptError::TapscriptMinimalIf);
+                        }
+                        value = (condition.len() == 1) == (op_code == OP_IF);
+                    }
+                    exec_stack.push(value);
+                }
+                OP_ELSE => op_else(&mut exec_stack)?,
+                OP_ENDIF => op_endif(&mut exec_stack)?,
+                OP_CODESEPARATOR => codesep_pos = pos as u32,
+                OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                    if stack.len() < 2 {
+                        return Err(ScriptError::StackUnderflow);
+                    }
+                    let key = stack.pop().unwrap();
+                    let sig = stack.pop().unwrap();
+                    let success = spend.check_signature(&sig, &key, checker, codesep_pos)?;
+                    if op_code == OP_CHECKSIGVERIFY {
+                        if !success {
+                            return Err(ScriptError::CheckSigVerify);
+                        }
+                    } else {
+                        stack.push(encode_num(success as i32));
+                    }
+                }
+                OP_CHECKSIGADD => {
+                    if stack.len() < 3 {
+                        return Err(ScriptError::StackUnderflow);
+                    }
+                    let key = stack.pop().unwrap();
+                    let n = stack.pop().unwrap();
+                    let sig = stack.pop().unwrap();
+                    if n.len() > 4 || (spend.flags.contains(VerifyFlags::MINIMALDATA) && !is_minimal_num(&n)) {
+                        return Err(ScriptError::InvalidNumber);
+                    }
+                    let success = spend.check_signature(&sig, &key, checker, codesep_pos)?;
+                    stack.push(encode_num_i64(decode_num_i64(&n) + success as i64));
+                }
+                OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => return Err(ScriptError::TapscriptCheckMultisig),
+                OP_CHECKLOCKTIMEVERIFY => {
+                    let sequence = checker.tx().tx_ins()[checker.input_index()].sequence();
+                    op_checklocktimeverify(&stack, checker.tx().locktime(), sequence)?;
+                }
+                OP_CHECKSEQUENCEVERIFY => {
+                    let sequence = checker.tx().tx_ins()[checker.input_index()].sequence();
+                    op_checksequenceverify(&stack, checker.tx().version(), sequence)?;
+                }
+                OP_NOOP1 | OP_NOP4..=OP_NOP10 => {
+                    if spend.flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+                        return Err(ScriptError::DiscourageUpgradableNops);
+                    }
+                }
+                _ => match execute_stack_op(op_code, &mut stack, &mut altstack) {
+                    Some(result) => result?,
+                    None => return Err(ScriptError::BadOpcode),
+                },
+            }
+            if stack.len() + altstack.len() > MAX_STACK_SIZE {
+                return Err(ScriptError::StackSize);
+            }
+        }
+        if let Some(tracer) = checker.tracer() {
+            tracer.script_end(TraceState { stack: &stack, altstack: &altstack, exec_stack: &exec_stack });
+        }
+        if !exec_stack.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
+        }
+        if stack.len() != 1 {
+            return Err(ScriptError::CleanStack);
+        }
+        if !cast_to_bool(&stack[0]) {
+            return Err(ScriptError::EvalFalse);
+        }
+        Ok(())
     }
     pub fn p2pkh_script(h160: Vec<u8>) -> Self {
         let mut cmds: Vec<Vec<u8>> = Vec::new();
@@ -276,7 +634,49 @@ impl Script {
         cmds.push(h160);
         cmds.push(vec![0x88]); // OP_EQUALVERIFY
         cmds.push(vec![0xac]); // OP_CHECKSIG
-        Script{cmds:cmds}
+        Script::new(cmds)
+    }
+    /// OP_1 <32 byte x-only output key>
+    pub fn p2tr_script(output_key: &XOnlyPublicKey) -> Self {
+        Script::new(vec![vec![0x51], output_key.serialize().to_vec()])
+    }
+    /// OP_HASH160 <20 byte script hash> OP_EQUAL
+    pub fn p2sh_script(h160: Vec<u8>) -> Self {
+        Script::new(vec![vec![OP_HASH160], h160, vec![OP_EQUAL]])
+    }
+    /// OP_m <sec pubkeys> OP_n OP_CHECKMULTISIG, None unless 1 <= m <= n <= 16
+    pub fn multisig_script(m: u8, sec_pubkeys: &[Vec<u8>]) -> Option<Self> {
+        if m == 0 || m as usize > sec_pubkeys.len() || sec_pubkeys.len() > 16 {
+            return None;
+        }
+        let mut cmds = vec![vec![OP_1 + m - 1]];
+        cmds.extend(sec_pubkeys.iter().cloned());
+        cmds.push(vec![OP_1 + sec_pubkeys.len() as u8 - 1]);
+        cmds.push(vec![OP_CHECKMULTISIG]);
+        Some(Script::new(cmds))
+    }
+    /// m and the SEC pubkeys of a script made by multisig_script
+    pub fn multisig_keys(&self) -> Option<(usize, Vec<Vec<u8>>)> {
+        let instructions = self.instructions();
+        let [Instruction::Op(m @ OP_1..=OP_16), keys @ .., Instruction::Op(n @ OP_1..=OP_16), Instruction::Op(OP_CHECKMULTISIG)] = instructions.as_slice() else {
+            return None;
+        };
+        let (m, n) = ((m - OP_1 + 1) as usize, (n - OP_1 + 1) as usize);
+        let sec_pubkeys: Vec<Vec<u8>> = keys
+            .iter()
+            .filter_map(|key| match key {
+                Instruction::Push { data, .. } if data.len() == 33 || data.len() == 65 => Some(data.clone()),
+                _ => None,
+            })
+            .collect();
+        if sec_pubkeys.len() != keys.len() || n != keys.len() || m > n {
+            return None;
+        }
+        Some((m, sec_pubkeys))
+    }
+    /// which standard output template the script is, see ScriptType::classify
+    pub fn script_type(&self) -> ScriptType {
+        ScriptType::classify(self)
     }
     pub fn is_p2pkh_script_pubkey(&self) -> bool {
         self.cmds.len() == 5 && self.cmds[0] == [0x76] && self.cmds[1] == [0xa9] && self.cmds[2].len() == 20 && self.cmds[3] == [0x88] && self.cmds[4] == [0xac]
@@ -290,6 +690,359 @@ impl Script {
     pub fn is_p2wsh_script_pubkey(&self) -> bool {
         self.cmds.len() == 2 && self.cmds[0] == [0x00] && self.cmds[1].len() == 32
     }
+    pub fn is_p2tr_script_pubkey(&self) -> bool {
+        self.cmds.len() == 2 && self.cmds[0] == [0x51] && self.cmds[1].len() == 32
+    }
+    /// version and program of a witness program script: OP_0 to OP_16 followed by a
+    /// single push of 2 to 40 bytes
+    pub fn witness_program(&self) -> Option<(u8, Vec<u8>)> {
+        let raw = self.raw_serialize();
+        if raw.len() < 4 || raw.len() > 42 || raw[1] as usize + 2 != raw.len() {
+            return None;
+        }
+        match raw[0] {
+            OP_0 => Some((0, raw[2..].to_vec())),
+            OP_1..=OP_16 => Some((raw[0] - OP_1 + 1, raw[2..].to_vec())),
+            _ => None,
+        }
+    }
+    /// Signature operations of the script, after Bitcoin Core's GetSigOpCount: a multisig
+    /// counts its number of keys when accurate and an OP_1 to OP_16 precedes it, 20 otherwise
+    pub fn sig_op_count(&self, accurate: bool) -> usize {
+        let mut count = 0;
+        let mut last = None;
+        for instruction in self.instructions() {
+            match instruction {
+                Instruction::Op(OP_CHECKSIG | OP_CHECKSIGVERIFY) => count += 1,
+                Instruction::Op(OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY) => count += match last {
+                    Some(Instruction::Op(n @ OP_1..=OP_16)) if accurate => (n - OP_1 + 1) as usize,
+                    _ => 20,
+                },
+                _ => {}
+            }
+            last = Some(instruction);
+        }
+        count
+    }
+    /// true when the script only pushes data, as a P2SH scriptSig must
+    pub fn is_push_only(&self) -> bool {
+        !self.is_malformed() && self.instructions().iter().all(|instruction| match instruction {
+            Instruction::Push { .. } => true,
+            Instruction::Op(op_code) => *op_code <= OP_16,
+        })
+    }
+    /// script encoding each push with the opcode it carries
+    pub fn from_instructions(instructions: &[Instruction]) -> Script {
+        let mut raw = vec![];
+        for instruction in instructions {
+            match instruction {
+                Instruction::Op(op_code) => raw.push(*op_code),
+                Instruction::Push { op_code, data } => {
+                    raw.push(*op_code);
+                    match *op_code {
+                        OP_PUSHDATA1 => raw.push(data.len() as u8),
+                        OP_PUSHDATA2 => raw.extend((data.len() as u16).to_le_bytes()),
+                        OP_PUSHDATA4 => raw.extend((data.len() as u32).to_le_bytes()),
+                        _ => {}
+                    }
+                    raw.extend(data);
+                }
+            }
+        }
+        Script::from_raw(raw)
+    }
+    /// the script without any op_code instruction, pushes of that byte are kept
+    pub fn without_op(&self, op_code: u8) -> Script {
+        let instructions = self.instructions();
+        if !instructions.contains(&Instruction::Op(op_code)) {
+            return self.clone();
+        }
+        let kept: Vec<Instruction> = instructions.into_iter().filter(|i| *i != Instruction::Op(op_code)).collect();
+        Script::from_instructions(&kept)
+    }
+    /// Bitcoin Core's FindAndDelete for a signature: the script without any minimal push
+    /// of data. A legacy signature can not commit to itself.
+    pub fn find_and_delete(&self, data: &[u8]) -> Script {
+        if data.is_empty() {
+            return self.clone();
+        }
+        let minimal_op = match data.len() {
+            1..=75 => data.len() as u8,
+            76..=255 => OP_PUSHDATA1,
+            256..=65535 => OP_PUSHDATA2,
+            _ => OP_PUSHDATA4,
+        };
+        let target = Instruction::Push { op_code: minimal_op, data: data.to_vec() };
+        let instructions = self.instructions();
+        if !instructions.contains(&target) {
+            return self.clone();
+        }
+        let kept: Vec<Instruction> = instructions.into_iter().filter(|i| *i != target).collect();
+        Script::from_instructions(&kept)
+    }
+}
+// what a tapscript execution tracks besides its stacks
+struct TapscriptSpend {
+    leaf_hash: [u8; 32],
+    annex: Option<Vec<u8>>,
+    validation_weight_left: i64,
+    flags: VerifyFlags,
+}
+impl TapscriptSpend {
+    // BIP342 signature opcode rules: an empty key, the budget running out, or a non empty
+    // signature that does not verify fail the script. An empty signature is a failed
+    // check, a key of unknown type (not 32 bytes) passes any signature unless
+    // DISCOURAGE_UPGRADABLE_PUBKEYTYPE is set.
+    fn check_signature(&mut self, sig: &[u8], key: &[u8], checker: &TaprootChecker, codesep_pos: u32) -> Result<bool, ScriptError> {
+        if key.is_empty() {
+            return Err(ScriptError::TapscriptEmptyPubKey);
+        }
+        if !sig.is_empty() {
+            self.validation_weight_left -= VALIDATION_WEIGHT_PER_SIGOP_PASSED;
+            if self.validation_weight_left < 0 {
+                return Err(ScriptError::TapscriptValidationWeight);
+            }
+        }
+        if key.len() == 32 {
+            if !sig.is_empty() {
+                check_schnorr_encoding(sig)?;
+                let key = XOnlyPublicKey::parse(key).map_err(|_| ScriptError::SigCheck)?;
+                if !checker.check_schnorr(sig, &key, Some(self.leaf_hash), self.annex.as_deref(), codesep_pos) {
+                    return Err(ScriptError::SigCheck);
+                }
+            }
+        } else if self.flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_PUBKEYTYPE) {
+            return Err(ScriptError::DiscourageUpgradablePubKeyType);
+        }
+        Ok(!sig.is_empty())
+    }
+}
+// a schnorr signature is 64 bytes, or 65 with an explicit hash type other than SIGHASH_DEFAULT
+fn check_schnorr_encoding(sig: &[u8]) -> Result<(), ScriptError> {
+    match sig.len() {
+        64 => Ok(()),
+        65 if sig[64] == SIGHASH_DEFAULT => Err(ScriptError::SchnorrSigHashType),
+        65 => Ok(()),
+        _ => Err(ScriptError::SchnorrSigSize),
+    }
+}
+// the rules of Script::verify_taproot for the witness v1 program, the x-only output key
+fn verify_taproot_program(program: &[u8], witness: &[Vec<u8>], checker: &TaprootChecker, flags: VerifyFlags) -> Result<(), ScriptError> {
+    let Ok(output_key) = XOnlyPublicKey::parse(program) else {
+        return Err(ScriptError::WitnessProgramMismatch);
+    };
+    let mut stack = witness.to_vec();
+    let annex = match stack.last() {
+        Some(last) if stack.len() >= 2 && last.first() == Some(&TAPROOT_ANNEX_TAG) => stack.pop(),
+        _ => None,
+    };
+    match stack.len() {
+        0 => Err(ScriptError::WitnessProgramWitnessEmpty),
+        1 => {
+            // a key path spend runs no script, the trace shows the signature and its hash
+            if let Some(tracer) = checker.tracer() {
+                tracer.script(ScriptPhase::KeyPath, &Script::new(vec![]));
+                tracer.script_end(TraceState { stack: &stack, altstack: &[], exec_stack: &[] });
+            }
+            check_schnorr_encoding(&stack[0])?;
+            if !checker.check_schnorr(&stack[0], &output_key, None, annex.as_deref(), 0xffffffff) {
+                return Err(ScriptError::SigCheck);
+            }
+            Ok(())
+        }
+        _ => {
+            let control = stack.pop().unwrap();
+            let tapscript = Script::from_raw(stack.pop().unwrap());
+            let Ok(control_block) = ControlBlock::parse(&control) else {
+                return Err(ScriptError::TaprootWrongControlSize);
+            };
+            if !control_block.verify_taproot_commitment(&output_key, &tapscript) {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            if control_block.leaf_version() != TAPROOT_LEAF_TAPSCRIPT {
+                if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION) {
+                    return Err(ScriptError::DiscourageUpgradableTaprootVersion);
+                }
+                return Ok(());
+            }
+            // the budget counts the whole serialized witness, annex and control block included
+            let mut witness_size = encode_varint(witness.len() as u64).unwrap().len();
+            for item in witness {
+                witness_size += encode_varint(item.len() as u64).unwrap().len() + item.len();
+            }
+            let tapscript_spend = TapscriptSpend {
+                leaf_hash: tap_leaf_hash(TAPROOT_LEAF_TAPSCRIPT, &tapscript),
+                annex,
+                validation_weight_left: witness_size as i64 + VALIDATION_WEIGHT_OFFSET,
+                flags,
+            };
+            if let Some(tracer) = checker.tracer() {
+                tracer.script(ScriptPhase::Tapscript, &tapscript);
+            }
+            tapscript.execute_tapscript(stack, checker, tapscript_spend)
+        }
+    }
+}
+// BIP141 version 0 programs: a 20 byte key hash spent like P2PKH, or the sha256 of the
+// witness script. The witness script runs on the rest of the witness and has to leave
+// exactly one true element. Native taproot under TAPROOT is checked against the spent
+// outputs of the context, other versions pass.
+fn verify_witness_program(version: u8, program: &[u8], witness: &Option<Vec<Vec<u8>>>, context: &ExecutionContext, is_p2sh: bool) -> Result<(), ScriptError> {
+    if version == 1 && program.len() == 32 && !is_p2sh && context.has(VerifyFlags::TAPROOT) {
+        let Some(prevouts) = context.prevouts() else {
+            return Err(ScriptError::TaprootMissingPrevouts);
+        };
+        let witness = witness.as_deref().unwrap_or_default();
+        let mut checker = TaprootChecker::new(context.tx(), context.input_index(), prevouts);
+        if let Some(tracer) = context.tracer() {
+            checker = checker.with_tracer(tracer);
+        }
+        return verify_taproot_program(program, witness, &checker, context.flags());
+    }
+    if version != 0 {
+        if context.has(VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM) {
+            return Err(ScriptError::DiscourageUpgradableWitnessProgram);
+        }
+        return Ok(());
+    }
+    let mut stack = witness.clone().unwrap_or_default();
+    let witness_script = match program.len() {
+        20 => {
+            if stack.len() != 2 {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            Script::p2pkh_script(program.to_vec())
+        }
+        32 => {
+            let Some(witness_script) = stack.pop() else {
+                return Err(ScriptError::WitnessProgramWitnessEmpty);
+            };
+            if Sha256::digest(&witness_script).as_slice() != program {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            Script::from_raw(witness_script)
+        }
+        _ => return Err(ScriptError::WitnessProgramWrongLength),
+    };
+    if stack.iter().any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+        return Err(ScriptError::PushSize);
+    }
+    if let Some(tracer) = context.tracer() {
+        tracer.script(ScriptPhase::WitnessScript, &witness_script);
+    }
+    witness_script.execute(&mut stack, context, SigVersion::WitnessV0)?;
+    if stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    if !cast_to_bool(&stack[0]) {
+        return Err(ScriptError::EvalFalse);
+    }
+    Ok(())
+}
+// the shortest way to push data: OP_0, OP_1NEGATE and OP_1 to OP_16 for what they
+// push, then a direct push, then the smallest OP_PUSHDATA
+fn is_minimal_push(op_code: u8, data: &[u8]) -> bool {
+    match data {
+        [] => op_code == OP_0,
+        [n @ 1..=16] => op_code == OP_1 + n - 1,
+        [0x81] => op_code == OP_1NEGATE,
+        _ if data.len() <= 75 => op_code as usize == data.len(),
+        _ if data.len() <= 255 => op_code == OP_PUSHDATA1,
+        _ if data.len() <= 65535 => op_code == OP_PUSHDATA2,
+        _ => true,
+    }
+}
+// how many elements on top of the stack op_code reads as numbers; the counts of
+// OP_CHECKMULTISIG are checked as it pops them
+fn numeric_operands(op_code: u8, flags: VerifyFlags) -> usize {
+    match op_code {
+        OP_PICK | OP_ROLL | OP_1ADD..=OP_0NOTEQUAL => 1,
+        OP_ADD..=OP_MAX => 2,
+        OP_WITHIN => 3,
+        OP_CHECKLOCKTIMEVERIFY if flags.contains(VerifyFlags::CHECKLOCKTIMEVERIFY) => 1,
+        OP_CHECKSEQUENCEVERIFY if flags.contains(VerifyFlags::CHECKSEQUENCEVERIFY) => 1,
+        _ => 0,
+    }
+}
+// opcodes that only work on the two stacks and mean the same in every script version,
+// None when op_code is not one of them
+fn execute_stack_op(op_code: u8, stack: &mut Vec<Vec<u8>>, altstack: &mut Vec<Vec<u8>>) -> Option<Result<(), ScriptError>> {
+    let ok = match op_code {
+        OP_0 => op_0(stack),
+        OP_1NEGATE => op_1negate(stack),
+        OP_1 => op_1(stack),
+        OP_2 => op_2(stack),
+        OP_3 => op_3(stack),
+        OP_4 => op_4(stack),
+        OP_5 => op_5(stack),
+        OP_6 => op_6(stack),
+        OP_7 => op_7(stack),
+        OP_8 => op_8(stack),
+        OP_9 => op_9(stack),
+        OP_10 => op_10(stack),
+        OP_11 => op_11(stack),
+        OP_12 => op_12(stack),
+        OP_13 => op_13(stack),
+        OP_14 => op_14(stack),
+        OP_15 => op_15(stack),
+        OP_16 => op_16(stack),
+        OP_NOP => op_nop(stack),
+        // OP_IF => {}
+        // OP_NOTIF => {}
+        // OP_ELSE => {}
+        // OP_ENDIF => {}
+        OP_VERIFY => op_verify(stack),
+        OP_RETURN => op_return(stack),
+        OP_TOALTSTACK => op_toaltstack(stack, altstack),
+        OP_FROMALTSTACK => op_fromaltstack(stack, altstack),
+        OP_2DROP => op_2drop(stack),
+        OP_2DUP => op_2dup(stack),
+        OP_3DUP => op_3dup(stack),
+        OP_2OVER => op_2over(stack),
+        OP_2ROT => op_2rot(stack),
+        OP_2SWAP => op_2swap(stack),
+        OP_IFDUP => op_ifdup(stack),
+        OP_DEPTH => op_depth(stack),
+        OP_DROP => op_drop(stack),
+        OP_DUP => op_dup(stack),
+        OP_NIP => op_nip(stack),
+        OP_OVER => op_over(stack),
+        OP_PICK => op_pick(stack),
+        OP_ROLL => op_roll(stack),
+        OP_ROT => op_rot(stack),
+        OP_SWAP => op_swap(stack),
+        OP_TUCK => op_tuck(stack),
+        OP_SIZE => op_size(stack),
+        OP_EQUAL => op_equal(stack),
+        OP_EQUALVERIFY => op_equalverify(stack),
+        OP_1ADD => op_1add(stack),
+        OP_1SUB => op_1sub(stack),
+        OP_NEGATE => op_negate(stack),
+        OP_ABS => op_abs(stack),
+        OP_NOT => op_not(stack),
+        OP_0NOTEQUAL => op_0notequal(stack),
+        OP_ADD => op_add(stack),
+        OP_SUB => op_sub(stack),
+        OP_BOOLAND => op_booland(stack),
+        OP_BOOLOR => op_boolor(stack),
+        OP_NUMEQUAL => op_numequal(stack),
+        OP_NUMEQUALVERIFY => op_numequalverify(stack),
+        OP_NUMNOTEQUAL => op_numnotequal(stack),
+        OP_LESSTHAN => op_lessthan(stack),
+        OP_GREATERTHAN => op_greaterthan(stack),
+        OP_LESSTHANOREQUAL => op_lessthanorequal(stack),
+        OP_GREATERTHANOREQUAL => op_greaterthanorequal(stack),
+        OP_MIN => op_min(stack),
+        OP_MAX => op_max(stack),
+        OP_WITHIN => op_within(stack),
+        OP_RIPEMD160 => op_ripemd160(stack),
+        OP_SHA1 => op_sha1(stack),
+        OP_SHA256 => op_sha256(stack),
+        OP_HASH160 => op_hash160(stack),
+        OP_HASH256 => op_hash256(stack),
+        _ => return None,
+    };
+    Some(ok)
 }
 impl Add for Script {
     type Output = Self;
@@ -300,34 +1053,52 @@ impl Add for Script {
         Script::new(combined)
     }
 }
-impl fmt::Display for Script {
+// an opcode by name, a byte naming no opcode as OP_UNKNOWN_0x.., and a push by the hex
+// of its data, in <> when the hex is only digits so from_asm does not read a number
+impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let op_code_names = op_code_names();
-        let mut result = String::new();
-
-        for cmd in &self.cmds {
-            if cmd.len() == 1 {
-                if !is_op(&cmd) {
-                    panic!("OP not handled {:?}", cmd);
+        match self {
+            Instruction::Op(op_code) => match op_code_names().get(op_code) {
+                Some(name) => write!(f, "{}", name),
+                None => write!(f, "OP_UNKNOWN_{:#04x}", op_code),
+            },
+            Instruction::Push { data, .. } if data.is_empty() => write!(f, "OP_0"),
+            Instruction::Push { data, .. } => {
+                let hex = hex::encode(data);
+                if hex.bytes().all(|b| b.is_ascii_digit()) {
+                    write!(f, "<{}>", hex)
+                } else {
+                    write!(f, "{}", hex)
                 }
-                let op_code = cmd[0];
-                result.push_str(&op_code_names[&op_code]);
-            } else {
-                result.push_str(
-                    &cmd.iter()
-                        .map(|byte| format!("{:02x}", byte))
-                        .collect::<String>(),
-                );
             }
-            result.push(' ');
         }
-        write!(f, "{}", result)
+    }
+}
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in self.instructions() {
+            write!(f, "{} ", instruction)?;
+        }
+        if self.is_malformed() {
+            write!(f, "[error] ")?;
+        }
+        Ok(())
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
     use num::Num;
+    use crate::execution_context::FixedHashChecker;
+    use crate::helpers::hash160::hash160;
+    use crate::tx::Tx;
+
+    // the book evaluates scripts without a transaction, signatures are checked against z
+    fn evaluate_with_z(script: &Script, z: &BigUint) -> Result<(), ScriptError> {
+        let tx = Tx::new(1, vec![], vec![], 0, false, false);
+        let checker = FixedHashChecker::new(z.clone());
+        script.evaluate(&ExecutionContext::new(&tx, 0, 0).with_checker(&checker))
+    }
     #[test]
     fn test_parse() {
 
@@ -348,8 +1119,8 @@ mod tests {
         let required = hex::decode("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa").unwrap();
         assert_eq!(script.cmds[0], required);
 
-        // fake test OP_PUSHDATA1
-        let script_pubkey = hex::decode("4F4c4caaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa").unwrap();
+        // fake test OP_PUSHDATA1, the length prefix counts the 78 bytes that follow
+        let script_pubkey = hex::decode("4E4c4caaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa").unwrap();
         let mut stream = Cursor::new(script_pubkey.clone());
         let script = Script::parse(stream.by_ref()).unwrap();
         println!("{}", script);
@@ -382,6 +1153,27 @@ mod tests {
 
     }
     #[test]
+    fn test_p2tr_script() {
+        let key = crate::x_only_public_key::XOnlyPublicKey::parse(&hex::decode("53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343").unwrap()).unwrap();
+        let script = Script::p2tr_script(&key);
+        assert!(script.is_p2tr_script_pubkey());
+        assert!(!script.is_p2wsh_script_pubkey());
+        assert_eq!(hex::encode(script.serialize()), "22512053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343");
+        let mut stream = Cursor::new(script.serialize());
+        assert!(Script::parse(&mut stream).unwrap().is_p2tr_script_pubkey());
+    }
+    #[test]
+    fn test_multisig_script() {
+        let secs: Vec<Vec<u8>> = (0..17u8).map(|i| [vec![0x02], vec![i; 32]].concat()).collect();
+        let script = Script::multisig_script(2, &secs[..3]).unwrap();
+        assert_eq!(script.multisig_keys(), Some((2, secs[..3].to_vec())));
+        assert!(Script::multisig_script(16, &secs[..16]).is_some());
+        assert!(Script::multisig_script(0, &secs[..3]).is_none());
+        assert!(Script::multisig_script(4, &secs[..3]).is_none());
+        assert!(Script::multisig_script(1, &secs).is_none());
+        assert!(Script::multisig_script(1, &[]).is_none());
+    }
+    #[test]
     fn test_is_op_1() {
         let mut cmds = vec![];
         cmds.push(76 as u8);
@@ -441,6 +1233,36 @@ mod tests {
 
     }
     #[test]
+    fn test_from_asm() {
+        let h160 = "e94ba250bd0dcd459173f00d84433c1bb96747cd";
+        let p2pkh = Script::p2pkh_script(hex::decode(h160).unwrap());
+        let asm = format!("OP_DUP OP_HASH160 {} OP_EQUALVERIFY OP_CHECKSIG", h160);
+        assert_eq!(Script::from_asm(&asm).unwrap(), p2pkh);
+        assert_eq!(Script::from_asm(&format!("DUP HASH160 <{}> EQUALVERIFY CHECKSIG", h160)).unwrap(), p2pkh);
+        // Display reads back
+        assert_eq!(Script::from_asm(&p2pkh.to_string()).unwrap(), p2pkh);
+        let raw = |asm: &str| hex::encode(Script::from_asm(asm).unwrap().serialize_raw());
+        // numbers as Core's push_int64
+        assert_eq!(raw("0 -1 1 16 17 -2 1000 2147483648"), "004f51600111018202e803050000008000");
+        assert_eq!(raw("OP_1NEGATE FALSE TRUE OP_NOP2 NOP3 OP_NOP10"), "4f0051b1b2b9");
+        // minimal data pushes, the bytes after 0x as they are
+        assert_eq!(raw("<> <05> <81> <00> ff"), "00554f010001ff");
+        // hex made only of digits is a number
+        assert_eq!(raw("81 <81>"), "01514f");
+        assert_eq!(raw("'Az' 0x4c01 0x05"), "02417a4c0105");
+        assert_eq!(raw(&"11".repeat(75)), format!("4b{}", "11".repeat(75)));
+        assert_eq!(raw(&"11".repeat(76)), format!("4c4c{}", "11".repeat(76)));
+        assert_eq!(raw(&"11".repeat(256)), format!("4d0001{}", "11".repeat(256)));
+        assert_eq!(Script::from_asm("-1").unwrap().to_string(), "OP_1NEGATE ");
+        // 1 byte pushes show as data and read back as the same data
+        let script = Script::from_raw(vec![0x01, 0x20, 0x01, 0xab, 0x02, 0x12, 0x34]);
+        assert_eq!(script.to_string(), "<20> ab <1234> ");
+        assert_eq!(Script::from_asm(&script.to_string()).unwrap().cmds, script.cmds);
+        for asm in ["OP_FOO", "abc", "0xzz", "4294967296", "op_dup", "'open"] {
+            assert!(Script::from_asm(asm).is_err(), "{}", asm);
+        }
+    }
+    #[test]
     fn test_eval_1() {
         let mut full_script = vec![];
         let hex = "767695935687"; // OP_DUP OP_DUP OP_MUL OP_ADD OP_6 OP_EQUAL
@@ -462,9 +1284,10 @@ mod tests {
 
         let combined_script =  script_sig + script_pubkey;
         println!("COMBINED: {}", combined_script);
-        let eval = combined_script.evaluate(&BigUint::from(0u32), &None);
+        let eval = evaluate_with_z(&combined_script, &BigUint::from(0u32));
         println!("EVAL: {:?}", eval);
-        assert_eq!(eval, true);
+        // the book's example, but OP_MUL has been disabled since 2010
+        assert_eq!(eval, Err(ScriptError::DisabledOpcode));
     }
     #[test]
     fn test_eval_2() {
@@ -488,9 +1311,9 @@ mod tests {
 
         let combined_script =  script_sig + script_pubkey;
         println!("COMBINED: {}", combined_script);
-        let eval = combined_script.evaluate(&BigUint::from(0u32), &None);
+        let eval = evaluate_with_z(&combined_script, &BigUint::from(0u32));
         println!("EVAL: {:?}", eval);
-        assert_eq!(eval, true);
+        assert!(eval.is_ok());
     }
     #[test]
     fn test_p2pk() {
@@ -512,9 +1335,9 @@ mod tests {
 
         let combined_script =  script_sig + script_pubkey;
         println!("COMBINED: {}", combined_script);
-        let eval = combined_script.evaluate(&z, &None);
+        let eval = evaluate_with_z(&combined_script, &z);
         println!("EVAL: {:?}", eval);
-        assert_eq!(eval, true);
+        assert!(eval.is_ok());
     }
     #[test]
     fn test_asm_4() {
@@ -535,4 +1358,159 @@ mod tests {
         println!("{}", script);
 
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_unknown_and_long_commands() {
+        // bytes naming no opcode print and serialize instead of panicking
+        assert_eq!(Script::from_raw(vec![0xff]).to_string(), "OP_UNKNOWN_0xff ");
+        assert_eq!(Script::from_raw(vec![0x02, 0x05]).to_string(), "[error] ");
+        assert_eq!(Script::new(vec![vec![0xff]]).serialize_raw(), vec![0xff]);
+        let script = Script::new(vec![vec![0x11; 75], vec![0x22; 600]]);
+        let raw = script.serialize_raw();
+        assert_eq!(&raw[..2], &[75, 0x11]);
+        assert_eq!(&raw[76..79], &[OP_PUSHDATA2, 0x58, 0x02]);
+        assert_eq!(Script::from_raw(raw).cmds, script.cmds);
+    }
+    #[test]
+    fn test_parse_truncated() {
+        // a length prefix past the end of the stream, small and close to 2^64
+        for raw in ["0351", "ffffffffffffffffff51"] {
+            let mut stream = Cursor::new(hex::decode(raw).unwrap());
+            assert_eq!(Script::parse(&mut stream).unwrap_err().kind(), ErrorKind::UnexpectedEof, "{}", raw);
+        }
+    }
+    #[test]
+    fn test_parse_keeps_raw_bytes() {
+        // non minimal pushes and a 1 byte push of 0x51 serialize as they were parsed
+        let raw = hex::decode("064c0161015151").unwrap();
+        let script = Script::parse(&mut Cursor::new(raw.clone())).unwrap();
+        assert_eq!(script.cmds, vec![vec![0x61], vec![0x51], vec![0x51]]);
+        assert_eq!(script.serialize(), raw);
+        assert_eq!(script, Script::new(vec![vec![0x61], vec![0x51], vec![0x51]]));
+        assert!(!script.is_malformed());
+        // once the commands change the script is serialized from them
+        let mut changed = script.clone();
+        changed.cmds.pop();
+        assert_eq!(changed.serialize(), hex::decode("026151").unwrap());
+
+        // a push running past the end
+        let raw = hex::decode("03ac4b01").unwrap();
+        let script = Script::parse(&mut Cursor::new(raw.clone())).unwrap();
+        assert!(script.is_malformed());
+        assert_eq!(script.serialize(), raw);
+        assert_eq!(evaluate_with_z(&script, &BigUint::from(0u8)), Err(ScriptError::BadOpcode));
+    }
+    #[test]
+    fn test_eval_errors() {
+        let z = BigUint::from(0u8);
+        let eval = |hex: &str| evaluate_with_z(&Script::from_raw(hex::decode(hex).unwrap()), &z);
+        // OP_1 OP_IF OP_2 OP_ELSE OP_RETURN OP_ENDIF: the OP_RETURN branch does not run
+        assert!(eval("516352676a68").is_ok());
+        // OP_0 OP_IF OP_RETURN OP_ELSE OP_2 OP_ENDIF
+        assert!(eval("00636a675268").is_ok());
+        // OP_1 OP_IF OP_1
+        assert_eq!(eval("516351"), Err(ScriptError::UnbalancedConditional));
+        assert_eq!(eval("51516a"), Err(ScriptError::OpReturn));
+        assert_eq!(eval("5152"), Ok(()));
+        assert_eq!(eval("5100"), Err(ScriptError::EvalFalse));
+        assert_eq!(eval("515288"), Err(ScriptError::EqualVerify));
+        assert_eq!(eval("76"), Err(ScriptError::StackUnderflow));
+        // OP_1 OP_VERIF
+        assert_eq!(eval("5165"), Err(ScriptError::BadOpcode));
+        // OP_SIZE keeps the element it measures
+        let script = Script::from_asm("'abc' SIZE 3 EQUALVERIFY 'abc' EQUAL").unwrap();
+        assert_eq!(evaluate_with_z(&script, &z), Ok(()));
+    }
+    #[test]
+    fn test_limits() {
+        let z = BigUint::from(0u8);
+        let eval = |raw: Vec<u8>| evaluate_with_z(&Script::from_raw(raw), &z);
+        // OP_0 OP_IF OP_CAT OP_ENDIF OP_1: disabled even where it does not run
+        assert_eq!(eval(vec![OP_0, OP_IF, OP_CAT, OP_ENDIF, OP_1]), Err(ScriptError::DisabledOpcode));
+        assert_eq!(eval(vec![OP_0, OP_IF, OP_VERIF, OP_ENDIF, OP_1]), Err(ScriptError::BadOpcode));
+        // and OP_MUL is no longer a working opcode
+        assert_eq!(eval(vec![OP_2, OP_3, OP_MUL]), Err(ScriptError::DisabledOpcode));
+
+        let mut raw = vec![OP_1];
+        raw.extend(vec![OP_NOP; 201]);
+        assert!(eval(raw.clone()).is_ok());
+        raw.push(OP_NOP);
+        assert_eq!(eval(raw), Err(ScriptError::OpCount));
+        // pushes do not count, the keys of OP_CHECKMULTISIG do
+        let mut raw = vec![OP_1; 300];
+        raw.extend(vec![OP_NOP; 201]);
+        assert!(eval(raw).is_ok());
+        let mut raw = vec![OP_0, OP_0, OP_0, OP_0, OP_0, OP_0, OP_0, OP_0, OP_2, OP_0, OP_0, OP_2];
+        raw.extend(vec![OP_NOP; 199]);
+        raw.extend([OP_CHECKMULTISIG, OP_DROP, OP_1]);
+        assert_eq!(eval(raw), Err(ScriptError::OpCount));
+
+        let mut push = vec![OP_PUSHDATA2, 0x08, 0x02];
+        push.extend(vec![1; 520]);
+        assert!(eval(push.clone()).is_ok());
+        let mut push = vec![OP_PUSHDATA2, 0x09, 0x02];
+        push.extend(vec![1; 521]);
+        assert_eq!(eval(push), Err(ScriptError::PushSize));
+
+        let mut raw = vec![OP_1; 999];
+        raw.push(OP_TOALTSTACK);
+        raw.push(OP_1);
+        assert!(eval(raw.clone()).is_ok());
+        raw.push(OP_1);
+        assert_eq!(eval(raw), Err(ScriptError::StackSize));
+
+        let mut raw = vec![OP_1];
+        raw.extend([OP_PUSHDATA1, 1, 1, OP_DROP].repeat(2500));
+        assert_eq!(raw.len(), 10_001);
+        assert_eq!(eval(raw), Err(ScriptError::ScriptSize));
+    }
+    #[test]
+    fn test_sig_op_count() {
+        let script = Script::from_raw(vec![OP_CHECKSIG, OP_3, OP_CHECKMULTISIG, OP_0, OP_CHECKMULTISIGVERIFY, OP_CHECKSIGVERIFY]);
+        assert_eq!(script.sig_op_count(true), 25);
+        assert_eq!(script.sig_op_count(false), 42);
+        // a push of 0x03 is not OP_3
+        assert_eq!(Script::from_raw(vec![0x01, 0x03, OP_CHECKMULTISIG]).sig_op_count(true), 20);
+    }
+    #[test]
+    fn test_verify_flags() {
+        let tx = Tx::new(1, vec![], vec![], 0, false, false);
+        let checker = FixedHashChecker::new(BigUint::from(0u8));
+        let verify = |script_sig: &str, script_pubkey: &str, witness: Option<Vec<Vec<u8>>>, flags: VerifyFlags| {
+            let context = ExecutionContext::new(&tx, 0, 0).with_checker(&checker).with_flags(flags);
+            let script_sig = Script::from_raw(hex::decode(script_sig).unwrap());
+            let script_pubkey = Script::from_raw(hex::decode(script_pubkey).unwrap());
+            Script::verify_script(&script_sig, &script_pubkey, &witness, &context)
+        };
+        // a P2SH output whose redeem script is OP_0 only has to match the hash before BIP16
+        let script_pubkey = format!("a914{}87", hex::encode(hash160(&[0x00])));
+        assert!(verify("0100", &script_pubkey, None, VerifyFlags::NONE).is_ok());
+        assert_eq!(verify("0100", &script_pubkey, None, VerifyFlags::P2SH), Err(ScriptError::EvalFalse));
+
+        // 0x01 pushed with a direct push instead of OP_1
+        assert!(verify("", "0101", None, VerifyFlags::NONE).is_ok());
+        assert_eq!(verify("", "0101", None, VerifyFlags::MINIMALDATA), Err(ScriptError::MinimalData));
+        // <0x0100> OP_1ADD, 1 with a needless zero byte
+        assert!(verify("", "0201008b", None, VerifyFlags::NONE).is_ok());
+        assert_eq!(verify("", "0201008b", None, VerifyFlags::MINIMALDATA), Err(ScriptError::InvalidNumber));
+
+        let clean = VerifyFlags::CLEANSTACK | VerifyFlags::P2SH | VerifyFlags::WITNESS;
+        assert!(verify("51", "51", None, VerifyFlags::NONE).is_ok());
+        assert_eq!(verify("51", "51", None, clean), Err(ScriptError::CleanStack));
+
+        // OP_1 OP_NOP4, OP_1 OP_CODESEPARATOR
+        assert!(verify("", "51b3", None, VerifyFlags::NONE).is_ok());
+        assert_eq!(verify("", "51b3", None, VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS), Err(ScriptError::DiscourageUpgradableNops));
+        // OP_1 OP_NOP2 OP_NOP3 without their flags are plain NOPs, not discouraged
+        assert!(verify("", "51b1b2", None, VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS).is_ok());
+        assert!(verify("", "51ab", None, VerifyFlags::NONE).is_ok());
+        assert_eq!(verify("", "51ab", None, VerifyFlags::CONST_SCRIPTCODE), Err(ScriptError::OpCodeSeparator));
+
+        // a witness for a non witness output
+        assert!(verify("", "51", Some(vec![vec![1]]), VerifyFlags::NONE).is_ok());
+        assert_eq!(verify("", "51", Some(vec![vec![1]]), VerifyFlags::WITNESS), Err(ScriptError::WitnessUnexpected));
+        // a version 2 witness program is anyone can spend until a soft fork gives it rules
+        assert!(verify("", "52020101", None, VerifyFlags::WITNESS).is_ok());
+        let discourage = VerifyFlags::WITNESS | VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM;
+        assert_eq!(verify("", "52020101", None, discourage), Err(ScriptError::DiscourageUpgradableWitnessProgram));
+    }
+}
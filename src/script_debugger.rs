@@ -0,0 +1,345 @@
+use std::cell::RefCell;
+use std::fmt;
+use serde_json::{json, Value};
+use crate::script::{Instruction, Script};
+use crate::script_error::ScriptError;
+use crate::tx::Tx;
+use crate::verify_flags::VerifyFlags;
+
+/// Which of the scripts verifying an input is running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptPhase {
+    ScriptSig,
+    ScriptPubKey,
+    /// the script a P2SH scriptSig pushes last
+    RedeemScript,
+    /// the P2WSH script, or the P2PKH script a P2WPKH program stands for
+    WitnessScript,
+    /// the leaf script of a taproot script path spend
+    Tapscript,
+    /// a taproot key path spend, checking a signature without running a script
+    KeyPath,
+}
+impl fmt::Display for ScriptPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ScriptPhase::ScriptSig => "scriptSig",
+            ScriptPhase::ScriptPubKey => "scriptPubKey",
+            ScriptPhase::RedeemScript => "redeemScript",
+            ScriptPhase::WitnessScript => "witnessScript",
+            ScriptPhase::Tapscript => "tapscript",
+            ScriptPhase::KeyPath => "keyPath",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The interpreter state a tracer is shown
+#[derive(Debug, Clone, Copy)]
+pub struct TraceState<'s> {
+    pub stack: &'s [Vec<u8>],
+    pub altstack: &'s [Vec<u8>],
+    /// one entry per open OP_IF, false inside a branch not taken
+    pub exec_stack: &'s [bool],
+}
+
+/// What the interpreter reports while it verifies an input, see
+/// ExecutionContext::with_tracer and TaprootChecker::with_tracer
+pub trait ScriptTracer {
+    /// the script of phase starts running
+    fn script(&self, phase: ScriptPhase, script: &Script);
+    /// the instruction at pos of the running script is about to run on state
+    fn instruction(&self, pos: usize, instruction: &Instruction, state: TraceState);
+    /// the running script ran past its last instruction, leaving state
+    fn script_end(&self, state: TraceState);
+    /// a signature hash computed to check a signature, big endian for ECDSA and
+    /// as signed for schnorr
+    fn sig_hash(&self, hash: &[u8; 32]);
+}
+
+/// The interpreter state before an instruction runs, or once a script ran to its end
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub phase: ScriptPhase,
+    /// index of the instruction in its script, the instruction count at the end
+    pub pos: usize,
+    /// None once the script ran to its end
+    pub instruction: Option<Instruction>,
+    pub stack: Vec<Vec<u8>>,
+    pub altstack: Vec<Vec<u8>>,
+    pub exec_stack: Vec<bool>,
+    /// signature hashes computed while the instruction ran
+    pub sig_hashes: Vec<[u8; 32]>,
+}
+impl Step {
+    /// the opcode name of the instruction, the hex of what it pushes for a push
+    pub fn op_name(&self) -> Option<String> {
+        self.instruction.as_ref().map(Instruction::to_string)
+    }
+}
+
+/// Where ScriptDebugger::resume stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// the instruction at pos of the script of phase
+    Position(ScriptPhase, usize),
+    /// every instruction running op_code
+    OpCode(u8),
+}
+impl Breakpoint {
+    fn matches(&self, step: &Step) -> bool {
+        match self {
+            Breakpoint::Position(phase, pos) => step.instruction.is_some() && step.phase == *phase && step.pos == *pos,
+            Breakpoint::OpCode(op_code) => matches!(step.instruction, Some(Instruction::Op(op)) if op == *op_code),
+        }
+    }
+}
+
+// collects the trace of a verification, behind a RefCell since tracers are shared
+#[derive(Default)]
+struct Recorder {
+    scripts: RefCell<Vec<(ScriptPhase, Script)>>,
+    steps: RefCell<Vec<Step>>,
+}
+impl Recorder {
+    fn record(&self, pos: usize, instruction: Option<&Instruction>, state: TraceState) {
+        let Some(phase) = self.scripts.borrow().last().map(|(phase, _)| *phase) else {
+            return;
+        };
+        self.steps.borrow_mut().push(Step {
+            phase,
+            pos,
+            instruction: instruction.cloned(),
+            stack: state.stack.to_vec(),
+            altstack: state.altstack.to_vec(),
+            exec_stack: state.exec_stack.to_vec(),
+            sig_hashes: vec![],
+        });
+    }
+}
+impl ScriptTracer for Recorder {
+    fn script(&self, phase: ScriptPhase, script: &Script) {
+        self.scripts.borrow_mut().push((phase, script.clone()));
+    }
+    fn instruction(&self, pos: usize, instruction: &Instruction, state: TraceState) {
+        self.record(pos, Some(instruction), state);
+    }
+    fn script_end(&self, state: TraceState) {
+        let pos = self.scripts.borrow().last().map_or(0, |(_, script)| script.instructions().len());
+        self.record(pos, None, state);
+    }
+    fn sig_hash(&self, hash: &[u8; 32]) {
+        if let Some(step) = self.steps.borrow_mut().last_mut() {
+            step.sig_hashes.push(*hash);
+        }
+    }
+}
+
+/// Verifies an input once, recording every step of the scripts it runs, then replays
+/// them: step one instruction at a time, or resume to the next breakpoint. The last
+/// step is the one the verification failed at, if it failed.
+pub struct ScriptDebugger {
+    input_index: usize,
+    flags: VerifyFlags,
+    scripts: Vec<(ScriptPhase, Script)>,
+    steps: Vec<Step>,
+    result: Result<(), ScriptError>,
+    position: usize,
+    breakpoints: Vec<Breakpoint>,
+}
+impl ScriptDebugger {
+    /// Runs what Tx::verify_input_with_flags runs for input_index
+    pub fn new(tx: &Tx, input_index: usize, flags: VerifyFlags) -> Self {
+        let recorder = Recorder::default();
+        let result = tx.check_input(input_index, flags, Some(&recorder));
+        ScriptDebugger {
+            input_index,
+            flags,
+            scripts: recorder.scripts.into_inner(),
+            steps: recorder.steps.into_inner(),
+            result,
+            position: 0,
+            breakpoints: vec![],
+        }
+    }
+    /// Ok when the input verifies, the error it fails with otherwise
+    pub fn result(&self) -> Result<(), ScriptError> {
+        self.result
+    }
+    /// the scripts run, in order
+    pub fn scripts(&self) -> &[(ScriptPhase, Script)] {
+        &self.scripts
+    }
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+    /// the step shown, None when no script ran
+    pub fn current(&self) -> Option<&Step> {
+        self.steps.get(self.position)
+    }
+    /// the step the verification failed at, None when it succeeded or failed before
+    /// any script ran
+    pub fn failed_step(&self) -> Option<&Step> {
+        self.result.err().and(self.steps.last())
+    }
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+    /// back to the first step
+    pub fn restart(&mut self) {
+        self.position = 0;
+    }
+    pub fn is_finished(&self) -> bool {
+        self.position + 1 >= self.steps.len()
+    }
+    /// Moves to the next step, None at the last one
+    pub fn step(&mut self) -> Option<&Step> {
+        if self.is_finished() {
+            return None;
+        }
+        self.position += 1;
+        self.current()
+    }
+    /// Continues to the next step on a breakpoint, or to the last step. None when
+    /// already at the last step.
+    pub fn resume(&mut self) -> Option<&Step> {
+        if self.is_finished() {
+            return None;
+        }
+        self.position += 1;
+        while !self.is_finished() && !self.breakpoints.iter().any(|b| b.matches(&self.steps[self.position])) {
+            self.position += 1;
+        }
+        self.current()
+    }
+    /// The whole trace: the scripts, every step with its stacks and signature hashes,
+    /// and the result, byte strings in hex
+    pub fn to_json(&self) -> Value {
+        let scripts: Vec<Value> = self.scripts
+            .iter()
+            .map(|(phase, script)| json!({
+                "phase": phase.to_string(),
+                "asm": script.instructions().iter().map(Instruction::to_string).collect::<Vec<String>>().join(" "),
+            }))
+            .collect();
+        let steps: Vec<Value> = self.steps
+            .iter()
+            .map(|step| json!({
+                "phase": step.phase.to_string(),
+                "pos": step.pos,
+                "op": step.op_name(),
+                "stack": step.stack.iter().map(hex::encode).collect::<Vec<String>>(),
+                "altstack": step.altstack.iter().map(hex::encode).collect::<Vec<String>>(),
+                "exec_stack": step.exec_stack,
+                "sig_hashes": step.sig_hashes.iter().map(hex::encode).collect::<Vec<String>>(),
+            }))
+            .collect();
+        json!({
+            "input_index": self.input_index,
+            "flags": self.flags.to_string(),
+            "scripts": scripts,
+            "steps": steps,
+            "error": self.result.err().map(|e| e.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigUint;
+    use crate::helpers::hash160::hash160;
+    use crate::helpers::op_codes::*;
+    use crate::helpers::sig_hash::SIGHASH_ALL;
+    use crate::private_key::PrivateKey;
+    use crate::tx::test_util::{legacy_sign, legacy_tx};
+
+    // spends a P2PKH output of key, signed by signer with sec in the scriptSig
+    fn p2pkh_spend(key: &PrivateKey, signer: &PrivateKey, sec: Vec<u8>) -> Tx {
+        let script_pubkey = Script::p2pkh_script(hash160(&key.point().sec(true)).to_vec());
+        let mut tx = legacy_tx(&script_pubkey, 0, 0xffffffff);
+        legacy_sign(&mut tx, signer, &script_pubkey, SIGHASH_ALL, &[sec]);
+        tx
+    }
+
+    #[test]
+    fn test_trace_p2pkh() {
+        let key = PrivateKey::new(&BigUint::from(8675309u32));
+        let mut tx = p2pkh_spend(&key, &key, key.point().sec(true));
+        assert!(tx.verify_input(0));
+        let debugger = ScriptDebugger::new(&tx, 0, VerifyFlags::MANDATORY);
+        assert_eq!(debugger.result(), Ok(()));
+        assert!(debugger.failed_step().is_none());
+        let phases: Vec<ScriptPhase> = debugger.scripts().iter().map(|(phase, _)| *phase).collect();
+        assert_eq!(phases, [ScriptPhase::ScriptSig, ScriptPhase::ScriptPubKey]);
+        // 2 pushes and the end, 5 instructions and the end
+        assert_eq!(debugger.steps().len(), 9);
+        let checksig = &debugger.steps()[7];
+        assert_eq!(checksig.op_name().as_deref(), Some("OP_CHECKSIG"));
+        assert_eq!(checksig.stack.len(), 2);
+        let z = tx.sig_hash_legacy(0, &tx.tx_ins()[0].script_pubkey(true), SIGHASH_ALL);
+        assert_eq!(checksig.sig_hashes, [<[u8; 32]>::try_from(z.to_bytes_be()).unwrap()]);
+        assert_eq!(debugger.steps()[8].stack, [vec![1]]);
+    }
+
+    #[test]
+    fn test_trace_failure() {
+        let key = PrivateKey::new(&BigUint::from(8675309u32));
+        let other = PrivateKey::new(&BigUint::from(8675310u32));
+        // signed by the wrong key: the hash matches, OP_CHECKSIG pushes false
+        let debugger = ScriptDebugger::new(&p2pkh_spend(&key, &other, key.point().sec(true)), 0, VerifyFlags::MANDATORY);
+        assert_eq!(debugger.result(), Err(ScriptError::EvalFalse));
+        let failed = debugger.failed_step().unwrap();
+        assert_eq!((failed.phase, failed.instruction.clone()), (ScriptPhase::ScriptPubKey, None));
+        assert_eq!(failed.stack, [Vec::<u8>::new()]);
+        // the pubkey of the wrong key: OP_EQUALVERIFY fails
+        let tx = p2pkh_spend(&key, &other, other.point().sec(true));
+        let debugger = ScriptDebugger::new(&tx, 0, VerifyFlags::MANDATORY);
+        assert_eq!(debugger.result(), Err(ScriptError::EqualVerify));
+        let failed = debugger.failed_step().unwrap();
+        assert_eq!(failed.op_name().as_deref(), Some("OP_EQUALVERIFY"));
+        assert_eq!(failed.pos, 3);
+    }
+
+    #[test]
+    fn test_step_and_breakpoints() {
+        let key = PrivateKey::new(&BigUint::from(8675309u32));
+        let mut debugger = ScriptDebugger::new(&p2pkh_spend(&key, &key, key.point().sec(true)), 0, VerifyFlags::MANDATORY);
+        assert_eq!(debugger.current().unwrap().pos, 0);
+        assert_eq!(debugger.step().unwrap().pos, 1);
+        debugger.add_breakpoint(Breakpoint::OpCode(OP_HASH160));
+        debugger.add_breakpoint(Breakpoint::Position(ScriptPhase::ScriptPubKey, 4));
+        let step = debugger.resume().unwrap();
+        assert_eq!((step.phase, step.op_name().as_deref()), (ScriptPhase::ScriptPubKey, Some("OP_HASH160")));
+        assert_eq!(debugger.resume().unwrap().op_name().as_deref(), Some("OP_CHECKSIG"));
+        // no breakpoint left, the end of the scriptPubKey
+        assert!(debugger.resume().unwrap().instruction.is_none());
+        assert!(debugger.is_finished());
+        assert!(debugger.step().is_none());
+        assert!(debugger.resume().is_none());
+        debugger.restart();
+        debugger.clear_breakpoints();
+        debugger.resume();
+        assert!(debugger.is_finished());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let key = PrivateKey::new(&BigUint::from(8675309u32));
+        let other = PrivateKey::new(&BigUint::from(8675310u32));
+        let debugger = ScriptDebugger::new(&p2pkh_spend(&key, &other, key.point().sec(true)), 0, VerifyFlags::MANDATORY);
+        let trace = debugger.to_json();
+        let text = trace.to_string();
+        assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), trace);
+        assert_eq!(trace["scripts"][1]["phase"], "scriptPubKey");
+        assert!(trace["scripts"][1]["asm"].as_str().unwrap().starts_with("OP_DUP OP_HASH160 "));
+        assert_eq!(trace["steps"].as_array().unwrap().len(), debugger.steps().len());
+        assert_eq!(trace["steps"][7]["op"], "OP_CHECKSIG");
+        assert_eq!(trace["steps"][7]["sig_hashes"].as_array().unwrap().len(), 1);
+        assert_eq!(trace["steps"][8]["stack"], json!([""]));
+        assert_eq!(trace["error"], ScriptError::EvalFalse.to_string());
+    }
+}
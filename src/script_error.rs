@@ -0,0 +1,149 @@
+use std::fmt;
+use crate::signature::SignatureError;
+
+/// Why a script failed, after Bitcoin Core's ScriptError_t
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptError {
+    /// the script ran to the end with an empty or false top stack element
+    EvalFalse,
+    OpReturn,
+    /// unknown opcode, or a push running past the end of the script
+    BadOpcode,
+    /// OP_CAT, OP_MUL and the other opcodes disabled in 2010
+    DisabledOpcode,
+    /// script longer than 10,000 bytes
+    ScriptSize,
+    /// push of more than 520 bytes
+    PushSize,
+    /// more than 201 opcodes other than pushes
+    OpCount,
+    /// more than 1000 elements on the stack and altstack together
+    StackSize,
+    /// OP_ELSE or OP_ENDIF without OP_IF, or OP_IF without OP_ENDIF
+    UnbalancedConditional,
+    /// an opcode needs more stack elements than there are
+    StackUnderflow,
+    AltStackUnderflow,
+    Verify,
+    EqualVerify,
+    NumEqualVerify,
+    CheckSigVerify,
+    CheckMultisigVerify,
+    /// numeric operand longer than 4 bytes, or not minimally encoded under MINIMALDATA
+    InvalidNumber,
+    PubKeyCount,
+    SigCount,
+    NegativeLocktime,
+    UnsatisfiedLocktime,
+    /// signature or key encoding rule broken
+    Signature(SignatureError),
+    /// non empty signature that does not verify where it must (tapscript)
+    SigCheck,
+    SchnorrSigSize,
+    SchnorrSigHashType,
+    /// a witness program spent with a witness that does not match it
+    WitnessProgramMismatch,
+    WitnessProgramWitnessEmpty,
+    WitnessProgramWrongLength,
+    /// native witness program spent with a non empty scriptSig
+    WitnessMalleated,
+    /// P2SH wrapped witness program with more in the scriptSig than the redeem script
+    WitnessMalleatedP2SH,
+    /// P2SH scriptSig running opcodes other than pushes
+    SigPushOnly,
+    TaprootWrongControlSize,
+    /// a taproot spend verified without the outputs spent by the transaction
+    TaprootMissingPrevouts,
+    TapscriptValidationWeight,
+    TapscriptCheckMultisig,
+    /// tapscript OP_IF argument other than empty or 0x01, a consensus rule there
+    TapscriptMinimalIf,
+    TapscriptEmptyPubKey,
+    /// more than the single result element left on the stack
+    CleanStack,
+    /// a push or number not in its shortest encoding
+    MinimalData,
+    /// OP_IF argument other than empty or 0x01
+    MinimalIf,
+    /// the extra element of OP_CHECKMULTISIG is not empty
+    SigNullDummy,
+    /// a failed signature check with a non empty signature
+    NullFail,
+    DiscourageUpgradableNops,
+    DiscourageUpgradableWitnessProgram,
+    DiscourageUpgradableTaprootVersion,
+    DiscourageOpSuccess,
+    DiscourageUpgradablePubKeyType,
+    /// a witness where the spent output is not a witness program
+    WitnessUnexpected,
+    /// uncompressed key in a witness v0 script
+    WitnessPubKeyType,
+    /// OP_CODESEPARATOR in a legacy script under CONST_SCRIPTCODE
+    OpCodeSeparator,
+    /// a legacy script code containing the signature under CONST_SCRIPTCODE
+    SigFindAndDelete,
+}
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            ScriptError::EvalFalse => "script evaluated without error but finished with a false/empty top stack element",
+            ScriptError::OpReturn => "OP_RETURN was encountered",
+            ScriptError::BadOpcode => "opcode missing or not understood",
+            ScriptError::DisabledOpcode => "attempted to use a disabled opcode",
+            ScriptError::ScriptSize => "script is too big",
+            ScriptError::PushSize => "push value size limit exceeded",
+            ScriptError::OpCount => "operation limit exceeded",
+            ScriptError::StackSize => "stack size limit exceeded",
+            ScriptError::UnbalancedConditional => "invalid OP_IF construction",
+            ScriptError::StackUnderflow => "operation not valid with the current stack size",
+            ScriptError::AltStackUnderflow => "operation not valid with the current altstack size",
+            ScriptError::Verify => "script failed an OP_VERIFY operation",
+            ScriptError::EqualVerify => "script failed an OP_EQUALVERIFY operation",
+            ScriptError::NumEqualVerify => "script failed an OP_NUMEQUALVERIFY operation",
+            ScriptError::CheckSigVerify => "script failed an OP_CHECKSIGVERIFY operation",
+            ScriptError::CheckMultisigVerify => "script failed an OP_CHECKMULTISIGVERIFY operation",
+            ScriptError::InvalidNumber => "numeric operand too long or not minimally encoded",
+            ScriptError::PubKeyCount => "pubkey count negative or limit exceeded",
+            ScriptError::SigCount => "signature count negative or greater than pubkey count",
+            ScriptError::NegativeLocktime => "negative locktime",
+            ScriptError::UnsatisfiedLocktime => "locktime requirement not satisfied",
+            ScriptError::Signature(e) => return write!(f, "{}", e),
+            ScriptError::SigCheck => "signature check failed with a non empty signature",
+            ScriptError::SchnorrSigSize => "invalid Schnorr signature size",
+            ScriptError::SchnorrSigHashType => "invalid Schnorr signature hash type",
+            ScriptError::WitnessProgramMismatch => "witness program hash mismatch",
+            ScriptError::WitnessProgramWitnessEmpty => "witness program was passed an empty witness",
+            ScriptError::WitnessProgramWrongLength => "witness program has incorrect length",
+            ScriptError::WitnessMalleated => "witness requires empty scriptSig",
+            ScriptError::WitnessMalleatedP2SH => "witness requires only-redeemscript scriptSig",
+            ScriptError::SigPushOnly => "only push operators allowed in signatures",
+            ScriptError::TaprootWrongControlSize => "invalid taproot control block size",
+            ScriptError::TaprootMissingPrevouts => "taproot spend needs the outputs spent by every input",
+            ScriptError::TapscriptValidationWeight => "too much signature validation relative to witness weight",
+            ScriptError::TapscriptCheckMultisig => "OP_CHECKMULTISIG(VERIFY) is not available in tapscript",
+            ScriptError::TapscriptMinimalIf => "OP_IF/NOTIF argument must be minimal in tapscript",
+            ScriptError::TapscriptEmptyPubKey => "empty public key in tapscript",
+            ScriptError::CleanStack => "stack size must be exactly one after execution",
+            ScriptError::MinimalData => "data push larger than necessary",
+            ScriptError::MinimalIf => "OP_IF/NOTIF argument must be minimal",
+            ScriptError::SigNullDummy => "dummy CHECKMULTISIG argument must be zero",
+            ScriptError::NullFail => "signature must be zero for failed CHECK(MULTI)SIG operation",
+            ScriptError::DiscourageUpgradableNops => "NOPx reserved for soft-fork upgrades",
+            ScriptError::DiscourageUpgradableWitnessProgram => "witness version reserved for soft-fork upgrades",
+            ScriptError::DiscourageUpgradableTaprootVersion => "taproot version reserved for soft-fork upgrades",
+            ScriptError::DiscourageOpSuccess => "OP_SUCCESSx reserved for soft-fork upgrades",
+            ScriptError::DiscourageUpgradablePubKeyType => "public key version reserved for soft-fork upgrades",
+            ScriptError::WitnessUnexpected => "witness provided for non-witness script",
+            ScriptError::WitnessPubKeyType => "using non-compressed keys in segwit",
+            ScriptError::OpCodeSeparator => "using OP_CODESEPARATOR in non-witness script",
+            ScriptError::SigFindAndDelete => "signature is found in scriptCode",
+        };
+        write!(f, "{}", msg)
+    }
+}
+impl std::error::Error for ScriptError {}
+impl From<SignatureError> for ScriptError {
+    fn from(e: SignatureError) -> Self {
+        ScriptError::Signature(e)
+    }
+}
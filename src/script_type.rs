@@ -0,0 +1,202 @@
+use std::fmt;
+use crate::helpers::op_codes::{OP_0, OP_1, OP_16, OP_1NEGATE, OP_CHECKSIG, OP_DUP, OP_EQUAL, OP_EQUALVERIFY, OP_HASH160, OP_RETURN};
+use crate::script::{Instruction, Script};
+
+/// The standard output templates, after Bitcoin Core's Solver, with what they pay to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptType {
+    /// <pubkey> OP_CHECKSIG
+    P2pk { pubkey: Vec<u8> },
+    /// OP_DUP OP_HASH160 <20 byte pubkey hash> OP_EQUALVERIFY OP_CHECKSIG
+    P2pkh { hash: [u8; 20] },
+    /// OP_HASH160 <20 byte script hash> OP_EQUAL
+    P2sh { hash: [u8; 20] },
+    /// OP_0 <20 byte pubkey hash>
+    P2wpkh { hash: [u8; 20] },
+    /// OP_0 <32 byte sha256 of the witness script>
+    P2wsh { hash: [u8; 32] },
+    /// OP_1 <32 byte x-only output key>
+    P2tr { output_key: [u8; 32] },
+    /// OP_m <n pubkeys> OP_n OP_CHECKMULTISIG
+    Multisig { m: usize, pubkeys: Vec<Vec<u8>> },
+    /// OP_RETURN followed by pushes only, the data pushed with OP_1 to OP_16 as the
+    /// numbers they push
+    NullData { data: Vec<Vec<u8>> },
+    /// OP_1 <0x4e73>, the keyless pay to anchor output
+    P2a,
+    /// a witness program of a version without rules yet
+    WitnessUnknown { version: u8, program: Vec<u8> },
+    NonStandard,
+}
+
+impl ScriptType {
+    /// Matches script_pubkey against each template byte for byte, so a template pushed
+    /// with a longer push opcode than needed is non standard
+    pub fn classify(script_pubkey: &Script) -> Self {
+        let raw = script_pubkey.serialize_raw();
+        if let [OP_HASH160, 20, hash @ .., OP_EQUAL] = raw.as_slice() {
+            if let Ok(hash) = hash.try_into() {
+                return ScriptType::P2sh { hash };
+            }
+        }
+        if let Some((version, program)) = script_pubkey.witness_program() {
+            return match (version, program.len()) {
+                (0, 20) => ScriptType::P2wpkh { hash: program.try_into().unwrap() },
+                (0, 32) => ScriptType::P2wsh { hash: program.try_into().unwrap() },
+                (0, _) => ScriptType::NonStandard,
+                (1, 32) => ScriptType::P2tr { output_key: program.try_into().unwrap() },
+                (1, 2) if program == [0x4e, 0x73] => ScriptType::P2a,
+                _ => ScriptType::WitnessUnknown { version, program },
+            };
+        }
+        if raw.first() == Some(&OP_RETURN) {
+            let rest = Script::from_raw(raw[1..].to_vec());
+            if !rest.is_push_only() {
+                return ScriptType::NonStandard;
+            }
+            let data = rest
+                .instructions()
+                .into_iter()
+                .map(|instruction| match instruction {
+                    Instruction::Push { data, .. } => data,
+                    Instruction::Op(OP_0) => vec![],
+                    Instruction::Op(OP_1NEGATE) => vec![0x81],
+                    Instruction::Op(op_code @ OP_1..=OP_16) => vec![op_code - OP_1 + 1],
+                    Instruction::Op(op_code) => vec![op_code],
+                })
+                .collect();
+            return ScriptType::NullData { data };
+        }
+        if let [len, pubkey @ .., OP_CHECKSIG] = raw.as_slice() {
+            if *len as usize == pubkey.len() && is_valid_pubkey_size(pubkey) {
+                return ScriptType::P2pk { pubkey: pubkey.to_vec() };
+            }
+        }
+        if let [OP_DUP, OP_HASH160, 20, hash @ .., OP_EQUALVERIFY, OP_CHECKSIG] = raw.as_slice() {
+            if let Ok(hash) = hash.try_into() {
+                return ScriptType::P2pkh { hash };
+            }
+        }
+        match script_pubkey.multisig_keys() {
+            Some((m, pubkeys)) if pubkeys.iter().all(|pubkey| is_valid_pubkey_size(pubkey)) => ScriptType::Multisig { m, pubkeys },
+            _ => ScriptType::NonStandard,
+        }
+    }
+    /// The hash or key the output pays to: the pubkey, pubkey hash, script hash, output
+    /// key or witness program. None for the types paying to several keys or to nothing.
+    pub fn hash_or_key(&self) -> Option<&[u8]> {
+        match self {
+            ScriptType::P2pk { pubkey } => Some(pubkey),
+            ScriptType::P2pkh { hash } | ScriptType::P2sh { hash } | ScriptType::P2wpkh { hash } => Some(hash),
+            ScriptType::P2wsh { hash } => Some(hash),
+            ScriptType::P2tr { output_key } => Some(output_key),
+            ScriptType::WitnessUnknown { program, .. } => Some(program),
+            ScriptType::Multisig { .. } | ScriptType::NullData { .. } | ScriptType::P2a | ScriptType::NonStandard => None,
+        }
+    }
+    /// true for a witness program of any version
+    pub fn is_witness(&self) -> bool {
+        matches!(self, ScriptType::P2wpkh { .. } | ScriptType::P2wsh { .. } | ScriptType::P2tr { .. } | ScriptType::P2a | ScriptType::WitnessUnknown { .. })
+    }
+}
+
+// Bitcoin Core's GetTxnOutputType names
+impl fmt::Display for ScriptType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ScriptType::P2pk { .. } => "pubkey",
+            ScriptType::P2pkh { .. } => "pubkeyhash",
+            ScriptType::P2sh { .. } => "scripthash",
+            ScriptType::P2wpkh { .. } => "witness_v0_keyhash",
+            ScriptType::P2wsh { .. } => "witness_v0_scripthash",
+            ScriptType::P2tr { .. } => "witness_v1_taproot",
+            ScriptType::Multisig { .. } => "multisig",
+            ScriptType::NullData { .. } => "nulldata",
+            ScriptType::P2a => "anchor",
+            ScriptType::WitnessUnknown { .. } => "witness_unknown",
+            ScriptType::NonStandard => "nonstandard",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// a compressed key starts with 02 or 03, an uncompressed or hybrid key with 04, 06 or 07
+fn is_valid_pubkey_size(pubkey: &[u8]) -> bool {
+    matches!((pubkey.len(), pubkey.first()), (33, Some(0x02 | 0x03)) | (65, Some(0x04 | 0x06 | 0x07)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigUint;
+    use crate::helpers::address::address_to_script_pubkey;
+    use crate::helpers::op_codes::{OP_2, OP_CHECKMULTISIG, OP_NOP};
+    use crate::private_key::PrivateKey;
+
+    fn classify_hex(script_pubkey: &str) -> ScriptType {
+        ScriptType::classify(&Script::from_raw(hex::decode(script_pubkey).unwrap()))
+    }
+
+    #[test]
+    fn test_classify_addresses() {
+        let hash = |h: &str| hex::decode(h).unwrap();
+        for (address, want, payload) in [
+            ("1BenRpVUFK65JFWcQSuHnJKzc4M8ZP8Eqa", "pubkeyhash", hash("74d691da1574e6b3c192ecfb52cc8984ee7b6c56")),
+            ("3CLoMMyuoDQTPRD3XYZtCvgvkadrAdvdXh", "scripthash", hash("74d691da1574e6b3c192ecfb52cc8984ee7b6c56")),
+            ("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", "witness_v0_keyhash", hash("751e76e8199196d454941c45d1b3a323f1433bd6")),
+            ("bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3", "witness_v0_scripthash", hash("1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262")),
+            ("bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0", "witness_v1_taproot", hash("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")),
+        ] {
+            let script_type = ScriptType::classify(&address_to_script_pubkey(address).unwrap());
+            assert_eq!(script_type.to_string(), want, "{}", address);
+            assert_eq!(script_type.hash_or_key(), Some(payload.as_slice()), "{}", address);
+        }
+    }
+
+    #[test]
+    fn test_classify_templates() {
+        let key = PrivateKey::new(&BigUint::from(8675309u32));
+        let sec = key.point().sec(true);
+        let p2pk = Script::new(vec![sec.clone(), vec![OP_CHECKSIG]]);
+        assert_eq!(ScriptType::classify(&p2pk), ScriptType::P2pk { pubkey: sec.clone() });
+        let uncompressed = key.point().sec(false);
+        let p2pk = Script::new(vec![uncompressed.clone(), vec![OP_CHECKSIG]]);
+        assert_eq!(ScriptType::classify(&p2pk).hash_or_key(), Some(uncompressed.as_slice()));
+
+        let secs = vec![sec.clone(), uncompressed.clone()];
+        let multisig = Script::multisig_script(1, &secs).unwrap();
+        assert_eq!(ScriptType::classify(&multisig), ScriptType::Multisig { m: 1, pubkeys: secs });
+        assert_eq!(ScriptType::classify(&multisig).hash_or_key(), None);
+
+        assert_eq!(classify_hex("51024e73"), ScriptType::P2a);
+        assert_eq!(classify_hex("6a"), ScriptType::NullData { data: vec![] });
+        assert_eq!(classify_hex("6a0568656c6c6f0051"), ScriptType::NullData { data: vec![b"hello".to_vec(), vec![], vec![1]] });
+        assert_eq!(classify_hex("5210000102030405060708090a0b0c0d0e0f"), ScriptType::WitnessUnknown { version: 2, program: (0..16).collect() });
+        assert_eq!(classify_hex("5102abcd"), ScriptType::WitnessUnknown { version: 1, program: vec![0xab, 0xcd] });
+        assert!(classify_hex("51024e73").is_witness());
+        assert!(!ScriptType::classify(&multisig).is_witness());
+    }
+
+    #[test]
+    fn test_classify_non_standard() {
+        let key = PrivateKey::new(&BigUint::from(8675309u32));
+        let sec = key.point().sec(true);
+        for script in [
+            // a version 0 program of neither 20 nor 32 bytes
+            hex::decode("0015000102030405060708090a0b0c0d0e0f1011121314").unwrap(),
+            // OP_RETURN followed by an opcode
+            vec![OP_RETURN, OP_NOP],
+            // the pubkey hash pushed with OP_PUSHDATA1
+            [vec![OP_DUP, OP_HASH160, 0x4c, 20], vec![0x11; 20], vec![OP_EQUALVERIFY, OP_CHECKSIG]].concat(),
+            // a key with an unknown header byte
+            [vec![33, 0x05], vec![0x11; 32], vec![OP_CHECKSIG]].concat(),
+            // 2 of 1
+            [vec![OP_2, 33], sec.clone(), vec![OP_1, OP_CHECKMULTISIG]].concat(),
+            vec![],
+        ] {
+            let script_type = ScriptType::classify(&Script::from_raw(script.clone()));
+            assert_eq!(script_type, ScriptType::NonStandard, "{}", hex::encode(&script));
+            assert_eq!(script_type.hash_or_key(), None);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use core::fmt;
-use std::io::{Cursor, Error};
+use std::io::{Cursor, Error, ErrorKind, Read};
 use num::{BigUint};
+use crate::helpers::sig_hash::{SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_SINGLE};
+use crate::secp256k1::Secp256k1;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Signature {
@@ -22,55 +24,255 @@ impl Signature {
         &self.s
     }
 
+    /// Lax DER parser, the lengths must add up but the encoding is not checked
     pub fn parse(der: &Vec<u8>) -> Result<Signature, Error> {
-
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+        let mut stream = Cursor::new(der);
         let mut buffer = [0u8; 1];
-        let mut stream = &mut Cursor::new(der);
-        //stream.read(&mut buffer)?;
-        std::io::Read::read(&mut stream, &mut buffer)?;
-        let compound = buffer[0];
-        if compound != 0x30 {
-            panic!("Invalid signature compound");
+        stream.read_exact(&mut buffer)?;
+        if buffer[0] != 0x30 {
+            return Err(invalid("Invalid signature compound"));
         }
-        let mut buffer = [0u8; 1];
-        std::io::Read::read(&mut stream, &mut buffer)?;
+        stream.read_exact(&mut buffer)?;
         let length = buffer[0];
         if length as usize + 2usize != der.len() {
-            panic!("Invalid signature length");
+            return Err(invalid("Invalid signature length"));
         }
-        let mut buffer = [0u8; 1];
-        std::io::Read::read(&mut stream, &mut buffer)?;
-        let marker = buffer[0];
-        if marker != 0x02 {
-            panic!("Invalid signature marker");
+        stream.read_exact(&mut buffer)?;
+        if buffer[0] != 0x02 {
+            return Err(invalid("Invalid signature marker"));
         }
-        let mut buffer = [0u8; 1];
-        std::io::Read::read(&mut stream, &mut buffer)?;
+        stream.read_exact(&mut buffer)?;
         let rlength = buffer[0];
+        let mut rbuffer = vec![0u8; rlength as usize];
+        stream.read_exact(&mut rbuffer)?;
+        let r = BigUint::from_bytes_be(rbuffer.as_slice());
 
-        let mut buffer = vec![0u8; rlength as usize];
-
-        std::io::Read::read(&mut stream, &mut buffer)?;
-        let r = BigUint::from_bytes_be(buffer.as_slice());
-
-        let mut buffer = [0u8; 1];
-        std::io::Read::read(&mut stream, &mut buffer)?;
-        let marker = buffer[0];
-        if marker != 0x02 {
-            panic!("Invalid signature marker 2");
+        stream.read_exact(&mut buffer)?;
+        if buffer[0] != 0x02 {
+            return Err(invalid("Invalid signature marker 2"));
         }
-        std::io::Read::read(&mut stream, &mut buffer)?;
+        stream.read_exact(&mut buffer)?;
         let slength = buffer[0];
-        let mut buffer = vec![0u8; slength as usize];
-
-        std::io::Read::read(&mut stream, &mut buffer)?;
-        let s = BigUint::from_bytes_be(buffer.as_slice());
+        let mut sbuffer = vec![0u8; slength as usize];
+        stream.read_exact(&mut sbuffer)?;
+        let s = BigUint::from_bytes_be(sbuffer.as_slice());
 
         if der.len() != 6usize + rlength as usize  + slength as usize {
-            panic!("Signature too long");
+            return Err(invalid("Signature too long"));
         }
         Ok(Signature::new(&r, &s))
     }
+    /// Bitcoin Core's ecdsa_signature_parse_der_lax, what signatures are verified with
+    /// whatever the flags: the sequence length is skipped, integer lengths may use the
+    /// long form, leading zeros are dropped and bytes after s are ignored. None when
+    /// the structure is broken or r or s is longer than 32 bytes, as such a signature
+    /// never verifies.
+    pub fn parse_der_lax(der: &[u8]) -> Option<Signature> {
+        let mut pos = 0;
+        if der.get(pos) != Some(&0x30) {
+            return None;
+        }
+        pos += 1;
+        let len_byte = *der.get(pos)? as usize;
+        pos += 1;
+        if len_byte & 0x80 != 0 {
+            // the sequence length is not used, only its bytes are skipped
+            if len_byte - 0x80 > der.len() - pos {
+                return None;
+            }
+            pos += len_byte - 0x80;
+        }
+        let r = lax_der_integer(der, &mut pos)?;
+        let s = lax_der_integer(der, &mut pos)?;
+        Some(Signature::new(&BigUint::from_bytes_be(r), &BigUint::from_bytes_be(s)))
+    }
+    /// BIP66 strict DER parser (without the sighash byte)
+    pub fn parse_strict(der: &[u8]) -> Result<Signature, SignatureError> {
+        // 0x30 len 0x02 rlen r 0x02 slen s, with r and s 1 to 33 bytes
+        if der.len() < 8 || der.len() > 72 {
+            return Err(SignatureError::Size);
+        }
+        if der[0] != 0x30 {
+            return Err(SignatureError::SequenceTag);
+        }
+        if der[1] as usize != der.len() - 2 {
+            return Err(SignatureError::Length);
+        }
+        let rlen = der[3] as usize;
+        if 5 + rlen >= der.len() {
+            return Err(SignatureError::Length);
+        }
+        let slen = der[5 + rlen] as usize;
+        if rlen + slen + 6 != der.len() {
+            return Err(SignatureError::Length);
+        }
+        if der[2] != 0x02 || der[4 + rlen] != 0x02 {
+            return Err(SignatureError::IntegerTag);
+        }
+        let r = &der[4..4 + rlen];
+        let s = &der[6 + rlen..];
+        check_der_integer(r, true)?;
+        check_der_integer(s, false)?;
+        Ok(Signature::new(&BigUint::from_bytes_be(r), &BigUint::from_bytes_be(s)))
+    }
+    /// s is at most n/2 (BIP146)
+    pub fn is_low_s(&self) -> bool {
+        let n = Secp256k1::new().n;
+        self.s <= n >> 1
+    }
+    /// the equivalent signature with s replaced by n - s when s is high
+    pub fn normalize_s(&self) -> Signature {
+        if self.is_low_s() {
+            return self.clone();
+        }
+        Signature::new(&self.r, &(Secp256k1::new().n - &self.s))
+    }
+}
+
+// an integer tag, a short or long form length and the value without its leading zeros,
+// which may be at most 32 bytes
+fn lax_der_integer<'a>(der: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    if der.get(*pos) != Some(&0x02) {
+        return None;
+    }
+    *pos += 1;
+    let len_byte = *der.get(*pos)? as usize;
+    *pos += 1;
+    let mut len = len_byte;
+    if len_byte & 0x80 != 0 {
+        let mut len_len = len_byte - 0x80;
+        if len_len > der.len() - *pos {
+            return None;
+        }
+        while len_len > 0 && der[*pos] == 0 {
+            *pos += 1;
+            len_len -= 1;
+        }
+        if len_len >= 4 {
+            return None;
+        }
+        len = 0;
+        for _ in 0..len_len {
+            len = (len << 8) + der[*pos] as usize;
+            *pos += 1;
+        }
+    }
+    if len > der.len() - *pos {
+        return None;
+    }
+    let mut value = &der[*pos..*pos + len];
+    *pos += len;
+    while let [0, rest @ ..] = value {
+        value = rest;
+    }
+    if value.len() > 32 {
+        return None;
+    }
+    Some(value)
+}
+
+/// BIP66 rules for the r (first) or s integer of a DER signature
+fn check_der_integer(bytes: &[u8], first: bool) -> Result<(), SignatureError> {
+    if bytes.is_empty() {
+        return Err(if first { SignatureError::ZeroLengthR } else { SignatureError::ZeroLengthS });
+    }
+    if bytes[0] & 0x80 != 0 {
+        return Err(if first { SignatureError::NegativeR } else { SignatureError::NegativeS });
+    }
+    // a leading zero is only allowed when the next byte would read as negative
+    if bytes.len() > 1 && bytes[0] == 0x00 && bytes[1] & 0x80 == 0 {
+        return Err(if first { SignatureError::PaddedR } else { SignatureError::PaddedS });
+    }
+    Ok(())
+}
+
+/// The signature encoding rule a script signature or key broke
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    /// DER signature shorter than 8 or longer than 72 bytes
+    Size,
+    /// missing the 0x30 sequence tag
+    SequenceTag,
+    /// the sequence or integer lengths don't match the size
+    Length,
+    /// r or s not introduced by the 0x02 integer tag
+    IntegerTag,
+    ZeroLengthR,
+    ZeroLengthS,
+    NegativeR,
+    NegativeS,
+    /// unnecessary leading zero byte in r
+    PaddedR,
+    PaddedS,
+    /// s greater than n/2 (BIP146)
+    HighS,
+    /// sighash byte is not ALL, NONE or SINGLE, optionally with ANYONECANPAY
+    HashType,
+    /// public key is neither 33 byte compressed nor 65 byte uncompressed SEC
+    PubKeyType,
+}
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            SignatureError::Size => "DER signature size out of range",
+            SignatureError::SequenceTag => "DER signature missing sequence tag",
+            SignatureError::Length => "DER signature lengths don't match",
+            SignatureError::IntegerTag => "DER signature missing integer tag",
+            SignatureError::ZeroLengthR => "DER signature has an empty r",
+            SignatureError::ZeroLengthS => "DER signature has an empty s",
+            SignatureError::NegativeR => "DER signature has a negative r",
+            SignatureError::NegativeS => "DER signature has a negative s",
+            SignatureError::PaddedR => "DER signature has a padded r",
+            SignatureError::PaddedS => "DER signature has a padded s",
+            SignatureError::HighS => "signature s is not low",
+            SignatureError::HashType => "undefined sighash type",
+            SignatureError::PubKeyType => "public key is not SEC encoded",
+        };
+        write!(f, "{}", msg)
+    }
+}
+impl std::error::Error for SignatureError {}
+
+/// Encoding rules the script interpreter applies to signatures and keys, Bitcoin Core's
+/// SCRIPT_VERIFY_DERSIG, SCRIPT_VERIFY_LOW_S and SCRIPT_VERIFY_STRICTENC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SignatureChecks {
+    pub der: bool,
+    pub low_s: bool,
+    pub strict_enc: bool,
+}
+impl SignatureChecks {
+    /// consensus before BIP66, nothing checked
+    pub const NONE: SignatureChecks = SignatureChecks { der: false, low_s: false, strict_enc: false };
+    /// relay policy of Bitcoin Core
+    pub const STANDARD: SignatureChecks = SignatureChecks { der: true, low_s: true, strict_enc: true };
+
+    /// checks a script signature (DER plus sighash byte), an empty one is always allowed
+    pub fn check_signature(&self, sig: &[u8]) -> Result<(), SignatureError> {
+        let Some((&hash_type, der)) = sig.split_last() else {
+            return Ok(());
+        };
+        if self.der || self.low_s || self.strict_enc {
+            let signature = Signature::parse_strict(der)?;
+            if self.low_s && !signature.is_low_s() {
+                return Err(SignatureError::HighS);
+            }
+        }
+        if self.strict_enc && !(SIGHASH_ALL..=SIGHASH_SINGLE).contains(&(hash_type & !SIGHASH_ANYONECANPAY)) {
+            return Err(SignatureError::HashType);
+        }
+        Ok(())
+    }
+    pub fn check_pubkey(&self, sec: &[u8]) -> Result<(), SignatureError> {
+        if !self.strict_enc {
+            return Ok(());
+        }
+        match (sec.len(), sec.first()) {
+            (33, Some(0x02 | 0x03)) | (65, Some(0x04)) => Ok(()),
+            _ => Err(SignatureError::PubKeyType),
+        }
+    }
 }
 impl Signature {
     pub fn der(&self) -> Vec<u8> {
@@ -148,4 +350,99 @@ mod tests {
             ).unwrap()
         );
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_parse_strict() {
+        let der = hex::decode("3045022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c6\
+            0221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec").unwrap();
+        let sig = Signature::parse_strict(&der).unwrap();
+        assert_eq!(sig.der(), der);
+
+        let cases: Vec<(&str, SignatureError)> = vec![
+            ("30050201010201", SignatureError::Size),
+            ("3106020101020101", SignatureError::SequenceTag),
+            ("3007020101020101", SignatureError::Length),
+            ("3006020201020101", SignatureError::Length),
+            ("3006030101020101", SignatureError::IntegerTag),
+            ("3006020101030101", SignatureError::IntegerTag),
+            ("3006020181020101", SignatureError::NegativeR),
+            ("3006020101020181", SignatureError::NegativeS),
+            ("300702020001020101", SignatureError::PaddedR),
+            ("300702010102020001", SignatureError::PaddedS),
+        ];
+        for (hex_der, error) in cases {
+            assert_eq!(Signature::parse_strict(&hex::decode(hex_der).unwrap()), Err(error), "{}", hex_der);
+        }
+        assert_eq!(Signature::parse_strict(&hex::decode("3006020002020101").unwrap()), Err(SignatureError::ZeroLengthR));
+        // the padding that keeps r positive is required, not excess
+        assert!(Signature::parse_strict(&hex::decode("300702020081020101").unwrap()).is_ok());
+    }
+    #[test]
+    fn test_parse_lax_errors() {
+        assert!(Signature::parse(&vec![]).is_err());
+        assert!(Signature::parse(&hex::decode("3106020101020101").unwrap()).is_err());
+        assert!(Signature::parse(&hex::decode("300602010102").unwrap()).is_err());
+        // lax DER still accepts excess padding
+        assert!(Signature::parse(&hex::decode("300702020001020101").unwrap()).is_ok());
+    }
+    #[test]
+    fn test_parse_der_lax() {
+        let strict = hex::decode("3045022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c6\
+            0221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec").unwrap();
+        let sig = Signature::parse_der_lax(&strict).unwrap();
+        assert_eq!(sig.der(), strict);
+        for lax in [
+            // wrong sequence length, long form sequence length, trailing bytes
+            "3000022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec",
+            "308145022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec",
+            "3045022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec0000",
+            // excess zeros in r, long form lengths of r and s
+            "30470222000037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec",
+            "304802812037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c602820021008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec",
+        ] {
+            assert_eq!(Signature::parse_der_lax(&hex::decode(lax).unwrap()), Some(sig.clone()), "{}", lax);
+        }
+        for broken in ["", "31", "3045", "30450320", "304502200102", "3045022101", "300002840100000001020101"] {
+            assert!(Signature::parse_der_lax(&hex::decode(broken).unwrap()).is_none(), "{}", broken);
+        }
+        // r longer than 32 bytes once its zeros are dropped
+        let long_r = format!("3027022101{}020101", "11".repeat(32));
+        assert!(Signature::parse_der_lax(&hex::decode(long_r).unwrap()).is_none());
+    }
+    #[test]
+    fn test_low_s() {
+        let n = Secp256k1::new().n;
+        let half = &n >> 1;
+        let r = BigUint::from(1u32);
+        assert!(Signature::new(&r, &half).is_low_s());
+        let high = Signature::new(&r, &(&half + 1u32));
+        assert!(!high.is_low_s());
+        let low = high.normalize_s();
+        assert!(low.is_low_s());
+        assert_eq!(low.s(), &(&n - &half - 1u32));
+        assert_eq!(low.normalize_s(), low);
+    }
+    #[test]
+    fn test_signature_checks() {
+        let mut low = Signature::new(&BigUint::from(1u32), &BigUint::from(1u32)).der();
+        low.push(0x01);
+        assert!(SignatureChecks::STANDARD.check_signature(&low).is_ok());
+        assert!(SignatureChecks::STANDARD.check_signature(&[]).is_ok());
+
+        let mut high = Signature::new(&BigUint::from(1u32), &(Secp256k1::new().n - 1u32)).der();
+        high.push(0x81);
+        assert!(SignatureChecks::NONE.check_signature(&high).is_ok());
+        assert!(SignatureChecks { der: true, ..SignatureChecks::NONE }.check_signature(&high).is_ok());
+        assert_eq!(SignatureChecks::STANDARD.check_signature(&high), Err(SignatureError::HighS));
+
+        *low.last_mut().unwrap() = 0x84;
+        assert_eq!(SignatureChecks::STANDARD.check_signature(&low), Err(SignatureError::HashType));
+        assert!(SignatureChecks { strict_enc: false, ..SignatureChecks::STANDARD }.check_signature(&low).is_ok());
+
+        let mut compressed = vec![0x02];
+        compressed.extend([0u8; 32]);
+        assert!(SignatureChecks::STANDARD.check_pubkey(&compressed).is_ok());
+        compressed[0] = 0x04;
+        assert_eq!(SignatureChecks::STANDARD.check_pubkey(&compressed), Err(SignatureError::PubKeyType));
+        assert!(SignatureChecks::NONE.check_pubkey(&compressed).is_ok());
+    }
+}
@@ -0,0 +1,492 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{Error, ErrorKind};
+use crate::helpers::merkle_hash::tap_branch_hash;
+use crate::helpers::sig_hash::SIGHASH_DEFAULT;
+use crate::helpers::tagged_hash::tagged_hash;
+use crate::schnorr_signature::SchnorrSignature;
+use crate::script::Script;
+use crate::script_debugger::ScriptTracer;
+use crate::tx::Tx;
+use crate::tx_output::TxOutput;
+use crate::x_only_public_key::XOnlyPublicKey;
+
+/// leaf version of BIP342 tapscript
+pub const TAPROOT_LEAF_TAPSCRIPT: u8 = 0xc0;
+/// longest merkle path a control block can carry
+pub const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+const TAPROOT_CONTROL_BASE_SIZE: usize = 33;
+/// first byte of an annex, the last witness element when there are at least two
+pub const TAPROOT_ANNEX_TAG: u8 = 0x50;
+/// BIP342 validation weight: the budget is the witness size plus this offset, and every
+/// signature checked uses up VALIDATION_WEIGHT_PER_SIGOP_PASSED of it
+pub const VALIDATION_WEIGHT_OFFSET: i64 = 50;
+pub const VALIDATION_WEIGHT_PER_SIGOP_PASSED: i64 = 50;
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg.to_string())
+}
+
+/// A script leaf of a taproot tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapLeaf {
+    version: u8,
+    script: Script,
+}
+
+impl TapLeaf {
+    /// Errors for an odd version: its low bit is the parity bit of the control block
+    pub fn new(script: Script, version: u8) -> Result<Self, Error> {
+        if version & 1 != 0 {
+            return Err(invalid("leaf version must be even"));
+        }
+        Ok(TapLeaf { version, script })
+    }
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+    pub fn script(&self) -> &Script {
+        &self.script
+    }
+    /// hash_TapLeaf(version || compact_size(script) || script)
+    pub fn leaf_hash(&self) -> [u8; 32] {
+        tap_leaf_hash(self.version, &self.script)
+    }
+}
+
+pub fn tap_leaf_hash(version: u8, script: &Script) -> [u8; 32] {
+    let mut data = vec![version];
+    // serialize() is the length prefixed script
+    data.extend(script.serialize());
+    tagged_hash("TapLeaf", &data)
+}
+
+/// A leaf and the sibling hashes from it up to the root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafInfo {
+    leaf: TapLeaf,
+    merkle_branch: Vec<[u8; 32]>,
+}
+
+impl LeafInfo {
+    pub fn leaf(&self) -> &TapLeaf {
+        &self.leaf
+    }
+    pub fn merkle_branch(&self) -> &[[u8; 32]] {
+        &self.merkle_branch
+    }
+    pub fn depth(&self) -> usize {
+        self.merkle_branch.len()
+    }
+}
+
+/// A subtree: its hash and the leaves under it
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NodeInfo {
+    hash: [u8; 32],
+    leaves: Vec<LeafInfo>,
+}
+
+impl NodeInfo {
+    fn new_leaf(leaf: TapLeaf) -> Self {
+        NodeInfo {
+            hash: leaf.leaf_hash(),
+            leaves: vec![LeafInfo { leaf, merkle_branch: vec![] }],
+        }
+    }
+    fn combine(left: NodeInfo, right: NodeInfo) -> Result<Self, Error> {
+        let hash = tap_branch_hash(&left.hash, &right.hash);
+        let mut leaves = Vec::with_capacity(left.leaves.len() + right.leaves.len());
+        for (mut info, sibling) in left.leaves.into_iter().map(|l| (l, right.hash))
+            .chain(right.leaves.into_iter().map(|l| (l, left.hash))) {
+            if info.merkle_branch.len() >= TAPROOT_CONTROL_MAX_NODE_COUNT {
+                return Err(invalid("taproot tree deeper than 128"));
+            }
+            info.merkle_branch.push(sibling);
+            leaves.push(info);
+        }
+        Ok(NodeInfo { hash, leaves })
+    }
+}
+
+/// Builds a taproot script tree from leaves given with their depth, in depth-first
+/// order, or from leaves with weights (a Huffman tree, likelier leaves closer to the root)
+#[derive(Debug, Clone, Default)]
+pub struct TaprootBuilder {
+    // partial subtrees waiting for a sibling, indexed by depth
+    branch: Vec<Option<NodeInfo>>,
+}
+
+impl TaprootBuilder {
+    pub fn new() -> Self {
+        TaprootBuilder { branch: vec![] }
+    }
+    /// tapscript leaf at depth, leaves must come in depth-first order
+    pub fn add_leaf(self, depth: u8, script: Script) -> Result<Self, Error> {
+        self.add_leaf_with_version(depth, script, TAPROOT_LEAF_TAPSCRIPT)
+    }
+    pub fn add_leaf_with_version(self, depth: u8, script: Script, version: u8) -> Result<Self, Error> {
+        self.insert(NodeInfo::new_leaf(TapLeaf::new(script, version)?), depth as usize)
+    }
+    /// tapscript leaves with weights, a heavier leaf gets a shorter path
+    pub fn with_weights(leaves: Vec<(u32, Script)>) -> Result<Self, Error> {
+        if leaves.is_empty() {
+            return Ok(TaprootBuilder::new());
+        }
+        let mut nodes: Vec<Option<NodeInfo>> = vec![];
+        // the insertion index breaks ties, so the tree only depends on the input order
+        let mut heap = BinaryHeap::new();
+        for (weight, script) in leaves {
+            heap.push(Reverse((weight as u64, nodes.len())));
+            nodes.push(Some(NodeInfo::new_leaf(TapLeaf::new(script, TAPROOT_LEAF_TAPSCRIPT)?)));
+        }
+        while heap.len() > 1 {
+            let Reverse((w1, i1)) = heap.pop().unwrap();
+            let Reverse((w2, i2)) = heap.pop().unwrap();
+            let a = nodes[i1].take().unwrap();
+            let b = nodes[i2].take().unwrap();
+            heap.push(Reverse((w1 + w2, nodes.len())));
+            nodes.push(Some(NodeInfo::combine(a, b)?));
+        }
+        let Reverse((_, root)) = heap.pop().unwrap();
+        Ok(TaprootBuilder { branch: vec![nodes[root].take()] })
+    }
+    fn insert(mut self, mut node: NodeInfo, mut depth: usize) -> Result<Self, Error> {
+        if depth > TAPROOT_CONTROL_MAX_NODE_COUNT {
+            return Err(invalid("taproot tree deeper than 128"));
+        }
+        // a shallower leaf while a deeper subtree is unfinished is not depth-first order
+        if depth + 1 < self.branch.len() {
+            return Err(invalid("leaves are not in depth-first order"));
+        }
+        // merge with the waiting siblings up the tree
+        while self.branch.len() == depth + 1 {
+            match self.branch.pop().unwrap() {
+                Some(sibling) => {
+                    if depth == 0 {
+                        return Err(invalid("taproot tree is already complete"));
+                    }
+                    node = NodeInfo::combine(sibling, node)?;
+                    depth -= 1;
+                }
+                None => {
+                    self.branch.push(None);
+                    break;
+                }
+            }
+        }
+        if self.branch.len() < depth + 1 {
+            self.branch.resize(depth + 1, None);
+        }
+        self.branch[depth] = Some(node);
+        Ok(self)
+    }
+    /// true when every subtree has its sibling, or nothing was added
+    pub fn is_complete(&self) -> bool {
+        self.branch.is_empty() || (self.branch.len() == 1 && self.branch[0].is_some())
+    }
+    pub fn finalize(self, internal_key: &XOnlyPublicKey) -> Result<TaprootSpendInfo, Error> {
+        if !self.is_complete() {
+            return Err(invalid("taproot tree is incomplete"));
+        }
+        let root = self.branch.into_iter().next().flatten();
+        let merkle_root = root.as_ref().map(|node| node.hash);
+        let (output_key, output_key_parity) = internal_key.point().taproot_tweak(merkle_root);
+        Ok(TaprootSpendInfo {
+            internal_key: internal_key.clone(),
+            merkle_root,
+            output_key,
+            output_key_parity,
+            leaves: root.map(|node| node.leaves).unwrap_or_default(),
+        })
+    }
+}
+
+/// Everything needed to pay to and spend from a taproot output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaprootSpendInfo {
+    internal_key: XOnlyPublicKey,
+    merkle_root: Option<[u8; 32]>,
+    output_key: XOnlyPublicKey,
+    output_key_parity: bool,
+    leaves: Vec<LeafInfo>,
+}
+
+impl TaprootSpendInfo {
+    pub fn internal_key(&self) -> &XOnlyPublicKey {
+        &self.internal_key
+    }
+    pub fn merkle_root(&self) -> Option<[u8; 32]> {
+        self.merkle_root
+    }
+    pub fn output_key(&self) -> &XOnlyPublicKey {
+        &self.output_key
+    }
+    pub fn output_key_parity(&self) -> bool {
+        self.output_key_parity
+    }
+    pub fn leaves(&self) -> &[LeafInfo] {
+        &self.leaves
+    }
+    pub fn script_pubkey(&self) -> Script {
+        Script::p2tr_script(&self.output_key)
+    }
+    /// control block for spending the leaf, the shortest path when it appears twice
+    pub fn control_block(&self, leaf: &TapLeaf) -> Option<ControlBlock> {
+        self.leaves
+            .iter()
+            .filter(|info| info.leaf == *leaf)
+            .min_by_key(|info| info.depth())
+            .map(|info| ControlBlock {
+                leaf_version: leaf.version,
+                output_key_parity: self.output_key_parity,
+                internal_key: self.internal_key.clone(),
+                merkle_branch: info.merkle_branch.clone(),
+            })
+    }
+}
+
+/// Last witness element of a script path spend: leaf version and output key parity,
+/// internal key, then the merkle path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlBlock {
+    leaf_version: u8,
+    output_key_parity: bool,
+    internal_key: XOnlyPublicKey,
+    merkle_branch: Vec<[u8; 32]>,
+}
+
+impl ControlBlock {
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let invalid_data = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+        if bytes.len() < TAPROOT_CONTROL_BASE_SIZE
+            || !(bytes.len() - TAPROOT_CONTROL_BASE_SIZE).is_multiple_of(32)
+            || bytes.len() > TAPROOT_CONTROL_BASE_SIZE + 32 * TAPROOT_CONTROL_MAX_NODE_COUNT {
+            return Err(invalid_data("invalid control block size"));
+        }
+        let internal_key = XOnlyPublicKey::parse(&bytes[1..TAPROOT_CONTROL_BASE_SIZE])?;
+        let merkle_branch = bytes[TAPROOT_CONTROL_BASE_SIZE..]
+            .chunks(32)
+            .map(|chunk| chunk.try_into().unwrap())
+            .collect();
+        Ok(ControlBlock {
+            leaf_version: bytes[0] & 0xfe,
+            output_key_parity: bytes[0] & 1 == 1,
+            internal_key,
+            merkle_branch,
+        })
+    }
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = vec![self.leaf_version | self.output_key_parity as u8];
+        result.extend(self.internal_key.serialize());
+        for node in &self.merkle_branch {
+            result.extend(node);
+        }
+        result
+    }
+    pub fn leaf_version(&self) -> u8 {
+        self.leaf_version
+    }
+    pub fn output_key_parity(&self) -> bool {
+        self.output_key_parity
+    }
+    pub fn internal_key(&self) -> &XOnlyPublicKey {
+        &self.internal_key
+    }
+    pub fn merkle_branch(&self) -> &[[u8; 32]] {
+        &self.merkle_branch
+    }
+    /// merkle root of the tree committing to script along this path
+    pub fn merkle_root(&self, script: &Script) -> [u8; 32] {
+        self.merkle_branch
+            .iter()
+            .fold(tap_leaf_hash(self.leaf_version, script), |k, node| tap_branch_hash(&k, node))
+    }
+    /// BIP341 script path commitment check against the output key being spent
+    pub fn verify_taproot_commitment(&self, output_key: &XOnlyPublicKey, script: &Script) -> bool {
+        let merkle_root = self.merkle_root(script);
+        let (key, parity) = self.internal_key.point().taproot_tweak(Some(merkle_root));
+        key == *output_key && parity == self.output_key_parity
+    }
+}
+
+/// Checks taproot signatures of one input: what the BIP341 signature hash commits to
+pub struct TaprootChecker<'a> {
+    tx: &'a Tx,
+    input_index: usize,
+    prevouts: &'a [TxOutput],
+    tracer: Option<&'a dyn ScriptTracer>,
+}
+
+impl<'a> TaprootChecker<'a> {
+    /// prevouts are the outputs spent by every input of tx, in input order
+    pub fn new(tx: &'a Tx, input_index: usize, prevouts: &'a [TxOutput]) -> Self {
+        TaprootChecker { tx, input_index, prevouts, tracer: None }
+    }
+    /// reports every tapscript instruction executed and signature hash computed to tracer
+    pub fn with_tracer(mut self, tracer: &'a dyn ScriptTracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+    pub fn tracer(&self) -> Option<&'a dyn ScriptTracer> {
+        self.tracer
+    }
+    pub fn tx(&self) -> &Tx {
+        self.tx
+    }
+    pub fn input_index(&self) -> usize {
+        self.input_index
+    }
+    /// A 64 byte signature signs with SIGHASH_DEFAULT, a 65 byte one ends with an explicit
+    /// hash type. leaf_hash and codesep_pos are those of a script path spend.
+    pub fn check_schnorr(&self, sig: &[u8], key: &XOnlyPublicKey, leaf_hash: Option<[u8; 32]>, annex: Option<&[u8]>, codesep_pos: u32) -> bool {
+        let hash_type = match sig.len() {
+            64 => SIGHASH_DEFAULT,
+            65 if sig[64] != SIGHASH_DEFAULT => sig[64],
+            _ => return false,
+        };
+        let Ok(signature) = SchnorrSignature::parse(&sig[..64]) else {
+            return false;
+        };
+        match self.tx.sig_hash_taproot_with_codesep(self.input_index, self.prevouts, hash_type, leaf_hash, annex, codesep_pos) {
+            Ok(msg) => {
+                if let Some(tracer) = self.tracer {
+                    tracer.sig_hash(&msg);
+                }
+                key.verify_schnorr(&msg, &signature)
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn key(hex_key: &str) -> XOnlyPublicKey {
+        XOnlyPublicKey::parse(&hex::decode(hex_key).unwrap()).unwrap()
+    }
+    fn script(hex_script: &str) -> Script {
+        let raw = hex::decode(hex_script).unwrap();
+        let mut bytes = vec![raw.len() as u8];
+        bytes.extend(raw);
+        Script::parse(&mut Cursor::new(bytes)).unwrap()
+    }
+    fn op(code: u8) -> Script {
+        Script::new(vec![vec![code]])
+    }
+
+    #[test]
+    fn test_single_leaf() {
+        // BIP341 wallet test vectors, scriptPubKey index 1
+        let internal = key("187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27");
+        let leaf_script = script("20d85a959b0290bf19bb89ed43c916be835475d013da4b362117393e25a48229b8ac");
+        let info = TaprootBuilder::new().add_leaf(0, leaf_script.clone()).unwrap().finalize(&internal).unwrap();
+        let leaf = TapLeaf::new(leaf_script.clone(), TAPROOT_LEAF_TAPSCRIPT).unwrap();
+        assert_eq!(hex::encode(leaf.leaf_hash()), "5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21");
+        assert_eq!(info.merkle_root(), Some(leaf.leaf_hash()));
+        assert_eq!(info.output_key().to_string(), "147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3");
+        let control_block = info.control_block(&leaf).unwrap();
+        assert_eq!(hex::encode(control_block.serialize()), "c1187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27");
+        assert!(control_block.verify_taproot_commitment(info.output_key(), &leaf_script));
+    }
+    #[test]
+    fn test_key_path_only() {
+        let internal = key("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+        let info = TaprootBuilder::new().finalize(&internal).unwrap();
+        assert_eq!(info.merkle_root(), None);
+        assert_eq!(info.output_key().to_string(), "53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343");
+        assert!(info.script_pubkey().is_p2tr_script_pubkey());
+        assert!(info.control_block(&TapLeaf::new(op(0x51), TAPROOT_LEAF_TAPSCRIPT).unwrap()).is_none());
+    }
+    #[test]
+    fn test_depths() {
+        // ((A, (B, C)), D)
+        let internal = key("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+        let scripts: Vec<Script> = (0x51..0x55).map(op).collect();
+        let info = TaprootBuilder::new()
+            .add_leaf(2, scripts[0].clone()).unwrap()
+            .add_leaf(3, scripts[1].clone()).unwrap()
+            .add_leaf(3, scripts[2].clone()).unwrap()
+            .add_leaf(1, scripts[3].clone()).unwrap()
+            .finalize(&internal).unwrap();
+        let h: Vec<[u8; 32]> = scripts.iter().map(|s| tap_leaf_hash(TAPROOT_LEAF_TAPSCRIPT, s)).collect();
+        let bc = tap_branch_hash(&h[1], &h[2]);
+        let abc = tap_branch_hash(&h[0], &bc);
+        assert_eq!(info.merkle_root(), Some(tap_branch_hash(&abc, &h[3])));
+
+        let depths: Vec<usize> = scripts.iter()
+            .map(|s| info.control_block(&TapLeaf::new(s.clone(), TAPROOT_LEAF_TAPSCRIPT).unwrap()).unwrap().merkle_branch().len())
+            .collect();
+        assert_eq!(depths, vec![2, 3, 3, 1]);
+        let b = info.control_block(&TapLeaf::new(scripts[1].clone(), TAPROOT_LEAF_TAPSCRIPT).unwrap()).unwrap();
+        assert_eq!(b.merkle_branch(), &[h[2], h[0], h[3]]);
+        for s in &scripts {
+            let control_block = info.control_block(&TapLeaf::new(s.clone(), TAPROOT_LEAF_TAPSCRIPT).unwrap()).unwrap();
+            let parsed = ControlBlock::parse(&control_block.serialize()).unwrap();
+            assert_eq!(parsed, control_block);
+            assert!(parsed.verify_taproot_commitment(info.output_key(), s));
+            assert!(!parsed.verify_taproot_commitment(info.output_key(), &op(0x60)));
+        }
+    }
+    #[test]
+    fn test_invalid_depths() {
+        // a leaf at depth 1 alone is not a tree
+        let builder = TaprootBuilder::new().add_leaf(1, op(0x51)).unwrap();
+        assert!(!builder.is_complete());
+        let internal = key("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+        assert!(builder.clone().finalize(&internal).is_err());
+        // depth 1 or 2 while the depth 3 leaf still waits for its sibling
+        let builder = TaprootBuilder::new().add_leaf(1, op(0x51)).unwrap().add_leaf(3, op(0x52)).unwrap();
+        assert!(builder.clone().add_leaf(1, op(0x53)).is_err());
+        assert!(builder.add_leaf(2, op(0x53)).is_err());
+        // a second root
+        let builder = TaprootBuilder::new().add_leaf(0, op(0x51)).unwrap();
+        assert!(builder.add_leaf(0, op(0x52)).is_err());
+        assert!(TaprootBuilder::new().add_leaf(129, op(0x51)).is_err());
+    }
+    #[test]
+    fn test_leaf_version() {
+        for version in [0xc1, 0xff, 0x01] {
+            assert!(TapLeaf::new(op(0x51), version).is_err(), "{:#04x}", version);
+            assert!(TaprootBuilder::new().add_leaf_with_version(0, op(0x51), version).is_err(), "{:#04x}", version);
+        }
+        assert_eq!(TapLeaf::new(op(0x51), 0xfe).unwrap().version(), 0xfe);
+        assert!(TaprootBuilder::new().add_leaf_with_version(0, op(0x51), 0xfe).unwrap().is_complete());
+    }
+    #[test]
+    fn test_weights() {
+        let internal = key("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+        let scripts: Vec<Script> = (0x51..0x55).map(op).collect();
+        let weights = vec![(10, scripts[0].clone()), (1, scripts[1].clone()), (1, scripts[2].clone()), (4, scripts[3].clone())];
+        let info = TaprootBuilder::with_weights(weights).unwrap().finalize(&internal).unwrap();
+        let depth = |s: &Script| info.control_block(&TapLeaf::new(s.clone(), TAPROOT_LEAF_TAPSCRIPT).unwrap()).unwrap().merkle_branch().len();
+        assert_eq!(depth(&scripts[0]), 1);
+        assert_eq!(depth(&scripts[3]), 2);
+        assert_eq!(depth(&scripts[1]), 3);
+        assert_eq!(depth(&scripts[2]), 3);
+
+        // the same shape built from depths has the same root
+        let by_depth = TaprootBuilder::new()
+            .add_leaf(1, scripts[0].clone()).unwrap()
+            .add_leaf(2, scripts[3].clone()).unwrap()
+            .add_leaf(3, scripts[1].clone()).unwrap()
+            .add_leaf(3, scripts[2].clone()).unwrap()
+            .finalize(&internal).unwrap();
+        assert_eq!(by_depth.merkle_root(), info.merkle_root());
+        assert_eq!(by_depth.output_key(), info.output_key());
+    }
+    #[test]
+    fn test_control_block_parse_invalid() {
+        let mut bytes = vec![0xc0];
+        bytes.extend(hex::decode("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d").unwrap());
+        assert!(ControlBlock::parse(&bytes).is_ok());
+        assert!(ControlBlock::parse(&bytes[..32]).is_err());
+        bytes.push(0);
+        assert!(ControlBlock::parse(&bytes).is_err());
+        bytes.extend([0u8; 31]);
+        assert_eq!(ControlBlock::parse(&bytes).unwrap().merkle_branch().len(), 1);
+    }
+}
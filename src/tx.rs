@@ -1,14 +1,22 @@
 use std::{fmt, io::{Cursor, Read}, vec};
-use std::io::{Seek, SeekFrom};
-use num::{BigUint, ToPrimitive, Zero};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Seek, SeekFrom};
+use num::{BigUint, ToPrimitive};
+use sha2::{Digest, Sha256};
 use crate::helpers::endianness::{int_to_little_endian, little_endian_to_int};
 use crate::tx_input::TxInput;
 use crate::tx_output::TxOutput;
 use crate::helpers::varint::{encode_varint, read_varint};
 use crate::helpers::hash256::hash256;
-use crate::helpers::sig_hash::SIGHASH_ALL;
+use crate::helpers::op_codes::{OP_0, OP_CODESEPARATOR};
+use crate::helpers::sig_hash::{SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_DEFAULT, SIGHASH_NONE, SIGHASH_SINGLE};
+use crate::helpers::tagged_hash::tagged_hash;
+use crate::execution_context::ExecutionContext;
 use crate::private_key::PrivateKey;
 use crate::script::Script;
+use crate::script_debugger::ScriptTracer;
+use crate::script_error::ScriptError;
+use crate::verify_flags::VerifyFlags;
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Tx {
@@ -49,6 +57,20 @@ impl Tx {
     pub fn locktime(&self) -> u32 {
         self.locktime
     }
+    pub fn segwit(&self) -> bool {
+        self.segwit
+    }
+    pub fn testnet(&self) -> bool {
+        self.testnet
+    }
+    /// BIP141 weight: the size without witnesses counts 4 times, witness bytes once
+    pub fn weight(&self) -> usize {
+        self.serialize_legacy().len() * 3 + self.serialize(false).len()
+    }
+    /// virtual size, the weight divided by 4 rounded up
+    pub fn vsize(&self) -> usize {
+        self.weight().div_ceil(4)
+    }
     pub fn parse(stream: &mut Cursor<Vec<u8>>, testnet: bool) -> Result<Self, std::io::Error> {
         let mut buffer = [0; 4];
         stream.read(&mut buffer)?;
@@ -66,7 +88,7 @@ impl Tx {
             let mut buffer = [0; 2];
             stream.read(&mut buffer)?;
             if buffer != [0x00,0x01] { // segwit marker
-                panic!("invalid segwit marker");
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid segwit marker"));
             }
         }
         let mut inputs: Vec<TxInput> = Vec::new();
@@ -74,13 +96,13 @@ impl Tx {
 
         if let Ok(num_inputs) = read_varint(stream) {
             for _ in 0..num_inputs {
-                inputs.push(TxInput::parse(stream).unwrap());
+                inputs.push(TxInput::parse(stream)?);
             }
         }
         //let mut outputs = vec![];
         if let Ok(num_outputs) = read_varint(stream) {
             for _ in 0..num_outputs {
-                outputs.push(TxOutput::parse(stream).unwrap());
+                outputs.push(TxOutput::parse(stream)?);
             }
         }
 
@@ -91,13 +113,9 @@ impl Tx {
                     let mut items: Vec<Vec<u8>> = vec![];
                     for _ in 0..num_items {
                         if let Ok(item_len) = read_varint(stream) {
-                            if item_len == 0 {
-                                items.push(vec![0])
-                            } else {
-                                let mut buffer: Vec<u8> = vec![0;item_len as usize];
-                                stream.read(&mut buffer)?;
-                                items.push(buffer)
-                            }
+                            let mut buffer: Vec<u8> = vec![0;item_len as usize];
+                            stream.read_exact(&mut buffer)?;
+                            items.push(buffer)
                         }
                     }
                     tx_in.witness = Some(items);
@@ -145,17 +163,14 @@ impl Tx {
             for tx_in in self.tx_ins() {
                 match tx_in.witness {
                     Some(witness) => {
-                        result.extend(int_to_little_endian(BigUint::from(witness.len()), 1));
+                        result.extend(encode_varint(witness.len() as u64).unwrap());
                         for item in witness {
-                            if item.len() == 1 {
-                                result.extend(int_to_little_endian(BigUint::from(item[0]), 1))
-                            } else {
-                                result.extend(encode_varint(item.len() as u64).unwrap());
-                                result.extend(item);
-                            }
+                            result.extend(encode_varint(item.len() as u64).unwrap());
+                            result.extend(item);
                         }
                     }
-                    None => {}
+                    // every input of a segwit transaction has a witness count
+                    None => result.push(0x00),
                 }
             }
         }
@@ -233,177 +248,251 @@ impl Tx {
         sum_tx_ins as i64 - sum_tx_outs as i64
     }
     pub fn sig_hash(&self, input_index: usize, redeem_script: Option<Script>) -> BigUint {
+        // if the RedeemScript was passed in, that's the ScriptSig
+        // otherwise the previous tx's ScriptPubkey is the ScriptSig
+        let script_code = match redeem_script {
+            Some(script) => script,
+            None => self.inputs[input_index].script_pubkey(self.testnet),
+        };
+        self.sig_hash_legacy(input_index, &script_code, SIGHASH_ALL)
+    }
+    /// Original signature hash for any hash type. script_code is the script being
+    /// executed from its last OP_CODESEPARATOR, the separators themselves are dropped.
+    pub fn sig_hash_legacy(&self, input_index: usize, script_code: &Script, hash_type: u8) -> BigUint {
+        let base_type = hash_type & 0x1f;
+        let anyone_can_pay = hash_type & SIGHASH_ANYONECANPAY != 0;
+        // SIGHASH_SINGLE without a matching output signs the number one as Core's
+        // uint256, the bytes 01 00 .. 00
+        if base_type == SIGHASH_SINGLE && input_index >= self.outputs.len() {
+            return BigUint::from(1u8) << 248;
+        }
+        let script_code = script_code.without_op(OP_CODESEPARATOR);
+
         let mut result = Vec::new();
         result.extend(int_to_little_endian(BigUint::from(self.version), 4));
-        let num_ins = encode_varint(self.inputs.len() as u64).unwrap();
-        result.extend(num_ins);
-
+        if anyone_can_pay {
+            result.extend(encode_varint(1).unwrap());
+        } else {
+            result.extend(encode_varint(self.inputs.len() as u64).unwrap());
+        }
         for (idx, tx_in) in self.inputs.iter().enumerate() {
             if idx == input_index {
-                // if the RedeemScript was passed in, that's the ScriptSig
-                // otherwise the previous tx's ScriptPubkey is the ScriptSig
-                match &redeem_script {
-                    Some(script) => {
-                        println!("WITH REDEEM");
-                        let tx_input = TxInput::new(tx_in.prev_tx(), tx_in.prev_index(), script.clone(), tx_in.sequence());
-                        result.extend(tx_input.serialize());
-                    }
-
-                    None => {
-                        println!("NO REDEEM");
-                        let tx_input = TxInput::new(tx_in.prev_tx(), tx_in.prev_index(), tx_in.script_pubkey(self.testnet), tx_in.sequence());
-                        result.extend(tx_input.serialize());
-                    }
-                }
-            } else {
-                let tx_input = TxInput::new(tx_in.prev_tx(), tx_in.prev_index(), Script::new(vec![]), tx_in.sequence());
+                let tx_input = TxInput::new(tx_in.prev_tx(), tx_in.prev_index(), script_code.clone(), tx_in.sequence());
+                result.extend(tx_input.serialize());
+            } else if !anyone_can_pay {
+                // with NONE and SINGLE the other inputs may be replaced
+                let sequence = if base_type == SIGHASH_NONE || base_type == SIGHASH_SINGLE { 0 } else { tx_in.sequence() };
+                let tx_input = TxInput::new(tx_in.prev_tx(), tx_in.prev_index(), Script::new(vec![]), sequence);
                 result.extend(tx_input.serialize());
             }
         }
-        result.extend(encode_varint(self.outputs.len() as u64).unwrap());
-        for tx_out in self.tx_outs() {
-            result.extend(tx_out.serialize());
+        match base_type {
+            SIGHASH_NONE => result.extend(encode_varint(0).unwrap()),
+            SIGHASH_SINGLE => {
+                // outputs before ours are blanked, the ones after it are left out
+                result.extend(encode_varint(input_index as u64 + 1).unwrap());
+                for _ in 0..input_index {
+                    result.extend(TxOutput::new(u64::MAX, Script::new(vec![])).serialize());
+                }
+                result.extend(self.outputs[input_index].serialize());
+            }
+            _ => {
+                result.extend(encode_varint(self.outputs.len() as u64).unwrap());
+                for tx_out in &self.outputs {
+                    result.extend(tx_out.serialize());
+                }
+            }
         }
         result.extend(int_to_little_endian(BigUint::from(self.locktime), 4));
-        result.extend(int_to_little_endian(BigUint::from(SIGHASH_ALL), 4));
+        result.extend((hash_type as u32).to_le_bytes());
         let hash = hash256(&result);
-        let z = BigUint::from_bytes_be(hash.as_slice());
-        z
+        BigUint::from_bytes_be(hash.as_slice())
     }
     pub fn sig_hash_bip143(&mut self, input_index: usize, redeem_script: Option<Script>, witness_script: Option<Script>) -> BigUint {
-
-        let pr = self.hash_prevouts().unwrap();
-        let se = self.hash_sequence().unwrap();
-
         let tx_in = &self.inputs[input_index];
-        let mut s: Vec<u8> = Vec::new();
-        // per BIP143 spec
-        s.extend(int_to_little_endian(BigUint::from(self.version), 4));
-
-        println!("pr: {}", hex::encode(pr.clone()));
-        s.extend(pr);
-        s.extend(se);
-
-        let mut prev = tx_in.prev_tx();
-        prev.reverse();
-        s.extend(prev);
-        s.extend(int_to_little_endian(BigUint::from(tx_in.prev_index()), 4));
-
-        let mut script_code = Vec::new();
-        if witness_script.is_some() {
-                script_code = witness_script.unwrap().serialize()
-        } else if redeem_script.is_some() {
-            let script = redeem_script.unwrap();
-            let h160 = script.cmds[1].clone();
-            script_code = Script::p2pkh_script(h160).serialize();
+        let script_code = if let Some(script) = witness_script {
+            script
+        } else if let Some(script) = redeem_script {
+            Script::p2pkh_script(script.cmds[1].clone())
         } else {
             let script = tx_in.script_pubkey(self.testnet);
-            let h160 = script.cmds[1].clone();
-            script_code = Script::p2pkh_script(h160).serialize();
+            Script::p2pkh_script(script.cmds[1].clone())
+        };
+        let amount = tx_in.value(self.testnet);
+        self.sig_hash_segwit_v0(input_index, &script_code, amount, SIGHASH_ALL)
+    }
+    /// BIP143 signature hash for any hash type. amount is the value of the output spent,
+    /// script_code the witness script, or the P2PKH script of a P2WPKH key hash.
+    pub fn sig_hash_segwit_v0(&self, input_index: usize, script_code: &Script, amount: u64, hash_type: u8) -> BigUint {
+        let base_type = hash_type & 0x1f;
+        let anyone_can_pay = hash_type & SIGHASH_ANYONECANPAY != 0;
+        let tx_in = &self.inputs[input_index];
+
+        let mut hash_prevouts = [0u8; 32];
+        let mut hash_sequence = [0u8; 32];
+        let mut hash_outputs = [0u8; 32];
+        if !anyone_can_pay {
+            let prevouts: Vec<u8> = self.inputs.iter().flat_map(Self::outpoint).collect();
+            hash_prevouts = hash256(&prevouts);
+        }
+        if !anyone_can_pay && base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE {
+            let sequences: Vec<u8> = self.inputs.iter().flat_map(|tx_in| tx_in.sequence().to_le_bytes()).collect();
+            hash_sequence = hash256(&sequences);
+        }
+        if base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE {
+            let outputs: Vec<u8> = self.outputs.iter().flat_map(|tx_out| tx_out.serialize()).collect();
+            hash_outputs = hash256(&outputs);
+        } else if base_type == SIGHASH_SINGLE && input_index < self.outputs.len() {
+            hash_outputs = hash256(&self.outputs[input_index].serialize());
         }
-        s.extend(script_code.clone());
-        s.extend(int_to_little_endian(BigUint::from(tx_in.value(self.testnet)), 8));
-        s.extend(int_to_little_endian(BigUint::from(tx_in.sequence()), 4));
-        s.extend(self.hash_outputs().unwrap());
-        s.extend(int_to_little_endian(BigUint::from(self.locktime), 4));
-        s.extend(int_to_little_endian(BigUint::from(SIGHASH_ALL), 4));
 
-        println!("sc: {:?}", script_code);
+        // per BIP143 spec
+        let mut s: Vec<u8> = Vec::new();
+        s.extend(int_to_little_endian(BigUint::from(self.version), 4));
+        s.extend(hash_prevouts);
+        s.extend(hash_sequence);
+        s.extend(Self::outpoint(tx_in));
+        s.extend(script_code.serialize());
+        s.extend(amount.to_le_bytes());
+        s.extend(tx_in.sequence().to_le_bytes());
+        s.extend(hash_outputs);
+        s.extend(int_to_little_endian(BigUint::from(self.locktime), 4));
+        s.extend((hash_type as u32).to_le_bytes());
         let hash = hash256(s.as_slice());
         BigUint::from_bytes_be(hash.as_slice())
     }
+    /// BIP341 signature hash. prevouts are the outputs spent by every input, in input
+    /// order; leaf_hash is set for a script path spend and annex includes its 0x50 tag.
+    pub fn sig_hash_taproot(&self, input_index: usize, prevouts: &[TxOutput], hash_type: u8, leaf_hash: Option<[u8; 32]>, annex: Option<&[u8]>) -> Result<[u8; 32], Error> {
+        self.sig_hash_taproot_with_codesep(input_index, prevouts, hash_type, leaf_hash, annex, 0xffffffff)
+    }
+    /// sig_hash_taproot committing to the position of the last executed OP_CODESEPARATOR
+    /// in the tapscript, 0xffffffff when there is none
+    pub fn sig_hash_taproot_with_codesep(&self, input_index: usize, prevouts: &[TxOutput], hash_type: u8, leaf_hash: Option<[u8; 32]>, annex: Option<&[u8]>, codesep_pos: u32) -> Result<[u8; 32], Error> {
+        if !matches!(hash_type, 0x00..=0x03 | 0x81..=0x83) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("invalid taproot hash type {:#04x}", hash_type)));
+        }
+        if input_index >= self.inputs.len() || prevouts.len() != self.inputs.len() {
+            return Err(Error::new(ErrorKind::InvalidInput, "one prevout per input is required"));
+        }
+        let output_type = if hash_type == SIGHASH_DEFAULT { SIGHASH_ALL } else { hash_type & 0x03 };
+        let anyone_can_pay = hash_type & SIGHASH_ANYONECANPAY != 0;
 
-    pub fn verify_input(&mut self, input_index: usize) -> bool {
-        let tx_ins = self.tx_ins(); //[input_index];
-        let tx_in = &tx_ins[input_index];
-        let prev_script_pubkey = tx_in.script_pubkey(self.testnet);
-
-        let mut z: BigUint = BigUint::zero();
-        let mut witness: Option<Vec<Vec<u8>>> = None;
-        let mut redeem_script: Option<Script> = None;
-
-        if prev_script_pubkey.is_p2sh_script_pubkey() {
-            // the last cmd in a p2sh is the RedeemScript
-            let mut script_sig = tx_in.script_sig.clone();
-            let cmd = script_sig.cmds.pop().unwrap();
-            let mut raw_redeem: Vec<u8> = vec![];
-            let len_raw_redeem = encode_varint(cmd.len() as u64).unwrap();
-            raw_redeem.extend(len_raw_redeem);
-            raw_redeem.extend(cmd);
-            let mut stream = Cursor::new(raw_redeem);
-            match Script::parse(&mut stream) {
-                Ok(script) => {
-                    redeem_script = Some(script.clone());
-
-                    if script.is_p2wpkh_script_pubkey() {
-                        z = self.sig_hash_bip143(input_index, redeem_script.clone(), None);
-                        witness = tx_in.witness.clone();
-                    } else if redeem_script.clone().unwrap().is_p2wsh_script_pubkey() {
-                        let mut raw_witness: Vec<u8> = vec![];
-                        let mut part = tx_in.witness.clone().unwrap();
-                        let cmd: Vec<u8> = part.pop().unwrap();
-                        raw_witness.extend(encode_varint(cmd.len() as u64).unwrap());
-                        raw_witness.extend(cmd);
-                        let mut w_stream = Cursor::new(raw_witness);
-                        let witness_script = Script::parse(&mut w_stream).unwrap();
-                        z = self.sig_hash_bip143(input_index, None, Some(witness_script));
-                        witness = tx_in.clone().witness;
-                    } else {
-                        z = self.sig_hash(input_index, redeem_script.clone());
-                        witness = None;
-                    }
-                }
-                Err(e) => {
-                    println!("{:?}", e);
-                    println!("{:?} {:?} {:?}", redeem_script, z, witness);
-                    panic!("Can't parse redeem script");
-                }
+        // BIP341 epoch 0, then the common signature message
+        let mut s: Vec<u8> = vec![0x00, hash_type];
+        s.extend(self.version.to_le_bytes());
+        s.extend(self.locktime.to_le_bytes());
+        if !anyone_can_pay {
+            let mut outpoints = Vec::new();
+            let mut amounts = Vec::new();
+            let mut script_pubkeys = Vec::new();
+            let mut sequences = Vec::new();
+            for (tx_in, prevout) in self.inputs.iter().zip(prevouts) {
+                outpoints.extend(Self::outpoint(tx_in));
+                amounts.extend(prevout.amount().to_le_bytes());
+                script_pubkeys.extend(prevout.script_pubkey().serialize());
+                sequences.extend(tx_in.sequence().to_le_bytes());
             }
+            s.extend(Sha256::digest(&outpoints));
+            s.extend(Sha256::digest(&amounts));
+            s.extend(Sha256::digest(&script_pubkeys));
+            s.extend(Sha256::digest(&sequences));
+        }
+        if output_type == SIGHASH_ALL {
+            let outputs: Vec<u8> = self.outputs.iter().flat_map(|tx_out| tx_out.serialize()).collect();
+            s.extend(Sha256::digest(&outputs));
+        }
+        let spend_type = (leaf_hash.is_some() as u8) << 1 | annex.is_some() as u8;
+        s.push(spend_type);
+        if anyone_can_pay {
+            let tx_in = &self.inputs[input_index];
+            s.extend(Self::outpoint(tx_in));
+            s.extend(prevouts[input_index].amount().to_le_bytes());
+            s.extend(prevouts[input_index].script_pubkey().serialize());
+            s.extend(tx_in.sequence().to_le_bytes());
         } else {
-
-            if prev_script_pubkey.is_p2wpkh_script_pubkey() {
-
-                z = self.sig_hash_bip143(input_index, None, None);
-                witness = tx_in.clone().witness;
-
-            } else if prev_script_pubkey.is_p2wsh_script_pubkey() {
-
-                let mut raw_witness: Vec<u8> = Vec::new();
-                let mut part = tx_in.witness.clone().unwrap();
-                let cmd: Vec<u8> = part.pop().unwrap();
-                raw_witness.extend(encode_varint(cmd.len() as u64).unwrap());
-                raw_witness.extend(cmd);
-                let mut w_stream = Cursor::new(raw_witness);
-                let witness_script = Script::parse(&mut w_stream).unwrap();
-                z = self.sig_hash_bip143(input_index, None, Some(witness_script));
-                witness = tx_in.clone().witness;
-            } else {
-                z = self.sig_hash(input_index, None);
-                witness = None;
-            }
+            s.extend((input_index as u32).to_le_bytes());
         }
+        if let Some(annex) = annex {
+            let mut data = encode_varint(annex.len() as u64).unwrap();
+            data.extend(annex);
+            s.extend(Sha256::digest(&data));
+        }
+        if output_type == SIGHASH_SINGLE {
+            let Some(tx_out) = self.outputs.get(input_index) else {
+                return Err(Error::new(ErrorKind::InvalidInput, "SIGHASH_SINGLE without a matching output"));
+            };
+            s.extend(Sha256::digest(tx_out.serialize()));
+        }
+        // BIP342 extension for script path spends, key version 0
+        if let Some(leaf_hash) = leaf_hash {
+            s.extend(leaf_hash);
+            s.push(0x00);
+            s.extend(codesep_pos.to_le_bytes());
+        }
+        Ok(tagged_hash("TapSighash", &s))
+    }
+    // txid in internal byte order and output index
+    fn outpoint(tx_in: &TxInput) -> Vec<u8> {
+        let mut result = tx_in.prev_tx();
+        result.reverse();
+        result.extend(tx_in.prev_index().to_le_bytes());
+        result
+    }
 
-        let ss = tx_in.script_sig();
-        let pp = prev_script_pubkey;
-        // println!("z: {}", z);
-        // let w1 = &witness.clone().unwrap()[0];
-        // let w2 = &witness.clone().unwrap()[1];
-        // println!("witness 0 : {:?}", hex::encode(w1));
-        // println!("witness 1 : {:?}", hex::encode(w2));
-        // println!("ss: {}", ss.clone());
-        // println!("pp: {}", pp.clone());
-
-        let combined_script = ss + pp;
-        combined_script.evaluate(&z.clone(), &witness.clone())
+    /// Attaches the output each input spends from prev_outputs, keyed by previous tx id
+    /// and index, so verifying needs no TxFetcher. False when one is missing.
+    pub fn set_prev_outputs(&mut self, prev_outputs: &HashMap<(String, u32), TxOutput>) -> bool {
+        for tx_in in self.inputs.iter_mut() {
+            let Some(prev_output) = prev_outputs.get(&(hex::encode(tx_in.prev_tx()), tx_in.prev_index())) else {
+                return false;
+            };
+            tx_in.set_prev_output(prev_output.clone());
+        }
+        true
+    }
+    /// verify_input under today's consensus rules
+    pub fn verify_input(&mut self, input_index: usize) -> bool {
+        self.verify_input_with_flags(input_index, VerifyFlags::MANDATORY)
+    }
+    /// verify_input under the rules of flags, VerifyFlags::for_height for a historical block
+    pub fn verify_input_with_flags(&mut self, input_index: usize, flags: VerifyFlags) -> bool {
+        self.check_input(input_index, flags, None).is_ok()
+    }
+    /// Why input_index does not verify under flags, tracer sees every script it runs
+    pub(crate) fn check_input(&self, input_index: usize, flags: VerifyFlags, tracer: Option<&dyn ScriptTracer>) -> Result<(), ScriptError> {
+        let tx_in = &self.inputs[input_index];
+        let prev_script_pubkey = tx_in.script_pubkey(self.testnet);
+        // a taproot signature commits to the outputs spent by every input, only fetched
+        // when this input needs them
+        let prevouts: Option<Vec<TxOutput>> = (flags.contains(VerifyFlags::TAPROOT) && prev_script_pubkey.is_p2tr_script_pubkey()).then(|| {
+            self.inputs
+                .iter()
+                .map(|tx_in| TxOutput::new(tx_in.value(self.testnet), tx_in.script_pubkey(self.testnet)))
+                .collect()
+        });
+        let amount = tx_in.value(self.testnet);
+        let mut context = ExecutionContext::new(self, input_index, amount).with_flags(flags);
+        if let Some(prevouts) = &prevouts {
+            context = context.with_prevouts(prevouts);
+        }
+        if let Some(tracer) = tracer {
+            context = context.with_tracer(tracer);
+        }
+        Script::verify_script(&tx_in.script_sig, &prev_script_pubkey, &tx_in.witness, &context)
     }
     pub fn verify(&mut self) -> bool {
+        self.verify_with_flags(VerifyFlags::MANDATORY)
+    }
+    /// verify with every input checked under the rules of flags
+    pub fn verify_with_flags(&mut self, flags: VerifyFlags) -> bool {
         if self.fee() < 0 {
             println!("----------> fee is negative");
             return false;
         }
 
         for i in 0..self.tx_ins().len() {
-            if !self.verify_input(i) {
+            if !self.verify_input_with_flags(i, flags) {
                 println!("----------> input is invalid {}/{}", i, self.tx_ins().len());
                 return false;
             }
@@ -424,6 +513,42 @@ impl Tx {
         self.inputs[input_index].script_sig = combined_script;
         self.verify_input(input_index)
     }
+    /// Signs a bare multisig input, or a P2SH one spending redeem_script, with the keys
+    /// of private_keys the script lists. Signatures follow the order of the keys in the
+    /// script, as OP_CHECKMULTISIG matches them in order; false without m of them.
+    pub fn sign_input_multisig(&mut self, input_index: usize, private_keys: &[PrivateKey], redeem_script: Option<&Script>) -> bool {
+        let multisig = match redeem_script {
+            Some(redeem_script) => redeem_script.clone(),
+            None => self.inputs[input_index].script_pubkey(self.testnet),
+        };
+        let Some((m, sec_pubkeys)) = multisig.multisig_keys() else {
+            return false;
+        };
+        let z = self.sig_hash_legacy(input_index, &multisig, SIGHASH_ALL);
+        let mut sigs: Vec<Vec<u8>> = vec![];
+        for sec in &sec_pubkeys {
+            if sigs.len() == m {
+                break;
+            }
+            let key = private_keys.iter().find(|key| key.point().sec(true) == *sec || key.point().sec(false) == *sec);
+            if let Some(key) = key {
+                let mut sig = key.sign(&z).der();
+                sig.push(SIGHASH_ALL);
+                sigs.push(sig);
+            }
+        }
+        if sigs.len() < m {
+            return false;
+        }
+        // OP_0 for the extra element OP_CHECKMULTISIG pops
+        let mut cmds: Vec<Vec<u8>> = vec![vec![OP_0]];
+        cmds.extend(sigs);
+        if let Some(redeem_script) = redeem_script {
+            cmds.push(redeem_script.serialize_raw());
+        }
+        self.inputs[input_index].script_sig = Script::new(cmds);
+        self.verify_input(input_index)
+    }
     pub fn is_coinbase(&self) -> bool {
         if self.tx_ins().len() != 1 || self.tx_ins().len() == 0 {
             return false;
@@ -468,6 +593,31 @@ impl fmt::Display for Tx {
     }
 }
 
+// fixtures for the tests of the modules that verify spends
+#[cfg(test)]
+pub(crate) mod test_util {
+    use crate::private_key::PrivateKey;
+    use crate::script::Script;
+    use crate::tx_input::TxInput;
+    use crate::tx_output::TxOutput;
+    use super::Tx;
+
+    // unsigned legacy spend of a single output of script_pubkey
+    pub(crate) fn legacy_tx(script_pubkey: &Script, locktime: u32, sequence: u32) -> Tx {
+        let mut tx_in = TxInput::new(vec![0x22; 32], 0, Script::new(vec![]), sequence);
+        tx_in.set_prev_output(TxOutput::new(50_000, script_pubkey.clone()));
+        let tx_out = TxOutput::new(40_000, Script::p2pkh_script(vec![0x33; 20]));
+        Tx::new(1, vec![tx_in], vec![tx_out], locktime, true, false)
+    }
+    // signs input 0 with hash_type, the scriptSig is the signature followed by pushes
+    pub(crate) fn legacy_sign(tx: &mut Tx, key: &PrivateKey, script_code: &Script, hash_type: u8, pushes: &[Vec<u8>]) {
+        let z = tx.sig_hash_legacy(0, script_code, hash_type);
+        let mut sig = key.sign(&z).der();
+        sig.push(hash_type);
+        tx.inputs[0].script_sig = Script::new([vec![sig], pushes.to_vec()].concat());
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::tx_fetcher::TxFetcher;
@@ -475,8 +625,12 @@ mod tests {
     use crate::helpers::base58::decode_base58;
     use crate::script::Script;
     use crate::private_key::PrivateKey;
+    use crate::helpers::op_codes::*;
+    use crate::taproot::{TapLeaf, TaprootBuilder, TaprootSpendInfo, TAPROOT_ANNEX_TAG, TAPROOT_LEAF_TAPSCRIPT};
+    use crate::helpers::hash160::hash160;
 
     use super::*;
+    use super::test_util::{legacy_sign, legacy_tx};
     #[test]
     fn test_parse_version() {
         let raw_tx = hex::decode("0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600").unwrap();
@@ -962,4 +1116,386 @@ mod tests {
         assert_eq!(tx.tx_id(), tx_id);
         assert_eq!(hex::encode(tx.hash(false)), hash);
     }
+    fn bip341_prevouts() -> Vec<TxOutput> {
+        // BIP341 wallet test vectors, keyPathSpending utxosSpent
+        [
+            (420000000, "512053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343"),
+            (462000000, "5120147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3"),
+            (294000000, "76a914751e76e8199196d454941c45d1b3a323f1433bd688ac"),
+            (504000000, "5120e4d810fd50586274face62b8a807eb9719cef49c04177cc6b76a9a4251d5450e"),
+            (630000000, "512091b64d5324723a985170e4dc5a0f84c041804f2cd12660fa5dec09fc21783605"),
+            (378000000, "00147dd65592d0ab2fe0d0257d571abf032cd9db93dc"),
+            (672000000, "512075169f4001aa68f15bbed28b218df1d0a62cbbcf1188c6665110c293c907b831"),
+            (546000000, "5120712447206d7a5238acc7ff53fbe94a3b64539ad291c7cdbc490b7577e4b17df5"),
+            (588000000, "512077e30a5522dd9f894c3f8b8bd4c4b2cf82ca7da8a3ea6a239655c39c050ab220"),
+        ]
+        .iter()
+        .map(|(amount, script_pubkey)| {
+            let raw = hex::decode(script_pubkey).unwrap();
+            let mut bytes = encode_varint(raw.len() as u64).unwrap();
+            bytes.extend(raw);
+            TxOutput::new(*amount, Script::parse(&mut Cursor::new(bytes)).unwrap())
+        })
+        .collect()
+    }
+    fn bip341_tx() -> Tx {
+        let raw_tx = hex::decode("02000000097de20cbff686da83a54981d2b9bab3586f4ca7e48f57f5b55963115f3b334e9c010000000000000000d7b7cab57b1393ace2d064f4d4a2cb8af6def61273e127517d44759b6dafdd990000000000fffffffff8e1f583384333689228c5d28eac13366be082dc57441760d957275419a418420000000000fffffffff0689180aa63b30cb162a73c6d2a38b7eeda2a83ece74310fda0843ad604853b0100000000feffffffaa5202bdf6d8ccd2ee0f0202afbbb7461d9264a25e5bfd3c5a52ee1239e0ba6c0000000000feffffff956149bdc66faa968eb2be2d2faa29718acbfe3941215893a2a3446d32acd050000000000000000000e664b9773b88c09c32cb70a2a3e4da0ced63b7ba3b22f848531bbb1d5d5f4c94010000000000000000e9aa6b8e6c9de67619e6a3924ae25696bb7b694bb677a632a74ef7eadfd4eabf0000000000ffffffffa778eb6a263dc090464cd125c466b5a99667720b1c110468831d058aa1b82af10100000000ffffffff0200ca9a3b000000001976a91406afd46bcdfd22ef94ac122aa11f241244a37ecc88ac807840cb0000000020ac9a87f5594be208f8532db38cff670c450ed2fea8fcdefcc9a663f78bab962b0065cd1d").unwrap();
+        Tx::parse(&mut Cursor::new(raw_tx), false).unwrap()
+    }
+    #[test]
+    fn test_sig_hash_taproot() {
+        let tx = bip341_tx();
+        let prevouts = bip341_prevouts();
+        let cases = [
+            (0, 0x03, "2514a6272f85cfa0f45eb907fcb0d121b808ed37c6ea160a5a9046ed5526d555"),
+            (1, 0x83, "325a644af47e8a5a2591cda0ab0723978537318f10e6a63d4eed783b96a71a4d"),
+            (3, 0x01, "bf013ea93474aa67815b1b6cc441d23b64fa310911d991e713cd34c7f5d46669"),
+            (4, 0x00, "4f900a0bae3f1446fd48490c2958b5a023228f01661cda3496a11da502a7f7ef"),
+            (6, 0x02, "15f25c298eb5cdc7eb1d638dd2d45c97c4c59dcaec6679cfc16ad84f30876b85"),
+            (7, 0x82, "cd292de50313804dabe4685e83f923d2969577191a3e1d2882220dca88cbeb10"),
+            (8, 0x81, "cccb739eca6c13a8a89e6e5cd317ffe55669bbda23f2fd37b0f18755e008edd2"),
+        ];
+        for (input_index, hash_type, want) in cases {
+            let sig_hash = tx.sig_hash_taproot(input_index, &prevouts, hash_type, None, None).unwrap();
+            assert_eq!(hex::encode(sig_hash), want, "input {}", input_index);
+        }
+    }
+    #[test]
+    fn test_sig_hash_taproot_invalid() {
+        let tx = bip341_tx();
+        let prevouts = bip341_prevouts();
+        assert!(tx.sig_hash_taproot(0, &prevouts, 0x04, None, None).is_err());
+        assert!(tx.sig_hash_taproot(0, &prevouts, 0x80, None, None).is_err());
+        assert!(tx.sig_hash_taproot(0, &prevouts[1..], 0x00, None, None).is_err());
+        assert!(tx.sig_hash_taproot(9, &prevouts, 0x00, None, None).is_err());
+        // two outputs, so input 3 has none to sign with SIGHASH_SINGLE
+        assert!(tx.sig_hash_taproot(3, &prevouts, 0x03, None, None).is_err());
+        // the spend type commits to a script path and to the annex
+        let key_path = tx.sig_hash_taproot(3, &prevouts, 0x00, None, None).unwrap();
+        let script_path = tx.sig_hash_taproot(3, &prevouts, 0x00, Some([0u8; 32]), None).unwrap();
+        let annex = tx.sig_hash_taproot(3, &prevouts, 0x00, None, Some(&[0x50])).unwrap();
+        assert_ne!(key_path, script_path);
+        assert_ne!(key_path, annex);
+        assert_ne!(script_path, tx.sig_hash_taproot_with_codesep(3, &prevouts, 0x00, Some([0u8; 32]), None, 0).unwrap());
+    }
+    fn taproot_key(secret: u32) -> PrivateKey {
+        PrivateKey::new(&BigUint::from(secret))
+    }
+    // unsigned transaction spending a single output of script_pubkey
+    fn taproot_tx(script_pubkey: Script) -> Tx {
+        let mut tx_in = TxInput::new(vec![0x11; 32], 0, Script::new(vec![]), 0xfffffffe);
+        tx_in.set_prev_output(TxOutput::new(100_000, script_pubkey.clone()));
+        let tx_out = TxOutput::new(90_000, script_pubkey);
+        Tx::new(2, vec![tx_in], vec![tx_out], 0, true, true)
+    }
+    fn taproot_verify(tx: &Tx, witness: Vec<Vec<u8>>) -> bool {
+        let mut tx = tx.clone();
+        tx.inputs[0].witness = Some(witness);
+        tx.verify_input(0)
+    }
+    // script path witness: inputs, script and control block of the leaf
+    fn tapscript_witness(info: &TaprootSpendInfo, script: &Script, inputs: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        let control_block = info.control_block(&TapLeaf::new(script.clone(), TAPROOT_LEAF_TAPSCRIPT).unwrap()).unwrap();
+        let mut witness = inputs;
+        witness.push(script.serialize_raw());
+        witness.push(control_block.serialize());
+        witness
+    }
+    fn tapscript_sig(tx: &Tx, key: &PrivateKey, script: &Script) -> Vec<u8> {
+        let prevouts = vec![tx.tx_ins()[0].prev_output().unwrap()];
+        let leaf_hash = TapLeaf::new(script.clone(), TAPROOT_LEAF_TAPSCRIPT).unwrap().leaf_hash();
+        let msg = tx.sig_hash_taproot(0, &prevouts, SIGHASH_DEFAULT, Some(leaf_hash), None).unwrap();
+        key.sign_schnorr(&msg, &[0u8; 32]).serialize().to_vec()
+    }
+    #[test]
+    fn test_verify_taproot_key_path() {
+        let key = taproot_key(7);
+        let (output_key, _) = key.point().taproot_tweak(None);
+        let tx = taproot_tx(Script::p2tr_script(&output_key));
+        let prevouts = vec![tx.tx_ins()[0].prev_output().unwrap()];
+        let tweaked = key.tap_tweak(None);
+
+        let msg = tx.sig_hash_taproot(0, &prevouts, SIGHASH_DEFAULT, None, None).unwrap();
+        let sig = tweaked.sign_schnorr(&msg, &[0u8; 32]).serialize().to_vec();
+        assert!(taproot_verify(&tx, vec![sig.clone()]));
+        // 65 bytes with an explicit hash type, which can not be SIGHASH_DEFAULT
+        let msg = tx.sig_hash_taproot(0, &prevouts, SIGHASH_ALL, None, None).unwrap();
+        let mut sig_all = tweaked.sign_schnorr(&msg, &[0u8; 32]).serialize().to_vec();
+        sig_all.push(SIGHASH_ALL);
+        assert!(taproot_verify(&tx, vec![sig_all]));
+        let mut sig_default = sig.clone();
+        sig_default.push(SIGHASH_DEFAULT);
+        assert!(!taproot_verify(&tx, vec![sig_default]));
+        // the untweaked key does not sign for the output key
+        let msg = tx.sig_hash_taproot(0, &prevouts, SIGHASH_DEFAULT, None, None).unwrap();
+        assert!(!taproot_verify(&tx, vec![key.sign_schnorr(&msg, &[0u8; 32]).serialize().to_vec()]));
+        assert!(!taproot_verify(&tx, vec![]));
+
+        // an annex is committed to by the signature
+        let annex = vec![TAPROOT_ANNEX_TAG, 0x01];
+        assert!(!taproot_verify(&tx, vec![sig.clone(), annex.clone()]));
+        let msg = tx.sig_hash_taproot(0, &prevouts, SIGHASH_DEFAULT, None, Some(&annex)).unwrap();
+        let sig_annex = tweaked.sign_schnorr(&msg, &[0u8; 32]).serialize().to_vec();
+        assert!(taproot_verify(&tx, vec![sig_annex, annex]));
+    }
+    #[test]
+    fn test_verify_tapscript() {
+        let internal = taproot_key(1).x_only_public_key();
+        let (k1, k2) = (taproot_key(2), taproot_key(3));
+        let checksig = Script::new(vec![k1.x_only_public_key().serialize().to_vec(), vec![OP_CHECKSIG]]);
+        // 2 of 2 with OP_CHECKSIGADD
+        let multi = Script::new(vec![
+            k1.x_only_public_key().serialize().to_vec(), vec![OP_CHECKSIG],
+            k2.x_only_public_key().serialize().to_vec(), vec![OP_CHECKSIGADD],
+            vec![OP_2], vec![OP_NUMEQUAL],
+        ]);
+        let info = TaprootBuilder::new()
+            .add_leaf(1, checksig.clone()).unwrap()
+            .add_leaf(1, multi.clone()).unwrap()
+            .finalize(&internal).unwrap();
+        let tx = taproot_tx(info.script_pubkey());
+
+        let sig1 = tapscript_sig(&tx, &k1, &checksig);
+        assert!(taproot_verify(&tx, tapscript_witness(&info, &checksig, vec![sig1.clone()])));
+        // an empty signature is a failed check, a bad one fails the script
+        assert!(!taproot_verify(&tx, tapscript_witness(&info, &checksig, vec![vec![]])));
+        let mut bad = sig1.clone();
+        bad[10] ^= 1;
+        assert!(!taproot_verify(&tx, tapscript_witness(&info, &checksig, vec![bad])));
+        // the signature commits to the leaf
+        assert!(!taproot_verify(&tx, tapscript_witness(&info, &multi, vec![sig1.clone(), sig1])));
+
+        let m1 = tapscript_sig(&tx, &k1, &multi);
+        let m2 = tapscript_sig(&tx, &k2, &multi);
+        assert!(taproot_verify(&tx, tapscript_witness(&info, &multi, vec![m2.clone(), m1.clone()])));
+        assert!(!taproot_verify(&tx, tapscript_witness(&info, &multi, vec![vec![], m1])));
+        // a control block of another tree
+        let mut witness = tapscript_witness(&info, &multi, vec![m2.clone(), m2]);
+        witness[3][0] ^= 1;
+        assert!(!taproot_verify(&tx, witness));
+    }
+    #[test]
+    fn test_verify_tapscript_rules() {
+        let internal = taproot_key(1).x_only_public_key();
+        let key = taproot_key(2);
+        let op_success = Script::from_raw(vec![0x50]);
+        // a push of the byte 0x50 is not OP_SUCCESS80
+        let push_success = Script::from_raw(vec![0x01, 0x50, OP_DROP]);
+        let multisig = Script::new(vec![vec![OP_1], key.point().sec(true), vec![OP_1], vec![OP_CHECKMULTISIG]]);
+        let minimal_if = Script::new(vec![vec![OP_IF], vec![OP_1], vec![OP_ELSE], vec![OP_1], vec![OP_ENDIF]]);
+        let unbalanced_if = Script::new(vec![vec![OP_1], vec![OP_IF], vec![OP_1]]);
+        // every repetition checks the same signature again and costs more than its bytes,
+        // 14 of them overrun the validation weight budget
+        let repeated = |n: usize| {
+            let mut cmds = vec![];
+            for _ in 0..n {
+                cmds.extend([vec![OP_DUP], key.x_only_public_key().serialize().to_vec(), vec![OP_CHECKSIGVERIFY]]);
+            }
+            cmds.extend([key.x_only_public_key().serialize().to_vec(), vec![OP_CHECKSIG]]);
+            Script::new(cmds)
+        };
+        let (short, long) = (repeated(13), repeated(14));
+        let info = TaprootBuilder::new()
+            .add_leaf(3, op_success.clone()).unwrap()
+            .add_leaf(3, push_success.clone()).unwrap()
+            .add_leaf(3, multisig.clone()).unwrap()
+            .add_leaf(3, minimal_if.clone()).unwrap()
+            .add_leaf(3, unbalanced_if.clone()).unwrap()
+            .add_leaf(3, short.clone()).unwrap()
+            .add_leaf(2, long.clone()).unwrap()
+            .finalize(&internal).unwrap();
+        let tx = taproot_tx(info.script_pubkey());
+
+        assert!(taproot_verify(&tx, tapscript_witness(&info, &op_success, vec![])));
+        assert!(!taproot_verify(&tx, tapscript_witness(&info, &push_success, vec![])));
+        assert!(!taproot_verify(&tx, tapscript_witness(&info, &multisig, vec![vec![]])));
+        assert!(taproot_verify(&tx, tapscript_witness(&info, &minimal_if, vec![vec![1]])));
+        assert!(taproot_verify(&tx, tapscript_witness(&info, &minimal_if, vec![vec![]])));
+        assert!(!taproot_verify(&tx, tapscript_witness(&info, &minimal_if, vec![vec![2]])));
+        assert!(!taproot_verify(&tx, tapscript_witness(&info, &minimal_if, vec![vec![1, 0]])));
+        assert!(!taproot_verify(&tx, tapscript_witness(&info, &unbalanced_if, vec![])));
+        let sig = tapscript_sig(&tx, &key, &short);
+        assert!(taproot_verify(&tx, tapscript_witness(&info, &short, vec![sig])));
+        let sig = tapscript_sig(&tx, &key, &long);
+        assert!(!taproot_verify(&tx, tapscript_witness(&info, &long, vec![sig])));
+    }
+    #[test]
+    fn test_verify_tapscript_flags() {
+        let internal = taproot_key(1).x_only_public_key();
+        // OP_1 OP_NOP4, OP_1 pushed with OP_PUSHDATA1, <0x0100> OP_1ADD
+        let nop = Script::from_raw(vec![OP_1, 0xb3]);
+        let pushdata = Script::from_raw(vec![0x4c, 0x01, 0x01]);
+        let number = Script::from_raw(vec![0x02, 0x01, 0x00, 0x8b]);
+        let info = TaprootBuilder::new()
+            .add_leaf(2, nop.clone()).unwrap()
+            .add_leaf(2, pushdata.clone()).unwrap()
+            .add_leaf(1, number.clone()).unwrap()
+            .finalize(&internal).unwrap();
+        for (script, error) in [(&nop, ScriptError::DiscourageUpgradableNops), (&pushdata, ScriptError::MinimalData), (&number, ScriptError::InvalidNumber)] {
+            let mut tx = taproot_tx(info.script_pubkey());
+            tx.inputs[0].witness = Some(tapscript_witness(&info, script, vec![]));
+            assert_eq!(tx.check_input(0, VerifyFlags::MANDATORY, None), Ok(()));
+            assert_eq!(tx.check_input(0, VerifyFlags::STANDARD, None), Err(error));
+        }
+    }
+    #[test]
+    fn test_verify_script_taproot() {
+        let key = taproot_key(7);
+        let (output_key, _) = key.point().taproot_tweak(None);
+        let script_pubkey = Script::p2tr_script(&output_key);
+        let tx = taproot_tx(script_pubkey.clone());
+        let prevouts = vec![tx.tx_ins()[0].prev_output().unwrap()];
+        let msg = tx.sig_hash_taproot(0, &prevouts, SIGHASH_DEFAULT, None, None).unwrap();
+        let good = Some(vec![key.tap_tweak(None).sign_schnorr(&msg, &[0u8; 32]).serialize().to_vec()]);
+        let bad = Some(vec![vec![0x11; 64]]);
+        let verify = |context: &ExecutionContext, witness: &Option<Vec<Vec<u8>>>| {
+            Script::verify_script(&Script::new(vec![]), &script_pubkey, witness, context)
+        };
+
+        // the signature commits to every spent output, without them nothing is checked
+        let context = ExecutionContext::new(&tx, 0, 100_000).with_flags(VerifyFlags::MANDATORY);
+        assert_eq!(verify(&context, &good), Err(ScriptError::TaprootMissingPrevouts));
+        let context = context.with_prevouts(&prevouts);
+        assert_eq!(verify(&context, &good), Ok(()));
+        assert_eq!(verify(&context, &bad), Err(ScriptError::SigCheck));
+        // before the soft fork a witness v1 program is anyone can spend
+        let context = ExecutionContext::new(&tx, 0, 100_000).with_flags(VerifyFlags::MANDATORY.without(VerifyFlags::TAPROOT));
+        assert_eq!(verify(&context, &bad), Ok(()));
+    }
+    #[test]
+    fn test_sig_hash_segwit_v0() {
+        // BIP143 native P2WPKH example, the second input spends 6 BTC
+        let raw = hex::decode("0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000").unwrap();
+        let tx = Tx::parse(&mut Cursor::new(raw), false).unwrap();
+        let h160 = hex::decode("1d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        let z = tx.sig_hash_segwit_v0(1, &Script::p2pkh_script(h160), 600_000_000, SIGHASH_ALL);
+        let want = BigUint::from_str_radix("c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670", 16).unwrap();
+        assert_eq!(z, want);
+    }
+    #[test]
+    fn test_verify_hash_types() {
+        let key = PrivateKey::new(&BigUint::from(8675309u32));
+        let script_pubkey = Script::new(vec![key.point().sec(true), vec![OP_CHECKSIG]]);
+        let all = legacy_tx(&script_pubkey, 0, 0xffffffff).sig_hash_legacy(0, &script_pubkey, SIGHASH_ALL);
+        for hash_type in [SIGHASH_ALL, SIGHASH_NONE, SIGHASH_SINGLE | SIGHASH_ANYONECANPAY] {
+            let mut tx = legacy_tx(&script_pubkey, 0, 0xffffffff);
+            legacy_sign(&mut tx, &key, &script_pubkey, hash_type, &[]);
+            assert!(tx.verify_input(0));
+            if hash_type != SIGHASH_ALL {
+                assert_ne!(tx.sig_hash_legacy(0, &script_pubkey, hash_type), all);
+            }
+        }
+        // a signature made for SIGHASH_ALL but labelled SIGHASH_NONE
+        let mut tx = legacy_tx(&script_pubkey, 0, 0xffffffff);
+        legacy_sign(&mut tx, &key, &script_pubkey, SIGHASH_ALL, &[]);
+        let mut sig = tx.inputs[0].script_sig.cmds[0].clone();
+        *sig.last_mut().unwrap() = SIGHASH_NONE;
+        tx.inputs[0].script_sig = Script::new(vec![sig]);
+        assert!(!tx.verify_input(0));
+        // SIGHASH_SINGLE without a matching output signs one, little endian
+        let mut tx = legacy_tx(&script_pubkey, 0, 0xffffffff);
+        tx.outputs.clear();
+        assert_eq!(tx.sig_hash_legacy(0, &script_pubkey, SIGHASH_SINGLE), BigUint::from(1u8) << 248);
+    }
+    #[test]
+    fn test_sign_input_multisig() {
+        let keys: Vec<PrivateKey> = (1..=3u32).map(|i| PrivateKey::new(&BigUint::from(8675309u32 + i))).collect();
+        let secs: Vec<Vec<u8>> = keys.iter().map(|key| key.point().sec(true)).collect();
+        let multisig = Script::multisig_script(2, &secs).unwrap();
+        assert_eq!(multisig.multisig_keys(), Some((2, secs.clone())));
+
+        // bare 2 of 3, the keys given in any order
+        let mut tx = legacy_tx(&multisig, 0, 0xffffffff);
+        assert!(tx.sign_input_multisig(0, &[keys[2].clone(), keys[0].clone()], None));
+        assert_eq!(tx.inputs[0].script_sig.cmds.len(), 3);
+        let mut tx = legacy_tx(&multisig, 0, 0xffffffff);
+        assert!(!tx.sign_input_multisig(0, &[keys[1].clone()], None));
+
+        // P2SH 2 of 3
+        let p2sh = Script::p2sh_script(hash160(&multisig.serialize_raw()).to_vec());
+        let mut tx = legacy_tx(&p2sh, 0, 0xffffffff);
+        assert!(tx.sign_input_multisig(0, &keys, Some(&multisig)));
+        assert_eq!(tx.inputs[0].script_sig.cmds.len(), 4);
+    }
+    #[test]
+    fn test_verify_multisig() {
+        let keys: Vec<PrivateKey> = (1..=3u32).map(|i| PrivateKey::new(&BigUint::from(8675309u32 + i))).collect();
+        let secs: Vec<Vec<u8>> = keys.iter().map(|key| key.point().sec(true)).collect();
+        let multisig = Script::multisig_script(2, &secs).unwrap();
+        let sign = |tx: &Tx, key: &PrivateKey, hash_type: u8| {
+            let mut sig = key.sign(&tx.sig_hash_legacy(0, &multisig, hash_type)).der();
+            sig.push(hash_type);
+            sig
+        };
+        let script_sig = |dummy: &[u8], sigs: &[&Vec<u8>]| {
+            let mut raw = vec![];
+            for item in std::iter::once(&dummy.to_vec()).chain(sigs.iter().copied()) {
+                raw.push(item.len() as u8);
+                raw.extend(item);
+            }
+            Script::from_raw(raw)
+        };
+        let mut tx = legacy_tx(&multisig, 0, 0xffffffff);
+        // every signature carries its own hash type
+        let sig1 = sign(&tx, &keys[0], SIGHASH_ALL);
+        let sig3 = sign(&tx, &keys[2], SIGHASH_NONE | SIGHASH_ANYONECANPAY);
+        tx.inputs[0].script_sig = script_sig(&[], &[&sig1, &sig3]);
+        assert!(tx.verify_input(0));
+        // signatures out of the order of their keys
+        tx.inputs[0].script_sig = script_sig(&[], &[&sig3, &sig1]);
+        assert!(!tx.verify_input(0));
+        // the same key twice
+        tx.inputs[0].script_sig = script_sig(&[], &[&sig1, &sig1]);
+        assert!(!tx.verify_input(0));
+        // a dummy other than OP_0 was fine until BIP147
+        tx.inputs[0].script_sig = script_sig(&[1], &[&sig1, &sig3]);
+        assert!(!tx.verify_input(0));
+        assert!(tx.verify_input_with_flags(0, VerifyFlags::for_height(400_000)));
+    }
+    #[test]
+    fn test_set_prev_outputs() {
+        let script_pubkey = Script::from_raw(vec![OP_1]);
+        let inputs = vec![
+            TxInput::new(vec![0x11; 32], 0, Script::new(vec![]), 0xffffffff),
+            TxInput::new(vec![0x22; 32], 1, Script::new(vec![]), 0xffffffff),
+        ];
+        let mut tx = Tx::new(1, inputs, vec![TxOutput::new(1_000, script_pubkey.clone())], 0, false, false);
+        let mut prev_outputs = HashMap::new();
+        prev_outputs.insert((hex::encode([0x11; 32]), 0), TxOutput::new(600, script_pubkey.clone()));
+        assert!(!tx.set_prev_outputs(&prev_outputs));
+        prev_outputs.insert((hex::encode([0x22; 32]), 1), TxOutput::new(700, script_pubkey));
+        assert!(tx.set_prev_outputs(&prev_outputs));
+        assert_eq!(tx.fee(), 300);
+        assert!(tx.verify());
+    }
+    #[test]
+    fn test_verify_timelocks() {
+        let key = PrivateKey::new(&BigUint::from(8675309u32));
+        // <50> OP_CHECKLOCKTIMEVERIFY OP_DROP <key> OP_CHECKSIG
+        let mut raw = vec![0x01, 50, OP_CHECKLOCKTIMEVERIFY, OP_DROP, 0x21];
+        raw.extend(key.point().sec(true));
+        raw.push(OP_CHECKSIG);
+        let cltv = Script::from_raw(raw);
+        for (locktime, sequence, valid) in [(50, 0xfffffffe, true), (100, 0, true), (49, 0xfffffffe, false), (100, 0xffffffff, false), (500_000_050, 0, false)] {
+            let mut tx = legacy_tx(&cltv, locktime, sequence);
+            legacy_sign(&mut tx, &key, &cltv, SIGHASH_ALL, &[]);
+            assert_eq!(tx.verify_input(0), valid, "locktime {} sequence {:x}", locktime, sequence);
+        }
+        // before BIP65 OP_CHECKLOCKTIMEVERIFY was OP_NOP2
+        let mut tx = legacy_tx(&cltv, 49, 0xfffffffe);
+        legacy_sign(&mut tx, &key, &cltv, SIGHASH_ALL, &[]);
+        assert!(tx.verify_input_with_flags(0, VerifyFlags::for_height(300_000)));
+        assert!(!tx.verify_input_with_flags(0, VerifyFlags::for_height(400_000)));
+        // <10> OP_CHECKSEQUENCEVERIFY OP_DROP <key> OP_CHECKSIG, only from version 2
+        let mut raw = vec![OP_10, OP_CHECKSEQUENCEVERIFY, OP_DROP, 0x21];
+        raw.extend(key.point().sec(true));
+        raw.push(OP_CHECKSIG);
+        let csv = Script::from_raw(raw);
+        for (version, sequence, valid) in [(2, 10, true), (2, 9, false), (1, 10, false), (2, 10 | (1 << 22), false), (2, 1 << 31, false)] {
+            let mut tx = legacy_tx(&csv, 0, sequence);
+            tx.version = version;
+            legacy_sign(&mut tx, &key, &csv, SIGHASH_ALL, &[]);
+            assert_eq!(tx.verify_input(0), valid, "version {} sequence {:x}", version, sequence);
+        }
+    }
 }
@@ -4,6 +4,7 @@ use num::{BigUint, ToPrimitive};
 use crate::helpers::endianness::{int_to_little_endian, little_endian_to_int};
 use crate::tx_fetcher::TxFetcher;
 use crate::tx::Tx;
+use crate::tx_output::TxOutput;
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct TxInput {
@@ -12,6 +13,7 @@ pub struct TxInput {
     pub script_sig: Script,
     sequence: u32,
     pub witness: Option<Vec<Vec<u8>>>,
+    prev_output: Option<TxOutput>,
 }
 impl TxInput {
     pub fn new(prev_tx: Vec<u8>, prev_index: u32, script_sig: Script, sequence: u32) -> Self {
@@ -21,6 +23,7 @@ impl TxInput {
             script_sig: script_sig,
             sequence: sequence,
             witness: None,
+            prev_output: None,
         }
     }
     pub fn parse(stream: &mut Cursor<Vec<u8>>) -> Result<Self, Error> {
@@ -45,6 +48,7 @@ impl TxInput {
             script_sig: script_sig,
             witness: None,
             sequence,
+            prev_output: None,
         })
     }
     pub fn serialize(&self) -> Vec<u8> {
@@ -79,11 +83,24 @@ impl TxInput {
             Err(e) => Err(e)
         }
     }
+    /// Attaches the output being spent, so value and script_pubkey don't need a TxFetcher
+    pub fn set_prev_output(&mut self, prev_output: TxOutput) {
+        self.prev_output = Some(prev_output);
+    }
+    pub fn prev_output(&self) -> Option<TxOutput> {
+        self.prev_output.clone()
+    }
     pub fn value(&self, testnet: bool) -> u64 {
+        if let Some(prev_output) = &self.prev_output {
+            return prev_output.amount();
+        }
         let tx = self.fetch_tx(testnet).unwrap();
         tx.tx_outs()[self.prev_index as usize].amount()
     }
     pub fn script_pubkey(&self, testnet: bool) -> Script {
+        if let Some(prev_output) = &self.prev_output {
+            return prev_output.script_pubkey();
+        }
         let tx = self.fetch_tx(testnet).unwrap();
         tx.tx_outs()[self.prev_index as usize].script_pubkey()
     }
@@ -0,0 +1,195 @@
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+use crate::signature::SignatureChecks;
+
+/// Script verification rules, Bitcoin Core's SCRIPT_VERIFY_* flags with the same bits.
+/// Soft forks add rules, so a block is checked with the flags active at its height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VerifyFlags(u32);
+
+impl VerifyFlags {
+    pub const NONE: VerifyFlags = VerifyFlags(0);
+    /// BIP16 pay to script hash
+    pub const P2SH: VerifyFlags = VerifyFlags(1 << 0);
+    /// signatures with an undefined hash type or keys that are not SEC fail
+    pub const STRICTENC: VerifyFlags = VerifyFlags(1 << 1);
+    /// BIP66 strict DER signatures
+    pub const DERSIG: VerifyFlags = VerifyFlags(1 << 2);
+    /// s has to be at most n/2 (BIP146)
+    pub const LOW_S: VerifyFlags = VerifyFlags(1 << 3);
+    /// the extra element OP_CHECKMULTISIG pops has to be empty (BIP147)
+    pub const NULLDUMMY: VerifyFlags = VerifyFlags(1 << 4);
+    /// every scriptSig, not only P2SH ones, is push only
+    pub const SIGPUSHONLY: VerifyFlags = VerifyFlags(1 << 5);
+    /// pushes and numbers use their shortest encoding
+    pub const MINIMALDATA: VerifyFlags = VerifyFlags(1 << 6);
+    /// OP_NOP1 and OP_NOP4 to OP_NOP10 fail, they are kept for soft forks
+    pub const DISCOURAGE_UPGRADABLE_NOPS: VerifyFlags = VerifyFlags(1 << 7);
+    /// a single element left on the stack
+    pub const CLEANSTACK: VerifyFlags = VerifyFlags(1 << 8);
+    /// BIP65 OP_CHECKLOCKTIMEVERIFY, OP_NOP2 before it
+    pub const CHECKLOCKTIMEVERIFY: VerifyFlags = VerifyFlags(1 << 9);
+    /// BIP112 OP_CHECKSEQUENCEVERIFY, OP_NOP3 before it
+    pub const CHECKSEQUENCEVERIFY: VerifyFlags = VerifyFlags(1 << 10);
+    /// BIP141 segregated witness
+    pub const WITNESS: VerifyFlags = VerifyFlags(1 << 11);
+    /// witness versions without rules yet fail
+    pub const DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM: VerifyFlags = VerifyFlags(1 << 12);
+    /// OP_IF arguments in witness v0 scripts are empty or 0x01
+    pub const MINIMALIF: VerifyFlags = VerifyFlags(1 << 13);
+    /// a failed signature check needs an empty signature
+    pub const NULLFAIL: VerifyFlags = VerifyFlags(1 << 14);
+    /// witness v0 keys are compressed
+    pub const WITNESS_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 15);
+    /// no OP_CODESEPARATOR and no signature pushes in legacy scripts
+    pub const CONST_SCRIPTCODE: VerifyFlags = VerifyFlags(1 << 16);
+    /// BIP341 and BIP342
+    pub const TAPROOT: VerifyFlags = VerifyFlags(1 << 17);
+    /// taproot leaf versions without rules yet fail
+    pub const DISCOURAGE_UPGRADABLE_TAPROOT_VERSION: VerifyFlags = VerifyFlags(1 << 18);
+    /// OP_SUCCESSx fail instead of succeeding
+    pub const DISCOURAGE_OP_SUCCESS: VerifyFlags = VerifyFlags(1 << 19);
+    /// tapscript keys that are not 32 bytes fail
+    pub const DISCOURAGE_UPGRADABLE_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 20);
+
+    /// every consensus rule in force today
+    pub const MANDATORY: VerifyFlags = VerifyFlags(
+        Self::P2SH.0 | Self::DERSIG.0 | Self::NULLDUMMY.0 | Self::CHECKLOCKTIMEVERIFY.0
+            | Self::CHECKSEQUENCEVERIFY.0 | Self::WITNESS.0 | Self::TAPROOT.0,
+    );
+    /// the relay policy of Bitcoin Core
+    pub const STANDARD: VerifyFlags = VerifyFlags(
+        Self::MANDATORY.0 | Self::STRICTENC.0 | Self::MINIMALDATA.0 | Self::DISCOURAGE_UPGRADABLE_NOPS.0
+            | Self::CLEANSTACK.0 | Self::MINIMALIF.0 | Self::NULLFAIL.0 | Self::LOW_S.0
+            | Self::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM.0 | Self::WITNESS_PUBKEYTYPE.0
+            | Self::CONST_SCRIPTCODE.0 | Self::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION.0
+            | Self::DISCOURAGE_OP_SUCCESS.0 | Self::DISCOURAGE_UPGRADABLE_PUBKEYTYPE.0,
+    );
+
+    /// Bitcoin Core's name of each flag, as in its script and transaction tests
+    pub const NAMES: [(&'static str, VerifyFlags); 21] = [
+        ("P2SH", Self::P2SH),
+        ("STRICTENC", Self::STRICTENC),
+        ("DERSIG", Self::DERSIG),
+        ("LOW_S", Self::LOW_S),
+        ("NULLDUMMY", Self::NULLDUMMY),
+        ("SIGPUSHONLY", Self::SIGPUSHONLY),
+        ("MINIMALDATA", Self::MINIMALDATA),
+        ("DISCOURAGE_UPGRADABLE_NOPS", Self::DISCOURAGE_UPGRADABLE_NOPS),
+        ("CLEANSTACK", Self::CLEANSTACK),
+        ("CHECKLOCKTIMEVERIFY", Self::CHECKLOCKTIMEVERIFY),
+        ("CHECKSEQUENCEVERIFY", Self::CHECKSEQUENCEVERIFY),
+        ("WITNESS", Self::WITNESS),
+        ("DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM", Self::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM),
+        ("MINIMALIF", Self::MINIMALIF),
+        ("NULLFAIL", Self::NULLFAIL),
+        ("WITNESS_PUBKEYTYPE", Self::WITNESS_PUBKEYTYPE),
+        ("CONST_SCRIPTCODE", Self::CONST_SCRIPTCODE),
+        ("TAPROOT", Self::TAPROOT),
+        ("DISCOURAGE_UPGRADABLE_TAPROOT_VERSION", Self::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION),
+        ("DISCOURAGE_OP_SUCCESS", Self::DISCOURAGE_OP_SUCCESS),
+        ("DISCOURAGE_UPGRADABLE_PUBKEYTYPE", Self::DISCOURAGE_UPGRADABLE_PUBKEYTYPE),
+    ];
+
+    /// Flags from a comma separated list of names like "P2SH,STRICTENC", empty or
+    /// "NONE" for no flag. None on an unknown name.
+    pub fn from_names(names: &str) -> Option<Self> {
+        let mut flags = VerifyFlags::NONE;
+        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty() && *name != "NONE") {
+            let (_, flag) = Self::NAMES.iter().find(|(known, _)| *known == name)?;
+            flags |= *flag;
+        }
+        Some(flags)
+    }
+    pub const fn from_bits(bits: u32) -> Self {
+        VerifyFlags(bits)
+    }
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+    /// true when every flag of other is set
+    pub const fn contains(&self, other: VerifyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+    pub const fn without(&self, other: VerifyFlags) -> Self {
+        VerifyFlags(self.0 & !other.0)
+    }
+    /// The consensus flags of a mainnet block at height, from the height each soft fork
+    /// activated at. P2SH activated by time, 173805 is its first block.
+    pub fn for_height(height: u32) -> Self {
+        let mut flags = VerifyFlags::NONE;
+        if height >= 173_805 {
+            flags |= VerifyFlags::P2SH;
+        }
+        if height >= 363_725 {
+            flags |= VerifyFlags::DERSIG;
+        }
+        if height >= 388_381 {
+            flags |= VerifyFlags::CHECKLOCKTIMEVERIFY;
+        }
+        if height >= 419_328 {
+            flags |= VerifyFlags::CHECKSEQUENCEVERIFY;
+        }
+        if height >= 481_824 {
+            flags |= VerifyFlags::WITNESS | VerifyFlags::NULLDUMMY;
+        }
+        if height >= 709_632 {
+            flags |= VerifyFlags::TAPROOT;
+        }
+        flags
+    }
+    /// the signature and key encoding rules among the flags
+    pub fn signature_checks(&self) -> SignatureChecks {
+        SignatureChecks {
+            der: self.contains(VerifyFlags::DERSIG),
+            low_s: self.contains(VerifyFlags::LOW_S),
+            strict_enc: self.contains(VerifyFlags::STRICTENC),
+        }
+    }
+}
+impl BitOr for VerifyFlags {
+    type Output = Self;
+    fn bitor(self, other: Self) -> Self {
+        VerifyFlags(self.0 | other.0)
+    }
+}
+impl BitOrAssign for VerifyFlags {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+impl fmt::Display for VerifyFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_for_height() {
+        assert_eq!(VerifyFlags::for_height(170_000), VerifyFlags::NONE);
+        assert_eq!(VerifyFlags::for_height(200_000), VerifyFlags::P2SH);
+        assert_eq!(VerifyFlags::for_height(400_000), VerifyFlags::P2SH | VerifyFlags::DERSIG | VerifyFlags::CHECKLOCKTIMEVERIFY);
+        assert!(!VerifyFlags::for_height(700_000).contains(VerifyFlags::TAPROOT));
+        assert_eq!(VerifyFlags::for_height(800_000), VerifyFlags::MANDATORY);
+    }
+    #[test]
+    fn test_signature_checks() {
+        assert_eq!(VerifyFlags::STANDARD.signature_checks(), SignatureChecks::STANDARD);
+        assert_eq!(VerifyFlags::NONE.signature_checks(), SignatureChecks::NONE);
+        assert!(VerifyFlags::MANDATORY.signature_checks().der);
+        assert!(VerifyFlags::STANDARD.contains(VerifyFlags::MANDATORY));
+        assert!(!VerifyFlags::STANDARD.without(VerifyFlags::P2SH).contains(VerifyFlags::MANDATORY));
+    }
+    #[test]
+    fn test_from_names() {
+        assert_eq!(VerifyFlags::from_names(""), Some(VerifyFlags::NONE));
+        assert_eq!(VerifyFlags::from_names("NONE"), Some(VerifyFlags::NONE));
+        assert_eq!(VerifyFlags::from_names("P2SH,STRICTENC"), Some(VerifyFlags::P2SH | VerifyFlags::STRICTENC));
+        assert_eq!(VerifyFlags::from_names("P2SH,BOGUS"), None);
+        let all = VerifyFlags::NAMES.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(",");
+        assert!(VerifyFlags::from_names(&all).unwrap().contains(VerifyFlags::STANDARD));
+    }
+}
@@ -0,0 +1,92 @@
+use core::fmt;
+use std::io::{Error, ErrorKind};
+use crate::fe::Fe;
+use crate::helpers::tagged_hash::tagged_hash;
+use crate::jacobian_point::{self, JacobianPoint};
+use crate::point::Point;
+use crate::scalar::Scalar;
+use crate::schnorr_signature::{challenge, SchnorrSignature};
+
+/// BIP340 public key: only the x coordinate is serialized, the point is the one with even y
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct XOnlyPublicKey {
+    point: Point,
+}
+
+impl XOnlyPublicKey {
+    /// the x-only key of a point and whether the point had an odd y
+    pub fn from_point(point: &Point) -> (Self, bool) {
+        let (x, y) = point.affine().expect("point at infinity has no x-only key");
+        let key = XOnlyPublicKey { point: Point::lift_x(&x).unwrap() };
+        (key, y.is_odd())
+    }
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes: &[u8; 32] = bytes.try_into()
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "x-only public key must be 32 bytes"))?;
+        let x = Fe::from_bytes(bytes)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "x-only public key not in field"))?;
+        let point = Point::lift_x(&x)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "x-only public key not on the curve"))?;
+        Ok(XOnlyPublicKey { point })
+    }
+    pub fn serialize(&self) -> [u8; 32] {
+        let (x, _) = self.point.affine().unwrap();
+        x.to_bytes()
+    }
+    pub fn point(&self) -> Point {
+        self.point.clone()
+    }
+    /// BIP341 tweak of this internal key: hash_TapTweak(x || merkle_root), just x for a
+    /// key path only output
+    pub fn tap_tweak_hash(&self, merkle_root: Option<[u8; 32]>) -> [u8; 32] {
+        let mut data = self.serialize().to_vec();
+        if let Some(root) = merkle_root {
+            data.extend(root);
+        }
+        tagged_hash("TapTweak", &data)
+    }
+    pub fn verify_schnorr(&self, msg: &[u8], signature: &SchnorrSignature) -> bool {
+        let Some(r) = Fe::from_bytes(signature.r()) else { return false };
+        let Some(s) = Scalar::from_bytes(signature.s()) else { return false };
+        let e = challenge(signature.r(), &self.serialize(), msg);
+
+        // R = s*G - e*P must have an even y and x(R) == r
+        let (px, py) = self.point.affine().unwrap();
+        let big_r = jacobian_point::mul_double_vartime(&s, &JacobianPoint::from_affine(&px, &py), &-e);
+        match big_r.to_affine() {
+            Some((x, y)) => y.is_even() && x == r,
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for XOnlyPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.serialize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num::BigUint;
+
+    #[test]
+    fn test_from_point_parity() {
+        let point = Point::generator() * BigUint::from(3u32);
+        let (key, odd) = XOnlyPublicKey::from_point(&point);
+        let (neg_key, neg_odd) = XOnlyPublicKey::from_point(&(Point::generator() * (crate::secp256k1::Secp256k1::new().n - BigUint::from(3u32))));
+        assert_eq!(key, neg_key);
+        assert_ne!(odd, neg_odd);
+        assert_eq!(XOnlyPublicKey::parse(&key.serialize()).unwrap(), key);
+    }
+    #[test]
+    fn test_parse_invalid() {
+        // x = 5 is not on the curve
+        let mut bytes = [0u8; 32];
+        bytes[31] = 5;
+        assert!(XOnlyPublicKey::parse(&bytes).is_err());
+        assert!(XOnlyPublicKey::parse(&[0xffu8; 32]).is_err());
+        assert!(XOnlyPublicKey::parse(&[2u8; 31]).is_err());
+    }
+}
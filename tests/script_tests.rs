@@ -0,0 +1,214 @@
+// Runs test_vectors/script_tests.json, in the format of Bitcoin Core's
+// src/test/data/script_tests.json, through the script interpreter.
+use std::collections::BTreeSet;
+use num::BigUint;
+use btc::execution_context::ExecutionContext;
+use btc::private_key::PrivateKey;
+use btc::script::Script;
+use btc::script_error::ScriptError;
+use btc::signature::SignatureError;
+use btc::taproot::{TapLeaf, TaprootBuilder, TaprootSpendInfo, TAPROOT_LEAF_TAPSCRIPT};
+use btc::tx::Tx;
+use btc::tx_input::TxInput;
+use btc::tx_output::TxOutput;
+use btc::verify_flags::VerifyFlags;
+use serde_json::Value;
+
+// the name Bitcoin Core's ScriptErrorString test helper gives an error
+fn core_name(error: &ScriptError) -> &'static str {
+    match error {
+        ScriptError::EvalFalse => "EVAL_FALSE",
+        ScriptError::OpReturn => "OP_RETURN",
+        ScriptError::BadOpcode => "BAD_OPCODE",
+        ScriptError::DisabledOpcode => "DISABLED_OPCODE",
+        ScriptError::ScriptSize => "SCRIPT_SIZE",
+        ScriptError::PushSize => "PUSH_SIZE",
+        ScriptError::OpCount => "OP_COUNT",
+        ScriptError::StackSize => "STACK_SIZE",
+        ScriptError::UnbalancedConditional => "UNBALANCED_CONDITIONAL",
+        ScriptError::StackUnderflow => "INVALID_STACK_OPERATION",
+        ScriptError::AltStackUnderflow => "INVALID_ALTSTACK_OPERATION",
+        ScriptError::Verify => "VERIFY",
+        ScriptError::EqualVerify => "EQUALVERIFY",
+        ScriptError::NumEqualVerify => "NUMEQUALVERIFY",
+        ScriptError::CheckSigVerify => "CHECKSIGVERIFY",
+        ScriptError::CheckMultisigVerify => "CHECKMULTISIGVERIFY",
+        // Core throws scriptnum_error, reported as an unknown error
+        ScriptError::InvalidNumber => "UNKNOWN_ERROR",
+        ScriptError::PubKeyCount => "PUBKEY_COUNT",
+        ScriptError::SigCount => "SIG_COUNT",
+        ScriptError::NegativeLocktime => "NEGATIVE_LOCKTIME",
+        ScriptError::UnsatisfiedLocktime => "UNSATISFIED_LOCKTIME",
+        ScriptError::Signature(SignatureError::HighS) => "SIG_HIGH_S",
+        ScriptError::Signature(SignatureError::HashType) => "SIG_HASHTYPE",
+        ScriptError::Signature(SignatureError::PubKeyType) => "PUBKEYTYPE",
+        ScriptError::Signature(_) => "SIG_DER",
+        ScriptError::SigCheck => "SCHNORR_SIG",
+        ScriptError::SchnorrSigSize => "SCHNORR_SIG_SIZE",
+        ScriptError::SchnorrSigHashType => "SCHNORR_SIG_HASHTYPE",
+        ScriptError::WitnessProgramMismatch => "WITNESS_PROGRAM_MISMATCH",
+        ScriptError::WitnessProgramWitnessEmpty => "WITNESS_PROGRAM_WITNESS_EMPTY",
+        ScriptError::WitnessProgramWrongLength => "WITNESS_PROGRAM_WRONG_LENGTH",
+        ScriptError::WitnessMalleated => "WITNESS_MALLEATED",
+        ScriptError::WitnessMalleatedP2SH => "WITNESS_MALLEATED_P2SH",
+        ScriptError::SigPushOnly => "SIG_PUSHONLY",
+        ScriptError::TaprootWrongControlSize => "TAPROOT_WRONG_CONTROL_SIZE",
+        // Core always has the spent outputs
+        ScriptError::TaprootMissingPrevouts => "UNKNOWN_ERROR",
+        ScriptError::TapscriptValidationWeight => "TAPSCRIPT_VALIDATION_WEIGHT",
+        ScriptError::TapscriptCheckMultisig => "TAPSCRIPT_CHECKMULTISIG",
+        ScriptError::TapscriptMinimalIf => "TAPSCRIPT_MINIMALIF",
+        ScriptError::TapscriptEmptyPubKey => "TAPSCRIPT_EMPTY_PUBKEY",
+        ScriptError::CleanStack => "CLEANSTACK",
+        ScriptError::MinimalData => "MINIMALDATA",
+        ScriptError::MinimalIf => "MINIMALIF",
+        ScriptError::SigNullDummy => "SIG_NULLDUMMY",
+        ScriptError::NullFail => "NULLFAIL",
+        ScriptError::DiscourageUpgradableNops => "DISCOURAGE_UPGRADABLE_NOPS",
+        ScriptError::DiscourageUpgradableWitnessProgram => "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM",
+        ScriptError::DiscourageUpgradableTaprootVersion => "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION",
+        ScriptError::DiscourageOpSuccess => "DISCOURAGE_OP_SUCCESS",
+        ScriptError::DiscourageUpgradablePubKeyType => "DISCOURAGE_UPGRADABLE_PUBKEYTYPE",
+        ScriptError::WitnessUnexpected => "WITNESS_UNEXPECTED",
+        ScriptError::WitnessPubKeyType => "WITNESS_PUBKEYTYPE",
+        ScriptError::OpCodeSeparator => "OP_CODESEPARATOR",
+        ScriptError::SigFindAndDelete => "SIG_FINDANDDELETE",
+    }
+}
+
+// Cases this crate cannot run, each as the case's JSON with the reason. Every entry
+// must still be in the vectors, and every case not listed here must pass.
+const SKIPPED: &[(&str, &str)] = &[];
+
+enum Outcome {
+    Passed,
+    Failed(String),
+}
+
+// Core builds its taproot cases from placeholders: "#SCRIPT# <asm>" is a tapscript,
+// "#CONTROLBLOCK#" the control block of a tree holding only the witness element before
+// it, under the internal key of secret 1, and "#TAPROOTOUTPUT#" that tree's output key
+fn control_block(witness: &[Vec<u8>]) -> Result<(Vec<u8>, TaprootSpendInfo), String> {
+    let script = Script::from_raw(witness.last().ok_or("#CONTROLBLOCK# without a script before it")?.clone());
+    let internal_key = PrivateKey::new(&BigUint::from(1u8)).x_only_public_key();
+    let spend_info = TaprootBuilder::new()
+        .add_leaf(0, script.clone())
+        .and_then(|builder| builder.finalize(&internal_key))
+        .map_err(|e| e.to_string())?;
+    let control_block = spend_info.control_block(&TapLeaf::new(script, TAPROOT_LEAF_TAPSCRIPT).map_err(|e| e.to_string())?).ok_or("leaf missing from its tree")?;
+    Ok((control_block.serialize(), spend_info))
+}
+
+// Verifies one case the way Core's script_tests do: a crediting transaction with
+// the scriptPubKey as its only output and a transaction spending it with the scriptSig
+fn run_case(case: &[Value]) -> Outcome {
+    let mut spend_info = None;
+    let (witness, amount, rest) = match &case[0] {
+        Value::Array(items) => {
+            let Some((amount, elements)) = items.split_last() else {
+                return Outcome::Failed("empty witness entry".to_string());
+            };
+            let mut witness = vec![];
+            for element in elements {
+                let element = element.as_str().unwrap_or_default();
+                if let Some(asm) = element.strip_prefix("#SCRIPT#") {
+                    match Script::from_asm(asm.trim()) {
+                        Ok(script) => witness.push(script.serialize_raw()),
+                        Err(e) => return Outcome::Failed(e.to_string()),
+                    }
+                } else if element == "#CONTROLBLOCK#" {
+                    match control_block(&witness) {
+                        Ok((control_block, info)) => {
+                            witness.push(control_block);
+                            spend_info = Some(info);
+                        }
+                        Err(e) => return Outcome::Failed(e),
+                    }
+                } else if element.starts_with('#') {
+                    return Outcome::Failed(format!("unknown witness placeholder {}", element));
+                } else {
+                    match hex::decode(element) {
+                        Ok(bytes) => witness.push(bytes),
+                        Err(_) => return Outcome::Failed(format!("bad witness hex {}", element)),
+                    }
+                }
+            }
+            let sats = (amount.as_f64().unwrap_or_default() * 100_000_000.0).round() as u64;
+            (Some(witness), sats, &case[1..])
+        }
+        _ => (None, 0, case),
+    };
+    if rest.len() < 4 {
+        return Outcome::Failed("too few fields".to_string());
+    }
+    let field = |i: usize| rest[i].as_str().unwrap_or_default();
+    let script_pubkey = if field(1) == "0x51 0x20 #TAPROOTOUTPUT#" {
+        match &spend_info {
+            Some(info) => Ok(info.script_pubkey()),
+            None => return Outcome::Failed("#TAPROOTOUTPUT# without #CONTROLBLOCK#".to_string()),
+        }
+    } else if field(1).contains('#') {
+        return Outcome::Failed(format!("unknown scriptPubKey placeholder {}", field(1)));
+    } else {
+        Script::from_asm(field(1))
+    };
+    let (script_sig, script_pubkey) = match (Script::from_asm(field(0)), script_pubkey) {
+        (Ok(script_sig), Ok(script_pubkey)) => (script_sig, script_pubkey),
+        (Err(e), _) | (_, Err(e)) => return Outcome::Failed(e.to_string()),
+    };
+    let Some(flags) = VerifyFlags::from_names(field(2)) else {
+        return Outcome::Failed(format!("unknown flags {}", field(2)));
+    };
+    let expected = field(3);
+
+    let coinbase = TxInput::new(vec![0; 32], 0xffff_ffff, Script::from_raw(vec![0x00, 0x00]), 0xffff_ffff);
+    let credit = Tx::new(1, vec![coinbase], vec![TxOutput::new(amount, script_pubkey.clone())], 0, false, false);
+    let prev_tx = hex::decode(credit.tx_id()).expect("tx id is hex");
+    let mut input = TxInput::new(prev_tx, 0, script_sig.clone(), 0xffff_ffff);
+    input.witness = witness.clone();
+    input.set_prev_output(TxOutput::new(amount, script_pubkey.clone()));
+    let spend = Tx::new(1, vec![input], vec![TxOutput::new(amount, Script::from_raw(vec![]))], 0, false, witness.is_some());
+
+    let prevouts = [TxOutput::new(amount, script_pubkey.clone())];
+    let context = ExecutionContext::new(&spend, 0, amount).with_flags(flags).with_prevouts(&prevouts);
+    let actual = match Script::verify_script(&script_sig, &script_pubkey, &witness, &context) {
+        Ok(()) => "OK",
+        Err(e) => core_name(&e),
+    };
+    if actual == expected {
+        Outcome::Passed
+    } else {
+        Outcome::Failed(format!("expected {} got {}", expected, actual))
+    }
+}
+
+#[test]
+fn test_script_tests_json() {
+    let cases: Vec<Vec<Value>> = serde_json::from_str(include_str!("../test_vectors/script_tests.json"))
+        .expect("script_tests.json is an array of arrays");
+    let (mut passed, mut taproot, mut failures) = (0, 0, vec![]);
+    let mut skipped = BTreeSet::new();
+    for case in cases.iter().filter(|case| case.len() > 1) {
+        let json = Value::Array(case.clone()).to_string();
+        if let Some((listed, reason)) = SKIPPED.iter().find(|(listed, _)| *listed == json) {
+            println!("skipped ({}): {}", reason, json);
+            skipped.insert(*listed);
+            continue;
+        }
+        match run_case(case) {
+            Outcome::Passed => {
+                passed += 1;
+                if json.contains("#CONTROLBLOCK#") {
+                    taproot += 1;
+                }
+            }
+            Outcome::Failed(reason) => failures.push(format!("{}: {}", reason, json)),
+        }
+    }
+    println!("script_tests.json: {} passed ({} taproot placeholder cases), {} failed, {} skipped", passed, taproot, failures.len(), skipped.len());
+    for (listed, _) in SKIPPED {
+        assert!(skipped.contains(listed), "skip list entry not in the vectors: {}", listed);
+    }
+    assert!(taproot > 0, "no taproot placeholder case ran");
+    assert!(failures.is_empty(), "{} failed:\n{}", failures.len(), failures.join("\n"));
+}
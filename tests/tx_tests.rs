@@ -0,0 +1,177 @@
+// Runs test_vectors/tx_valid.json and tx_invalid.json, in the format of Bitcoin
+// Core's src/test/data, through Tx::verify_input_with_flags with the spent outputs
+// supplied from the vectors instead of a TxFetcher.
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use btc::script::Script;
+use btc::tx::Tx;
+use btc::tx_output::TxOutput;
+use btc::verify_flags::VerifyFlags;
+use serde_json::Value;
+
+const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
+// Entries this crate cannot run, as (file, serialized transaction, reason). Every
+// entry must still be in its file, and every entry not listed here must pass.
+const SKIPPED: &[(&str, &str, &str)] = &[];
+
+// a transaction and the outputs it spends, or why the entry could not be read
+fn parse_case(case: &[Value]) -> Result<Tx, String> {
+    let mut prev_outputs = HashMap::new();
+    for prevout in case[0].as_array().ok_or("prevouts are not an array")? {
+        let prevout = prevout.as_array().ok_or("prevout is not an array")?;
+        let tx_id = prevout[0].as_str().ok_or("prevout hash is not a string")?;
+        // -1 stands for the index of a coinbase input
+        let index = prevout[1].as_i64().ok_or("prevout index is not a number")? as u32;
+        let script_pubkey = Script::from_asm(prevout[2].as_str().ok_or("prevout script is not a string")?)
+            .map_err(|e| e.to_string())?;
+        let amount = prevout.get(3).and_then(Value::as_u64).unwrap_or(0);
+        prev_outputs.insert((tx_id.to_string(), index), TxOutput::new(amount, script_pubkey));
+    }
+    let raw_tx = hex::decode(case[1].as_str().ok_or("transaction is not a string")?)
+        .map_err(|e| e.to_string())?;
+    let mut tx = Tx::parse(&mut Cursor::new(raw_tx), false).map_err(|e| e.to_string())?;
+    if !tx.set_prev_outputs(&prev_outputs) {
+        return Err("an input spends an output not listed".to_string());
+    }
+    Ok(tx)
+}
+
+// Bitcoin Core's CheckTransaction, the rules that need neither the spent outputs nor
+// the chain
+fn check_transaction(tx: &Tx) -> bool {
+    let (inputs, outputs) = (tx.tx_ins(), tx.tx_outs());
+    if inputs.is_empty() || outputs.is_empty() {
+        return false;
+    }
+    let mut total = 0u64;
+    for output in &outputs {
+        total = total.saturating_add(output.amount());
+        if output.amount() > MAX_MONEY || total > MAX_MONEY {
+            return false;
+        }
+    }
+    let mut outpoints = HashSet::new();
+    if !inputs.iter().all(|input| outpoints.insert((input.prev_tx(), input.prev_index()))) {
+        return false;
+    }
+    if tx.is_coinbase() {
+        (2..=100).contains(&inputs[0].script_sig().serialize_raw().len())
+    } else {
+        inputs.iter().all(|input| input.prev_tx() != [0u8; 32] || input.prev_index() != 0xffffffff)
+    }
+}
+
+fn verify(tx: &Tx, flags: VerifyFlags) -> bool {
+    let mut tx = tx.clone();
+    (0..tx.tx_ins().len()).all(|i| tx.verify_input_with_flags(i, flags))
+}
+
+fn all_flags() -> VerifyFlags {
+    VerifyFlags::NAMES.iter().fold(VerifyFlags::NONE, |all, (_, flag)| all | *flag)
+}
+
+// Core's TrimFlags and FillFlags: WITNESS needs P2SH and CLEANSTACK needs WITNESS
+fn trim_flags(flags: VerifyFlags) -> VerifyFlags {
+    let mut flags = flags;
+    if !flags.contains(VerifyFlags::P2SH) {
+        flags = flags.without(VerifyFlags::WITNESS);
+    }
+    if !flags.contains(VerifyFlags::WITNESS) {
+        flags = flags.without(VerifyFlags::CLEANSTACK);
+    }
+    flags
+}
+fn fill_flags(flags: VerifyFlags) -> VerifyFlags {
+    let mut flags = flags;
+    if flags.contains(VerifyFlags::CLEANSTACK) {
+        flags |= VerifyFlags::WITNESS;
+    }
+    if flags.contains(VerifyFlags::WITNESS) {
+        flags |= VerifyFlags::P2SH;
+    }
+    flags
+}
+
+// valid under every flag but the excluded ones, and with any one more flag removed
+fn run_valid(case: &[Value]) -> Result<(), String> {
+    let tx = parse_case(case)?;
+    let names = case[2].as_str().unwrap_or_default();
+    let excluded = VerifyFlags::from_names(names).ok_or(format!("unknown flags {}", names))?;
+    if !check_transaction(&tx) {
+        return Err("fails CheckTransaction".to_string());
+    }
+    let flags = all_flags().without(excluded);
+    if !verify(&tx, flags) {
+        return Err(format!("invalid under {}", flags));
+    }
+    for (name, flag) in VerifyFlags::NAMES {
+        if !verify(&tx, trim_flags(flags.without(flag))) {
+            return Err(format!("invalid without {}", name));
+        }
+    }
+    Ok(())
+}
+
+// invalid under the flags, and with any one more flag added
+fn run_invalid(case: &[Value]) -> Result<(), String> {
+    let tx = parse_case(case)?;
+    let names = case[2].as_str().unwrap_or_default();
+    if names == "BADTX" {
+        return match check_transaction(&tx) {
+            true => Err("passes CheckTransaction".to_string()),
+            false => Ok(()),
+        };
+    }
+    let flags = VerifyFlags::from_names(names).ok_or(format!("unknown flags {}", names))?;
+    if !check_transaction(&tx) {
+        return Err("fails CheckTransaction without BADTX".to_string());
+    }
+    if verify(&tx, flags) {
+        return Err(format!("valid under {}", flags));
+    }
+    for (name, flag) in VerifyFlags::NAMES {
+        if verify(&tx, fill_flags(flags | flag)) {
+            return Err(format!("valid with {} added", name));
+        }
+    }
+    Ok(())
+}
+
+fn run_file(name: &str, json: &str, run: fn(&[Value]) -> Result<(), String>) {
+    let cases: Vec<Vec<Value>> = serde_json::from_str(json).expect("test vectors are an array of arrays");
+    let (mut passed, mut failures) = (0, vec![]);
+    let mut skipped = HashSet::new();
+    // single strings are comments
+    for case in cases.iter().filter(|case| case.len() > 1) {
+        if case.len() != 3 {
+            failures.push(format!("expected 3 fields: {}", Value::Array(case.clone())));
+            continue;
+        }
+        let raw_tx = case[1].as_str().unwrap_or_default();
+        if let Some((_, listed, reason)) = SKIPPED.iter().find(|(file, listed, _)| *file == name && *listed == raw_tx) {
+            println!("skipped ({}): {}", reason, raw_tx);
+            skipped.insert(*listed);
+            continue;
+        }
+        match run(case) {
+            Ok(()) => passed += 1,
+            Err(reason) => failures.push(format!("{}: {}", reason, Value::Array(case.clone()))),
+        }
+    }
+    println!("{}: {} passed, {} failed, {} skipped", name, passed, failures.len(), skipped.len());
+    for (_, listed, _) in SKIPPED.iter().filter(|(file, _, _)| *file == name) {
+        assert!(skipped.contains(listed), "skip list entry not in {}: {}", name, listed);
+    }
+    assert!(failures.is_empty(), "{} failed:\n{}", failures.len(), failures.join("\n"));
+}
+
+#[test]
+fn test_tx_valid_json() {
+    run_file("tx_valid.json", include_str!("../test_vectors/tx_valid.json"), run_valid);
+}
+
+#[test]
+fn test_tx_invalid_json() {
+    run_file("tx_invalid.json", include_str!("../test_vectors/tx_invalid.json"), run_invalid);
+}